@@ -0,0 +1,160 @@
+//! Waiting for the time given to `--alarm` before playing, to wake up to
+//! music. The time is local time, read from the C library on Unix; elsewhere
+//! it is taken as UTC.
+
+use std::fmt;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often the clock is read while waiting, rather than sleeping through:
+/// the computer may be suspended in between.
+const CHECK: Duration = Duration::from_secs(1);
+
+/// A time of the day, to the minute.
+#[derive(Clone, Copy)]
+pub struct AlarmTime {
+    hour: u32,
+    minute: u32,
+}
+
+impl fmt::Display for AlarmTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+/// `HH:MM`, on 24 hours, e.g. "07:00" or "7:00".
+pub fn parse_time(time: &str) -> Result<AlarmTime, String> {
+    let invalid = || format!("invalid time \"{}\" (expected HH:MM)", time);
+    let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+    let (hour, minute): (u32, u32) = (
+        hour.parse().map_err(|_| invalid())?,
+        minute.parse().map_err(|_| invalid())?,
+    );
+    if hour > 23 || minute > 59 {
+        return Err(invalid());
+    }
+    Ok(AlarmTime { hour, minute })
+}
+
+/// A length such as "90s", "5m" or "1h"; a bare number is in seconds.
+pub fn parse_length(length: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid length \"{}\" (expected e.g. 30s, 5m or 1h)", length);
+    let (number, unit) = match length.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => length.split_at(index),
+        None => (length, "s"),
+    };
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => Some(number),
+        "m" => number.checked_mul(60),
+        "h" => number.checked_mul(3600),
+        _ => None,
+    };
+    seconds.map(Duration::from_secs).ok_or_else(invalid)
+}
+
+/// Blocks until the next `time`, today or tomorrow.
+pub fn wait(time: AlarmTime) {
+    let at = next(time, now());
+    // In minutes, rounded up.
+    let left = at.saturating_sub(now()).div_ceil(60);
+    println!("Alarm set for {}, in {} h {:02} min. Ctrl-C to cancel.", time, left / 60, left % 60);
+    tracing::info!("Waiting for {} to play", time);
+    loop {
+        let left = at.saturating_sub(now());
+        if left == 0 {
+            return;
+        }
+        thread::sleep(CHECK.min(Duration::from_secs(left)));
+    }
+}
+
+/// Seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Seconds since the Unix epoch of the first `time` after `now`. On the
+/// days the clocks change, a time skipped is taken an hour later.
+#[cfg(unix)]
+fn next(time: AlarmTime, now: u64) -> u64 {
+    let now = now as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&now, &mut tm) };
+    let mut at = now;
+    for _ in 0..2 {
+        tm.tm_hour = time.hour as libc::c_int;
+        tm.tm_min = time.minute as libc::c_int;
+        tm.tm_sec = 0;
+        // Whether it is summer time then is for mktime to tell.
+        tm.tm_isdst = -1;
+        at = unsafe { libc::mktime(&mut tm) };
+        if at > now {
+            break;
+        }
+        tm.tm_mday += 1;
+    }
+    at.max(0) as u64
+}
+
+#[cfg(not(unix))]
+fn next(time: AlarmTime, now: u64) -> u64 {
+    let at = now - now % 86_400 + time.hour as u64 * 3600 + time.minute as u64 * 60;
+    if at > now {
+        at
+    } else {
+        at + 86_400
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_times() {
+        let time = parse_time("7:00").unwrap();
+        assert_eq!((time.hour, time.minute), (7, 0));
+        assert_eq!(time.to_string(), "07:00");
+        assert_eq!(parse_time("23:59").unwrap().to_string(), "23:59");
+        assert!(parse_time("24:00").is_err());
+        assert!(parse_time("7:60").is_err());
+        assert!(parse_time("7").is_err());
+        assert!(parse_time("").is_err());
+    }
+
+    #[test]
+    fn parses_lengths() {
+        assert_eq!(parse_length("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_length("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_length("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_length("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_length("10x").is_err());
+        assert!(parse_length("").is_err());
+        assert!(parse_length("m").is_err());
+        assert!(parse_length(&format!("{}m", u64::MAX)).is_err());
+    }
+
+    #[cfg(unix)]
+    fn local_hour_minute(at: u64) -> (i32, i32) {
+        let at = at as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe { libc::localtime_r(&at, &mut tm) };
+        (tm.tm_hour, tm.tm_min)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn next_is_the_first_time_after_now() {
+        // 2024-03-15 12:00:00 UTC, away from the days the clocks change.
+        let now = 1_710_504_000;
+        for (hour, minute) in [(0, 0), (7, 30), (23, 59)] {
+            let at = next(AlarmTime { hour, minute }, now);
+            assert!(at > now && at - now <= 86_400);
+            assert_eq!(local_hour_minute(at), (hour as i32, minute as i32));
+            // At the very time, the alarm is for the day after.
+            assert_eq!(next(AlarmTime { hour, minute }, at), at + 86_400);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use crate::config;
+use crate::player::{self, TrackMetadata};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Name of the index of tags and lengths in the cache directory.
+const INDEX_NAME: &str = "metadata.json";
+
+/// Side of the square cover thumbnails stored in the cache.
+const THUMBNAIL_SIZE: u32 = 64;
+
+/// A small RGB cover image, ready to be drawn in the terminal.
+#[derive(Clone)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Thumbnail {
+    fn from_image(image: &image::DynamicImage) -> Self {
+        let rgb = image.to_rgb8();
+        Thumbnail {
+            width: rgb.width(),
+            height: rgb.height(),
+            pixels: rgb.pixels().map(|p| p.0).collect(),
+        }
+    }
+
+    /// Nearest-neighbour sample at relative coordinates in `[0, 1)`.
+    pub fn sample(&self, x: f32, y: f32) -> [u8; 3] {
+        let px = ((x * self.width as f32) as u32).min(self.width.saturating_sub(1));
+        let py = ((y * self.height as f32) as u32).min(self.height.saturating_sub(1));
+        self.pixels[(py * self.width + px) as usize]
+    }
+}
+
+/// Tells a version of a file from the next one.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+struct Stamp {
+    size: u64,
+    /// Last modification, in nanoseconds since the Unix epoch.
+    mtime: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedTags {
+    stamp: Stamp,
+    metadata: Option<TrackMetadata>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedDuration {
+    stamp: Stamp,
+    millis: Option<u64>,
+}
+
+/// Version of the cached tags: older ones lack fields and are parsed again.
+const TAGS_VERSION: u32 = 3;
+
+/// Both maps are keyed by canonical path.
+#[derive(Serialize, Deserialize, Default)]
+struct CacheFile {
+    #[serde(default)]
+    tags_version: u32,
+    #[serde(default)]
+    tags: HashMap<String, CachedTags>,
+    #[serde(default)]
+    durations: HashMap<String, CachedDuration>,
+}
+
+/// Parsed tags, lengths and cover thumbnails under `~/.cache/ksound`, so
+/// that unchanged files are never parsed twice: tags and lengths are in one
+/// JSON index, keyed by canonical path and checked against the size and
+/// modification time of the file.
+pub struct MetadataCache {
+    index_path: PathBuf,
+    art_dir: PathBuf,
+    entries: CacheFile,
+    /// Canonical path of the paths seen, as given.
+    keys: HashMap<PathBuf, String>,
+    /// Bumped whenever `peek` may find something new.
+    revision: u64,
+    dirty: bool,
+}
+
+/// The key of `path` in the index, and the version of the file.
+fn identify(path: &Path) -> Option<(String, Stamp)> {
+    let canonical = fs::canonicalize(path).ok()?;
+    let metadata = fs::metadata(&canonical).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let stamp = Stamp {
+        size: metadata.len(),
+        mtime: mtime.as_nanos() as u64,
+    };
+    Some((canonical.to_string_lossy().into_owned(), stamp))
+}
+
+impl MetadataCache {
+    pub fn new() -> Result<Self, io::Error> {
+        let cache_dir = config::cache_dir()?;
+        let art_dir = cache_dir.join("art");
+        fs::create_dir_all(&art_dir)?;
+
+        // The TOML index of older versions, slow to read on big libraries.
+        let _ = fs::remove_file(cache_dir.join("metadata.toml"));
+        let index_path = cache_dir.join(INDEX_NAME);
+        // A corrupt or outdated cache is simply rebuilt.
+        let mut entries: CacheFile = fs::read(&index_path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+        let dirty = entries.tags_version != TAGS_VERSION;
+        if dirty {
+            entries.tags.clear();
+            entries.tags_version = TAGS_VERSION;
+        }
+
+        Ok(MetadataCache {
+            index_path,
+            art_dir,
+            entries,
+            keys: HashMap::new(),
+            revision: 0,
+            dirty,
+        })
+    }
+
+    /// `identify`, remembering the key for `peek`.
+    fn identify(&mut self, path: &Path) -> Option<(String, Stamp)> {
+        let (key, stamp) = identify(path)?;
+        if self.keys.insert(path.to_path_buf(), key.clone()).is_none() {
+            self.revision += 1;
+        }
+        Some((key, stamp))
+    }
+
+    /// Returns the tags of `path`, parsing the file only if it changed since
+    /// it was last cached.
+    pub fn metadata(&mut self, path: &Path) -> Option<TrackMetadata> {
+        let Some((key, stamp)) = self.identify(path) else {
+            return TrackMetadata::from_path(path);
+        };
+        if let Some(cached) = self.entries.tags.get(&key) {
+            if cached.stamp == stamp {
+                return cached.metadata.clone();
+            }
+        }
+
+        let metadata = TrackMetadata::from_path(path);
+        self.entries.tags.insert(
+            key,
+            CachedTags {
+                stamp,
+                metadata: metadata.clone(),
+            },
+        );
+        self.revision += 1;
+        self.dirty = true;
+        metadata
+    }
+
+    /// Parses the tags of those of `paths` not cached yet, or changed since,
+    /// several files at a time, so that `peek` finds them all.
+    pub fn preload(&mut self, paths: &[PathBuf]) {
+        let unique: HashSet<&PathBuf> = paths.iter().filter(|path| !self.keys.contains_key(*path)).collect();
+        let entries = &self.entries;
+        let found: Vec<(PathBuf, String, Option<CachedTags>)> = unique
+            .into_par_iter()
+            .filter_map(|path| {
+                let (key, stamp) = identify(path)?;
+                if entries.tags.get(&key).is_some_and(|cached| cached.stamp == stamp) {
+                    return Some((path.clone(), key, None));
+                }
+                let metadata = TrackMetadata::from_path(path);
+                Some((path.clone(), key, Some(CachedTags { stamp, metadata })))
+            })
+            .collect();
+        if found.is_empty() {
+            return;
+        }
+        for (path, key, parsed) in found {
+            if let Some(parsed) = parsed {
+                self.entries.tags.insert(key.clone(), parsed);
+                self.dirty = true;
+            }
+            self.keys.insert(path, key);
+        }
+        self.revision += 1;
+    }
+
+    /// Changes whenever `peek` may answer differently.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns the length of `path`, opening a decoder only if the file
+    /// changed since it was last cached.
+    pub fn duration(&mut self, path: &Path) -> Option<Duration> {
+        let Some((key, stamp)) = self.identify(path) else {
+            return player::probe_duration(path);
+        };
+        if let Some(cached) = self.entries.durations.get(&key) {
+            if cached.stamp == stamp {
+                return cached.millis.map(Duration::from_millis);
+            }
+        }
+
+        let duration = player::probe_duration(path);
+        self.entries.durations.insert(
+            key,
+            CachedDuration {
+                stamp,
+                millis: duration.map(|d| d.as_millis() as u64),
+            },
+        );
+        self.dirty = true;
+        duration
+    }
+
+    /// Returns the tags cached for `path` without touching the file, even if
+    /// they may be stale. Meant for cheap display purposes: only paths seen
+    /// by `metadata` or `preload` are found.
+    pub fn peek(&self, path: &Path) -> Option<&TrackMetadata> {
+        let key = self.keys.get(path)?;
+        self.entries.tags.get(key).and_then(|cached| cached.metadata.as_ref())
+    }
+
+    /// Where the thumbnail of this version of `path` is stored, by the same
+    /// key and stamp as the index, whatever path the file is reached by.
+    fn thumbnail_path(&self, path: &Path) -> Option<PathBuf> {
+        let (key, stamp) = identify(path)?;
+        let hash = config::fnv1a(format!("{}\0{}\0{}", key, stamp.size, stamp.mtime).as_bytes());
+        Some(self.art_dir.join(format!("{:016x}.png", hash)))
+    }
+
+    /// Returns the cover thumbnail of `path`, decoding the embedded picture
+    /// only the first time a given version of the file is seen.
+    pub fn thumbnail(&mut self, path: &Path) -> Option<Thumbnail> {
+        let thumbnail_path = self.thumbnail_path(path)?;
+        if let Ok(image) = image::open(&thumbnail_path) {
+            return Some(Thumbnail::from_image(&image));
+        }
+        self.store_thumbnail(path, &player::embedded_cover(path)?)
+    }
+
+    /// Makes `picture` the cover thumbnail of `path`, as if it was embedded.
+    pub fn store_thumbnail(&mut self, path: &Path, picture: &[u8]) -> Option<Thumbnail> {
+        let thumbnail_path = self.thumbnail_path(path)?;
+        let image = image::load_from_memory(picture)
+            .ok()?
+            .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+        // Failing to store the thumbnail only costs a decode next time.
+        if let Err(e) = image.to_rgb8().save(&thumbnail_path) {
+            tracing::warn!("Couldn't cache the thumbnail {}: {}", thumbnail_path.display(), e);
+        }
+        Some(Thumbnail::from_image(&image))
+    }
+
+    /// Writes the index back to disk if anything was added. Another player
+    /// reading it meanwhile sees the old one or the new one, never half of it.
+    pub fn save(&mut self) -> Result<(), io::Error> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let content = serde_json::to_vec(&self.entries).map_err(io::Error::other)?;
+        config::write_whole(&self.index_path, content)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+//! Artists and albums never to be played (`banned.toml` in `data_dir`):
+//! their tracks are recognized by their tags, wherever the files are, and
+//! left out of the playlist like those of the skip list.
+
+use super::{data_dir, lock, write_whole};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Every track of an artist, or an album.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Ban {
+    /// Random identifier, kept for the life of the ban.
+    pub id: String,
+    /// With an album, only its tracks by this artist; `None` bans the album
+    /// whoever plays on it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    /// `None` bans every track of `artist`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub added: u64,
+}
+
+impl Ban {
+    /// Whether a track with these tags is banned. Tags are compared without
+    /// regard to case or surrounding spaces.
+    pub fn matches(&self, artist: Option<&str>, album: Option<&str>) -> bool {
+        let same = |banned: &Option<String>, tag: Option<&str>| {
+            tag.zip(banned.as_deref()).is_some_and(|(tag, banned)| same_tag(tag, banned))
+        };
+        match (&self.artist, &self.album) {
+            (None, None) => false,
+            (Some(_), None) => same(&self.artist, artist),
+            (None, Some(_)) => same(&self.album, album),
+            (Some(_), Some(_)) => same(&self.artist, artist) && same(&self.album, album),
+        }
+    }
+
+    /// "every track by <artist>", "the album <album> by <artist>", ...
+    pub fn describe(&self) -> String {
+        match (&self.artist, &self.album) {
+            (Some(artist), None) => format!("every track by {}", artist),
+            (Some(artist), Some(album)) => format!("the album {} by {}", album, artist),
+            (None, Some(album)) => format!("the album {}", album),
+            (None, None) => "nothing".to_string(),
+        }
+    }
+}
+
+fn same_tag(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct BansFile {
+    #[serde(default, rename = "ban")]
+    bans: Vec<Ban>,
+}
+
+pub struct Bans {
+    file_path: PathBuf,
+    bans: Vec<Ban>,
+}
+
+impl Bans {
+    pub fn open() -> Result<Bans, io::Error> {
+        let mut bans = Bans {
+            file_path: data_dir()?.join("banned.toml"),
+            bans: Vec::new(),
+        };
+        bans.read()?;
+        Ok(bans)
+    }
+
+    /// Reads the file again, for the changes of another ksound since.
+    fn read(&mut self) -> Result<(), io::Error> {
+        let file_path = &self.file_path;
+        self.bans = match fs::read_to_string(file_path) {
+            Ok(content) => {
+                toml::from_str::<BansFile>(&content)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file_path.display(), e)))?
+                    .bans
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(())
+    }
+
+    /// Locks the file until the lock returned is dropped, and reads it
+    /// again: a change made then is saved over the latest bans.
+    fn lock(&mut self) -> Result<File, io::Error> {
+        let lock = lock(&self.file_path)?;
+        self.read()?;
+        Ok(lock)
+    }
+
+    /// The bans, oldest first.
+    pub fn entries(&self) -> &[Ban] {
+        &self.bans
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bans.is_empty()
+    }
+
+    /// The ban a track with these tags falls under, if any.
+    pub fn matching(&self, artist: Option<&str>, album: Option<&str>) -> Option<&Ban> {
+        self.bans.iter().find(|ban| ban.matches(artist, album))
+    }
+
+    /// Bans every track of `artist`, or the album `album` (by `artist` if
+    /// given), unless it is already. Returns the new ban, if any.
+    pub fn add(&mut self, artist: Option<&str>, album: Option<&str>) -> Result<Option<&Ban>, io::Error> {
+        let clean = |tag: Option<&str>| tag.map(str::trim).filter(|tag| !tag.is_empty()).map(str::to_string);
+        let (artist, album) = (clean(artist), clean(album));
+        if artist.is_none() && album.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "an artist or an album is needed"));
+        }
+        let _lock = self.lock()?;
+        if self.contains(&artist, &album) {
+            return Ok(None);
+        }
+        let added = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.bans.push(Ban {
+            id: format!("{:016x}", rand::random::<u64>()),
+            artist,
+            album,
+            added,
+        });
+        self.save()?;
+        Ok(self.bans.last())
+    }
+
+    /// Adds the bans of another machine that aren't here, by identifier or
+    /// by what they ban. Returns how many were added.
+    pub fn merge(&mut self, bans: Vec<Ban>) -> Result<usize, io::Error> {
+        let _lock = self.lock()?;
+        let mut added = 0;
+        for ban in bans {
+            if !self.bans.iter().any(|kept| kept.id == ban.id) && !self.contains(&ban.artist, &ban.album) {
+                self.bans.push(ban);
+                added += 1;
+            }
+        }
+        if added > 0 {
+            self.bans.sort_by_key(|ban| ban.added);
+            self.save()?;
+        }
+        Ok(added)
+    }
+
+    /// Whether this artist, or this album, is banned already.
+    fn contains(&self, artist: &Option<String>, album: &Option<String>) -> bool {
+        let same = |a: &Option<String>, b: &Option<String>| match (a, b) {
+            (Some(a), Some(b)) => same_tag(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        self.bans.iter().any(|ban| same(&ban.artist, artist) && same(&ban.album, album))
+    }
+
+    /// Lifts the ban with this identifier. Returns whether there was one.
+    pub fn remove_id(&mut self, id: &str) -> Result<bool, io::Error> {
+        let _lock = self.lock()?;
+        let count = self.bans.len();
+        self.bans.retain(|ban| ban.id != id);
+        if self.bans.len() == count {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        let file = BansFile {
+            bans: self.bans.clone(),
+        };
+        let content = toml::to_string(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_whole(&self.file_path, &content)
+    }
+}
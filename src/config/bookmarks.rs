@@ -0,0 +1,155 @@
+//! Named places in tracks (`bookmarks.toml` in `data_dir`), such as the
+//! chapters of an audiobook in one file, to jump back to. Like the saved
+//! positions, tracks are known by the fingerprint of their audio.
+
+use super::store::fingerprint;
+use super::{data_dir, lock, write_whole};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A named place in a track.
+#[derive(Clone, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub position: Duration,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct TrackBookmarks {
+    /// Fingerprint of the audio.
+    id: String,
+    /// Where the file was, for whoever reads the file.
+    path: String,
+    #[serde(default, rename = "bookmark")]
+    bookmarks: Vec<SavedBookmark>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SavedBookmark {
+    name: String,
+    /// In seconds.
+    position: f64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct BookmarksFile {
+    #[serde(default, rename = "track")]
+    tracks: Vec<TrackBookmarks>,
+}
+
+pub struct Bookmarks {
+    file_path: PathBuf,
+    tracks: Vec<TrackBookmarks>,
+}
+
+impl Bookmarks {
+    pub fn open() -> Result<Bookmarks, io::Error> {
+        let mut bookmarks = Bookmarks {
+            file_path: data_dir()?.join("bookmarks.toml"),
+            tracks: Vec::new(),
+        };
+        bookmarks.read()?;
+        Ok(bookmarks)
+    }
+
+    /// Reads the file again, for the changes of another ksound since.
+    fn read(&mut self) -> Result<(), io::Error> {
+        let file_path = &self.file_path;
+        self.tracks = match fs::read_to_string(file_path) {
+            Ok(content) => {
+                toml::from_str::<BookmarksFile>(&content)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file_path.display(), e)))?
+                    .tracks
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(())
+    }
+
+    /// Locks the file until the lock returned is dropped, and reads it
+    /// again: a change made then is saved over the latest bookmarks.
+    fn lock(&mut self) -> Result<File, io::Error> {
+        let lock = lock(&self.file_path)?;
+        self.read()?;
+        Ok(lock)
+    }
+
+    /// The bookmarks of `track`, in the order of their positions.
+    pub fn list(&mut self, track: &Path) -> Result<Vec<Bookmark>, io::Error> {
+        self.read()?;
+        let Some(id) = fingerprint(track) else {
+            return Ok(Vec::new());
+        };
+        let saved = self.tracks.iter().find(|saved| saved.id == id);
+        Ok(saved
+            .into_iter()
+            .flat_map(|saved| &saved.bookmarks)
+            .filter_map(|bookmark| {
+                Some(Bookmark {
+                    name: bookmark.name.clone(),
+                    position: Duration::try_from_secs_f64(bookmark.position).ok()?,
+                })
+            })
+            .collect())
+    }
+
+    /// Bookmarks `position` in `track` as `name`, in place of a bookmark of
+    /// the same name.
+    pub fn add(&mut self, track: &Path, name: &str, position: Duration) -> Result<(), io::Error> {
+        let id = fingerprint(track).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: the audio can't be read", track.display()))
+        })?;
+        let _lock = self.lock()?;
+        let index = match self.tracks.iter().position(|saved| saved.id == id) {
+            Some(index) => index,
+            None => {
+                self.tracks.push(TrackBookmarks {
+                    id,
+                    path: String::new(),
+                    bookmarks: Vec::new(),
+                });
+                self.tracks.len() - 1
+            }
+        };
+        let saved = &mut self.tracks[index];
+        saved.path = track.to_string_lossy().to_string();
+        saved.bookmarks.retain(|bookmark| bookmark.name != name);
+        saved.bookmarks.push(SavedBookmark {
+            name: name.to_string(),
+            position: position.as_secs_f64(),
+        });
+        saved.bookmarks.sort_by(|a, b| a.position.total_cmp(&b.position));
+        self.save()
+    }
+
+    /// Removes the bookmark `name` of `track`. Returns whether there was one.
+    pub fn remove(&mut self, track: &Path, name: &str) -> Result<bool, io::Error> {
+        let Some(id) = fingerprint(track) else {
+            return Ok(false);
+        };
+        let _lock = self.lock()?;
+        let Some(saved) = self.tracks.iter_mut().find(|saved| saved.id == id) else {
+            return Ok(false);
+        };
+        let count = saved.bookmarks.len();
+        saved.bookmarks.retain(|bookmark| bookmark.name != name);
+        if saved.bookmarks.len() == count {
+            return Ok(false);
+        }
+        self.tracks.retain(|saved| !saved.bookmarks.is_empty());
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        let file = BookmarksFile {
+            tracks: self.tracks.clone(),
+        };
+        let content = toml::to_string(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_whole(&self.file_path, &content)
+    }
+}
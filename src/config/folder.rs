@@ -0,0 +1,56 @@
+//! Settings for the tracks of one folder, from a `.ksound.toml` file placed
+//! in it or in one of its parents.
+
+use crate::player::SortKey;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Name of the per-folder settings file.
+const FOLDER_FILE: &str = ".ksound.toml";
+
+/// Keys of a `.ksound.toml`; they win over the config file for the tracks
+/// below it, and the command line flags win over them.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct FolderSettings {
+    /// Play in random order when ksound is started on this folder.
+    pub shuffle: Option<bool>,
+    /// Order of the playlist when ksound is started on this folder unshuffled.
+    pub sort: Option<SortKey>,
+    /// Chain the tracks without a gap or a crossfade between them.
+    pub gapless: Option<bool>,
+    /// Playback speed, from 0.25 to 4 (1 is normal).
+    pub speed: Option<f32>,
+}
+
+impl FolderSettings {
+    /// The settings of the closest `.ksound.toml` in `path` (or the folder of
+    /// `path` when it is a file) and its parents; the defaults if there is none.
+    pub fn find(path: &Path) -> Result<FolderSettings, io::Error> {
+        let Ok(path) = path.canonicalize() else {
+            return Ok(FolderSettings::default());
+        };
+        let start = if path.is_dir() { path.as_path() } else { path.parent().unwrap_or(&path) };
+
+        for dir in start.ancestors() {
+            let file = dir.join(FOLDER_FILE);
+            let content = match fs::read_to_string(&file) {
+                Ok(content) => content,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let invalid = |e: &dyn std::fmt::Display| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file.display(), e))
+            };
+            let settings: FolderSettings = toml::from_str(&content).map_err(|e| invalid(&e))?;
+            if settings.speed.is_some_and(|speed| !(0.25..=4.0).contains(&speed)) {
+                return Err(invalid(&"speed must be between 0.25 and 4"));
+            }
+            return Ok(settings);
+        }
+
+        Ok(FolderSettings::default())
+    }
+}
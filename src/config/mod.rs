@@ -1,218 +1,655 @@
-use std::collections::HashSet;
-use std::fs::{self, File, OpenOptions};
-use std::io::{self, BufRead, BufReader, Write};
+mod bans;
+mod bookmarks;
+mod folder;
+mod positions;
+mod store;
+
+pub use bans::{Ban, Bans};
+pub use bookmarks::{Bookmark, Bookmarks};
+pub use folder::FolderSettings;
+pub use positions::Positions;
+pub(crate) use store::fnv1a;
+pub use store::{TagSnapshot, TrackEntry, TrackStore};
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use toml_edit::DocumentMut;
 
-pub struct SkipList {
-    skip_file_path: PathBuf,
-    cached_skipped_tracks: Option<HashSet<String>>,
+/// Config file and profile picked on the command line, set once at startup.
+static LOCATION: OnceLock<Location> = OnceLock::new();
+
+struct Location {
+    config: Option<PathBuf>,
+    profile: Option<String>,
 }
 
-impl SkipList {
-    pub fn new() -> Result<Self, io::Error> {
-        let home_dir = dirs::home_dir().ok_or_else(|| {
-            io::Error::new(io::ErrorKind::NotFound, "Could not find home directory")
-        })?;
+/// Uses the config file `config` instead of the default one, and keeps the
+/// config (unless `config` is given) and the data of `profile` apart from
+/// the others. Must be called before anything is read.
+pub fn select(config: Option<PathBuf>, profile: Option<String>) -> Result<(), io::Error> {
+    if let Some(name) = &profile {
+        let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if name.is_empty() || !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid profile name \"{}\" (use letters, digits, - and _)", name),
+            ));
+        }
+    }
+    let _ = LOCATION.set(Location { config, profile });
+    Ok(())
+}
 
-        let config_dir = home_dir.join(".ksound");
+/// The profile in use, if any.
+pub fn profile() -> Option<&'static str> {
+    LOCATION.get()?.profile.as_deref()
+}
 
-        if !config_dir.exists() {
-            fs::create_dir(&config_dir)?;
+/// Files kept in `~/.ksound` before the XDG directories were used.
+const LEGACY_DATA_FILES: [&str; 3] = ["skipped_tracks.txt", "favorites_tracks.txt", "play_counts.txt"];
+
+/// Returns `$XDG_DATA_HOME/ksound` (`~/.local/share/ksound`), creating it if
+/// needed and moving in the files of an older `~/.ksound`.
+pub fn data_dir() -> Result<PathBuf, io::Error> {
+    let data_dir = dirs::data_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not find data directory"))?
+        .join("ksound");
+    if let Some(profile) = profile() {
+        // Profiles start empty rather than with the files of `~/.ksound`.
+        let profile_dir = data_dir.join("profiles").join(profile);
+        fs::create_dir_all(&profile_dir)?;
+        return Ok(profile_dir);
+    }
+    fs::create_dir_all(&data_dir)?;
+
+    if let Some(legacy_dir) = dirs::home_dir().map(|home| home.join(".ksound")) {
+        for name in LEGACY_DATA_FILES {
+            let (old, new) = (legacy_dir.join(name), data_dir.join(name));
+            if old.is_file() && !new.exists() {
+                move_file(&old, &new)?;
+            }
         }
+    }
 
-        let skip_file_path = config_dir.join("skipped_tracks.txt");
+    Ok(data_dir)
+}
 
-        if !skip_file_path.exists() {
-            File::create(&skip_file_path)?;
-        }
+/// Returns `$XDG_CACHE_HOME/ksound` (`~/.cache/ksound`), creating it if needed.
+pub fn cache_dir() -> Result<PathBuf, io::Error> {
+    let cache_dir = dirs::cache_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not find cache directory"))?
+        .join("ksound");
+    fs::create_dir_all(&cache_dir)?;
+    Ok(cache_dir)
+}
 
-        Ok(SkipList {
-            skip_file_path,
-            cached_skipped_tracks: None,
-        })
+/// Returns `$XDG_RUNTIME_DIR/ksound`, or `data_dir` without a runtime
+/// directory, for the sockets of a running player.
+pub fn runtime_dir() -> Result<PathBuf, io::Error> {
+    let Some(runtime_dir) = dirs::runtime_dir() else {
+        return data_dir();
+    };
+    let mut runtime_dir = runtime_dir.join("ksound");
+    if let Some(profile) = profile() {
+        runtime_dir = runtime_dir.join("profiles").join(profile);
     }
+    fs::create_dir_all(&runtime_dir)?;
+    Ok(runtime_dir)
+}
 
-    fn load_skipped_tracks(&mut self) -> Result<(), io::Error> {
-        if self.cached_skipped_tracks.is_some() {
-            return Ok(());
-        }
+/// Replaces a leading `~/` with the home directory, for paths written in
+/// the config file.
+pub fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+        _ => path.to_string(),
+    }
+}
 
-        let mut skipped_tracks = HashSet::new();
+/// Renames `from` to `to`, copying when they are on different file systems.
+pub fn move_file(from: &Path, to: &Path) -> Result<(), io::Error> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
 
-        let file = File::open(&self.skip_file_path)?;
-        let reader = BufReader::new(file);
+/// Locks `path`, a list of the data directory, through `<path>.lock`, until
+/// the file returned is dropped: another ksound changing the same list
+/// waits for it, so that neither loses the changes of the other.
+fn lock(path: &Path) -> Result<File, io::Error> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let file = File::options().create(true).truncate(false).write(true).open(lock_path)?;
+    file.lock()?;
+    Ok(file)
+}
 
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                if !line.trim().is_empty() {
-                    skipped_tracks.insert(line);
-                }
-            }
+/// Writes `content` to a file next to `path`, then renames it over `path`:
+/// readers never see half a file, and a crash or a full disk leaves the
+/// old one whole. The file keeps its permissions.
+pub(crate) fn write_whole(path: &Path, content: impl AsRef<[u8]>) -> Result<(), io::Error> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    fs::write(&partial, content)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(&partial, metadata.permissions())?;
+    }
+    fs::rename(&partial, path)
+}
+
+/// Settings from `~/.config/ksound/config.toml`. Every key is optional;
+/// command line flags win over them.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directories or files played when none is given on the command line.
+    pub paths: Vec<String>,
+    /// Start again from the track and position playing at the last quit.
+    pub resume: bool,
+    /// Volume at startup, in percent (0 to 200).
+    pub volume: u16,
+    /// Volume change of one key press, in percent (1 to 100).
+    pub volume_step: u16,
+    /// Play in random order, like `--random`.
+    pub shuffle: bool,
+    /// With `shuffle`, albums come in random order, each played through in
+    /// order, like `--sort random-albums`.
+    pub shuffle_albums: bool,
+    /// With `shuffle`, how many other tracks come at least between two of
+    /// the same artist (0 to 50); 0 for any.
+    pub artist_spacing: u16,
+    pub repeat: RepeatMode,
+    /// Random tracks of the library (`paths`, or else the tracks played) are
+    /// added once the last track plays, rather than stopping or starting
+    /// over after it.
+    pub party: bool,
+    /// Seconds over which a track fades into the next one; 0 disables it.
+    pub crossfade: f32,
+    /// With `crossfade`, whether Next fades into the next track too.
+    pub crossfade_on_next: bool,
+    /// With `crossfade`, whether a track ending fades into the next one;
+    /// otherwise the next one follows it without a gap.
+    pub crossfade_on_end: bool,
+    /// With `crossfade`, whether the tracks of an album fade into each
+    /// other; otherwise they follow without a gap, as on a live album.
+    pub crossfade_within_albums: bool,
+    /// Tracks at least this many minutes long, such as audiobooks and DJ
+    /// sets, start again where they were left; 0 for none.
+    pub remember_position: u32,
+    /// File extensions picked up when scanning directories.
+    pub extensions: Vec<String>,
+    /// Glob patterns of the files and folders left out when scanning
+    /// directories, like `--exclude`.
+    pub exclude: Vec<String>,
+    /// Files deleted from the player go to the trash, rather than being
+    /// removed for good.
+    pub trash: bool,
+    /// Refuse to delete a favorite from the player, rather than asking
+    /// twice.
+    pub protect_favorites: bool,
+    /// Ask before `q` quits while a track is playing.
+    pub confirm_quit: bool,
+    /// Pause when the audio output changes, e.g. headphones unplugged.
+    pub pause_on_output_change: bool,
+    /// Folders offered when moving the current track, e.g. `keep` and
+    /// `maybe` folders to triage downloads into.
+    pub move_to: Vec<String>,
+    /// Name of a built-in color theme.
+    pub theme: Option<String>,
+    /// Colors replacing the theme's ones, by role (`[colors]` table).
+    pub colors: BTreeMap<String, String>,
+    /// Key binding preset: "default" or "vim".
+    pub keymap: Option<String>,
+    /// Player actions bound to other keys than the preset's (`[keys]` table).
+    pub keys: BTreeMap<String, String>,
+    pub layout: PaneLayout,
+    /// Account the tracks played are scrobbled to (`[lastfm]` table).
+    pub lastfm: Option<LastFm>,
+    /// Account the tracks played are submitted to (`[listenbrainz]` table).
+    pub listenbrainz: Option<ListenBrainz>,
+    /// Show the track playing on Discord (`[discord]` table).
+    pub discord: Option<Discord>,
+    /// Download the covers missing from the files (`[cover_art]` table).
+    pub cover_art: Option<CoverArt>,
+    pub hooks: Hooks,
+    /// Tracks left early, again and again, offered for the skip list
+    /// (`[early_skips]` table).
+    pub early_skips: EarlySkips,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            paths: Vec::new(),
+            resume: false,
+            volume: 100,
+            volume_step: 10,
+            shuffle: false,
+            shuffle_albums: false,
+            artist_spacing: 0,
+            repeat: RepeatMode::default(),
+            party: false,
+            crossfade: 0.0,
+            crossfade_on_next: false,
+            crossfade_on_end: true,
+            crossfade_within_albums: false,
+            remember_position: 20,
+            extensions: vec!["mp3".to_string()],
+            exclude: Vec::new(),
+            trash: true,
+            protect_favorites: false,
+            confirm_quit: false,
+            pause_on_output_change: true,
+            move_to: Vec::new(),
+            theme: None,
+            colors: BTreeMap::new(),
+            keymap: None,
+            keys: BTreeMap::new(),
+            layout: PaneLayout::default(),
+            lastfm: None,
+            listenbrainz: None,
+            discord: None,
+            cover_art: None,
+            hooks: Hooks::default(),
+            early_skips: EarlySkips::default(),
         }
+    }
+}
 
-        self.cached_skipped_tracks = Some(skipped_tracks);
-        Ok(())
+/// Last.fm credentials: an API account from <https://www.last.fm/api> and the
+/// login of the user. The session key obtained with them is kept in `data_dir`.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LastFm {
+    pub api_key: String,
+    pub api_secret: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// ListenBrainz user token, from <https://listenbrainz.org/settings/>, and the
+/// server for a self-hosted instance.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ListenBrainz {
+    pub token: String,
+    #[serde(default = "ListenBrainz::default_url")]
+    pub url: String,
+}
+
+impl ListenBrainz {
+    fn default_url() -> String {
+        "https://api.listenbrainz.org".to_string()
     }
+}
 
-    pub fn add(&mut self, track_path: &Path) -> Result<(), io::Error> {
-        let absolute_path = if track_path.is_absolute() {
-            track_path.to_path_buf()
-        } else {
-            std::env::current_dir()?.join(track_path)
-        };
+/// Discord Rich Presence, through the Discord application `client_id` (from
+/// <https://discord.com/developers/applications>), whose name Discord shows.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Discord {
+    #[serde(default = "Discord::default_enabled")]
+    pub enabled: bool,
+    pub client_id: String,
+}
 
-        let canonical_path = absolute_path.canonicalize()?;
-        let track_path_str = canonical_path.to_string_lossy().to_string();
-        let mut file = OpenOptions::new().append(true).open(&self.skip_file_path)?;
+impl Discord {
+    fn default_enabled() -> bool {
+        true
+    }
+}
 
-        writeln!(file, "{}", track_path_str)?;
+/// Covers from the Cover Art Archive for the tracks without one, found by
+/// their artist and album tags, and written into the MP3 files if `embed`.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CoverArt {
+    #[serde(default = "CoverArt::default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub embed: bool,
+}
 
-        Ok(())
+impl CoverArt {
+    fn default_enabled() -> bool {
+        true
     }
+}
 
-    pub fn is_skipped(&mut self, track_path: &Path) -> Result<bool, io::Error> {
-        self.load_skipped_tracks()?;
+/// Shell commands run on player events (`[hooks]` table), with the track in
+/// `KSOUND_*` environment variables.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    pub on_track_change: Option<String>,
+    pub on_pause: Option<String>,
+    pub on_resume: Option<String>,
+    /// When the player quits or the playlist is over.
+    pub on_stop: Option<String>,
+}
 
-        let canonical_path_str = match self.to_canonical_path(track_path)? {
-            Some(path) => path,
-            None => return Ok(false),
-        };
+/// When a track left with Next within its first seconds, again and again,
+/// is offered for the skip list.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct EarlySkips {
+    /// Early skips of a track, since it was last played to the end, before
+    /// it is offered; 0 never offers it.
+    pub threshold: usize,
+    /// Add it to the skip list without asking.
+    pub automatic: bool,
+}
 
-        if let Some(ref cached) = self.cached_skipped_tracks {
-            Ok(cached.contains(&canonical_path_str))
-        } else {
-            Ok(false)
+impl Default for EarlySkips {
+    fn default() -> Self {
+        EarlySkips {
+            threshold: 3,
+            automatic: false,
         }
     }
+}
 
-    fn to_canonical_path(&self, path: &Path) -> io::Result<Option<String>> {
-        let absolute_path = if path.is_absolute() {
-            path.to_path_buf()
-        } else {
-            std::env::current_dir()?.join(path)
-        };
+/// What happens when a track ends.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatMode {
+    /// Stop after the last track of the playlist.
+    Off,
+    /// Play the same track again.
+    One,
+    /// Start the playlist over.
+    #[default]
+    All,
+}
+
+/// Which panes of the main screen are shown, and how big (`[layout]` table).
+/// Saved back to the config file whenever it is changed from the player.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaneLayout {
+    pub art: bool,
+    pub meters: bool,
+    pub controls: bool,
+    pub queue: bool,
+    /// Height of the album art and now playing section, in lines.
+    pub now_playing_height: u16,
+    /// Show the time left instead of the time played on the progress bar.
+    pub remaining_time: bool,
+}
 
-        match absolute_path.canonicalize() {
-            Ok(canonical) => Ok(Some(canonical.to_string_lossy().to_string())),
-            Err(_) => Ok(None), // Le fichier n'existe pas ou n'est pas accessible
+impl Default for PaneLayout {
+    fn default() -> Self {
+        PaneLayout {
+            art: true,
+            meters: true,
+            controls: true,
+            queue: true,
+            now_playing_height: 10,
+            remaining_time: false,
         }
     }
 }
 
-pub struct FavoritesList {
-    favorites_file_path: PathBuf,
-    cached_favorites_tracks: Option<HashSet<String>>,
-}
+impl Config {
+    /// The file given with `--config`, else `profiles/<name>.toml` for a
+    /// profile, else `config.toml`, in `~/.config/ksound`.
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = LOCATION.get().and_then(|location| location.config.clone()) {
+            return Some(path);
+        }
+        let dir = dirs::config_dir()?.join("ksound");
+        match profile() {
+            Some(profile) => Some(dir.join("profiles").join(format!("{}.toml", profile))),
+            None => Some(dir.join("config.toml")),
+        }
+    }
 
-impl FavoritesList {
-    pub fn new() -> Result<Self, io::Error> {
-        let home_dir = dirs::home_dir().ok_or_else(|| {
-            io::Error::new(io::ErrorKind::NotFound, "Could not find home directory")
-        })?;
+    /// The folders of `paths`: the library that favorites and skipped
+    /// tracks are located in, besides their absolute path.
+    pub fn library(&self) -> Vec<PathBuf> {
+        self.paths.iter().map(|path| PathBuf::from(expand_home(path))).collect()
+    }
 
-        let config_dir = home_dir.join(".ksound");
+    /// Reads the config file; a missing file gives the defaults.
+    pub fn load() -> Result<Self, io::Error> {
+        let Some(path) = Self::path() else {
+            return Ok(Config::default());
+        };
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e),
+        };
+        let invalid = |e: &dyn std::fmt::Display| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+        };
+        let config: Config = toml::from_str(&content).map_err(|e| invalid(&e))?;
+        config.check().map_err(|e| invalid(&e))?;
+        Ok(config)
+    }
 
-        if !config_dir.exists() {
-            fs::create_dir(&config_dir)?;
+    /// Checks the values that can be out of range.
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.volume > 200 {
+            return Err("volume must be between 0 and 200");
+        }
+        if !(1..=100).contains(&self.volume_step) {
+            return Err("volume_step must be between 1 and 100");
+        }
+        if self.artist_spacing > 50 {
+            return Err("artist_spacing must be between 0 and 50");
         }
+        if !(self.crossfade >= 0.0 && self.crossfade <= 30.0) {
+            return Err("crossfade must be between 0 and 30 seconds");
+        }
+        if self.extensions.is_empty() {
+            return Err("extensions can't be empty");
+        }
+        Ok(())
+    }
 
-        let favorites_file_path = config_dir.join("favorites_tracks.txt");
+    /// Writes the current value of the top-level `key` back to the config file.
+    pub fn save(&self, key: &str) -> Result<(), io::Error> {
+        match key {
+            "paths" => save_key(key, &self.paths),
+            "resume" => save_key(key, &self.resume),
+            "volume" => save_key(key, &self.volume),
+            "volume_step" => save_key(key, &self.volume_step),
+            "shuffle" => save_key(key, &self.shuffle),
+            "shuffle_albums" => save_key(key, &self.shuffle_albums),
+            "artist_spacing" => save_key(key, &self.artist_spacing),
+            "repeat" => save_key(key, &self.repeat),
+            "party" => save_key(key, &self.party),
+            "crossfade" => save_key(key, &self.crossfade),
+            "crossfade_on_next" => save_key(key, &self.crossfade_on_next),
+            "crossfade_on_end" => save_key(key, &self.crossfade_on_end),
+            "crossfade_within_albums" => save_key(key, &self.crossfade_within_albums),
+            "extensions" => save_key(key, &self.extensions),
+            "theme" => save_key(key, &self.theme),
+            "keymap" => save_key(key, &self.keymap),
+            "layout" => save_key(key, &self.layout),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no config key \"{}\"", key),
+            )),
+        }
+    }
+}
 
-        if !favorites_file_path.exists() {
-            File::create(&favorites_file_path)?;
+/// Replaces the value or table `key` of the config file with `value`, keeping
+/// the rest of the file, comments included, as it is. `None` removes the key.
+pub fn save_key<T: Serialize>(key: &str, value: &T) -> Result<(), io::Error> {
+    let path = Config::path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not find config directory"))?;
+    let invalid = |e: &dyn std::fmt::Display| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut document: DocumentMut = content.parse().map_err(|e| invalid(&e))?;
+    let wrapped: DocumentMut = toml::to_string(&BTreeMap::from([(key, value)]))
+        .map_err(|e| invalid(&e))?
+        .parse()
+        .map_err(|e| invalid(&e))?;
+    match wrapped.get(key) {
+        Some(item) => document[key] = item.clone(),
+        None => {
+            document.remove(key);
         }
+    }
 
-        let mut favorites_tracks = HashSet::new();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_whole(&path, document.to_string())
+}
 
-        let file = File::open(&favorites_file_path)?;
-        let reader = BufReader::new(file);
+fn canonical_path_string(path: &Path) -> io::Result<Option<String>> {
+    let absolute_path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
 
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                if !line.trim().is_empty() {
-                    favorites_tracks.insert(line);
-                }
-            }
-        }
+    match absolute_path.canonicalize() {
+        Ok(canonical) => Ok(Some(canonical.to_string_lossy().to_string())),
+        Err(_) => Ok(None), // Le fichier n'existe pas ou n'est pas accessible
+    }
+}
 
-        Ok(FavoritesList {
-            favorites_file_path,
-            cached_favorites_tracks: Some(favorites_tracks),
-        })
+/// Where playback stood when ksound was last quit, in `session.toml` of `data_dir`.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub track: PathBuf,
+    /// Playback position in the track, in seconds.
+    pub position: f64,
+    /// Whether only the favorites of `playlist` were played.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub favorites_only: bool,
+    /// Filter the tracks played matched, as typed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// The playlist in its play order, shuffled or not, with the tracks
+    /// favorites-only play and the filter leave out. Empty in the sessions of older
+    /// versions and those imported by `ksound sync`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub playlist: Vec<PathBuf>,
+}
+
+impl Session {
+    fn path() -> Result<PathBuf, io::Error> {
+        Ok(data_dir()?.join("session.toml"))
     }
 
-    pub fn add(&mut self, track_path: &Path) -> Result<(), io::Error> {
-        let absolute_path = if track_path.is_absolute() {
-            track_path.to_path_buf()
-        } else {
-            std::env::current_dir()?.join(track_path)
-        };
+    /// The last saved session, if there is a readable one.
+    pub fn load() -> Option<Session> {
+        let content = fs::read_to_string(Self::path().ok()?).ok()?;
+        toml::from_str(&content).ok()
+    }
 
-        let canonical_path = absolute_path.canonicalize()?;
-        let track_path_str = canonical_path.to_string_lossy().to_string();
+    pub fn save(&self) -> Result<(), io::Error> {
+        let content =
+            toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_whole(&Self::path()?, content)
+    }
 
-        if let Some(ref cached) = self.cached_favorites_tracks {
-            if cached.contains(&track_path_str) {
-                return Ok(()); // Already in favorites, do nothing
-            }
-        }
+    /// When the last session was saved, if there is one.
+    pub fn saved_at() -> Option<SystemTime> {
+        fs::metadata(Self::path().ok()?).and_then(|metadata| metadata.modified()).ok()
+    }
+}
 
-        let mut file = OpenOptions::new()
-            .append(true)
-            .open(&self.favorites_file_path)?;
+/// Number of completed plays per track, kept in `play_counts.txt` of `data_dir`
+/// as `count<TAB>path` lines.
+pub struct PlayCounts {
+    counts_file_path: PathBuf,
+    counts: HashMap<String, u64>,
+}
 
-        writeln!(file, "{}", track_path_str)?;
+impl PlayCounts {
+    pub fn new() -> Result<Self, io::Error> {
+        let counts_file_path = data_dir()?.join("play_counts.txt");
 
-        // Update the cached favorites tracks
-        if let Some(ref mut cached) = self.cached_favorites_tracks {
-            cached.insert(track_path_str);
+        if !counts_file_path.exists() {
+            File::create(&counts_file_path)?;
         }
 
-        Ok(())
+        let mut play_counts = PlayCounts {
+            counts_file_path,
+            counts: HashMap::new(),
+        };
+        play_counts.read()?;
+        Ok(play_counts)
     }
 
-    pub fn remove(&mut self, track_path: &Path) -> Result<(), io::Error> {
-        let canonical_path_str = match self.to_canonical_path(track_path)? {
-            Some(path) => path,
-            None => return Ok(()),
+    /// Reads the counts again, for those of another ksound since.
+    fn read(&mut self) -> Result<(), io::Error> {
+        self.counts.clear();
+        let file = match File::open(&self.counts_file_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
         };
+        let reader = BufReader::new(file);
 
-        if let Some(ref mut cached) = self.cached_favorites_tracks {
-            cached.remove(&canonical_path_str);
-
-            // Réécrire le fichier avec la liste mise à jour
-            let mut file = File::create(&self.favorites_file_path)?;
-            for path in cached.iter() {
-                writeln!(file, "{}", path)?;
+        // Lines that aren't UTF-8 are skipped; a read error ends the file.
+        for line in reader.split(b'\n').map_while(Result::ok) {
+            let Ok(line) = String::from_utf8(line) else { continue };
+            if let Some((count, path)) = line.trim_end_matches('\r').split_once('\t') {
+                if let Ok(count) = count.trim().parse::<u64>() {
+                    self.counts.insert(path.to_string(), count);
+                }
             }
         }
-
         Ok(())
     }
 
-    pub fn is_favorite(&self, track_path: &Path) -> Result<bool, io::Error> {
-        let canonical_path_str = match self.to_canonical_path(track_path)? {
-            Some(path) => path,
-            None => return Ok(false),
+    /// Records one more completed play and returns the new count.
+    pub fn increment(&mut self, track_path: &Path) -> Result<u64, io::Error> {
+        let Some(path) = canonical_path_string(track_path)? else {
+            return Ok(0);
         };
 
-        if let Some(ref cached) = self.cached_favorites_tracks {
-            Ok(cached.contains(&canonical_path_str))
-        } else {
-            Ok(false)
-        }
+        let _lock = lock(&self.counts_file_path)?;
+        self.read()?;
+        let count = self.counts.entry(path).or_insert(0);
+        *count += 1;
+        let count = *count;
+        self.save()?;
+
+        Ok(count)
     }
 
-    fn to_canonical_path(&self, path: &Path) -> io::Result<Option<String>> {
-        let absolute_path = if path.is_absolute() {
-            path.to_path_buf()
-        } else {
-            std::env::current_dir()?.join(path)
+    /// Carries the count of a file moved from `from`, its canonical path
+    /// before the move, over to `to`.
+    pub fn moved(&mut self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        let _lock = lock(&self.counts_file_path)?;
+        self.read()?;
+        let Some(count) = self.counts.remove(from.to_string_lossy().as_ref()) else {
+            return Ok(());
         };
+        if let Some(path) = canonical_path_string(to)? {
+            *self.counts.entry(path).or_insert(0) += count;
+        }
+        self.save()
+    }
 
-        match absolute_path.canonicalize() {
-            Ok(canonical) => Ok(Some(canonical.to_string_lossy().to_string())),
-            Err(_) => Ok(None), // Le fichier n'existe pas ou n'est pas accessible
+    fn save(&self) -> Result<(), io::Error> {
+        let mut content = String::new();
+        for (path, count) in &self.counts {
+            content.push_str(&format!("{}\t{}\n", count, path));
         }
+        write_whole(&self.counts_file_path, &content)
     }
 }
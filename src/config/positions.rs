@@ -0,0 +1,119 @@
+//! Where long tracks, such as audiobooks, DJ sets and podcasts, were left
+//! (`positions.toml` in `data_dir`), to pick them up there the next time
+//! they play. Tracks are known by the fingerprint of their audio, so a
+//! position survives moving, renaming and retagging the file.
+
+use super::store::fingerprint;
+use super::{data_dir, lock, write_whole};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Positions kept, the oldest dropped beyond.
+const KEPT: usize = 500;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SavedPosition {
+    /// Fingerprint of the audio.
+    id: String,
+    /// Where the file was, for whoever reads the file.
+    path: String,
+    /// In seconds.
+    position: f64,
+    /// Seconds since the Unix epoch.
+    saved: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PositionsFile {
+    #[serde(default, rename = "track")]
+    tracks: Vec<SavedPosition>,
+}
+
+pub struct Positions {
+    file_path: PathBuf,
+    /// Oldest first.
+    positions: Vec<SavedPosition>,
+}
+
+impl Positions {
+    pub fn open() -> Result<Positions, io::Error> {
+        let mut positions = Positions {
+            file_path: data_dir()?.join("positions.toml"),
+            positions: Vec::new(),
+        };
+        positions.read()?;
+        Ok(positions)
+    }
+
+    /// Reads the file again, for the changes of another ksound since.
+    fn read(&mut self) -> Result<(), io::Error> {
+        let file_path = &self.file_path;
+        self.positions = match fs::read_to_string(file_path) {
+            Ok(content) => {
+                toml::from_str::<PositionsFile>(&content)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file_path.display(), e)))?
+                    .tracks
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(())
+    }
+
+    /// Locks the file until the lock returned is dropped, and reads it
+    /// again: a change made then is saved over the latest positions.
+    fn lock(&mut self) -> Result<File, io::Error> {
+        let lock = lock(&self.file_path)?;
+        self.read()?;
+        Ok(lock)
+    }
+
+    /// Where `track` was left, if it was.
+    pub fn get(&self, track: &Path) -> Option<Duration> {
+        let id = fingerprint(track)?;
+        let saved = self.positions.iter().find(|saved| saved.id == id)?;
+        Duration::try_from_secs_f64(saved.position).ok()
+    }
+
+    /// Keeps `position` as where `track` was left.
+    pub fn set(&mut self, track: &Path, position: Duration) -> Result<(), io::Error> {
+        let Some(id) = fingerprint(track) else {
+            return Ok(());
+        };
+        let _lock = self.lock()?;
+        self.positions.retain(|saved| saved.id != id);
+        self.positions.push(SavedPosition {
+            id,
+            path: track.to_string_lossy().to_string(),
+            position: position.as_secs_f64(),
+            saved: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        });
+        let dropped = self.positions.len().saturating_sub(KEPT);
+        self.positions.drain(..dropped);
+        self.save()
+    }
+
+    /// Forgets where `track` was left, once it was played to the end.
+    pub fn forget(&mut self, track: &Path) -> Result<(), io::Error> {
+        let Some(id) = fingerprint(track) else {
+            return Ok(());
+        };
+        if !self.positions.iter().any(|saved| saved.id == id) {
+            return Ok(());
+        }
+        let _lock = self.lock()?;
+        self.positions.retain(|saved| saved.id != id);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        let file = PositionsFile {
+            tracks: self.positions.clone(),
+        };
+        let content = toml::to_string(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_whole(&self.file_path, &content)
+    }
+}
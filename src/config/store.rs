@@ -0,0 +1,489 @@
+//! The favorites and the skip list: one TOML file each in `data_dir`, with
+//! when and why every track was added and what its tags were at the time.
+//!
+//! Besides its absolute path, an entry keeps its path relative to the library
+//! (the `paths` of the config file) and a fingerprint of its audio data, so
+//! that it can be found again after the files or the library have moved.
+
+use super::{canonical_path_string, data_dir, lock, move_file, write_whole};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Tags of a track when it was added, to recognize the entry if the file
+/// is gone or was renamed.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TagSnapshot {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+impl TagSnapshot {
+    /// Lowercased artist, album and title, if there is at least a title.
+    fn key(&self) -> Option<String> {
+        let field = |value: &Option<String>| value.as_deref().unwrap_or("").to_lowercase();
+        self.title
+            .is_some()
+            .then(|| format!("{}\n{}\n{}", field(&self.artist), field(&self.album), field(&self.title)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrackEntry {
+    /// Random identifier, kept for the life of the entry.
+    pub id: String,
+    /// Canonical path of the file.
+    pub path: String,
+    /// Path below the library folder holding the file, if it is in one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative: Option<String>,
+    /// See `fingerprint`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub added: u64,
+    pub reason: String,
+    #[serde(flatten)]
+    pub tags: TagSnapshot,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct StoreFile {
+    #[serde(default, rename = "track")]
+    tracks: Vec<TrackEntry>,
+}
+
+/// A list of tracks kept in the data directory, the favorites or the skip
+/// list, with when and why each was added.
+pub struct TrackStore {
+    file_path: PathBuf,
+    /// Canonical library folders, for the relative paths.
+    library: Vec<PathBuf>,
+    entries: Vec<TrackEntry>,
+    /// Paths and relative paths of `entries`, for quick lookups.
+    paths: HashSet<String>,
+    relatives: HashSet<String>,
+}
+
+/// How `TrackStore::relink` found the new place of an entry.
+#[derive(Clone, Copy)]
+pub enum RelinkMatch {
+    Relative,
+    Fingerprint,
+    Tags,
+}
+
+impl RelinkMatch {
+    pub fn name(self) -> &'static str {
+        match self {
+            RelinkMatch::Relative => "library path",
+            RelinkMatch::Fingerprint => "fingerprint",
+            RelinkMatch::Tags => "tags",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RelinkReport {
+    /// Old path, new path and how the file was found.
+    pub relinked: Vec<(String, String, RelinkMatch)>,
+    /// Entries whose file could not be found.
+    pub missing: Vec<String>,
+    /// Entries dropped because their new path was already listed.
+    pub merged: Vec<String>,
+    /// Entries given the relative path or fingerprint they lacked.
+    pub completed: usize,
+}
+
+impl TrackStore {
+    /// Tracks never to be played again (`skipped.toml`).
+    pub fn skip_list(library: &[PathBuf]) -> Result<Self, io::Error> {
+        Self::open("skipped.toml", "skipped_tracks.txt", library)
+    }
+
+    /// Tracks played more often in random mode (`favorites.toml`).
+    pub fn favorites(library: &[PathBuf]) -> Result<Self, io::Error> {
+        Self::open("favorites.toml", "favorites_tracks.txt", library)
+    }
+
+    /// Loads `name`, or on first run imports the one-path-per-line `legacy`
+    /// file of older versions, which is then renamed to `<legacy>.imported`.
+    fn open(name: &str, legacy: &str, library: &[PathBuf]) -> Result<Self, io::Error> {
+        let dir = data_dir()?;
+        let mut store = TrackStore {
+            file_path: dir.join(name),
+            library: library.iter().filter_map(|root| root.canonicalize().ok()).collect(),
+            entries: Vec::new(),
+            paths: HashSet::new(),
+            relatives: HashSet::new(),
+        };
+        store.read()?;
+
+        let legacy_path = dir.join(legacy);
+        if !store.file_path.exists() && legacy_path.is_file() {
+            let _lock = store.lock()?;
+            let reason = format!("imported from {}", legacy);
+            for line in fs::read_to_string(&legacy_path)?.lines() {
+                let path = line.trim();
+                if !path.is_empty() && !store.paths.contains(path) {
+                    store.push(path.to_string(), &reason, TagSnapshot::default());
+                }
+            }
+            store.save()?;
+            move_file(&legacy_path, &dir.join(format!("{}.imported", legacy)))?;
+        }
+
+        Ok(store)
+    }
+
+    /// Reads the file again, for the changes of another ksound since.
+    fn read(&mut self) -> Result<(), io::Error> {
+        let file_path = &self.file_path;
+        self.entries = match fs::read_to_string(file_path) {
+            Ok(content) => {
+                toml::from_str::<StoreFile>(&content)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file_path.display(), e)))?
+                    .tracks
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        self.index();
+        Ok(())
+    }
+
+    /// Locks the file until the lock returned is dropped, and reads it
+    /// again: a change made then is saved over the latest list.
+    fn lock(&mut self) -> Result<File, io::Error> {
+        let lock = lock(&self.file_path)?;
+        self.read()?;
+        Ok(lock)
+    }
+
+    fn index(&mut self) {
+        self.paths = self.entries.iter().map(|entry| entry.path.clone()).collect();
+        self.relatives = self.entries.iter().filter_map(|entry| entry.relative.clone()).collect();
+    }
+
+    /// `path` relative to the library folder holding it.
+    fn relative(&self, path: &str) -> Option<String> {
+        self.library
+            .iter()
+            .find_map(|root| Path::new(path).strip_prefix(root).ok())
+            .map(|relative| relative.to_string_lossy().to_string())
+    }
+
+    /// The tracks listed, oldest first.
+    pub fn entries(&self) -> &[TrackEntry] {
+        &self.entries
+    }
+
+    /// Whether the track is listed, by its path or by its place in the library.
+    pub fn contains(&self, track_path: &Path) -> Result<bool, io::Error> {
+        let Some(path) = canonical_path_string(track_path)? else {
+            return Ok(false);
+        };
+        Ok(self.paths.contains(&path)
+            || self.relative(&path).is_some_and(|relative| self.relatives.contains(&relative)))
+    }
+
+    /// Adds the track unless it is already there. Returns the identifier of
+    /// the new entry, if any.
+    pub fn add(&mut self, track_path: &Path, reason: &str, tags: TagSnapshot) -> Result<Option<String>, io::Error> {
+        let _lock = self.lock()?;
+        if self.contains(track_path)? {
+            return Ok(None);
+        }
+        let path = canonical_path_string(track_path)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{}: not found", track_path.display())))?;
+        self.push(path, reason, tags);
+        self.save()?;
+        Ok(self.entries.last().map(|entry| entry.id.clone()))
+    }
+
+    /// Takes the track out, if it is there. Returns the entries taken out.
+    pub fn remove(&mut self, track_path: &Path) -> Result<Vec<TrackEntry>, io::Error> {
+        let Some(path) = canonical_path_string(track_path)? else {
+            return Ok(Vec::new());
+        };
+        let _lock = self.lock()?;
+        let relative = self.relative(&path);
+        let (removed, kept) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|entry| entry.path == path || (relative.is_some() && entry.relative == relative));
+        self.entries = kept;
+        if !removed.is_empty() {
+            self.index();
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// Adds the entries of the same list from another machine that aren't
+    /// in this one, by identifier, path, place in the library or audio data.
+    /// An entry whose file is at the same place in the local library is
+    /// pointed to it. Returns how many were added.
+    pub fn merge(&mut self, entries: Vec<TrackEntry>) -> Result<usize, io::Error> {
+        let _lock = self.lock()?;
+        let mut added = 0;
+        for mut entry in entries {
+            let local = entry
+                .relative
+                .as_ref()
+                .and_then(|relative| self.library.iter().map(|root| root.join(relative)).find(|path| path.is_file()));
+            if let Some(path) = local {
+                entry.path = path.to_string_lossy().to_string();
+            }
+            let known = self.entries.iter().any(|kept| {
+                kept.id == entry.id
+                    || kept.path == entry.path
+                    || kept.relative.is_some() && kept.relative == entry.relative
+                    || kept.fingerprint.is_some() && kept.fingerprint == entry.fingerprint
+            });
+            if !known {
+                let index = self.entries.partition_point(|kept| kept.added <= entry.added);
+                self.entries.insert(index, entry);
+                added += 1;
+            }
+        }
+        if added > 0 {
+            self.index();
+            self.save()?;
+        }
+        Ok(added)
+    }
+
+    /// Puts back entries taken out with `remove`, in the order they were
+    /// added.
+    pub fn restore(&mut self, entries: Vec<TrackEntry>) -> Result<(), io::Error> {
+        let _lock = self.lock()?;
+        for entry in entries {
+            if !self.entries.iter().any(|kept| kept.id == entry.id) {
+                let index = self.entries.partition_point(|kept| kept.added <= entry.added);
+                self.entries.insert(index, entry);
+            }
+        }
+        self.index();
+        self.save()
+    }
+
+    /// Points the entries of a file moved from `from`, its canonical path
+    /// before the move, to `to`. Returns whether there were any.
+    pub fn moved(&mut self, from: &Path, to: &Path) -> Result<bool, io::Error> {
+        let Some(path) = canonical_path_string(to)? else {
+            return Ok(false);
+        };
+        let _lock = self.lock()?;
+        let from = from.to_string_lossy();
+        let relative = self.relative(&path);
+        let mut found = false;
+        for entry in self.entries.iter_mut().filter(|entry| entry.path == from) {
+            entry.path = path.clone();
+            entry.relative = relative.clone();
+            found = true;
+        }
+        if found {
+            self.index();
+            self.save()?;
+        }
+        Ok(found)
+    }
+
+    /// Takes out the entry with this `id`, even if its file is gone.
+    pub fn remove_id(&mut self, id: &str) -> Result<(), io::Error> {
+        let _lock = self.lock()?;
+        let count = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        if self.entries.len() != count {
+            self.index();
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, path: String, reason: &str, tags: TagSnapshot) {
+        let added = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let relative = self.relative(&path);
+        let fingerprint = fingerprint(Path::new(&path));
+        self.paths.insert(path.clone());
+        self.relatives.extend(relative.clone());
+        self.entries.push(TrackEntry {
+            id: format!("{:016x}", rand::random::<u64>()),
+            path,
+            relative,
+            fingerprint,
+            added,
+            reason: reason.to_string(),
+            tags,
+        });
+    }
+
+    /// Points the entries whose file is gone to where it is now among `files`:
+    /// same place in the library, else same audio data, else same tags (read
+    /// with `tags`, and only when they match a single file). Entries still in
+    /// place get the relative path and fingerprint they may lack. Nothing is
+    /// written when `dry_run` is set.
+    pub fn relink(
+        &mut self,
+        files: &[PathBuf],
+        tags: &mut dyn FnMut(&Path) -> Option<TagSnapshot>,
+        dry_run: bool,
+    ) -> Result<RelinkReport, io::Error> {
+        // Held while the files are searched: the player waits to change the
+        // list meanwhile, rather than having its change lost.
+        let _lock = self.lock()?;
+        let mut report = RelinkReport::default();
+        // Built on first need, as they read every file.
+        let mut by_length: Option<HashMap<u64, Vec<&PathBuf>>> = None;
+        let mut by_tags: Option<HashMap<String, Vec<&PathBuf>>> = None;
+        let mut entries = self.entries.clone();
+        let mut kept_paths: HashSet<String> =
+            entries.iter().filter(|entry| Path::new(&entry.path).exists()).map(|entry| entry.path.clone()).collect();
+
+        entries.retain_mut(|entry| {
+            if Path::new(&entry.path).exists() {
+                let mut completed = false;
+                if entry.relative.is_none() {
+                    entry.relative = self.relative(&entry.path);
+                    completed |= entry.relative.is_some();
+                }
+                if entry.fingerprint.is_none() {
+                    entry.fingerprint = fingerprint(Path::new(&entry.path));
+                    completed |= entry.fingerprint.is_some();
+                }
+                report.completed += completed as usize;
+                return true;
+            }
+
+            let by_relative = entry.relative.as_ref().and_then(|relative| {
+                self.library.iter().map(|root| root.join(relative)).find(|path| path.is_file())
+            });
+            let found = by_relative.map(|path| (path, RelinkMatch::Relative)).or_else(|| {
+                let (length, _) = entry.fingerprint.as_ref()?.split_once(':')?;
+                let candidates = by_length
+                    .get_or_insert_with(|| {
+                        let mut map: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+                        for file in files {
+                            if let Some((_, length)) = audio_span(file) {
+                                map.entry(length).or_default().push(file);
+                            }
+                        }
+                        map
+                    })
+                    .get(&length.parse().ok()?)?;
+                candidates
+                    .iter()
+                    .find(|file| fingerprint(file) == entry.fingerprint)
+                    .map(|file| ((*file).clone(), RelinkMatch::Fingerprint))
+            });
+            let found = found.or_else(|| {
+                let key = entry.tags.key()?;
+                let candidates = by_tags
+                    .get_or_insert_with(|| {
+                        let mut map: HashMap<String, Vec<&PathBuf>> = HashMap::new();
+                        for file in files {
+                            if let Some(key) = tags(file).and_then(|tags| tags.key()) {
+                                map.entry(key).or_default().push(file);
+                            }
+                        }
+                        map
+                    })
+                    .get(&key)?;
+                match candidates.as_slice() {
+                    [file] => Some(((*file).clone(), RelinkMatch::Tags)),
+                    _ => None,
+                }
+            });
+
+            let Some((new_path, how)) = found else {
+                report.missing.push(entry.path.clone());
+                return true;
+            };
+            let new_path = new_path.canonicalize().unwrap_or(new_path).to_string_lossy().to_string();
+            if !kept_paths.insert(new_path.clone()) {
+                report.merged.push(entry.path.clone());
+                return false;
+            }
+            report.relinked.push((entry.path.clone(), new_path.clone(), how));
+            entry.relative = self.relative(&new_path);
+            entry.fingerprint = fingerprint(Path::new(&new_path));
+            entry.path = new_path;
+            true
+        });
+
+        if dry_run {
+            return Ok(report);
+        }
+        self.entries = entries;
+        self.index();
+        self.save()?;
+        Ok(report)
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        let file = StoreFile {
+            tracks: self.entries.clone(),
+        };
+        let content =
+            toml::to_string(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_whole(&self.file_path, &content)
+    }
+}
+
+/// Bytes of audio hashed for a fingerprint.
+const FINGERPRINT_BYTES: u64 = 64 * 1024;
+
+/// "<audio length>:<hash of its first 64 KiB>", ID3 tags left out so that
+/// editing the tags of an MP3 doesn't change it.
+pub fn fingerprint(path: &Path) -> Option<String> {
+    let (start, length) = audio_span(path)?;
+    let mut file = File::open(path).ok()?;
+    file.seek(SeekFrom::Start(start)).ok()?;
+    let mut data = Vec::new();
+    file.take(FINGERPRINT_BYTES.min(length)).read_to_end(&mut data).ok()?;
+    Some(format!("{}:{:016x}", length, fnv1a(&data)))
+}
+
+/// 64-bit FNV-1a of `data`: stable across versions and platforms, unlike
+/// std's hasher, for names kept on disk.
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Where the audio of `path` starts and how long it is, without the ID3v2 tag
+/// at the start and the ID3v1 tag at the end.
+fn audio_span(path: &Path) -> Option<(u64, u64)> {
+    let mut file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+
+    let mut start = 0;
+    let mut header = [0u8; 10];
+    if file.read_exact(&mut header).is_ok() && &header[..3] == b"ID3" {
+        // Syncsafe size, then 10 more bytes when the footer flag is set.
+        let tag_size = header[6..10].iter().fold(0u64, |size, byte| (size << 7) | (*byte & 0x7f) as u64);
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        start = (10 + tag_size + footer).min(size);
+    }
+
+    let mut end = size;
+    let mut trailer = [0u8; 3];
+    if size >= start + 128
+        && file.seek(SeekFrom::End(-128)).is_ok()
+        && file.read_exact(&mut trailer).is_ok()
+        && &trailer == b"TAG"
+    {
+        end -= 128;
+    }
+    Some((start, end - start))
+}
@@ -0,0 +1,217 @@
+//! Copying tracks to a folder or a mounted device, such as a USB stick for
+//! the car: below the same folders as in the library, or all in one, and
+//! converted by `ffmpeg` when the device doesn't play their format.
+//!
+//! Names are cleaned of the characters FAT file systems refuse, and files
+//! already copied, with the same size, are left alone: copying again to
+//! the same device only adds what is new.
+
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Formats `--transcode` can convert to, by file extension.
+pub const FORMATS: [&str; 4] = ["mp3", "m4a", "ogg", "opus"];
+
+/// Conversion of the tracks in other formats.
+#[derive(Clone)]
+pub struct Transcode {
+    /// Extension of the files made, one of `FORMATS`.
+    pub format: String,
+    /// In kbit/s.
+    pub bitrate: u32,
+}
+
+/// One file to copy, and where to.
+pub struct Item {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// Whether it is converted on the way.
+    pub transcoded: bool,
+}
+
+/// What happened to a file.
+pub enum Outcome {
+    Copied,
+    Transcoded,
+    /// There already, with the same size.
+    Unchanged,
+    Failed(String),
+}
+
+/// Where each of `tracks` goes below `destination`: at its path below the
+/// folder of `roots` holding it, or with `flatten`, or outside of them,
+/// right in `destination`. Two tracks landing on the same name get a
+/// number, e.g. "Intro (2).mp3".
+pub fn plan(
+    tracks: &[PathBuf],
+    roots: &[PathBuf],
+    destination: &Path,
+    flatten: bool,
+    transcode: Option<&Transcode>,
+) -> Vec<Item> {
+    let roots: Vec<PathBuf> = roots
+        .iter()
+        .filter(|root| root.is_dir())
+        .filter_map(|root| root.canonicalize().ok())
+        .collect();
+    let mut taken = HashSet::new();
+    let mut items = Vec::new();
+    for source in tracks {
+        let Some(name) = source.file_name() else {
+            continue;
+        };
+        let canonical = source.canonicalize().unwrap_or_else(|_| source.clone());
+        let below = match flatten {
+            true => None,
+            false => roots
+                .iter()
+                .filter_map(|root| canonical.strip_prefix(root).ok())
+                .min_by_key(|below| below.components().count()),
+        };
+        let mut relative: PathBuf = below
+            .unwrap_or(Path::new(name))
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(clean_name(&part.to_string_lossy())),
+                _ => None,
+            })
+            .collect();
+        let transcoded = transcode.filter(|transcode| !has_extension(source, &transcode.format));
+        if let Some(transcode) = transcoded {
+            relative.set_extension(&transcode.format);
+        }
+
+        let mut target = destination.join(&relative);
+        let mut number = 2;
+        while !taken.insert(target.to_string_lossy().to_lowercase()) {
+            let stem = relative.file_stem().unwrap_or_default().to_string_lossy();
+            let name = match relative.extension() {
+                Some(extension) => format!("{} ({}).{}", stem, number, extension.to_string_lossy()),
+                None => format!("{} ({})", stem, number),
+            };
+            target = destination.join(relative.with_file_name(name));
+            number += 1;
+        }
+        items.push(Item {
+            source: source.clone(),
+            destination: target,
+            transcoded: transcoded.is_some(),
+        });
+    }
+    items
+}
+
+/// Copies, or converts with `transcode`, each file of `items`, making the
+/// folders needed, and tells `progress` how it went. Fails, stopping there,
+/// only when a file is to be converted and `ffmpeg` can't be run.
+pub fn run(
+    items: &[Item],
+    transcode: Option<&Transcode>,
+    mut progress: impl FnMut(&Item, Outcome),
+) -> Result<()> {
+    for item in items {
+        let outcome = match (item.transcoded, transcode) {
+            (true, Some(transcode)) => convert(item, transcode)?,
+            _ => copy_file(item),
+        };
+        progress(item, outcome);
+    }
+    Ok(())
+}
+
+fn copy_file(item: &Item) -> Outcome {
+    let size = |path: &Path| fs::metadata(path).map(|metadata| metadata.len()).ok();
+    if size(&item.destination).is_some_and(|copied| Some(copied) == size(&item.source)) {
+        return Outcome::Unchanged;
+    }
+    let result = make_parent(&item.destination).and_then(|_| {
+        // Not in place, so that an interrupted copy isn't taken for a file
+        // of another size left there on purpose.
+        let partial = partial_path(&item.destination);
+        fs::copy(&item.source, &partial)?;
+        fs::rename(&partial, &item.destination)
+    });
+    match result {
+        Ok(()) => Outcome::Copied,
+        Err(e) => Outcome::Failed(e.to_string()),
+    }
+}
+
+/// Fails only when `ffmpeg` can't be started; a file it can't convert is a
+/// failed outcome.
+fn convert(item: &Item, transcode: &Transcode) -> Result<Outcome> {
+    if item.destination.is_file() {
+        return Ok(Outcome::Unchanged);
+    }
+    if let Err(e) = make_parent(&item.destination) {
+        return Ok(Outcome::Failed(e.to_string()));
+    }
+    let partial = partial_path(&item.destination);
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-nostdin", "-loglevel", "error", "-y", "-i"])
+        .arg(&item.source)
+        .args(["-map", "0:a", "-map_metadata", "0", "-b:a"])
+        .arg(format!("{}k", transcode.bitrate));
+    // Car stereos read ID3v2.3 tags more often than v2.4.
+    if transcode.format == "mp3" {
+        command.args(["-id3v2_version", "3"]);
+    }
+    let output = command
+        .arg(&partial)
+        .stdin(Stdio::null())
+        .output()
+        .context("Couldn't run ffmpeg, needed by --transcode")?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&partial);
+        let error = String::from_utf8_lossy(&output.stderr);
+        let error = error.lines().last().unwrap_or("ffmpeg failed");
+        return Ok(Outcome::Failed(error.to_string()));
+    }
+    Ok(match fs::rename(&partial, &item.destination) {
+        Ok(()) => Outcome::Transcoded,
+        Err(e) => Outcome::Failed(e.to_string()),
+    })
+}
+
+fn make_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// "Song.part.mp3" for "Song.mp3": ffmpeg picks the format by the
+/// extension.
+fn partial_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(extension) => path.with_file_name(format!("{}.part.{}", stem, extension.to_string_lossy())),
+        None => path.with_file_name(format!("{}.part", stem)),
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|found| found.to_string_lossy().eq_ignore_ascii_case(extension))
+}
+
+/// `name` with the characters FAT refuses replaced by `_`, and without the
+/// trailing dots and spaces it drops.
+fn clean_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    match cleaned.trim_end_matches(['.', ' ']) {
+        "" => "_".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
@@ -0,0 +1,269 @@
+//! Covers of the tracks without embedded art, from the Cover Art Archive:
+//! the release is looked up on MusicBrainz by artist and album, and its
+//! front cover downloaded to `~/.cache/ksound/covers`, once per album.
+//!
+//! Lookups run on a thread of their own (a task with the `tokio` feature),
+//! one at a time, as MusicBrainz allows one request per second.
+
+use crate::config;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+const MUSICBRAINZ_URL: &str = "https://musicbrainz.org/ws/2/release/";
+const COVER_ART_ARCHIVE_URL: &str = "https://coverartarchive.org";
+
+/// Time between two MusicBrainz requests, as their rate limit asks.
+const MUSICBRAINZ_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Releases tried for an album, best matches first.
+const CANDIDATES: usize = 5;
+
+/// Lowest MusicBrainz search score taken as the same album.
+const MIN_SCORE: u64 = 90;
+
+/// Largest cover downloaded.
+const MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// A cover found for the track at `path`.
+pub struct Cover {
+    pub path: PathBuf,
+    /// The image file as downloaded (JPEG or PNG).
+    pub image: Vec<u8>,
+}
+
+struct Lookup {
+    path: PathBuf,
+    artist: String,
+    album: String,
+}
+
+#[cfg(not(feature = "tokio"))]
+type Lookups = Sender<Lookup>;
+#[cfg(feature = "tokio")]
+type Lookups = tokio::sync::mpsc::UnboundedSender<Lookup>;
+
+pub struct CoverArt {
+    sender: Lookups,
+    covers: Receiver<Result<Cover, String>>,
+}
+
+impl CoverArt {
+    pub fn start() -> Result<CoverArt, io::Error> {
+        let dir = config::cache_dir()?.join("covers");
+        fs::create_dir_all(&dir)?;
+        let (found, covers) = mpsc::channel();
+        #[cfg(not(feature = "tokio"))]
+        let sender = {
+            let (sender, lookups) = mpsc::channel();
+            std::thread::spawn(move || run(dir, lookups, found));
+            sender
+        };
+        #[cfg(feature = "tokio")]
+        let sender = {
+            let (sender, lookups) = tokio::sync::mpsc::unbounded_channel();
+            crate::net::spawn(run(dir, lookups, found));
+            sender
+        };
+        Ok(CoverArt { sender, covers })
+    }
+
+    /// Looks for the cover of `album`, for the track at `path`.
+    pub fn request(&self, path: PathBuf, artist: &str, album: &str) {
+        let _ = self.sender.send(Lookup {
+            path,
+            artist: artist.to_string(),
+            album: album.to_string(),
+        });
+    }
+
+    /// The covers found since the last call, and what went wrong looking for
+    /// the others.
+    pub fn take(&self) -> Vec<Result<Cover, String>> {
+        self.covers.try_iter().collect()
+    }
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .user_agent(&format!(
+            "ksound/{} ( {} )",
+            env!("CARGO_PKG_VERSION"),
+            env!("CARGO_PKG_REPOSITORY")
+        ))
+        .timeout(Duration::from_secs(20))
+        .build()
+}
+
+/// Where the cover of the album of `lookup` is kept, and where it is noted
+/// that it has none.
+fn cache_paths(dir: &Path, lookup: &Lookup) -> (PathBuf, PathBuf) {
+    let mut hasher = DefaultHasher::new();
+    (lookup.artist.to_lowercase(), lookup.album.to_lowercase()).hash(&mut hasher);
+    let key = format!("{:016x}", hasher.finish());
+    (dir.join(&key), dir.join(format!("{}.none", key)))
+}
+
+#[cfg(not(feature = "tokio"))]
+fn run(dir: PathBuf, lookups: Receiver<Lookup>, found: Sender<Result<Cover, String>>) {
+    let agent = agent();
+    let mut last_search: Option<Instant> = None;
+    let mut failing = false;
+    for lookup in lookups {
+        let (image_path, missing_path) = cache_paths(&dir, &lookup);
+        if missing_path.exists() {
+            continue;
+        }
+        let image = match fs::read(&image_path) {
+            Ok(image) => Ok(Some(image)),
+            Err(_) => {
+                let since = last_search.map(|last| last.elapsed());
+                if let Some(wait) = since.and_then(|since| MUSICBRAINZ_INTERVAL.checked_sub(since)) {
+                    std::thread::sleep(wait);
+                }
+                last_search = Some(Instant::now());
+                fetch(&agent, &lookup.artist, &lookup.album)
+            }
+        };
+        let Some(reply) = keep(lookup, image, &image_path, &missing_path, &mut failing) else {
+            continue;
+        };
+        if found.send(reply).is_err() {
+            return;
+        }
+    }
+}
+
+/// Downloads are made on the runtime's blocking pool.
+#[cfg(feature = "tokio")]
+async fn run(
+    dir: PathBuf,
+    mut lookups: tokio::sync::mpsc::UnboundedReceiver<Lookup>,
+    found: Sender<Result<Cover, String>>,
+) {
+    let agent = agent();
+    let mut last_search: Option<Instant> = None;
+    let mut failing = false;
+    while let Some(lookup) = lookups.recv().await {
+        let (image_path, missing_path) = cache_paths(&dir, &lookup);
+        if missing_path.exists() {
+            continue;
+        }
+        let image = match fs::read(&image_path) {
+            Ok(image) => Ok(Some(image)),
+            Err(_) => {
+                if let Some(last) = last_search {
+                    tokio::time::sleep_until((last + MUSICBRAINZ_INTERVAL).into()).await;
+                }
+                last_search = Some(Instant::now());
+                let (agent, artist, album) = (agent.clone(), lookup.artist.clone(), lookup.album.clone());
+                crate::net::blocking(move || fetch(&agent, &artist, &album))
+                    .await
+                    .unwrap_or_else(|e| Err(e.into()))
+            }
+        };
+        let Some(reply) = keep(lookup, image, &image_path, &missing_path, &mut failing) else {
+            continue;
+        };
+        if found.send(reply).is_err() {
+            return;
+        }
+    }
+}
+
+/// Caches what was found for `lookup`, and returns what to tell the player,
+/// if anything.
+fn keep(
+    lookup: Lookup,
+    image: Result<Option<Vec<u8>>>,
+    image_path: &Path,
+    missing_path: &Path,
+    failing: &mut bool,
+) -> Option<Result<Cover, String>> {
+    match image {
+        Ok(Some(image)) => {
+            // Failing to keep it only costs a download next time.
+            if let Err(e) = fs::write(image_path, &image) {
+                tracing::warn!("Couldn't cache the cover {}: {}", image_path.display(), e);
+            }
+            *failing = false;
+            Some(Ok(Cover { path: lookup.path, image }))
+        }
+        Ok(None) => {
+            // Not asked again: delete the file to try once more.
+            let _ = fs::write(missing_path, "");
+            *failing = false;
+            None
+        }
+        // Only the first of a series of failures is reported, as when
+        // offline every album would fail the same way.
+        Err(e) if !*failing => {
+            *failing = true;
+            Some(Err(format!("Couldn't fetch the cover of {}: {:#}", lookup.album, e)))
+        }
+        Err(e) => {
+            tracing::warn!("Couldn't fetch the cover of {}: {:#}", lookup.album, e);
+            None
+        }
+    }
+}
+
+/// Quotes `value` for a MusicBrainz search query.
+fn quote(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The front cover of the best matching release that has one, `None` when
+/// there is none.
+fn fetch(agent: &ureq::Agent, artist: &str, album: &str) -> Result<Option<Vec<u8>>> {
+    let query = format!("release:{} AND artist:{}", quote(album), quote(artist));
+    let body: Value = agent
+        .get(MUSICBRAINZ_URL)
+        .query("query", &query)
+        .query("fmt", "json")
+        .query("limit", &CANDIDATES.to_string())
+        .call()?
+        .into_json()?;
+    let matching: Vec<&Value> = body["releases"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|release| release["score"].as_u64().unwrap_or(0) >= MIN_SCORE)
+        .collect();
+
+    let mut urls: Vec<String> = matching
+        .iter()
+        .filter_map(|release| release["id"].as_str())
+        .map(|id| format!("{}/release/{}/front-500", COVER_ART_ARCHIVE_URL, id))
+        .collect();
+    // The group stands for every edition of the album.
+    if let Some(group) = matching.iter().find_map(|release| release["release-group"]["id"].as_str()) {
+        urls.push(format!("{}/release-group/{}/front-500", COVER_ART_ARCHIVE_URL, group));
+    }
+    for url in urls {
+        match agent.get(&url).call() {
+            Ok(response) => {
+                let mut image = Vec::new();
+                response.into_reader().take(MAX_SIZE).read_to_end(&mut image)?;
+                return Ok(Some(image));
+            }
+            Err(ureq::Error::Status(404, _)) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,204 @@
+//! Discord Rich Presence: the track playing shown on the user's Discord
+//! profile, through the local IPC socket of the Discord client.
+//!
+//! The socket is written to from a thread of its own, which connects when
+//! Discord is running and tries again every so often when it is not, so
+//! Discord can be started or quit at any time.
+
+use crate::media_keys::Status;
+use serde_json::{json, Value};
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long to wait before connecting again once Discord is gone.
+const RETRY_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Opcodes of the IPC frames.
+const HANDSHAKE: u32 = 0;
+const FRAME: u32 = 1;
+
+pub struct Presence {
+    sender: Sender<Value>,
+    /// What Discord was last sent, to send only changes.
+    last: Option<Status>,
+}
+
+impl Presence {
+    /// `client_id` is the id of a Discord application, whose name Discord
+    /// shows above the track.
+    pub fn start(client_id: String) -> Presence {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || run(&client_id, receiver));
+        Presence { sender, last: None }
+    }
+
+    /// Shows `status`, `position` being how far into the track it is. Only
+    /// changes of track and pauses are sent.
+    pub fn update(&mut self, status: &Status, position: Option<Duration>) {
+        if self.last.as_ref() == Some(status) {
+            return;
+        }
+        self.last = Some(status.clone());
+        let _ = self.sender.send(activity(status, position));
+    }
+}
+
+/// The activity shown for `status`, `null` to clear it.
+fn activity(status: &Status, position: Option<Duration>) -> Value {
+    if !status.loaded {
+        return Value::Null;
+    }
+    let mut activity = json!({
+        // "Listening to"
+        "type": 2,
+        "details": status.title.as_deref().unwrap_or("Unknown title"),
+        "state": match (&status.artist, status.playing) {
+            (Some(artist), true) => format!("by {}", artist),
+            (Some(artist), false) => format!("by {} (paused)", artist),
+            (None, true) => "Playing".to_string(),
+            (None, false) => "Paused".to_string(),
+        },
+    });
+    if let Some(album) = &status.album {
+        activity["assets"] = json!({ "large_text": album });
+    }
+    // Discord counts the time itself from these.
+    if let (true, Some(position)) = (status.playing, position) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let start = now.saturating_sub(position);
+        activity["timestamps"] = match status.length {
+            Some(length) => json!({
+                "start": start.as_millis() as u64,
+                "end": (start + length).as_millis() as u64,
+            }),
+            None => json!({ "start": start.as_millis() as u64 }),
+        };
+    }
+    activity
+}
+
+fn run(client_id: &str, receiver: Receiver<Value>) {
+    let mut connection = None;
+    // Latest activity, resent after connecting.
+    let mut activity = Value::Null;
+    let mut changed = false;
+    loop {
+        match receiver.recv_timeout(RETRY_INTERVAL) {
+            Ok(latest) => {
+                activity = latest;
+                changed = true;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        // Only the last of a burst of changes matters.
+        if let Some(latest) = receiver.try_iter().last() {
+            activity = latest;
+        }
+
+        if connection.is_none() {
+            connection = connect(client_id).ok();
+            changed |= connection.is_some();
+        }
+        if let (Some(stream), true) = (&mut connection, changed) {
+            changed = false;
+            if set_activity(stream, &activity).is_err() {
+                connection = None;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+
+#[cfg(windows)]
+type Stream = std::fs::File;
+
+/// Connects and says hello to the Discord client.
+#[cfg(any(unix, windows))]
+fn connect(client_id: &str) -> io::Result<Stream> {
+    let mut stream = socket_paths()
+        .into_iter()
+        .find_map(|path| open(&path).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Discord is not running"))?;
+    write_frame(&mut stream, HANDSHAKE, &json!({ "v": 1, "client_id": client_id }))?;
+    read_frame(&mut stream)?;
+    Ok(stream)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn connect(_client_id: &str) -> io::Result<std::fs::File> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "no Discord IPC on this platform"))
+}
+
+/// A Discord client that stops answering is given up on.
+#[cfg(unix)]
+fn open(path: &std::path::Path) -> io::Result<Stream> {
+    let stream = Stream::connect(path)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    Ok(stream)
+}
+
+#[cfg(windows)]
+fn open(path: &std::path::Path) -> io::Result<Stream> {
+    std::fs::OpenOptions::new().read(true).write(true).open(path)
+}
+
+/// Where the Discord client may listen: the runtime or temp directory, also
+/// as seen from its Flatpak and Snap packages.
+#[cfg(unix)]
+fn socket_paths() -> Vec<std::path::PathBuf> {
+    let mut dirs: Vec<std::path::PathBuf> = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(std::path::PathBuf::from)
+        .collect();
+    dirs.push("/tmp".into());
+    let mut paths = Vec::new();
+    for dir in dirs {
+        for sub in ["", "app/com.discordapp.Discord", "snap.discord"] {
+            for index in 0..10 {
+                paths.push(dir.join(sub).join(format!("discord-ipc-{}", index)));
+            }
+        }
+    }
+    paths
+}
+
+#[cfg(windows)]
+fn socket_paths() -> Vec<std::path::PathBuf> {
+    (0..10).map(|index| format!(r"\\?\pipe\discord-ipc-{}", index).into()).collect()
+}
+
+fn set_activity(stream: &mut (impl Read + Write), activity: &Value) -> io::Result<()> {
+    let nonce = format!("{:016x}", rand::random::<u64>());
+    let command = json!({
+        "cmd": "SET_ACTIVITY",
+        "args": { "pid": std::process::id(), "activity": activity },
+        "nonce": nonce,
+    });
+    write_frame(stream, FRAME, &command)?;
+    // The answer, read so that it doesn't pile up.
+    read_frame(stream)
+}
+
+/// Opcode, length and JSON, the numbers in little endian.
+fn write_frame(stream: &mut impl Write, opcode: u32, payload: &Value) -> io::Result<()> {
+    let payload = payload.to_string();
+    let mut frame = Vec::with_capacity(8 + payload.len());
+    frame.extend_from_slice(&opcode.to_le_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload.as_bytes());
+    stream.write_all(&frame)
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<()> {
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header)?;
+    let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    io::copy(&mut stream.take(length as u64), &mut io::sink())?;
+    Ok(())
+}
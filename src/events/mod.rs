@@ -0,0 +1,141 @@
+//! What the main loop reacts to, sent over one channel by the threads that
+//! wait for it: the terminal, the media keys, the remote controls, the
+//! player thread, and a clock for what moves on screen (progress, level
+//! meters, status messages).
+
+use crate::playback::Update;
+use crate::remote::Request;
+use crate::ui::UserAction;
+use crossterm::event;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub enum Event {
+    /// A key press, a resize, ... or why the terminal can't be read.
+    Terminal(Result<event::Event, io::Error>),
+    /// A media key pressed while the terminal isn't focused.
+    MediaKey(UserAction),
+    /// A command from the socket, the named pipe, the HTTP API or MPD.
+    Remote(Request),
+    /// A command of the named pipe that failed, with nobody to tell.
+    RemoteError(String),
+    /// A job of the player thread is done, or the playlist is over.
+    Playback(Update),
+    /// Ctrl-C outside the interface, or a termination signal: quits as `q`
+    /// would.
+    Interrupted,
+    Tick,
+}
+
+pub struct Events {
+    sender: Sender<Event>,
+    receiver: Receiver<Event>,
+}
+
+impl Events {
+    pub fn new() -> Events {
+        let (sender, receiver) = mpsc::channel();
+        Events { sender, receiver }
+    }
+
+    pub fn sender(&self) -> Sender<Event> {
+        self.sender.clone()
+    }
+
+    /// A sender for a source of `T`s, which come out of `next` as `wrap`
+    /// makes them.
+    pub fn source<T: Send + 'static>(&self, wrap: fn(T) -> Event) -> Sender<T> {
+        let (sender, receiver) = mpsc::channel();
+        let events = self.sender();
+        thread::spawn(move || {
+            for item in receiver {
+                if events.send(wrap(item)).is_err() {
+                    return;
+                }
+            }
+        });
+        sender
+    }
+
+    /// Reads the terminal from now on, until told otherwise: it must be in
+    /// raw mode already.
+    pub fn read_terminal(&self) -> TerminalReader {
+        let reader = TerminalReader {
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+        let (events, stopped) = (self.sender(), Arc::clone(&reader.stopped));
+        thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                // Waits a little at a time, to notice `stop`.
+                let event = match event::poll(READ_INTERVAL) {
+                    Ok(false) => continue,
+                    Ok(true) => event::read(),
+                    Err(e) => Err(e),
+                };
+                let failed = event.is_err();
+                if events.send(Event::Terminal(event)).is_err() || failed {
+                    return;
+                }
+            }
+        });
+        reader
+    }
+
+    /// Sends an `Interrupted` on SIGINT, SIGTERM and SIGHUP instead of
+    /// dying with the terminal in raw mode.
+    pub fn catch_interrupts(&self) -> Result<(), ctrlc::Error> {
+        let events = self.sender();
+        ctrlc::set_handler(move || {
+            let _ = events.send(Event::Interrupted);
+        })
+    }
+
+    /// Sends a `Tick` every `interval`, until told otherwise.
+    pub fn tick(&self, interval: Duration) -> Ticker {
+        let ticker = Ticker {
+            millis: Arc::new(AtomicU64::new(interval.as_millis() as u64)),
+        };
+        let (events, millis) = (self.sender(), Arc::clone(&ticker.millis));
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(millis.load(Ordering::Relaxed)));
+            if events.send(Event::Tick).is_err() {
+                return;
+            }
+        });
+        ticker
+    }
+
+    /// Waits for the next event.
+    pub fn next(&self) -> Option<Event> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Longest wait for a key before `TerminalReader::stop` is noticed.
+const READ_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Stops reading the terminal, so that it can go back to the shell.
+pub struct TerminalReader {
+    stopped: Arc<AtomicBool>,
+}
+
+impl TerminalReader {
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Changes the interval of the `Tick`s, from the next one on.
+pub struct Ticker {
+    millis: Arc<AtomicU64>,
+}
+
+impl Ticker {
+    pub fn set_interval(&self, interval: Duration) {
+        self.millis.store(interval.as_millis() as u64, Ordering::Relaxed);
+    }
+}
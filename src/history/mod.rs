@@ -0,0 +1,323 @@
+//! Listening history: the completed plays, and the tracks left early with
+//! Next, logged one JSON object per line
+//! in `history.jsonl` of `data_dir`, along with the skipped tracks and the
+//! favorites, whose lists keep when each track was added. `ksound history
+//! export` writes them out for spreadsheets and other tools.
+
+use crate::config::{self, TagSnapshot, TrackStore};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LOG_FILE: &str = "history.jsonl";
+
+/// One completed play, or a track left early with Next, as logged.
+#[derive(Serialize, Deserialize)]
+struct Play {
+    /// Seconds since the Unix epoch.
+    time: u64,
+    path: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    early_skip: bool,
+    #[serde(flatten)]
+    tags: TagSnapshot,
+}
+
+/// The log of completed plays.
+pub struct PlayLog {
+    path: PathBuf,
+    /// How many times each track was left early since it was last played
+    /// to the end, by canonical path: read from the log the first time it
+    /// is needed, then kept up to date.
+    early_skips: Option<HashMap<PathBuf, usize>>,
+}
+
+impl PlayLog {
+    pub fn new() -> Result<Self, io::Error> {
+        Ok(PlayLog {
+            path: config::data_dir()?.join(LOG_FILE),
+            early_skips: None,
+        })
+    }
+
+    pub fn record(&mut self, track: &Path, tags: TagSnapshot) -> Result<(), io::Error> {
+        self.append(track, false, tags)?;
+        if let Some(early_skips) = &mut self.early_skips {
+            early_skips.remove(&canonical(track));
+        }
+        Ok(())
+    }
+
+    /// Logs that `track` was left early with Next. Returns how many times
+    /// it was, this one included, since it was last played to the end.
+    pub fn record_early_skip(&mut self, track: &Path, tags: TagSnapshot) -> Result<usize, io::Error> {
+        if self.early_skips.is_none() {
+            let mut early_skips = HashMap::new();
+            for play in self.plays()? {
+                match play.early_skip {
+                    true => *early_skips.entry(PathBuf::from(play.path)).or_insert(0) += 1,
+                    false => {
+                        early_skips.remove(Path::new(&play.path));
+                    }
+                }
+            }
+            self.early_skips = Some(early_skips);
+        }
+        self.append(track, true, tags)?;
+        let early_skips = self.early_skips.get_or_insert_with(HashMap::new);
+        let count = early_skips.entry(canonical(track)).or_insert(0);
+        *count += 1;
+        Ok(*count)
+    }
+
+    fn append(&self, track: &Path, early_skip: bool, tags: TagSnapshot) -> Result<(), io::Error> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let play = Play {
+            time,
+            path: canonical(track).to_string_lossy().to_string(),
+            early_skip,
+            tags,
+        };
+        let line = serde_json::to_string(&play).map_err(io::Error::other)?;
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+
+    /// When each track was last played to the end, in seconds since the
+    /// Unix epoch, by canonical path.
+    pub fn last_played(&self) -> Result<HashMap<PathBuf, u64>, io::Error> {
+        let mut last_played = HashMap::new();
+        for play in self.plays()?.into_iter().filter(|play| !play.early_skip) {
+            let time = last_played.entry(PathBuf::from(play.path)).or_insert(play.time);
+            *time = play.time.max(*time);
+        }
+        Ok(last_played)
+    }
+
+    /// The plays logged so far. A line cut short by a crash is left out.
+    fn plays(&self) -> Result<Vec<Play>, io::Error> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn canonical(track: &Path) -> PathBuf {
+    track.canonicalize().unwrap_or_else(|_| track.to_path_buf())
+}
+
+/// A line of the export.
+#[derive(Serialize)]
+pub struct Event {
+    /// UTC, as `YYYY-MM-DDTHH:MM:SSZ`.
+    pub time: String,
+    /// `play`, `early_skip` (left with Next within its first seconds),
+    /// `skip` or `favorite`.
+    pub event: &'static str,
+    pub path: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    /// Why the track was skipped or made a favorite.
+    pub reason: Option<String>,
+    #[serde(skip)]
+    seconds: u64,
+}
+
+/// The plays, skips and favorites since `since` (seconds since the Unix
+/// epoch), oldest first.
+pub fn events(library: &[PathBuf], since: u64) -> Result<Vec<Event>> {
+    let event = |seconds: u64, event, path: String, tags: TagSnapshot, reason| Event {
+        time: format_time(seconds),
+        event,
+        path,
+        artist: tags.artist,
+        album: tags.album,
+        title: tags.title,
+        reason,
+        seconds,
+    };
+    let mut events: Vec<Event> = PlayLog::new()?
+        .plays()?
+        .into_iter()
+        .map(|play| {
+            let name = if play.early_skip { "early_skip" } else { "play" };
+            event(play.time, name, play.path, play.tags, None)
+        })
+        .collect();
+    for (store, name) in [(TrackStore::skip_list(library)?, "skip"), (TrackStore::favorites(library)?, "favorite")] {
+        events.extend(store.entries().iter().map(|entry| {
+            let entry = entry.clone();
+            event(entry.added, name, entry.path, entry.tags, Some(entry.reason))
+        }));
+    }
+    events.retain(|event| event.seconds >= since);
+    events.sort_by_key(|event| event.seconds);
+    Ok(events)
+}
+
+/// The events as CSV, with a header line.
+pub fn to_csv(events: &[Event]) -> String {
+    let mut csv = String::from("time,event,path,artist,album,title,reason\n");
+    for event in events {
+        let fields = [
+            Some(event.time.as_str()),
+            Some(event.event),
+            Some(event.path.as_str()),
+            event.artist.as_deref(),
+            event.album.as_deref(),
+            event.title.as_deref(),
+            event.reason.as_deref(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field.unwrap_or(""))).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quotes `value` if it holds a comma, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Seconds since the Unix epoch at midnight UTC of `date`, `YYYY-MM-DD`.
+pub fn parse_date(date: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid date \"{}\" (expected YYYY-MM-DD)", date);
+    let parts: Vec<&str> = date.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let (year, month, day): (i64, u32, u32) = (
+        year.parse().map_err(|_| invalid())?,
+        month.parse().map_err(|_| invalid())?,
+        day.parse().map_err(|_| invalid())?,
+    );
+    if !(1970..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let days = days_from_civil(year, month, day);
+    // Past the end of the month, such as 2023-02-31, the day lands in the next.
+    if civil_from_days(days) != (year, month, day) {
+        return Err(invalid());
+    }
+    Ok(days as u64 * 86_400)
+}
+
+/// `seconds` since the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_time(seconds: u64) -> String {
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Days since 1970-01-01 of a date of the proleptic Gregorian calendar
+/// (http://howardhinnant.github.io/date_algorithms.html).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The reverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("1970-01-01"), Ok(0));
+        assert_eq!(parse_date("2000-03-01"), Ok(951_868_800));
+        assert_eq!(parse_date("2024-02-29"), Ok(1_709_164_800));
+        assert_eq!(parse_date("2000-02-29"), Ok(951_782_400));
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("1900-02-29").is_err());
+        assert!(parse_date("2023-02-31").is_err());
+        assert!(parse_date("2023-04-31").is_err());
+        assert!(parse_date("2023-13-01").is_err());
+        assert!(parse_date("2023-00-10").is_err());
+        assert!(parse_date("1969-12-31").is_err());
+        assert!(parse_date("2023-1").is_err());
+        assert!(parse_date("99999999999999999-01-01").is_err());
+    }
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(format_time(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_time(951_868_799), "2000-02-29T23:59:59Z");
+        assert_eq!(format_time(1_704_067_199), "2023-12-31T23:59:59Z");
+        assert_eq!(format_time(1_704_067_200), "2024-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn dates_round_trip() {
+        // Every day from 1970 to 2100, past leap days, year ends and 2100,
+        // which isn't a leap year.
+        let end = days_from_civil(2101, 1, 1);
+        let mut expected = (1970, 1, 1);
+        for days in 0..end {
+            let date = civil_from_days(days);
+            assert_eq!(date, expected);
+            assert_eq!(days_from_civil(date.0, date.1, date.2), days);
+            let text = format!("{:04}-{:02}-{:02}", date.0, date.1, date.2);
+            assert_eq!(parse_date(&text), Ok(days as u64 * 86_400));
+            assert!(format_time(days as u64 * 86_400 + 3661).starts_with(&format!("{}T01:01:01", text)));
+            expected = next_day(date);
+        }
+        assert_eq!(civil_from_days(end), (2101, 1, 1));
+    }
+
+    /// The day after `date`, counted the long way.
+    fn next_day((year, month, day): (i64, u32, u32)) -> (i64, u32, u32) {
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let length = match month {
+            2 if leap => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        match (month, day) {
+            (12, 31) => (year + 1, 1, 1),
+            (_, day) if day == length => (year, month + 1, 1),
+            _ => (year, month, day + 1),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! The `[hooks]` of the config file: shell commands run when the track
+//! changes, on pause and resume, and when playback stops, for status bars,
+//! notifications or smart lights.
+//!
+//! A hook gets the event and the track in environment variables:
+//! `KSOUND_EVENT` (`track_change`, `pause`, `resume` or `stop`),
+//! `KSOUND_STATE` (`playing`, `paused` or `stopped`), `KSOUND_FILE`,
+//! `KSOUND_ARTIST`, `KSOUND_ALBUM`, `KSOUND_TITLE`, and `KSOUND_DURATION` and
+//! `KSOUND_POSITION` in seconds. Those not known are unset. Hooks run in the
+//! background: the player doesn't wait for them.
+
+use crate::config;
+use crate::media_keys::Status;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+pub struct Hooks {
+    commands: config::Hooks,
+    /// The track and whether it was playing at the last update.
+    last: Option<(Option<PathBuf>, bool)>,
+}
+
+impl Hooks {
+    /// `None` when no hook is set.
+    pub fn new(commands: &config::Hooks) -> Option<Hooks> {
+        let hooks = [
+            &commands.on_track_change,
+            &commands.on_pause,
+            &commands.on_resume,
+            &commands.on_stop,
+        ];
+        hooks.iter().any(|hook| hook.is_some()).then(|| Hooks {
+            commands: commands.clone(),
+            last: None,
+        })
+    }
+
+    /// Runs the hook of what changed since the last call: `file` being
+    /// another track, or `status` going from playing to paused or back.
+    pub fn update(
+        &mut self,
+        status: &Status,
+        file: Option<&Path>,
+        position: Option<Duration>,
+    ) -> Result<(), io::Error> {
+        let current = (file.map(Path::to_path_buf), status.playing);
+        let last = self.last.replace(current.clone());
+        let event = match last {
+            _ if current.0.is_none() => return Ok(()),
+            Some(last) if last.0 == current.0 => match (last.1, current.1) {
+                (true, false) => "pause",
+                (false, true) => "resume",
+                _ => return Ok(()),
+            },
+            _ => "track_change",
+        };
+        let command = match event {
+            "pause" => &self.commands.on_pause,
+            "resume" => &self.commands.on_resume,
+            _ => &self.commands.on_track_change,
+        };
+        match command {
+            Some(command) => run(command, event, status, file, position),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `on_stop`, as the player quits or the playlist is over.
+    pub fn stop(
+        &self,
+        status: &Status,
+        file: Option<&Path>,
+        position: Option<Duration>,
+    ) -> Result<(), io::Error> {
+        match &self.commands.on_stop {
+            Some(command) => run(command, "stop", status, file, position),
+            None => Ok(()),
+        }
+    }
+}
+
+fn run(
+    command: &str,
+    event: &str,
+    status: &Status,
+    file: Option<&Path>,
+    position: Option<Duration>,
+) -> Result<(), io::Error> {
+    let mut process = if cfg!(windows) {
+        let mut process = Command::new("cmd");
+        process.arg("/C");
+        process
+    } else {
+        let mut process = Command::new("sh");
+        process.arg("-c");
+        process
+    };
+    process
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    let state = match (event, status.playing) {
+        ("stop", _) => "stopped",
+        (_, true) => "playing",
+        (_, false) => "paused",
+    };
+    let variables = [
+        ("KSOUND_EVENT", Some(event.to_string())),
+        ("KSOUND_STATE", Some(state.to_string())),
+        ("KSOUND_FILE", file.map(|file| file.to_string_lossy().to_string())),
+        ("KSOUND_ARTIST", status.artist.clone()),
+        ("KSOUND_ALBUM", status.album.clone()),
+        ("KSOUND_TITLE", status.title.clone()),
+        ("KSOUND_DURATION", status.length.map(|length| length.as_secs().to_string())),
+        ("KSOUND_POSITION", position.map(|position| position.as_secs().to_string())),
+    ];
+    for (name, value) in variables {
+        match value {
+            Some(value) => process.env(name, value),
+            None => process.env_remove(name),
+        };
+    }
+
+    let mut child = process.spawn()?;
+    // Reaped in the background, so that a slow hook doesn't hold up playback.
+    thread::spawn(move || child.wait());
+    Ok(())
+}
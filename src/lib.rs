@@ -0,0 +1,52 @@
+//! The playback and library side of ksound, for tools that want its player
+//! without the terminal interface of the `ksound` binary:
+//!
+//! - `player`: the `Player`, its playlist and queue, and the tags and
+//!   technical details of the tracks (`TrackMetadata`, `TechnicalInfo`).
+//! - `playlist`: playlists from a folder or a playlist file.
+//! - `config`: the config file, and what is kept in the data directory: the
+//!   favorites and skip list (`TrackStore`), play counts and session.
+//! - `cache`, `cover_art`, `history`, `scrobble` and `tags`: the metadata
+//!   and cover cache, cover downloads, the listening history, scrobbling
+//!   and tag cleanup the player relies on.
+//! - `sync`: all of the above worth carrying to another machine, in one
+//!   file.
+//! - `copy`: tracks copied, or converted, to a folder or a device.
+//! - `organize`: tracks moved into folders named after their tags.
+//! - `net`, with the `tokio` feature: the runtime the network side of these
+//!   runs on.
+//!
+//! ```no_run
+//! use ksound::{player::Player, playlist};
+//! use std::path::PathBuf;
+//! use std::time::Duration;
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     let tracks = playlist::from_path("/music", &["mp3".to_string()])?;
+//!     let mut player = Player::new(&[PathBuf::from("/music")])?;
+//!     player.set_playlist(tracks, false)?;
+//!     player.play_next()?;
+//!     // Until the end of the playlist.
+//!     while player.handle_playback()? {
+//!         std::thread::sleep(Duration::from_millis(100));
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Files are read from and written to the same places as the binary's:
+//! call `config::select` first to use another profile.
+
+pub mod cache;
+pub mod config;
+pub mod copy;
+pub mod cover_art;
+pub mod history;
+#[cfg(feature = "tokio")]
+pub mod net;
+pub mod organize;
+pub mod player;
+pub mod playlist;
+pub mod scrobble;
+pub mod sync;
+pub mod tags;
@@ -0,0 +1,43 @@
+//! The log file, `ksound.log` in `config::data_dir`: the terminal belongs to
+//! the interface, so what goes wrong is written there as well as shown on
+//! the status line, which forgets it after a few seconds.
+//!
+//! Warnings and errors are always logged; each `-v` adds a level: what is
+//! played and skipped, then the commands received and the files read.
+
+use ksound::config;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::Level;
+
+/// Size over which the log is moved to `ksound.log.1` at startup, replacing
+/// the previous one.
+const MAX_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Where the log is written.
+pub fn path() -> Result<PathBuf, io::Error> {
+    Ok(config::data_dir()?.join("ksound.log"))
+}
+
+/// Starts logging, at a level raised by each `-v` of `verbosity`.
+pub fn init(verbosity: u8) -> Result<(), io::Error> {
+    let path = path()?;
+    if fs::metadata(&path).is_ok_and(|metadata| metadata.len() > MAX_SIZE) {
+        fs::rename(&path, path.with_extension("log.1"))?;
+    }
+    let file: File = OpenOptions::new().create(true).append(true).open(&path)?;
+    let level = match verbosity {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_max_level(level)
+        .init();
+    Ok(())
+}
@@ -1,22 +1,33 @@
-mod config;
-mod player;
+mod alarm;
+mod discord;
+mod events;
+mod hooks;
+mod logging;
+mod media_keys;
+mod playback;
+mod power;
+mod remote;
 mod ui;
 
 use anyhow::Result;
-use clap::Parser;
-use rand::seq::SliceRandom;
-use std::fs;
-use std::path::PathBuf;
-use std::thread;
+use clap::{Parser, Subcommand};
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
-use walkdir::WalkDir;
+
+use events::{Event, Events};
+use ksound::{cache, config, cover_art, history, player, playlist, scrobble, tags};
+use ui::TrackInfo;
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Directory containing MP3 files or specific MP3 file to play
-    #[arg(default_value = ".")]
-    path: String,
+    /// Directories containing MP3 files, specific MP3 files or M3U playlists to
+    /// play, in this order, or - for a list of paths on standard input (default:
+    /// the `paths` of the config file, or the current directory)
+    paths: Vec<String>,
 
     /// Playlist file to load
     #[arg(short, long)]
@@ -25,200 +36,1971 @@ struct Cli {
     /// Randomize playback order
     #[arg(short, long)]
     random: bool,
+
+    /// Initial order of the playlist: path, name, mtime (newest first), track
+    /// (album order from the tags, folder by folder), random, random-albums
+    /// (albums in random order, each in its order), or any order of the
+    /// Playlists tab (overrides the config file and .ksound.toml)
+    #[arg(long, value_name = "ORDER", value_parser = parse_order, conflicts_with = "random")]
+    sort: Option<Order>,
+
+    /// Seed of the random orders, for the same tracks to be shuffled the same
+    /// way on each run; the listening history is then left out of the
+    /// weights
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+
+    /// Only play the favorites among the tracks found ('F' plays them all again)
+    #[arg(long)]
+    favorites: bool,
+
+    /// Only play tracks rated at least this many stars (1-5)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=5))]
+    min_rating: Option<u8>,
+
+    /// Write play counts into the files' PCNT/POPM frames when a track completes
+    #[arg(long)]
+    write_play_counts: bool,
+
+    /// Leave out the files and folders matching this glob pattern when scanning
+    /// directories, e.g. "podcasts/" or "*-demo.mp3" (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Only pick up files this many folders down in the directories given: 1
+    /// plays the files directly in them
+    #[arg(long, value_name = "DEPTH", value_parser = clap::value_parser!(u64).range(1..))]
+    max_depth: Option<u64>,
+
+    /// Only pick up the files directly in the directories given, like
+    /// --max-depth 1
+    #[arg(long, conflicts_with = "max_depth")]
+    no_recurse: bool,
+
+    /// Pick up the last session where it was left: its playlist, in the same
+    /// order, at the same track and position (also with `resume = true` in the
+    /// config file and no paths given)
+    #[arg(long, conflicts_with_all = ["paths", "playlist", "random", "sort", "start_at"])]
+    resume: bool,
+
+    /// Start at this playlist entry instead of the first: its index (from 0, like
+    /// the position of `ksound status`), or a part of its file name
+    #[arg(long, value_name = "ENTRY")]
+    start_at: Option<String>,
+
+    /// Volume to start at, in percent, for this run only: the config file keeps
+    /// its own
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u16).range(0..=200))]
+    volume: Option<u16>,
+
+    /// Wait until this time of the day, HH:MM, before playing: an alarm clock
+    #[arg(long, value_name = "TIME", value_parser = alarm::parse_time)]
+    alarm: Option<alarm::AlarmTime>,
+
+    /// Raise the volume from silence over this long once the alarm goes off,
+    /// e.g. 30s, 5m
+    #[arg(long, value_name = "LENGTH", value_parser = alarm::parse_length, requires = "alarm")]
+    ramp: Option<Duration>,
+
+    /// At the end of a track: off stops after the last one, one plays it again,
+    /// all starts the playlist over (overrides the config file)
+    #[arg(long, value_name = "MODE", value_parser = parse_repeat)]
+    repeat: Option<config::RepeatMode>,
+
+    /// Color theme: default, light or mono (overrides the config file)
+    #[arg(long)]
+    theme: Option<String>,
+
+    /// Show only the current track on one line (m switches back)
+    #[arg(long)]
+    mini: bool,
+
+    /// No interface: print the tracks as they start, and take commands from
+    /// the remote controls only (ksound toggle, next, ...), e.g. under systemd
+    #[arg(long, conflicts_with = "mini")]
+    no_tui: bool,
+
+    /// Serve the HTTP API on this address, e.g. 127.0.0.1:8765
+    #[arg(long, value_name = "ADDRESS")]
+    listen: Option<String>,
+
+    /// Answer MPD clients on this address, e.g. 127.0.0.1:6600
+    #[arg(long, value_name = "ADDRESS")]
+    mpd: Option<String>,
+
+    /// Stream what is playing over HTTP on this address, e.g. 0.0.0.0:8000
+    #[arg(long, value_name = "ADDRESS")]
+    stream: Option<String>,
+
+    /// Config file to use instead of ~/.config/ksound/config.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Named profile, with its own config file (~/.config/ksound/profiles/NAME.toml),
+    /// favorites, skip list, play counts and session
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Log more to ksound.log in the data directory: -v what is played and
+    /// skipped, -vv the commands received, -vvv everything
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Inspect and clean up tags
+    Tags {
+        #[command(subcommand)]
+        action: TagsCommand,
+    },
+    /// Report missing titles, years and track numbers, and inconsistent album artists
+    Lint {
+        /// Directories or files to check
+        #[arg(default_value = ".")]
+        paths: Vec<String>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the key bindings in use and check the [keys] table of the config
+    Keys,
+    /// Work with the history of plays, skips and favorites
+    History {
+        #[command(subcommand)]
+        action: HistoryCommand,
+    },
+    /// Review the skipped tracks, and take some out of the skip list
+    Skiplist {
+        #[command(subcommand)]
+        action: SkiplistCommand,
+    },
+    /// Carry the favorites, skip list, bans, ratings and resume position to another machine
+    Sync {
+        #[command(subcommand)]
+        action: SyncCommand,
+    },
+    /// Find the favorites and skipped tracks whose files have moved, and update them
+    Relink {
+        /// Where to look for the files (default: the paths of the config file)
+        paths: Vec<String>,
+
+        /// Only report what would be changed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Copy tracks to a folder or a mounted device, e.g. a USB stick, but for
+    /// the skipped and banned ones
+    Copy {
+        /// Where to copy them
+        destination: PathBuf,
+
+        /// Directories or files to copy, keeping their folders below them
+        /// (default: the `paths` of the config file)
+        paths: Vec<String>,
+
+        /// Copy the tracks of this playlist file instead, keeping their folders
+        /// below the `paths` of the config file
+        #[arg(short, long, conflicts_with = "paths")]
+        playlist: Option<String>,
+
+        /// Only copy the favorites
+        #[arg(long)]
+        favorites: bool,
+
+        /// Only copy tracks rated at least this many stars (1-5)
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=5))]
+        min_rating: Option<u8>,
+
+        /// Put every file right in the destination, without folders
+        #[arg(long)]
+        flatten: bool,
+
+        /// Convert the tracks in other formats to this one with ffmpeg: mp3,
+        /// m4a, ogg or opus
+        #[arg(long, value_name = "FORMAT", value_parser = ksound::copy::FORMATS)]
+        transcode: Option<String>,
+
+        /// Bitrate of the converted files, in kbit/s
+        #[arg(long, default_value_t = 192, requires = "transcode")]
+        bitrate: u32,
+
+        /// Only print what would be copied
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Move the tracks of a folder into folders named after their tags
+    Organize {
+        /// Folder to sort, where the folders are made
+        #[arg(default_value = ".")]
+        folder: PathBuf,
+
+        /// Folders and name of each track, without the extension: {artist},
+        /// {album}, {title}, {track}, {disc} and {year} are its tags
+        #[arg(long, default_value = ksound::organize::DEFAULT_PATTERN)]
+        pattern: String,
+
+        /// Only print what would be moved
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Quit the running player, saving its session
+    Quit,
+    /// Resume playback in the running player
+    Play,
+    /// Pause the running player
+    Pause,
+    /// Pause or resume the running player
+    Toggle,
+    /// Skip to the next track in the running player
+    Next,
+    /// Go back to the previous track in the running player
+    Prev,
+    /// Print the state, track, position and volume of the running player
+    Status,
+    /// Print the next tracks of the running player
+    Queue,
+    /// Set the volume of the running player
+    Volume {
+        /// Percent, 0 to 200
+        #[arg(value_parser = clap::value_parser!(u16).range(0..=200))]
+        percent: u16,
+    },
+    /// Add files or directories at the end of the running player's playlist
+    Enqueue {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// Print the history, oldest first, for spreadsheets and other tools
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = HistoryFormat::Csv)]
+        format: HistoryFormat,
+
+        /// Only events from this day on (YYYY-MM-DD, UTC)
+        #[arg(long, value_name = "DATE", value_parser = history::parse_date)]
+        since: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SkiplistCommand {
+    /// Print the skipped tracks, oldest first
+    List {
+        /// Print them as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Take tracks out of the skip list, or lift bans, so that they are played again
+    Remove {
+        /// Paths of the tracks, or identifiers of the entries and bans as `list` prints them
+        #[arg(required = true)]
+        entries: Vec<String>,
+    },
+    /// Never play an artist, or an album, again: recognized by the tags of the tracks
+    Ban {
+        /// Every track of this artist, or with --album only that album by this artist
+        #[arg(long, required_unless_present = "album")]
+        artist: Option<String>,
+
+        /// The album, by any artist unless --artist is given
+        #[arg(long)]
+        album: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SyncCommand {
+    /// Write them to a file, with the ratings of the tracks of the library (`paths` in the config)
+    Export {
+        /// The file to write, - for standard output
+        file: String,
+    },
+    /// Merge a file written by `export` on another machine: what is here already is kept
+    Import {
+        /// The file to read, - for standard input
+        file: String,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Order {
+    Random,
+    /// Albums in random order, each in its order.
+    RandomAlbums,
+    Sorted(player::SortKey),
+}
+
+fn parse_order(name: &str) -> Result<Order, String> {
+    match name {
+        "random" => Ok(Order::Random),
+        "random-albums" => Ok(Order::RandomAlbums),
+        _ => player::SortKey::parse(name).map(Order::Sorted).ok_or_else(|| {
+            "expected path, name, mtime, track, random, random-albums, title, artist, album, year or \
+             duration"
+                .into()
+        }),
+    }
+}
+
+fn parse_repeat(mode: &str) -> Result<config::RepeatMode, String> {
+    match mode {
+        "off" => Ok(config::RepeatMode::Off),
+        "one" => Ok(config::RepeatMode::One),
+        "all" => Ok(config::RepeatMode::All),
+        _ => Err("expected off, one or all".to_string()),
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum HistoryFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum TagsCommand {
+    /// Remove junk frames: comments, links and ads, duplicates, ID3v1 next to ID3v2
+    Strip {
+        /// MP3 files or directories to clean
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Also remove the link frames (W***), official pages included
+        #[arg(long)]
+        links: bool,
+
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Do not ask for confirmation before writing
+        #[arg(short, long)]
+        yes: bool,
+    },
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    config::select(cli.config.clone(), cli.profile.clone())?;
+    if let Err(e) = logging::init(cli.verbose) {
+        eprintln!("Couldn't open the log: {}", e);
+    }
+    install_panic_hook();
+    tracing::info!("ksound {} starting", env!("CARGO_PKG_VERSION"));
+    let result = start(cli);
+    if let Err(e) = &result {
+        tracing::error!("{:#}", e);
+    }
+    result
+}
+
+/// Puts the terminal back before the panic message is printed, where it
+/// can be read, and logs it with a backtrace.
+fn install_panic_hook() {
+    let print = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        ui::restore_terminal();
+        print(info);
+        tracing::error!("{}\n{}", info, std::backtrace::Backtrace::force_capture());
+        // The other threads would carry on drawing on a terminal no longer
+        // set up for them.
+        std::process::exit(101);
+    }));
+}
+
+fn start(cli: Cli) -> Result<()> {
+    let mut config = config::Config::load()?;
+
+    if let Some(command) = cli.command {
+        return run_command(command, &config);
+    }
+
+    // Flags win over the config file.
+    if let Some(theme) = &cli.theme {
+        config.theme = Some(theme.clone());
+    }
+    if let Some(repeat) = cli.repeat {
+        config.repeat = repeat;
+    }
+    if let Some(volume) = cli.volume {
+        config.volume = volume;
+    }
+    config.exclude.extend(cli.exclude.iter().cloned());
+    let max_depth = if cli.no_recurse { Some(1) } else { cli.max_depth.map(|depth| depth as usize) };
+    let scan = playlist::Scan::new(&config.extensions)
+        .excluding(&config.exclude)?
+        .max_depth(max_depth);
+    let theme = ui::Theme::load(config.theme.as_deref().unwrap_or("default"), &config.colors)?;
+    let keymap = ui::Keymap::preset(config.keymap.as_deref().unwrap_or("default"))?
+        .with_overrides(&config.keys)?;
+
+    config.resume |= cli.resume;
+    // The playlist of the last session, to play in the same order.
+    let session = match (&cli.paths[..], &cli.playlist) {
+        ([], None) if config.resume => config::Session::load().filter(|session| !session.playlist.is_empty()),
+        _ => None,
+    };
+
+    let paths = match &cli.paths[..] {
+        [] if !config.paths.is_empty() => config.paths.iter().map(|path| config::expand_home(path)).collect(),
+        [] => vec![".".to_string()],
+        paths => paths.to_vec(),
+    };
+    // A `.ksound.toml` in the folder played wins over the config file. Several
+    // folders keep their order, whatever the first one says.
+    let folder = match (&cli.playlist, &paths[..]) {
+        (None, [path]) if path != STDIN_PATH => config::FolderSettings::find(Path::new(path))?,
+        _ => config::FolderSettings::default(),
+    };
+    config.shuffle = cli.random || folder.shuffle.unwrap_or(config.shuffle);
+    if session.is_some() {
+        config.shuffle = false;
+    }
+    let sort = match cli.sort {
+        Some(Order::Random) => {
+            config.shuffle = true;
+            None
+        }
+        Some(Order::RandomAlbums) => {
+            config.shuffle = true;
+            config.shuffle_albums = true;
+            None
+        }
+        Some(Order::Sorted(key)) => {
+            config.shuffle = false;
+            Some(key)
+        }
+        None if session.is_some() => None,
+        None => folder.sort,
+    };
 
     println!("KSound - Starting up...");
-    println!("Path: {}", cli.path);
     // Create the playlist
-    let mut playlist = if let Some(playlist_file) = &cli.playlist {
+    let mut playlist = if let Some(session) = &session {
+        println!("Resuming the last session");
+        session.playlist.iter().filter(|path| path.is_file()).cloned().collect()
+    } else if let Some(playlist_file) = &cli.playlist {
         println!("Playlist: {}", playlist_file);
-        load_playlist_from_file(playlist_file)?
+        playlist::from_file(playlist_file)?
     } else {
-        create_playlist_from_path(&cli.path)?
+        let mut playlist = Vec::new();
+        for path in &paths {
+            if path == STDIN_PATH {
+                println!("Paths from standard input");
+                playlist.extend(playlist::from_reader(io::stdin().lock())?);
+                continue;
+            }
+            if playlist::is_m3u(Path::new(path)) {
+                println!("Playlist: {}", path);
+                playlist.extend(playlist::from_file(path)?);
+                continue;
+            }
+            println!("Path: {}", path);
+            playlist.extend(scan.files(path)?);
+        }
+        playlist
     };
-    if cli.random {
+    if config.shuffle {
+        // The player shuffles it, knowing the favorites and ratings.
         println!("Randomizing playlist...");
-        let mut rng = rand::rng();
-        playlist.shuffle(&mut rng);
     }
     println!("Found {} MP3 files", playlist.len());
+    tracing::info!("{} tracks found", playlist.len());
+    if let (Some(time), false) = (cli.alarm, playlist.is_empty()) {
+        alarm::wait(time);
+    }
 
-    let mut ui = ui::UI::new()?;
+    let events = Events::new();
+    if let Err(e) = events.catch_interrupts() {
+        tracing::warn!("Couldn't catch Ctrl-C: {}", e);
+    }
+    let mut control = remote::Control::new(events.source(Event::Remote), events.source(Event::RemoteError));
+    if let Some(address) = &cli.listen {
+        control
+            .listen_http(address)
+            .map_err(|e| anyhow::anyhow!("Couldn't listen on {}: {}", address, e))?;
+        println!("HTTP API on http://{}", address);
+    }
+    if let Some(address) = &cli.mpd {
+        control
+            .listen_mpd(address)
+            .map_err(|e| anyhow::anyhow!("Couldn't listen on {}: {}", address, e))?;
+        println!("MPD protocol on {}", address);
+    }
+
+    let ui = match cli.no_tui {
+        true => None,
+        false => {
+            let mut ui = ui::UI::new(theme, keymap, config.clone())?;
+            if cli.mini {
+                ui.toggle_mini();
+            }
+            Some(ui)
+        }
+    };
 
     if !playlist.is_empty() {
-        let mut player = player::Player::new()?;
-        player.set_playlist(playlist, cli.random)?;
-        player.play_next()?;
-
-        let mut last_track = None;
-        let mut needs_redraw = true;
-        loop {
-            let current_track = player.get_current_track().cloned();
-            let current_position = player.get_current_position();
-            let total_duration = player.total_duration;
-
-            if needs_redraw {
-                let is_favorite = if let Some(ref track) = current_track {
-                    player.is_favorite(track)?
-                } else {
-                    false
+        let favorites_only = cli.favorites || session.as_ref().is_some_and(|session| session.favorites_only);
+        let filter = session.as_ref().and_then(|session| session.filter.clone());
+        let (write_play_counts, min_rating) = (cli.write_play_counts, cli.min_rating);
+        let (stream, setup_config) = (cli.stream.clone(), config.clone());
+        let (start_at, ramp, seed) = (cli.start_at.clone(), cli.ramp, cli.seed);
+        let mut playback = playback::Playback::start(events.sender(), move || {
+            let config = setup_config;
+            let mut player = player::Player::new(&config.library())?;
+            player.set_write_play_counts(write_play_counts);
+            player.set_volume(config.volume);
+            player.set_volume_step(config.volume_step);
+            if let Some(ramp) = ramp {
+                player.ramp_volume(ramp);
+            }
+            player.set_repeat(config.repeat);
+            if !config.paths.is_empty() {
+                let library_config = config.clone();
+                player.set_library(move || {
+                    scan_files(&library_config, &library_config.library()).unwrap_or_else(|e| {
+                        tracing::warn!("Couldn't scan the library for party mode: {}", e);
+                        Vec::new()
+                    })
+                });
+            }
+            player.set_party(config.party)?;
+            player.set_crossfade(Duration::from_secs_f32(config.crossfade));
+            player.set_crossfade_policy(crossfade_policy(&config));
+            player.set_remember_position(Duration::from_secs(config.remember_position as u64 * 60));
+            player.set_trash(config.trash);
+            if let Some(address) = &stream {
+                player
+                    .stream_to(address)
+                    .map_err(|e| anyhow::anyhow!("Couldn't listen on {}: {}", address, e))?;
+            }
+            if let Some(scrobbler) = scrobble::Scrobbler::start(&config)? {
+                player.set_scrobbler(scrobbler);
+            }
+            if let Some(cover_art) = config.cover_art.as_ref().filter(|cover_art| cover_art.enabled) {
+                player.set_cover_art(cover_art::CoverArt::start()?, cover_art.embed);
+            }
+            if let Some(min_rating) = min_rating {
+                playlist = player.filter_by_min_rating(playlist, min_rating);
+            }
+            if let Some(seed) = seed {
+                player.set_seed(seed);
+            }
+            player.set_shuffle_albums(config.shuffle_albums)?;
+            player.set_artist_spacing(config.artist_spacing as usize)?;
+            player.set_playlist(playlist, config.shuffle)?;
+            if let (Some(key), false) = (sort, config.shuffle) {
+                player.sort_playlist(key)?;
+            }
+            player.set_favorites_only(favorites_only)?;
+            // Lost rather than failing, when no track matches anymore.
+            let filter = filter.map(|filter| player::QueueFilter::parse(&filter));
+            if let Err(e) = filter.transpose().and_then(|filter| player.set_filter(filter)) {
+                tracing::warn!("Couldn't filter the playlist as in the last session: {}", e);
+            }
+            match &start_at {
+                Some(entry) => {
+                    let index = find_entry(player.playlist(), entry)
+                        .ok_or_else(|| anyhow::anyhow!("No playlist entry matches \"{}\"", entry))?;
+                    player.play_index(index)?;
+                }
+                None => resume_or_start(&mut player, config.resume)?,
+            }
+            Ok(player)
+        })?;
+        if config.pause_on_output_change {
+            playback.pause_on_output_change();
+        }
+        let media_keys = media_keys::MediaKeys::start(events.source(Event::MediaKey));
+        let listening = control.listen_socket();
+        let presence = config
+            .discord
+            .as_ref()
+            .filter(|discord| discord.enabled)
+            .map(|discord| discord::Presence::start(discord.client_id.clone()));
+        let terminal = ui.is_some().then(|| events.read_terminal());
+        let mut app = App {
+            view: playback::View::default(),
+            playback,
+            ticker: events.tick(FRAME_INTERVAL),
+            power: power::Power::default(),
+            ui,
+            terminal,
+            confirm_quit: config.confirm_quit,
+            scan,
+            media_keys,
+            presence,
+            hooks: hooks::Hooks::new(&config.hooks),
+            early_skips: config.early_skips,
+        };
+        app.refresh();
+        if let Some(address) = &cli.stream {
+            app.status(ui::StatusKind::Info, format!("Audio stream on http://{}/", address));
+        }
+        if let Err(e) = listening {
+            app.status(ui::StatusKind::Error, format!("Remote control unavailable: {}", e));
+        }
+        app.publish();
+        app.draw()?;
+        while let Some(event) = events.next() {
+            match app.handle(event)? {
+                Flow::Quit => break,
+                Flow::Redraw => {
+                    app.publish();
+                    app.draw()?;
+                }
+                Flow::Progress => {
+                    app.publish();
+                    if let Some(ui) = &mut app.ui {
+                        ui.draw_progress(&now_playing(&app.view))?;
+                    }
+                }
+                Flow::Unchanged => {}
+            }
+        }
+        if let Some(hooks) = &app.hooks {
+            let view = app.playback.view();
+            // There is no status line left to report a failure on.
+            if let Err(e) = hooks.stop(&media_status(&view), view.track.as_deref(), view.position) {
+                tracing::warn!("Couldn't run a hook: {}", e);
+            }
+        }
+    } else {
+        println!("No MP3 files found to play.");
+    }
+
+    Ok(())
+}
+
+/// The player and what shows it, changed by one event at a time.
+struct App {
+    playback: playback::Playback,
+    /// The player as of the last event.
+    view: playback::View,
+    ticker: events::Ticker,
+    power: power::Power,
+    /// `None` with `--no-tui`, and once detached.
+    ui: Option<ui::UI>,
+    /// Reads the keys of `ui`.
+    terminal: Option<events::TerminalReader>,
+    confirm_quit: bool,
+    /// Of the files enqueued from a directory.
+    scan: playlist::Scan,
+    media_keys: Option<media_keys::MediaKeys>,
+    presence: Option<discord::Presence>,
+    hooks: Option<hooks::Hooks>,
+    early_skips: config::EarlySkips,
+}
+
+/// What is left to do after an event.
+enum Flow {
+    Redraw,
+    /// Only the progress bar moved.
+    Progress,
+    Unchanged,
+    Quit,
+}
+
+impl App {
+    fn handle(&mut self, event: Event) -> Result<Flow> {
+        self.refresh();
+        // Nothing moves while paused but status messages, which can wait a
+        // little longer on battery. Without an interface, only what is told
+        // to the desktop and the hooks moves.
+        let interval = match self.ui.is_none() || !self.view.playing && self.power.on_battery() {
+            true => IDLE_INTERVAL,
+            false => FRAME_INTERVAL,
+        };
+        self.ticker.set_interval(interval);
+        match event {
+            Event::Terminal(event) => {
+                let Some(ui) = &mut self.ui else {
+                    return Ok(Flow::Unchanged);
                 };
-                ui.draw(
-                    current_track.as_ref(),
-                    player.get_current_metadata(),
-                    is_favorite,
-                    current_position,
-                    total_duration,
-                )?;
-                needs_redraw = false;
-                last_track = current_track.clone();
-                thread::sleep(Duration::from_millis(100));
-            }
-
-            if player.get_current_track() != last_track.as_ref() {
-                needs_redraw = true;
-            }
-
-            match ui.handle_input()? {
-                ui::UserAction::Quit => break,
-                ui::UserAction::PlayPause => {
-                    if player.is_playing() {
-                        player.pause();
-                        ui.set_playing(false);
-                    } else {
-                        player.play();
-                        ui.set_playing(true);
+                let action = ui.handle_event(event?);
+                self.handle_action(action)
+            }
+            Event::MediaKey(action) => self.handle_action(action),
+            Event::Interrupted => self.handle_action(ui::UserAction::QuitNow),
+            Event::Remote(request) if request.command == remote::Command::Quit => {
+                request.answer(Ok(serde_json::Value::Null));
+                self.handle_action(ui::UserAction::QuitNow)
+            }
+            Event::Remote(request) => {
+                self.remote(request);
+                Ok(Flow::Redraw)
+            }
+            Event::RemoteError(error) => {
+                self.status(ui::StatusKind::Error, error);
+                Ok(Flow::Redraw)
+            }
+            Event::Playback(update) => {
+                match update {
+                    playback::Update::Changed => {}
+                    playback::Update::Info(message) => self.status(ui::StatusKind::Info, message),
+                    playback::Update::Error(message) => self.status(ui::StatusKind::Error, message),
+                    playback::Update::Over => return Ok(Flow::Quit),
+                }
+                Ok(Flow::Redraw)
+            }
+            // A status message fades, then goes. The rest changes with
+            // events, but for the progress and level meters.
+            Event::Tick if self.ui.as_ref().is_some_and(|ui| ui.has_status()) => Ok(Flow::Redraw),
+            Event::Tick if self.view.playing => Ok(Flow::Progress),
+            Event::Tick => Ok(Flow::Unchanged),
+        }
+    }
+
+    /// Takes the latest view of the player. Without an interface, the
+    /// track starting is printed instead.
+    fn refresh(&mut self) {
+        let view = self.playback.view();
+        if let (None, Some(track)) = (&self.ui, &view.track) {
+            if self.view.track.as_ref() != Some(track) {
+                // Not `println!`, which panics once a detached player's
+                // terminal is closed.
+                let _ = writeln!(io::stdout(), "Playing {}", view.track_label(track));
+            }
+        }
+        self.view = view;
+    }
+
+    /// Shows `text` on the status line, or prints it without an interface.
+    fn status(&mut self, kind: ui::StatusKind, text: impl Into<String>) {
+        match (&mut self.ui, kind) {
+            (Some(ui), _) => ui.set_status(kind, text),
+            (None, ui::StatusKind::Info) => {
+                let _ = writeln!(io::stdout(), "{}", text.into());
+            }
+            (None, ui::StatusKind::Error) => {
+                let text = text.into();
+                tracing::warn!("{}", text);
+                let _ = writeln!(io::stderr(), "{}", text);
+            }
+        }
+    }
+
+    /// Closes the interface and gives the terminal back, playback going on:
+    /// the remote controls, the media keys and signals still reach it, but
+    /// for the hangup of the terminal, so that closing it doesn't stop the
+    /// music.
+    fn detach(&mut self) {
+        if let Some(terminal) = self.terminal.take() {
+            terminal.stop();
+        }
+        if self.ui.take().is_none() {
+            return;
+        }
+        #[cfg(unix)]
+        unsafe {
+            libc::signal(libc::SIGHUP, libc::SIG_IGN);
+        }
+        tracing::info!("Detached from the terminal");
+        println!("Detached: ksound keeps playing.");
+        println!("`ksound toggle`, `ksound next`, ... and the media keys control it, `ksound quit` stops it.");
+        println!("Ctrl-Z then `bg` gives this terminal back; closing it doesn't stop the music.");
+    }
+
+    /// Runs `job` on the player thread; what it says shows on the status line.
+    fn run(&self, job: impl FnOnce(&mut player::Player) -> Result<Option<String>> + Send + 'static) {
+        self.playback.run(job);
+    }
+
+    fn handle_action(&mut self, action: ui::UserAction) -> Result<Flow> {
+        if matches!(action, ui::UserAction::None) {
+            return Ok(Flow::Unchanged);
+        }
+        match action {
+            ui::UserAction::Quit if self.confirm_quit && self.view.playing => {
+                if let (Some(ui), Some(track)) = (&mut self.ui, &self.view.track) {
+                    ui.confirm_quit(self.view.track_label(track));
+                    return Ok(Flow::Redraw);
+                }
+                return self.handle_action(ui::UserAction::QuitNow);
+            }
+            ui::UserAction::Detach => self.detach(),
+            ui::UserAction::Quit | ui::UserAction::QuitNow => {
+                self.playback
+                    .call(|player| -> Result<()> {
+                        player.finish_listen()?;
+                        save_session(player);
+                        Ok(())
+                    })
+                    .transpose()?;
+                return Ok(Flow::Quit);
+            }
+            ui::UserAction::PlayPause => self.run(|player| {
+                toggle(player);
+                Ok(None)
+            }),
+            ui::UserAction::Next => {
+                let left = match self.playback.call(|player| player.record_early_skip()) {
+                    Some(Ok(left)) => left,
+                    Some(Err(e)) => {
+                        self.status(ui::StatusKind::Error, format!("Couldn't log the skip: {}", e));
+                        None
+                    }
+                    None => None,
+                };
+                // Offered again after as many more early skips.
+                let threshold = self.early_skips.threshold;
+                let offered = left.filter(|&(_, count)| threshold > 0 && count % threshold == 0);
+                match offered {
+                    Some((track, count)) if self.early_skips.automatic => self.run(move |player| {
+                        let label = player.track_label(&track);
+                        player.skip_track(&track, &left_early(count))?;
+                        player.skip_to_next()?;
+                        Ok(Some(format!("{} {}: added to the skip list", label, left_early(count))))
+                    }),
+                    offered => {
+                        self.run(|player| {
+                            player.skip_to_next()?;
+                            Ok(None)
+                        });
+                        if let (Some(ui), Some((track, count))) = (&mut self.ui, offered) {
+                            ui.confirm_skip(&track, self.view.track_label(&track), Some(count));
+                        }
                     }
-                    needs_redraw = true;
                 }
-                ui::UserAction::Next => {
-                    player.play_next()?;
-                    needs_redraw = true;
+            }
+            ui::UserAction::Previous => self.run(|player| {
+                player.play_previous()?;
+                Ok(None)
+            }),
+            // Formats that cannot seek just keep playing.
+            ui::UserAction::SeekForward => self.run(|player| {
+                player.seek_by(SEEK_STEP_SECS)?;
+                Ok(None)
+            }),
+            ui::UserAction::SeekBackward => self.run(|player| {
+                player.seek_by(-SEEK_STEP_SECS)?;
+                Ok(None)
+            }),
+            ui::UserAction::NextChapter => self.run(|player| {
+                Ok(Some(match player.next_chapter()? {
+                    Some(chapter) => chapter.title,
+                    None if player.chapters().is_empty() => "This track has no chapters".to_string(),
+                    None => "This is the last chapter".to_string(),
+                }))
+            }),
+            ui::UserAction::PreviousChapter => self.run(|player| {
+                Ok(Some(match player.previous_chapter()? {
+                    Some(chapter) => chapter.title,
+                    None => "This track has no chapters".to_string(),
+                }))
+            }),
+            // If the config cannot be written, the layout still holds for this session.
+            ui::UserAction::TogglePane(pane) => {
+                if let Some(ui) = &mut self.ui {
+                    ui.toggle_pane(pane);
+                    save_layout(ui);
                 }
-                ui::UserAction::Previous => {
-                    player.play_previous()?;
-                    needs_redraw = true;
+            }
+            ui::UserAction::ResizeNowPlaying(delta) => {
+                if let Some(ui) = &mut self.ui {
+                    ui.resize_now_playing(delta);
+                    save_layout(ui);
                 }
-                ui::UserAction::VolumeUp => {
-                    player.increase_volume();
-                    needs_redraw = true;
+            }
+            ui::UserAction::ToggleTimeDisplay => {
+                if let Some(ui) = &mut self.ui {
+                    ui.toggle_time_display();
+                    save_layout(ui);
                 }
-                ui::UserAction::VolumeDown => {
-                    player.decrease_volume();
-                    needs_redraw = true;
+            }
+            ui::UserAction::ToggleMini => {
+                if let Some(ui) = &mut self.ui {
+                    ui.toggle_mini();
                 }
-                ui::UserAction::MarkSkip => {
+            }
+            ui::UserAction::VolumeUp => self.run(|player| {
+                player.increase_volume();
+                Ok(None)
+            }),
+            ui::UserAction::VolumeDown => self.run(|player| {
+                player.decrease_volume();
+                Ok(None)
+            }),
+            ui::UserAction::MarkSkip => match (&mut self.ui, &self.view.track) {
+                (Some(ui), Some(track)) => ui.confirm_skip(track, self.view.track_label(track), None),
+                _ => self.run(|player| {
+                    let Some(track) = player.get_current_track() else {
+                        return Ok(None);
+                    };
+                    let message = format!("{} will be skipped from now on", player.track_label(track));
                     player.mark_skip()?;
-                    needs_redraw = true;
-                }
-                ui::UserAction::Delete => {
-                    player.pause();
-                    if let Some(track) = player.get_current_track() {
-                        if ui.confirm_deletion(track)? {
-                            player.delete_current_track()?;
-                            player.play_next()?;
-                        } else {
-                            player.play();
+                    Ok(Some(message))
+                }),
+            },
+            ui::UserAction::Undo => self.run(|player| {
+                Ok(Some(player.undo()?.unwrap_or_else(|| "Nothing to undo".to_string())))
+            }),
+            ui::UserAction::Ban => {
+                if let (Some(ui), Some(track)) = (&mut self.ui, &self.view.track) {
+                    let metadata = self.view.metadata.as_ref();
+                    ui.confirm_ban(
+                        self.view.track_label(track),
+                        metadata.and_then(|metadata| metadata.artist.clone()),
+                        metadata.and_then(|metadata| metadata.album.clone()),
+                    );
+                }
+            }
+            ui::UserAction::BanConfirmed { artist, album } => self.run(move |player| {
+                let (banned, count) = player.ban(artist.as_deref(), album.as_deref())?;
+                Ok(Some(match count {
+                    1 => format!("Banned {}: 1 track taken out of the playlist", banned),
+                    _ => format!("Banned {}: {} tracks taken out of the playlist", banned, count),
+                }))
+            }),
+            ui::UserAction::SkipForNow => self.run(|player| {
+                let Some(track) = player.get_current_track() else {
+                    return Ok(None);
+                };
+                let message = format!("{} skipped until quitting", player.track_label(track));
+                player.drop_current()?;
+                Ok(Some(message))
+            }),
+            // Paused while the confirmation is open.
+            ui::UserAction::Delete => {
+                if let (Some(ui), Some(track)) = (&mut self.ui, &self.view.track) {
+                    if self.view.is_favorite && ui.config().protect_favorites {
+                        let label = self.view.track_label(track);
+                        let message = format!("{} is a favorite, kept by protect_favorites", label);
+                        ui.set_status(ui::StatusKind::Error, message);
+                        return Ok(Flow::Redraw);
+                    }
+                    ui.confirm_deletion(track, self.view.is_favorite);
+                    self.run(|player| {
+                        player.pause();
+                        Ok(None)
+                    });
+                }
+            }
+            ui::UserAction::DeleteConfirmed(track, trash) => self.run(move |player| {
+                if player.get_current_track() != Some(&track) {
+                    return Ok(None);
+                }
+                let message = match trash {
+                    true => format!("Moved {} to the trash", player.track_label(&track)),
+                    false => format!("Deleted {}", player.track_label(&track)),
+                };
+                player.delete_current_track()?;
+                player.play_next()?;
+                Ok(Some(message))
+            }),
+            ui::UserAction::SkipConfirmed(track, count) => self.run(move |player| {
+                let label = player.track_label(&track);
+                match count {
+                    Some(count) => player.skip_track(&track, &left_early(count))?,
+                    // Moves on at once if it is still playing.
+                    None if player.get_current_track() == Some(&track) => player.mark_skip()?,
+                    None => player.skip_track(&track, "skipped from the player")?,
+                }
+                Ok(Some(format!("{} added to the skip list", label)))
+            }),
+            ui::UserAction::Move => {
+                if let (Some(ui), Some(track)) = (&mut self.ui, &self.view.track) {
+                    ui.choose_folder(track, self.view.track_label(track));
+                }
+            }
+            ui::UserAction::MoveConfirmed(track, folder) => self.run(move |player| {
+                let label = player.track_label(&track);
+                player.move_track(&track, &folder)?;
+                Ok(Some(format!("Moved {} to {}", label, folder.display())))
+            }),
+            ui::UserAction::Rename => {
+                if let (Some(ui), Some(track)) = (&mut self.ui, &self.view.track) {
+                    ui.rename_file(track);
+                }
+            }
+            ui::UserAction::RenameConfirmed(track, name) => self.run(move |player| {
+                let destination = player.rename_track(&track, &name)?;
+                let name = |path: &Path| path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                Ok(Some(format!("Renamed {} to {}", name(&track), name(&destination))))
+            }),
+            // Opened again after a removal, for the next one.
+            ui::UserAction::Bookmarks => {
+                if let (Some(ui), Some(track)) = (&mut self.ui, &self.view.track) {
+                    let listed = self.playback.call({
+                        let track = track.clone();
+                        move |player| player.bookmarks(&track)
+                    });
+                    let position = self.view.position.unwrap_or_default();
+                    match listed {
+                        Some(Ok(bookmarks)) => {
+                            ui.show_bookmarks(track, self.view.track_label(track), position, bookmarks)
                         }
+                        Some(Err(e)) => {
+                            let message = format!("Couldn't read the bookmarks: {:#}", e);
+                            ui.set_status(ui::StatusKind::Error, message)
+                        }
+                        None => {}
                     }
-                    needs_redraw = true;
-                }
-                ui::UserAction::MarkFavorite => {
-                    player.mark_favorite()?;
-                    ui.draw(
-                        current_track.as_ref(),
-                        player.get_current_metadata(),
-                        true,
-                        current_position,
-                        total_duration,
-                    )?;
-                }
-                ui::UserAction::EditTags => {
-                    // Clone les infos nécessaires AVANT tout appel à UI
-                    let (track, meta) = {
-                        let track = player.get_current_track().cloned();
-                        let meta = player.get_current_metadata().cloned();
-                        (track, meta)
+                }
+            }
+            ui::UserAction::AddBookmark(track, bookmark) => self.run(move |player| {
+                player.add_bookmark(&track, &bookmark)?;
+                Ok(Some(format!("Bookmarked \"{}\"", bookmark.name)))
+            }),
+            ui::UserAction::JumpToBookmark(track, bookmark) => self.run(move |player| {
+                if player.get_current_track() != Some(&track) {
+                    return Ok(None);
+                }
+                player.seek_to(bookmark.position)?;
+                Ok(Some(format!("Back to \"{}\"", bookmark.name)))
+            }),
+            ui::UserAction::RemoveBookmark(track, name) => {
+                let removed = self.playback.call(move |player| player.remove_bookmark(&track, &name));
+                if let Some(Err(e)) = removed {
+                    self.status(ui::StatusKind::Error, format!("Couldn't remove the bookmark: {:#}", e));
+                }
+                return self.handle_action(ui::UserAction::Bookmarks);
+            }
+            ui::UserAction::DeleteCancelled => self.run(|player| {
+                player.play();
+                Ok(None)
+            }),
+            ui::UserAction::MarkFavorite => self.run(|player| {
+                player.mark_favorite()?;
+                let Some(track) = player.get_current_track() else {
+                    return Ok(None);
+                };
+                let message = if player.is_favorite(track)? {
+                    "Added to favorites"
+                } else {
+                    "Removed from favorites"
+                };
+                Ok(Some(message.to_string()))
+            }),
+            ui::UserAction::ToggleFavoritesOnly => self.run(|player| {
+                let on = !player.favorites_only();
+                player.set_favorites_only(on)?;
+                let message = if on {
+                    "Playing only the favorites"
+                } else {
+                    "Playing the whole playlist"
+                };
+                Ok(Some(message.to_string()))
+            }),
+            ui::UserAction::ToggleStopAfterCurrent => self.run(|player| {
+                let on = !player.stop_after_current();
+                player.set_stop_after_current(on);
+                let message = match on {
+                    true => "Stopping after this track",
+                    false => "Playing on after this track",
+                };
+                Ok(Some(message.to_string()))
+            }),
+            ui::UserAction::ReshuffleRest => self.run(|player| {
+                if !player.shuffle() {
+                    return Ok(Some("Shuffle is off".to_string()));
+                }
+                player.reshuffle_rest()?;
+                Ok(Some("Shuffled the rest of the queue".to_string()))
+            }),
+            ui::UserAction::EditTags => {
+                if let (Some(ui), Some(track)) = (&mut self.ui, &self.view.track) {
+                    ui.edit_tags_form(track, self.view.metadata.as_ref());
+                }
+            }
+            ui::UserAction::SaveTags(track, (artist, album, title, year)) => self.run(move |player| {
+                let changed = artist.is_some() || album.is_some() || title.is_some() || year.is_some();
+                player.edit_tags(&track, artist, album, title, year)?;
+                // Reload the tags shown if the track is still playing
+                if player.get_current_track() == Some(&track) {
+                    player.current_metadata = player::TrackMetadata::from_path(&track);
+                }
+                Ok(changed.then(|| "Tags saved".to_string()))
+            }),
+            ui::UserAction::Rate(stars) => self.run(move |player| {
+                player.set_rating(stars)?;
+                Ok(Some(match stars {
+                    0 => "Rating cleared".to_string(),
+                    _ => format!("Rated {}", "★".repeat(stars as usize)),
+                }))
+            }),
+            ui::UserAction::FixEncoding => {
+                if let (Some(ui), Some(track)) = (&mut self.ui, &self.view.track) {
+                    let metadata = self.view.metadata.as_ref();
+                    let (encoding, fixes) = match metadata.and_then(|meta| meta.encoding_fixes()) {
+                        Some((encoding, fixes)) => (Some(encoding), fixes),
+                        None => (None, Vec::new()),
+                    };
+                    ui.confirm_encoding_fix(track, encoding, fixes);
+                }
+            }
+            ui::UserAction::FixEncodingConfirmed(track, fixes) => self.run(move |player| {
+                player.apply_encoding_fixes(&track, &fixes)?;
+                if player.get_current_track() == Some(&track) {
+                    player.current_metadata = player::TrackMetadata::from_path(&track);
+                }
+                Ok(Some("Tags rewritten as UTF-8".to_string()))
+            }),
+            ui::UserAction::Search => {
+                if let Some(ui) = &mut self.ui {
+                    let (indexes, entries) = search_entries(&self.view);
+                    ui.search_tracks(indexes, entries);
+                }
+            }
+            ui::UserAction::Filter => {
+                if let Some(ui) = &mut self.ui {
+                    ui.ask_filter(self.view.filter.clone());
+                }
+            }
+            ui::UserAction::FilterConfirmed(text) => self.run(move |player| {
+                if text.trim().is_empty() {
+                    player.set_filter(None)?;
+                    return Ok(Some("Playing every track".to_string()));
+                }
+                let filter = player::QueueFilter::parse(&text)?;
+                player.set_filter(Some(filter))?;
+                Ok(Some(format!("{} of {} tracks match", player.playlist().len(), player.all_tracks().len())))
+            }),
+            ui::UserAction::ClearFilter => self.run(|player| {
+                if player.filter().is_none() {
+                    return Ok(Some("No filter".to_string()));
+                }
+                player.set_filter(None)?;
+                Ok(Some("Playing every track".to_string()))
+            }),
+            ui::UserAction::Help => {
+                if let Some(ui) = &mut self.ui {
+                    ui.show_help();
+                }
+            }
+            ui::UserAction::EditSetting(setting) => {
+                if let Some(ui) = &mut self.ui {
+                    ui.edit_setting(setting);
+                }
+            }
+            ui::UserAction::ChangeSetting(setting, text) => {
+                if let Some(ui) = &mut self.ui {
+                    change_setting(ui, &self.playback, setting, text.as_deref());
+                }
+            }
+            ui::UserAction::PlayTrack(index) => self.run(move |player| {
+                player.play_index(index)?;
+                Ok(None)
+            }),
+            ui::UserAction::PlayFile(track) => self.run(move |player| {
+                player.play_track(&track)?;
+                Ok(None)
+            }),
+            ui::UserAction::PlayUpcoming(track) => self.run(move |player| {
+                player.play_upcoming(&track)?;
+                Ok(None)
+            }),
+            ui::UserAction::PlayNext(track) => self.run(move |player| {
+                if !track.is_file() {
+                    anyhow::bail!("{}: not found", track.display());
+                }
+                let message = match player.play_after_current(&track)? {
+                    false => "In the skip list",
+                    true => "Playing next",
+                };
+                Ok(Some(message.to_string()))
+            }),
+            ui::UserAction::Enqueue(track) => self.run(move |player| {
+                if !track.is_file() {
+                    anyhow::bail!("{}: not found", track.display());
+                }
+                let message = match player.enqueue(vec![track])? {
+                    0 => "In the skip list",
+                    _ => "Added to the playlist",
+                };
+                Ok(Some(message.to_string()))
+            }),
+            ui::UserAction::RemoveFavorite(id) => self.run(move |player| {
+                player.remove_favorite(&id)?;
+                Ok(Some("Removed from favorites".to_string()))
+            }),
+            ui::UserAction::Unskip(id) => self.run(move |player| {
+                player.unskip(&id)?;
+                Ok(Some("Removed from the skip list".to_string()))
+            }),
+            ui::UserAction::SortPlaylist(key) => self.run(move |player| {
+                player.sort_playlist(key)?;
+                Ok(Some(format!("Playlist sorted by {}", key.name())))
+            }),
+            _ => {}
+        }
+        Ok(Flow::Redraw)
+    }
+
+    /// Answers what the view knows right away, and hands the rest to the
+    /// player thread, which answers once done.
+    fn remote(&self, request: remote::Request) {
+        tracing::debug!("Remote command: {:?}", request.command);
+        match request.command {
+            remote::Command::Status => request.answer(Ok(remote_status(&self.view))),
+            remote::Command::Queue => {
+                let upcoming: Vec<_> = self
+                    .view
+                    .upcoming
+                    .iter()
+                    .take(REMOTE_QUEUE_LENGTH)
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .collect();
+                request.answer(Ok(serde_json::json!({ "upcoming": upcoming })));
+            }
+            remote::Command::Playlist => request.answer(Ok(remote_playlist(&self.view))),
+            _ => {
+                let scan = self.scan.clone();
+                self.run(move |player| {
+                    let reply = remote_command(&request.command, player, &scan);
+                    request.answer(reply);
+                    Ok(None)
+                });
+            }
+        }
+    }
+
+    /// Tells the desktop, Discord and the hooks what is playing.
+    fn publish(&mut self) {
+        let status = media_status(&self.view);
+        let position = self.view.position;
+        if let Some(presence) = &mut self.presence {
+            presence.update(&status, position);
+        }
+        if let Some(hooks) = &mut self.hooks {
+            if let Err(e) = hooks.update(&status, self.view.track.as_deref(), position) {
+                let message = format!("Couldn't run a hook: {}", e);
+                self.status(ui::StatusKind::Error, message);
+            }
+        }
+        if let Some(media_keys) = &self.media_keys {
+            media_keys.update(status);
+        }
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        if let Some(ui) = &mut self.ui {
+            ui.draw(&now_playing(&self.view))?;
+        }
+        Ok(())
+    }
+}
+
+fn run_command(command: Command, config: &config::Config) -> Result<()> {
+    match command {
+        Command::Tags {
+            action: TagsCommand::Strip { paths, links, dry_run, yes },
+        } => {
+            // ID3 frames only exist in MP3 files.
+            let mut files = Vec::new();
+            for path in &paths {
+                files.extend(playlist::from_path(path, &["mp3".to_string()])?);
+            }
+            tags::strip(&files, links, dry_run, || Ok(yes || ask_confirmation("Strip these frames?")?))
+        }
+        Command::Lint { paths, json } => {
+            let scan = playlist::Scan::new(&config.extensions).excluding(&config.exclude)?;
+            let mut files = Vec::new();
+            for path in &paths {
+                files.extend(scan.files(path)?);
+            }
+            let issues = tags::lint(&files);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&issues)?);
+            } else {
+                tags::print_lint_report(&files, &issues);
+            }
+            Ok(())
+        }
+        Command::Keys => {
+            let preset = ui::Keymap::preset(config.keymap.as_deref().unwrap_or("default"))?;
+            let (keymap, report) = preset.check_overrides(&config.keys);
+            keymap.print();
+            report.print();
+            if !report.errors.is_empty() {
+                anyhow::bail!("{} invalid entries in [keys]", report.errors.len());
+            }
+            Ok(())
+        }
+        Command::History {
+            action: HistoryCommand::Export { format, since },
+        } => {
+            let events = history::events(&config.library(), since.unwrap_or(0))?;
+            match format {
+                HistoryFormat::Csv => print!("{}", history::to_csv(&events)),
+                HistoryFormat::Json => println!("{}", serde_json::to_string_pretty(&events)?),
+            }
+            Ok(())
+        }
+        Command::Skiplist {
+            action: SkiplistCommand::List { json },
+        } => {
+            let store = config::TrackStore::skip_list(&config.library())?;
+            let bans = config::Bans::open()?;
+            if json {
+                let list = serde_json::json!({ "tracks": store.entries(), "bans": bans.entries() });
+                println!("{}", serde_json::to_string_pretty(&list)?);
+                return Ok(());
+            }
+            if store.entries().is_empty() && bans.is_empty() {
+                println!("The skip list is empty.");
+            }
+            for entry in store.entries() {
+                let missing = if Path::new(&entry.path).is_file() { "" } else { " [missing]" };
+                println!(
+                    "{}  {}  {}{}  ({})",
+                    entry.id,
+                    history::format_time(entry.added),
+                    entry.path,
+                    missing,
+                    entry.reason
+                );
+            }
+            for ban in bans.entries() {
+                println!("{}  {}  banned: {}", ban.id, history::format_time(ban.added), ban.describe());
+            }
+            Ok(())
+        }
+        Command::Skiplist {
+            action: SkiplistCommand::Remove { entries },
+        } => {
+            let mut store = config::TrackStore::skip_list(&config.library())?;
+            let mut bans = config::Bans::open()?;
+            let mut unknown = 0;
+            for name in &entries {
+                if bans.remove_id(name)? {
+                    println!("Lifted the ban {}", name);
+                    continue;
+                }
+                // Entries whose file is gone can only be found by what they hold.
+                let id = store
+                    .entries()
+                    .iter()
+                    .find(|entry| entry.id == *name || entry.path == *name)
+                    .map(|entry| entry.id.clone());
+                match id {
+                    Some(id) => store.remove_id(&id)?,
+                    None if store.contains(Path::new(name))? => {
+                        store.remove(Path::new(name))?;
+                    }
+                    None => {
+                        eprintln!("{}: not in the skip list", name);
+                        unknown += 1;
+                        continue;
+                    }
+                }
+                println!("Removed {}", name);
+            }
+            if unknown > 0 {
+                anyhow::bail!("{} of {} entries not found", unknown, entries.len());
+            }
+            Ok(())
+        }
+        Command::Skiplist {
+            action: SkiplistCommand::Ban { artist, album },
+        } => {
+            let mut bans = config::Bans::open()?;
+            match bans.add(artist.as_deref(), album.as_deref())? {
+                Some(ban) => println!("Banned {} ({})", ban.describe(), ban.id),
+                None => println!("Already banned"),
+            }
+            Ok(())
+        }
+        Command::Sync {
+            action: SyncCommand::Export { file },
+        } => {
+            let library = config.library();
+            let bundle = ksound::sync::export(&library, &scan_files(config, &library)?)?;
+            let content = bundle.to_toml()?;
+            match file.as_str() {
+                "-" => print!("{}", content),
+                _ => {
+                    std::fs::write(&file, content)?;
+                    println!(
+                        "{} favorites, {} skipped tracks, {} bans, {} ratings{} written to {}",
+                        bundle.favorites.len(),
+                        bundle.skipped.len(),
+                        bundle.bans.len(),
+                        bundle.ratings.len(),
+                        if bundle.session.is_some() { " and the resume position" } else { "" },
+                        file
+                    );
+                }
+            }
+            Ok(())
+        }
+        Command::Sync {
+            action: SyncCommand::Import { file },
+        } => {
+            let content = match file.as_str() {
+                "-" => io::read_to_string(io::stdin())?,
+                _ => std::fs::read_to_string(&file)?,
+            };
+            let bundle = ksound::sync::Bundle::parse(&content).map_err(|e| anyhow::anyhow!("{}: {}", file, e))?;
+            let report = ksound::sync::import(bundle, &config.library())?;
+            println!("Favorites: {} added", report.favorites);
+            println!("Skip list: {} added", report.skipped);
+            println!("Bans: {} added", report.bans);
+            println!(
+                "Ratings: {} set, {} already rated here, {} tracks not found",
+                report.ratings, report.ratings_kept, report.ratings_missing
+            );
+            if report.session {
+                println!("Resume position: taken, being newer than this machine's");
+            }
+            Ok(())
+        }
+        Command::Relink { paths, dry_run } => {
+            let library = config.library();
+            let search: Vec<PathBuf> = if paths.is_empty() {
+                library.clone()
+            } else {
+                paths.iter().map(PathBuf::from).collect()
+            };
+            if search.is_empty() {
+                anyhow::bail!("No folder to search: give some, or set `paths` in the config file");
+            }
+            let files = scan_files(config, &search)?;
+
+            let mut cache = cache::MetadataCache::new()?;
+            let mut tags = |path: &Path| {
+                cache.metadata(path).map(|metadata| config::TagSnapshot {
+                    artist: metadata.artist,
+                    album: metadata.album,
+                    title: metadata.title,
+                })
+            };
+            let stores = [
+                ("Favorites", config::TrackStore::favorites(&library)?),
+                ("Skipped tracks", config::TrackStore::skip_list(&library)?),
+            ];
+            for (name, mut store) in stores {
+                let report = store.relink(&files, &mut tags, dry_run)?;
+                println!(
+                    "{}: {} relinked, {} merged, {} not found, {} completed",
+                    name,
+                    report.relinked.len(),
+                    report.merged.len(),
+                    report.missing.len(),
+                    report.completed
+                );
+                for (old, new, how) in &report.relinked {
+                    println!("  {} -> {} (by {})", old, new, how.name());
+                }
+                for old in &report.merged {
+                    println!("  {}: already listed under its new path, dropped", old);
+                }
+                for old in &report.missing {
+                    println!("  {}: not found", old);
+                }
+            }
+            cache.save()?;
+            if dry_run {
+                println!("Dry run: nothing written.");
+            }
+            Ok(())
+        }
+        Command::Copy {
+            destination,
+            paths,
+            playlist,
+            favorites,
+            min_rating,
+            flatten,
+            transcode,
+            bitrate,
+            dry_run,
+        } => {
+            let library = config.library();
+            let (tracks, roots) = match &playlist {
+                Some(playlist) => (playlist::from_file(playlist)?, library.clone()),
+                None => {
+                    let roots: Vec<PathBuf> = match &paths[..] {
+                        [] => library.clone(),
+                        paths => paths.iter().map(PathBuf::from).collect(),
                     };
-                    if let Some(track) = track {
-                        let (artist, album, title, year) = ui.edit_tags_form(&track, meta.as_ref())?;
-                        player.edit_tags(
-                            &track,
-                            artist,
-                            album,
-                            title,
-                            year,
-                        )?;
-                        // Recharge les métadonnées à jour après édition
-                        let new_metadata = player::TrackMetadata::from_path(&track);
-                        player.current_metadata = new_metadata;
-                        // Redessine l'UI avec les nouvelles valeurs
-                        let is_favorite = player.is_favorite(&track)?;
-                        let current_position = player.get_current_position();
-                        let total_duration = player.total_duration;
-                        ui.draw(
-                            Some(&track),
-                            player.current_metadata.as_ref(),
-                            is_favorite,
-                            current_position,
-                            total_duration,
-                        )?;
-                        needs_redraw = false; // L'UI vient d'être redessinée
+                    if roots.is_empty() {
+                        anyhow::bail!("Nothing to copy: give folders or a playlist, or set `paths` in the config");
                     }
+                    (scan_files(config, &roots)?, roots)
                 }
-                _ => {}
+            };
+
+            // Left out like when playing them.
+            let skip_list = config::TrackStore::skip_list(&library)?;
+            let favorite_list = config::TrackStore::favorites(&library)?;
+            let bans = config::Bans::open()?;
+            let mut cache = cache::MetadataCache::new()?;
+            if min_rating.is_some() || !bans.is_empty() {
+                cache.preload(&tracks);
             }
+            let count = tracks.len();
+            let tracks: Vec<PathBuf> = tracks
+                .into_iter()
+                .filter(|track| {
+                    if skip_list.contains(track).unwrap_or(false)
+                        || favorites && !favorite_list.contains(track).unwrap_or(false)
+                    {
+                        return false;
+                    }
+                    if min_rating.is_none() && bans.is_empty() {
+                        return true;
+                    }
+                    let metadata = cache.metadata(track);
+                    let metadata = metadata.as_ref();
+                    let rating = metadata.and_then(|metadata| metadata.rating);
+                    let (artist, album) = (
+                        metadata.and_then(|metadata| metadata.artist.as_deref()),
+                        metadata.and_then(|metadata| metadata.album.as_deref()),
+                    );
+                    min_rating.is_none_or(|min_rating| rating.is_some_and(|rating| rating >= min_rating))
+                        && bans.matching(artist, album).is_none()
+                })
+                .collect();
+            cache.save()?;
 
-            let continue_playback = player.handle_playback()?;
-            if !continue_playback {
-                break;
+            let transcode = transcode.map(|format| ksound::copy::Transcode { format, bitrate });
+            let items = ksound::copy::plan(&tracks, &roots, &destination, flatten, transcode.as_ref());
+            println!(
+                "{} of {} tracks to copy to {}",
+                items.len(),
+                count,
+                destination.display()
+            );
+            if dry_run {
+                for item in &items {
+                    let how = if item.transcoded { "convert" } else { "copy" };
+                    println!("  {} {} -> {}", how, item.source.display(), item.destination.display());
+                }
+                println!("Dry run: nothing written.");
+                return Ok(());
             }
 
-            // Refresh the progress bar
-            if player.is_playing() {
-                let current_position = player.get_current_position();
-                let total_duration = player.total_duration;
-                ui.draw(
-                    current_track.as_ref(),
-                    player.get_current_metadata(),
-                    player.is_favorite(current_track.as_ref().unwrap_or(&PathBuf::new()))?,
-                    current_position,
-                    total_duration,
-                )?;
+            let (mut copied, mut transcoded, mut unchanged, mut failed) = (0, 0, 0, 0);
+            ksound::copy::run(&items, transcode.as_ref(), |item, outcome| {
+                let done = copied + transcoded + unchanged + failed + 1;
+                let name = item.destination.strip_prefix(&destination).unwrap_or(&item.destination);
+                match outcome {
+                    ksound::copy::Outcome::Copied => copied += 1,
+                    ksound::copy::Outcome::Transcoded => transcoded += 1,
+                    ksound::copy::Outcome::Unchanged => {
+                        unchanged += 1;
+                        return;
+                    }
+                    ksound::copy::Outcome::Failed(e) => {
+                        failed += 1;
+                        eprintln!("{}: {}", item.source.display(), e);
+                        return;
+                    }
+                }
+                println!("[{}/{}] {}", done, items.len(), name.display());
+            })?;
+            println!(
+                "{} copied, {} converted, {} already there, {} failed",
+                copied, transcoded, unchanged, failed
+            );
+            match failed {
+                0 => Ok(()),
+                1 => anyhow::bail!("1 track couldn't be copied"),
+                _ => anyhow::bail!("{} tracks couldn't be copied", failed),
             }
         }
-    } else {
-        println!("No MP3 files found to play.");
+        Command::Organize {
+            folder,
+            pattern,
+            dry_run,
+        } => {
+            let pattern = ksound::organize::Pattern::parse(&pattern)?;
+            if !folder.is_dir() {
+                anyhow::bail!("{}: not a folder", folder.display());
+            }
+            // Like the paths of the tracks found.
+            let folder = folder.canonicalize()?;
+            let tracks = scan_files(config, std::slice::from_ref(&folder))?;
+            let mut cache = cache::MetadataCache::new()?;
+            cache.preload(&tracks);
+            let items = ksound::organize::plan(&tracks, &folder, &pattern, |track| cache.metadata(track));
+            cache.save()?;
+            println!(
+                "{} of {} tracks to move in {}",
+                items.len(),
+                tracks.len(),
+                folder.display()
+            );
+            let name = |path: &Path| path.strip_prefix(&folder).unwrap_or(path).display().to_string();
+            if dry_run {
+                for item in &items {
+                    println!("  {} -> {}", name(&item.source), name(&item.destination));
+                }
+                println!("Dry run: nothing written.");
+                return Ok(());
+            }
+
+            let (mut moved, mut failed) = (0, 0);
+            ksound::organize::run(&items, &folder, &config.library(), |item, result| match result {
+                Ok(()) => {
+                    moved += 1;
+                    println!("[{}/{}] {}", moved + failed, items.len(), name(&item.destination));
+                }
+                Err(e) => {
+                    failed += 1;
+                    eprintln!("{}: {}", item.source.display(), e);
+                }
+            })?;
+            println!(
+                "{} moved, {} already in place, {} failed",
+                moved,
+                tracks.len() - items.len(),
+                failed
+            );
+            match failed {
+                0 => Ok(()),
+                1 => anyhow::bail!("1 track couldn't be moved"),
+                _ => anyhow::bail!("{} tracks couldn't be moved", failed),
+            }
+        }
+        Command::Quit => remote_print("quit"),
+        Command::Play => remote_print("play"),
+        Command::Pause => remote_print("pause"),
+        Command::Toggle => remote_print("toggle"),
+        Command::Next => remote_print("next"),
+        Command::Prev => remote_print("prev"),
+        Command::Status => remote_print("status"),
+        Command::Queue => remote_print("queue"),
+        Command::Volume { percent } => remote_print(&format!("volume {}", percent)),
+        Command::Enqueue { paths } => {
+            // The player doesn't share our working directory.
+            for path in paths {
+                let path = path
+                    .canonicalize()
+                    .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+                remote_print(&format!("enqueue {}", path.display()))?;
+            }
+            Ok(())
+        }
     }
+}
 
+/// The files under `folders` that a scan with the extensions and exclude
+/// patterns of `config` picks up, as canonical paths.
+fn scan_files(config: &config::Config, folders: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let scan = playlist::Scan::new(&config.extensions).excluding(&config.exclude)?;
+    let mut files = Vec::new();
+    for folder in folders {
+        files.extend(scan.files(&folder.to_string_lossy())?);
+    }
+    Ok(files.into_iter().filter_map(|file| file.canonicalize().ok()).collect())
+}
+
+/// Sends `command` to the running player and prints its answer.
+fn remote_print(command: &str) -> Result<()> {
+    let answer = remote::send(command)?;
+    if !answer.is_empty() {
+        println!("{}", answer);
+    }
     Ok(())
 }
 
-fn load_playlist_from_file(path: &str) -> Result<Vec<PathBuf>> {
-    let content = fs::read_to_string(path)?;
-    Ok(content
-        .lines()
-        .filter(|line| !line.trim().is_empty())
-        .map(|line| PathBuf::from(line.trim()))
-        .collect())
+/// Index of the entry `entry` names in `playlist`: its index, or the first
+/// whose file name contains it, whatever the case.
+fn find_entry(playlist: &[PathBuf], entry: &str) -> Option<usize> {
+    if let Ok(index) = entry.parse::<usize>() {
+        return (index < playlist.len()).then_some(index);
+    }
+    let wanted = entry.to_lowercase();
+    playlist.iter().position(|path| {
+        path.file_name()
+            .is_some_and(|name| name.to_string_lossy().to_lowercase().contains(&wanted))
+    })
 }
 
-fn create_playlist_from_path(path: &str) -> Result<Vec<PathBuf>> {
-    let mut playlist = Vec::new();
+/// Starts with the track playing at the last quit, where it was, if
+/// `resume` is set and the track is still in the playlist.
+fn resume_or_start(player: &mut player::Player, resume: bool) -> Result<()> {
+    let session = resume.then(config::Session::load).flatten();
+    let resumed = session.and_then(|session| {
+        let index = player.playlist().iter().position(|path| *path == session.track)?;
+        Some((index, session.position))
+    });
+    match resumed {
+        Some((index, position)) => {
+            player.play_index(index)?;
+            // Formats that cannot seek start over.
+            if let Err(e) = player.seek_to(Duration::from_secs_f64(position)) {
+                tracing::info!("Couldn't resume at {:.0}s: {:#}", position, e);
+            }
+            Ok(())
+        }
+        None => player.play_next(),
+    }
+}
 
-    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if path.is_file() {
-            if let Some(extension) = path.extension() {
-                if extension.to_string_lossy().to_lowercase() == "mp3" {
-                    playlist.push(path.to_path_buf());
-                }
+/// Remembers the playlist, the playing track and position for `resume`.
+/// Losing them only means starting from the top next time.
+fn save_session(player: &player::Player) {
+    if let (Some(track), Some(position)) = (player.get_current_track(), player.get_current_position()) {
+        let session = config::Session {
+            track: track.clone(),
+            position: position.as_secs_f64(),
+            favorites_only: player.favorites_only(),
+            filter: player.filter().map(|filter| filter.to_string()),
+            playlist: player.all_tracks().to_vec(),
+        };
+        if let Err(e) = session.save() {
+            tracing::warn!("Couldn't save the session: {}", e);
+        }
+    }
+}
+
+/// Why a track went to the skip list after `count` early skips.
+fn left_early(count: usize) -> String {
+    match count {
+        1 => "left early once".to_string(),
+        _ => format!("left early {} times", count),
+    }
+}
+
+/// Pauses a playing track, resumes a paused one.
+fn toggle(player: &mut player::Player) {
+    if player.is_playing() {
+        player.pause();
+    } else {
+        player.play();
+    }
+}
+
+/// Runs a command sent through the control socket or the HTTP API, on the
+/// player thread. Returns its answer, `null` for none, or what went wrong.
+fn remote_command(
+    command: &remote::Command,
+    player: &mut player::Player,
+    scan: &playlist::Scan,
+) -> Result<serde_json::Value, String> {
+    let error = |e: anyhow::Error| e.to_string();
+    match command {
+        remote::Command::Play => player.play(),
+        remote::Command::Pause => player.pause(),
+        remote::Command::Toggle => toggle(player),
+        remote::Command::Next => player.skip_to_next().map_err(error)?,
+        remote::Command::Previous => player.play_previous().map_err(error)?,
+        remote::Command::Volume(percent) => player.set_volume(*percent),
+        remote::Command::Enqueue(path) => {
+            let tracks = if path.is_dir() {
+                scan.files(&path.to_string_lossy()).map_err(error)?
+            } else if path.is_file() {
+                vec![path.clone()]
+            } else {
+                return Err(format!("{}: not found", path.display()));
+            };
+            let added = player.enqueue(tracks).map_err(error)?;
+            return Ok(serde_json::json!({ "added": added }));
+        }
+        remote::Command::PlayIndex(index) => {
+            if *index >= player.playlist().len() {
+                return Err(format!("no track at {}", index));
             }
+            player.play_index(*index).map_err(error)?;
         }
+        // Answered from the view, or by the main loop.
+        remote::Command::Status
+        | remote::Command::Queue
+        | remote::Command::Playlist
+        | remote::Command::Quit => {}
+    }
+    Ok(serde_json::Value::Null)
+}
+
+/// How many of the next tracks the `queue` command lists.
+const REMOTE_QUEUE_LENGTH: usize = 50;
+
+/// The playlist for MPD clients: tags are those already read, as reading
+/// every file would hold up playback.
+fn remote_playlist(view: &playback::View) -> serde_json::Value {
+    let tracks: Vec<_> = view
+        .playlist
+        .iter()
+        .map(|path| {
+            let metadata = view.cached_metadata(path);
+            serde_json::json!({
+                "file": path.to_string_lossy(),
+                "artist": metadata.and_then(|metadata| metadata.artist.as_deref()),
+                "album": metadata.and_then(|metadata| metadata.album.as_deref()),
+                "title": metadata.and_then(|metadata| metadata.title.as_deref()),
+            })
+        })
+        .collect();
+    serde_json::json!({ "tracks": tracks })
+}
+
+/// The current track, for the `status` command, with its position in the
+/// playlist and the number of tracks. Missing tags and times are `null`.
+fn remote_status(view: &playback::View) -> serde_json::Value {
+    let state = match (&view.track, view.playing) {
+        (None, _) => "stopped",
+        (Some(_), true) => "playing",
+        (Some(_), false) => "paused",
+    };
+    let metadata = view.metadata.as_ref();
+    serde_json::json!({
+        "state": state,
+        "file": view.track.as_ref().map(|track| track.to_string_lossy()),
+        "artist": metadata.and_then(|metadata| metadata.artist.as_deref()),
+        "album": metadata.and_then(|metadata| metadata.album.as_deref()),
+        "title": metadata.and_then(|metadata| metadata.title.as_deref()),
+        "elapsed": view.position.map(|position| position.as_secs()),
+        "duration": view.duration.map(|duration| duration.as_secs()),
+        "volume": view.volume,
+        "position": view.index,
+        "tracks": view.playlist.len(),
+    })
+}
+
+fn save_layout(ui: &mut ui::UI) {
+    if let Err(e) = config::save_key("layout", &ui.layout()) {
+        ui.set_status(ui::StatusKind::Error, format!("Couldn't save the layout: {}", e));
     }
+}
 
-    Ok(playlist)
+/// Applies a setting changed from the Settings tab and writes it to the config file.
+fn change_setting(ui: &mut ui::UI, playback: &playback::Playback, setting: ui::Setting, text: Option<&str>) {
+    if let Err(e) = ui.change_setting(setting, text) {
+        ui.set_status(ui::StatusKind::Error, format!("{}: {}", setting.name(), e));
+        return;
+    }
+    let config = ui.config();
+    let (volume_step, repeat, crossfade) = (config.volume_step, config.repeat, config.crossfade);
+    let (shuffle, shuffle_albums) = (config.shuffle, config.shuffle_albums);
+    let (artist_spacing, party) = (config.artist_spacing as usize, config.party);
+    let crossfade_policy = crossfade_policy(config);
+    playback.run(move |player| {
+        match setting {
+            ui::Setting::Shuffle => player.set_shuffle(shuffle)?,
+            ui::Setting::ShuffleAlbums => player.set_shuffle_albums(shuffle_albums)?,
+            ui::Setting::ArtistSpacing => player.set_artist_spacing(artist_spacing)?,
+            ui::Setting::VolumeStep => player.set_volume_step(volume_step),
+            ui::Setting::Repeat => player.set_repeat(repeat),
+            ui::Setting::Party => player.set_party(party)?,
+            ui::Setting::Crossfade => player.set_crossfade(Duration::from_secs_f32(crossfade)),
+            ui::Setting::CrossfadeOnNext
+            | ui::Setting::CrossfadeOnEnd
+            | ui::Setting::CrossfadeWithinAlbums => player.set_crossfade_policy(crossfade_policy),
+            _ => {}
+        }
+        Ok(None)
+    });
+
+    let saved = match setting {
+        ui::Setting::NowPlayingHeight => config::save_key("layout", &ui.layout()),
+        _ => config.save(setting.key()),
+    };
+    match saved {
+        Ok(()) => ui.set_status(ui::StatusKind::Info, format!("{} saved", setting.name())),
+        Err(e) => ui.set_status(ui::StatusKind::Error, format!("Couldn't save the settings: {}", e)),
+    }
+}
+
+fn crossfade_policy(config: &config::Config) -> player::CrossfadePolicy {
+    player::CrossfadePolicy {
+        on_next: config.crossfade_on_next,
+        on_end: config.crossfade_on_end,
+        within_albums: config.crossfade_within_albums,
+    }
+}
+
+/// Asks a yes/no question on the terminal, defaulting to no.
+fn ask_confirmation(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// How far the seek keys move in the track.
+const SEEK_STEP_SECS: i64 = 5;
+
+/// Time between two redraws while playing, for the progress bar and meters.
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time between two ticks while paused on battery.
+const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of upcoming tracks shown in the queue pane.
+const QUEUE_LENGTH: usize = 15;
+
+/// The path argument that stands for the paths on standard input.
+const STDIN_PATH: &str = "-";
+
+fn now_playing(view: &playback::View) -> ui::NowPlaying<'_> {
+    let upcoming = &view.upcoming[..view.upcoming.len().min(QUEUE_LENGTH)];
+    let mut queue: Vec<String> = view.track.iter().map(|track| view.track_label(track)).collect();
+    queue.extend(upcoming.iter().map(|path| view.track_label(path)));
+    ui::NowPlaying {
+        track: view.track.as_ref(),
+        playlist: &view.playlist,
+        index: view.index,
+        revision: view.revision,
+        tracks: view,
+        playing: view.playing,
+        queue,
+        upcoming,
+        priority: view.priority,
+        metadata: view.metadata.as_ref(),
+        technical_info: view.technical_info.as_ref(),
+        art: view.art.as_ref(),
+        chapters: &view.chapters,
+        is_favorite: view.is_favorite,
+        favorites: Arc::clone(&view.favorites),
+        skipped: Arc::clone(&view.skipped),
+        favorites_only: view.favorites_only,
+        filter: view.filter.as_deref(),
+        stop_after_current: view.stop_after_current,
+        levels: view.levels,
+        position: view.position,
+        duration: view.duration,
+    }
+}
+
+/// What the desktop shows of the current track next to its media controls,
+/// and Discord on the user's profile.
+fn media_status(view: &playback::View) -> media_keys::Status {
+    let metadata = view.metadata.as_ref();
+    let tag = |field: fn(&player::TrackMetadata) -> &Option<String>| metadata.and_then(|m| field(m).clone());
+    media_keys::Status {
+        playing: view.playing,
+        loaded: view.track.is_some(),
+        artist: tag(|m| &m.artist),
+        album: tag(|m| &m.album),
+        title: tag(|m| &m.title).or_else(|| {
+            let track = view.track.as_ref()?;
+            Some(track.file_stem()?.to_string_lossy().to_string())
+        }),
+        length: view.duration,
+    }
+}
+
+/// One searchable line per distinct playlist entry ("Artist - Title (file
+/// name)"), with the playlist index each one jumps to.
+fn search_entries(view: &playback::View) -> (Vec<usize>, Vec<String>) {
+    let mut seen = HashSet::new();
+    let mut indexes = Vec::new();
+    let mut entries = Vec::new();
+    for (index, path) in view.playlist.iter().enumerate() {
+        if !seen.insert(path) {
+            continue;
+        }
+        let label = view.track_label(path);
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        indexes.push(index);
+        if label == file_name {
+            entries.push(label);
+        } else {
+            entries.push(format!("{} ({})", label, file_name));
+        }
+    }
+    (indexes, entries)
 }
@@ -0,0 +1,53 @@
+//! Media keys (play/pause, next, previous) pressed while the terminal is not
+//! focused. On Linux ksound registers on the session bus as an MPRIS player,
+//! which desktops send their media keys to; other platforms have no listener
+//! yet.
+//!
+//! Keys are sent as the `UserAction` a key press in the terminal would give,
+//! so the main loop handles both the same way.
+
+#[cfg(target_os = "linux")]
+mod mpris;
+
+use crate::ui::UserAction;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// What the desktop is shown of the player, next to its media controls.
+#[derive(Clone, PartialEq, Default)]
+pub struct Status {
+    pub playing: bool,
+    /// Whether a track is loaded; "Stopped" is shown without one.
+    pub loaded: bool,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub length: Option<Duration>,
+}
+
+pub struct MediaKeys {
+    #[cfg(target_os = "linux")]
+    server: mpris::Server,
+}
+
+impl MediaKeys {
+    /// Starts listening, sending the keys pressed to `actions`. `None` when
+    /// there is nothing to listen to, as in a session without a D-Bus session
+    /// bus (over ssh, on a console).
+    #[cfg(target_os = "linux")]
+    pub fn start(actions: Sender<UserAction>) -> Option<MediaKeys> {
+        let server = mpris::Server::start(actions).ok()?;
+        Some(MediaKeys { server })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn start(_actions: Sender<UserAction>) -> Option<MediaKeys> {
+        None
+    }
+
+    /// Tells the desktop what is playing; it is only notified of changes.
+    pub fn update(&self, status: Status) {
+        #[cfg(target_os = "linux")]
+        self.server.update(status);
+    }
+}
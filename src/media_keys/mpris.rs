@@ -0,0 +1,267 @@
+//! The `org.mpris.MediaPlayer2` interfaces, served on the session bus from
+//! zbus' own thread.
+
+use super::Status;
+use crate::ui::UserAction;
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use zbus::blocking::{connection, Connection};
+use zbus::interface;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// Status of the player and a counter bumped on each track change, for the
+/// track ids MPRIS wants.
+type Shared = Arc<Mutex<(Status, u64)>>;
+
+pub struct Server {
+    connection: Connection,
+    shared: Shared,
+}
+
+impl Server {
+    pub fn start(actions: Sender<UserAction>) -> zbus::Result<Server> {
+        let shared = Shared::default();
+        // The instance suffix lets several ksound run side by side.
+        let name = format!("org.mpris.MediaPlayer2.ksound.instance{}", std::process::id());
+        let connection = connection::Builder::session()?
+            .name(name)?
+            .serve_at(PATH, Root { actions: actions.clone() })?
+            .serve_at(
+                PATH,
+                Player {
+                    actions,
+                    shared: shared.clone(),
+                },
+            )?
+            .build()?;
+        Ok(Server { connection, shared })
+    }
+
+    pub fn update(&self, status: Status) {
+        let changed = {
+            let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+            let (old, track_id) = &mut *shared;
+            if *old == status {
+                return;
+            }
+            let track_changed =
+                (&old.title, &old.artist, &old.album, old.length) != (&status.title, &status.artist, &status.album, status.length);
+            if track_changed {
+                *track_id += 1;
+            }
+            let mut changed: HashMap<&str, Value> = HashMap::new();
+            changed.insert("PlaybackStatus", Value::from(playback_status(&status)));
+            if track_changed {
+                changed.insert("Metadata", Value::from(metadata(&status, *track_id)));
+            }
+            *old = status;
+            changed
+        };
+
+        // Nothing to do about a bus gone away: the keys just stop working.
+        let _ = self.connection.emit_signal(
+            None::<&str>,
+            PATH,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &(PLAYER_INTERFACE, changed, Vec::<&str>::new()),
+        );
+    }
+}
+
+fn playback_status(status: &Status) -> &'static str {
+    match status {
+        Status { loaded: false, .. } => "Stopped",
+        Status { playing: true, .. } => "Playing",
+        _ => "Paused",
+    }
+}
+
+fn metadata(status: &Status, track_id: u64) -> HashMap<String, OwnedValue> {
+    let mut metadata = HashMap::new();
+    let mut insert = |key: &str, value: Value| {
+        if let Ok(value) = OwnedValue::try_from(value) {
+            metadata.insert(key.to_string(), value);
+        }
+    };
+    let path = format!("/org/ksound/track/{}", track_id);
+    if let Ok(path) = ObjectPath::try_from(path.as_str()) {
+        insert("mpris:trackid", Value::from(path));
+    }
+    if let Some(length) = status.length {
+        insert("mpris:length", Value::from(length.as_micros() as i64));
+    }
+    if let Some(title) = &status.title {
+        insert("xesam:title", Value::from(title.as_str()));
+    }
+    if let Some(artist) = &status.artist {
+        insert("xesam:artist", Value::from(vec![artist.as_str()]));
+    }
+    if let Some(album) = &status.album {
+        insert("xesam:album", Value::from(album.as_str()));
+    }
+    metadata
+}
+
+struct Root {
+    actions: Sender<UserAction>,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {
+        let _ = self.actions.send(UserAction::QuitNow);
+    }
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        "KSound".to_string()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct Player {
+    actions: Sender<UserAction>,
+    shared: Shared,
+}
+
+impl Player {
+    fn status(&self) -> (Status, u64) {
+        self.shared.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn send(&self, action: UserAction) {
+        let _ = self.actions.send(action);
+    }
+}
+
+/// Play, pause and stop map onto the play/pause toggle, when it is needed.
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn play_pause(&self) {
+        self.send(UserAction::PlayPause);
+    }
+
+    fn play(&self) {
+        if !self.status().0.playing {
+            self.send(UserAction::PlayPause);
+        }
+    }
+
+    fn pause(&self) {
+        if self.status().0.playing {
+            self.send(UserAction::PlayPause);
+        }
+    }
+
+    fn stop(&self) {
+        self.pause();
+    }
+
+    fn next(&self) {
+        self.send(UserAction::Next);
+    }
+
+    fn previous(&self) {
+        self.send(UserAction::Previous);
+    }
+
+    /// Seeks by the usual step, in the direction of `offset`.
+    fn seek(&self, offset: i64) {
+        if offset > 0 {
+            self.send(UserAction::SeekForward);
+        } else if offset < 0 {
+            self.send(UserAction::SeekBackward);
+        }
+    }
+
+    fn set_position(&self, _track_id: ObjectPath<'_>, _position: i64) {}
+
+    fn open_uri(&self, _uri: &str) {}
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        playback_status(&self.status().0).to_string()
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let (status, track_id) = self.status();
+        metadata(&status, track_id)
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,106 @@
+//! Just enough HTTP/1.1 for the servers of the network side: one request
+//! per connection, which is closed after the response.
+
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// Largest request line and headers read.
+const MAX_HEAD: u64 = 16 * 1024;
+
+/// Time a client has to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Request {
+    pub method: String,
+    /// With the query string, if any.
+    pub url: String,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Reads a request from `stream`, and at most `max_body` bytes of its body.
+pub async fn read_request(stream: &mut TcpStream, max_body: u64) -> Result<Request, io::Error> {
+    tokio::time::timeout(REQUEST_TIMEOUT, read(stream, max_body))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no request"))?
+}
+
+async fn read(stream: &mut TcpStream, max_body: u64) -> Result<Request, io::Error> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut reader = BufReader::new(stream.take(MAX_HEAD + max_body));
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(url)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let (method, url) = (method.to_string(), url.to_string());
+
+    let (mut length, mut content_type) = (0, None);
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(invalid("incomplete headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| invalid("invalid Content-Length"))?;
+            } else if name.eq_ignore_ascii_case("content-type") {
+                content_type = Some(value.trim().to_string());
+            }
+        }
+    }
+    let mut body = Vec::new();
+    (&mut reader).take(length.min(max_body)).read_to_end(&mut body).await?;
+    Ok(Request {
+        method,
+        url,
+        content_type,
+        body,
+    })
+}
+
+/// Writes the status line and `headers` of a response whose body follows
+/// until the connection is closed.
+pub async fn write_head(
+    stream: &mut TcpStream,
+    status: u16,
+    headers: &[(&str, &str)],
+) -> Result<(), io::Error> {
+    let mut head = format!("HTTP/1.1 {} {}\r\nConnection: close\r\n", status, reason(status));
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await
+}
+
+/// Writes a whole response.
+pub async fn respond(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+) -> Result<(), io::Error> {
+    let length = body.len().to_string();
+    write_head(stream, status, &[("Content-Type", content_type), ("Content-Length", &length)]).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        415 => "Unsupported Media Type",
+        _ => "",
+    }
+}
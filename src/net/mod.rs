@@ -0,0 +1,48 @@
+//! The tokio runtime of the network side, with the `tokio` feature: the
+//! HTTP API, the audio stream, scrobbling and cover downloads run on it as
+//! tasks, so that a stalled connection or a slow server only holds up its
+//! own task. The player itself stays synchronous, and talks to these tasks
+//! over channels.
+
+pub mod http;
+
+use std::future::Future;
+use std::io;
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Runtime};
+use tokio::task::JoinHandle;
+
+/// Threads running the tasks. Blocking calls get threads of their own.
+const WORKERS: usize = 2;
+
+/// The runtime, started on first use.
+pub fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .worker_threads(WORKERS)
+            .thread_name("ksound-net")
+            .enable_all()
+            .build()
+            .expect("the tokio runtime can start")
+    })
+}
+
+/// Runs `future` on the runtime, from any thread.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    runtime().spawn(future)
+}
+
+/// Runs `f`, which blocks (a `ureq` request, a wait on the main loop), on a
+/// thread of the runtime's blocking pool, for a task to wait on.
+pub async fn blocking<F, T>(f: F) -> Result<T, io::Error>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.map_err(io::Error::other)
+}
@@ -0,0 +1,319 @@
+//! Moving the tracks of a folder into folders named after their tags, by a
+//! pattern such as "{artist}/{album}/{track} - {title}", to sort a flat
+//! folder of downloads into a library.
+//!
+//! A file is never replaced: a track landing on the name of another file
+//! gets a number, e.g. "Intro (2).mp3". The favorites, the skip list and
+//! the play counts follow the files moved.
+
+use crate::config::{self, PlayCounts, TrackStore};
+use crate::player::TrackMetadata;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The pattern of `ksound organize` when none is given.
+pub const DEFAULT_PATTERN: &str = "{artist}/{album}/{track} - {title}";
+
+/// Placeholders of a pattern, replaced by the tags of each track.
+const FIELDS: [&str; 6] = ["artist", "album", "title", "track", "disc", "year"];
+
+/// Where a track goes below the folder organized, and its name without the
+/// extension.
+pub struct Pattern {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    /// One of `FIELDS`.
+    Field(&'static str),
+}
+
+impl Pattern {
+    /// Fails on unknown placeholders, unclosed braces, and patterns leading
+    /// out of the folder.
+    pub fn parse(pattern: &str) -> Result<Pattern> {
+        if pattern.trim().is_empty() {
+            anyhow::bail!("The pattern is empty");
+        }
+        if pattern.starts_with('/') || pattern.split('/').any(|part| part == "..") {
+            anyhow::bail!("\"{}\" leads out of the folder", pattern);
+        }
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .with_context(|| format!("Unclosed {{ in \"{}\"", pattern))?;
+            let name = &rest[start + 1..start + end];
+            let field = FIELDS.iter().find(|field| **field == name).with_context(|| {
+                format!("Unknown placeholder {{{}}}, expected one of {{{}}}", name, FIELDS.join("}, {"))
+            })?;
+            parts.push(Part::Field(field));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Pattern { parts })
+    }
+
+    /// The path of `source` below the folder organized, by its tags. A
+    /// missing artist or album is "Unknown artist" or "Unknown album", and a
+    /// missing title the name of the file. The separators left around a
+    /// missing track number, disc or year are dropped, and so is a folder
+    /// left empty.
+    fn path(&self, source: &Path, metadata: Option<&TrackMetadata>) -> PathBuf {
+        let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+        let text = |value: Option<&Option<String>>| {
+            value
+                .and_then(|value| value.as_deref())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let number = |value: Option<u32>| value.map(|value| format!("{:02}", value));
+        let rendered: String = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field(field) => {
+                    let value = match *field {
+                        "artist" => text(metadata.map(|m| &m.artist)).unwrap_or("Unknown artist".into()),
+                        "album" => text(metadata.map(|m| &m.album)).unwrap_or("Unknown album".into()),
+                        "title" => text(metadata.map(|m| &m.title)).unwrap_or(stem.to_string()),
+                        "track" => number(metadata.and_then(|m| m.track)).unwrap_or_default(),
+                        "disc" => number(metadata.and_then(|m| m.disc)).unwrap_or_default(),
+                        _ => text(metadata.map(|m| &m.year)).unwrap_or_default(),
+                    };
+                    clean_value(&value)
+                }
+            })
+            .collect();
+
+        let mut parts: Vec<&str> = rendered.split('/').map(trim_part).collect();
+        let name = match parts.pop() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => stem.to_string(),
+        };
+        let mut path: PathBuf = parts.into_iter().filter(|part| !part.is_empty()).collect();
+        path.push(match source.extension() {
+            Some(extension) => format!("{}.{}", name, extension.to_string_lossy()),
+            None => name,
+        });
+        path
+    }
+}
+
+/// One file to move, and where to.
+pub struct Item {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+}
+
+/// Where each of `tracks` goes below `folder` by `pattern`, read from their
+/// tags with `metadata`. Tracks already in place are left out; a track
+/// landing on a file there, or on the destination of another track, gets a
+/// number.
+pub fn plan(
+    tracks: &[PathBuf],
+    folder: &Path,
+    pattern: &Pattern,
+    mut metadata: impl FnMut(&Path) -> Option<TrackMetadata>,
+) -> Vec<Item> {
+    let wanted: Vec<PathBuf> = tracks
+        .iter()
+        .map(|track| folder.join(pattern.path(track, metadata(track).as_ref())))
+        .collect();
+    // Tracks in place first, so that the others don't take their names.
+    let mut taken: HashSet<PathBuf> = tracks
+        .iter()
+        .zip(&wanted)
+        .filter(|(track, wanted)| track == wanted)
+        .map(|(track, _)| track.clone())
+        .collect();
+    let mut items = Vec::new();
+    for (source, wanted) in tracks.iter().zip(wanted) {
+        if *source == wanted {
+            continue;
+        }
+        let mut destination = wanted.clone();
+        let mut number = 2;
+        // Back to its own name, numbered by an earlier run.
+        while destination != *source && (taken.contains(&destination) || destination.exists()) {
+            let stem = wanted.file_stem().unwrap_or_default().to_string_lossy();
+            let name = match wanted.extension() {
+                Some(extension) => format!("{} ({}).{}", stem, number, extension.to_string_lossy()),
+                None => format!("{} ({})", stem, number),
+            };
+            destination = wanted.with_file_name(name);
+            number += 1;
+        }
+        taken.insert(destination.clone());
+        if destination != *source {
+            items.push(Item {
+                source: source.clone(),
+                destination,
+            });
+        }
+    }
+    items
+}
+
+/// Moves each file of `items`, making the folders needed, points the
+/// favorites, the skip list and the play counts of the `library` to its new
+/// path, and tells `progress` how it went. The folders below `folder` left
+/// empty are removed. Fails, stopping there, only when the lists can't be
+/// read or written.
+pub fn run(
+    items: &[Item],
+    folder: &Path,
+    library: &[PathBuf],
+    mut progress: impl FnMut(&Item, Result<(), String>),
+) -> Result<()> {
+    let mut favorites = TrackStore::favorites(library)?;
+    let mut skip_list = TrackStore::skip_list(library)?;
+    let mut play_counts = PlayCounts::new()?;
+    let folder = folder.canonicalize().with_context(|| format!("{}: not found", folder.display()))?;
+    for item in items {
+        let moved = move_file(item);
+        if let Ok(from) = &moved {
+            favorites.moved(from, &item.destination)?;
+            skip_list.moved(from, &item.destination)?;
+            play_counts.moved(from, &item.destination)?;
+            if let Some(parent) = from.parent() {
+                remove_empty(parent, &folder);
+            }
+        }
+        progress(item, moved.map(|_| ()).map_err(|e| e.to_string()));
+    }
+    Ok(())
+}
+
+/// Returns the canonical path the file had.
+fn move_file(item: &Item) -> std::io::Result<PathBuf> {
+    let from = item.source.canonicalize()?;
+    if let Some(parent) = item.destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Made since the plan.
+    if item.destination.exists() {
+        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, "a file is there already"));
+    }
+    config::move_file(&item.source, &item.destination)?;
+    Ok(from)
+}
+
+/// Removes `dir` and the folders holding it while they are empty, up to
+/// `folder`, kept.
+fn remove_empty(dir: &Path, folder: &Path) {
+    let mut dir = dir;
+    while dir != folder && dir.starts_with(folder) && fs::remove_dir(dir).is_ok() {
+        let Some(parent) = dir.parent() else {
+            return;
+        };
+        dir = parent;
+    }
+}
+
+/// A tag value made fit for a file name: no `/`, and no control characters.
+fn clean_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// One folder or file name of a rendered pattern, without the separators
+/// left at its ends by missing tags, nor the trailing dots some file
+/// systems drop.
+fn trim_part(part: &str) -> &str {
+    let part = part.trim_matches(|c: char| c.is_whitespace() || c == '-' || c == '_');
+    match part.trim_end_matches('.') {
+        "" if !part.is_empty() => "_",
+        part => part,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(pattern: &str, source: &str, metadata: Option<&TrackMetadata>) -> PathBuf {
+        Pattern::parse(pattern).unwrap().path(Path::new(source), metadata)
+    }
+
+    fn tags(artist: &str, album: &str, title: &str, track: Option<u32>) -> TrackMetadata {
+        TrackMetadata {
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+            title: Some(title.to_string()),
+            track,
+            ..TrackMetadata::default()
+        }
+    }
+
+    #[test]
+    fn pads_track_numbers() {
+        let tags = tags("Air", "Moon Safari", "La femme d'argent", Some(1));
+        assert_eq!(
+            path(DEFAULT_PATTERN, "/in/01.mp3", Some(&tags)),
+            Path::new("Air/Moon Safari/01 - La femme d'argent.mp3")
+        );
+        let tags = TrackMetadata { track: Some(123), disc: Some(2), ..tags };
+        assert_eq!(path("{disc}-{track}", "/in/a.flac", Some(&tags)), Path::new("02-123.flac"));
+    }
+
+    #[test]
+    fn fills_in_missing_tags() {
+        assert_eq!(
+            path(DEFAULT_PATTERN, "/in/Intro.mp3", None),
+            Path::new("Unknown artist/Unknown album/Intro.mp3")
+        );
+        let tags = tags(" ", "Moon Safari", "Talisman", None);
+        assert_eq!(
+            path(DEFAULT_PATTERN, "/in/x.mp3", Some(&tags)),
+            Path::new("Unknown artist/Moon Safari/Talisman.mp3")
+        );
+        // Nothing left of the name but separators: the file keeps its own.
+        assert_eq!(path("{year}/{track} - {disc}", "/in/x.ogg", Some(&tags)), Path::new("x.ogg"));
+    }
+
+    #[test]
+    fn keeps_tag_values_within_the_folder() {
+        let tags = tags("AC/DC", "..", "../../etc/passwd", None);
+        let organized = path(DEFAULT_PATTERN, "/in/x.mp3", Some(&tags));
+        assert_eq!(organized, Path::new("AC_DC/_/.._.._etc_passwd.mp3"));
+        assert!(organized.components().all(|c| matches!(c, std::path::Component::Normal(_))));
+        let tags = TrackMetadata { artist: Some("Wham!...".into()), title: Some(".".into()), ..tags };
+        assert_eq!(path(DEFAULT_PATTERN, "/in/x.mp3", Some(&tags)), Path::new("Wham!/_/_.mp3"));
+    }
+
+    #[test]
+    fn trims_parts() {
+        assert_eq!(trim_part(" - Intro - "), "Intro");
+        assert_eq!(trim_part("_"), "");
+        assert_eq!(trim_part("..."), "_");
+        assert_eq!(trim_part("Vol. 1..."), "Vol. 1");
+        assert_eq!(trim_part(""), "");
+    }
+
+    #[test]
+    fn refuses_patterns_leading_out() {
+        assert!(Pattern::parse("/{artist}").is_err());
+        assert!(Pattern::parse("{artist}/../{title}").is_err());
+        assert!(Pattern::parse("{artist").is_err());
+        assert!(Pattern::parse("{genre}").is_err());
+        assert!(Pattern::parse(" ").is_err());
+    }
+}
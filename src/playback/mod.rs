@@ -0,0 +1,343 @@
+//! The player on a thread of its own, which owns the audio output: opening
+//! and decoding a file, reading its tags or a slow network share only holds
+//! up that thread, never the terminal.
+//!
+//! The main loop hands it jobs, closures run with the `Player`, and reads
+//! what to show from a `View` the thread keeps up to date. What the jobs
+//! have to say, and the end of the playlist, come back as `Update` events.
+
+mod output;
+
+use crate::events::Event;
+use crate::ui;
+use anyhow::Result;
+use ksound::cache::Thumbnail;
+use ksound::config;
+use ksound::player::{self, Chapter, Player, TechnicalInfo, TrackMetadata};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the thread moves playback on and refreshes the view when
+/// nothing else wakes it up.
+const TICK: Duration = Duration::from_millis(100);
+
+/// A tick this much later by the wall clock than by the monotonic one: the
+/// computer slept in between.
+const SLEPT: Duration = Duration::from_secs(5);
+
+/// Upcoming tracks kept in the view, for the queue pane and remote clients.
+const UPCOMING: usize = 50;
+
+/// A job returns the message to show when it went well, if any.
+type Job = Box<dyn FnOnce(&mut Player) -> Result<Option<String>> + Send>;
+
+enum Message {
+    Run(Job),
+    /// The player got to the end of a track: playback moves on at once.
+    TrackEnded,
+    /// The audio output changed, to the one named if there is one left.
+    OutputChanged(Option<String>),
+    Stop,
+}
+
+pub enum Update {
+    /// A job is done with nothing to say, or the player changed on its own:
+    /// a new track, a cover found, ...
+    Changed,
+    Info(String),
+    Error(String),
+    /// The playlist is over (repeat off).
+    Over,
+}
+
+/// What the player looks like from the outside, as of the last job or tick.
+#[derive(Clone, Default)]
+pub struct View {
+    pub track: Option<PathBuf>,
+    pub playing: bool,
+    pub position: Option<Duration>,
+    pub duration: Option<Duration>,
+    pub volume: u16,
+    pub metadata: Option<TrackMetadata>,
+    pub technical_info: Option<TechnicalInfo>,
+    pub art: Option<Thumbnail>,
+    /// Chapters of the track, in order.
+    pub chapters: Arc<Vec<Chapter>>,
+    pub is_favorite: bool,
+    /// Left and right peak levels since the previous view.
+    pub levels: [f32; 2],
+    /// The next tracks to play, in order.
+    pub upcoming: Vec<PathBuf>,
+    /// How many of `upcoming`, first, were put there to play next.
+    pub priority: usize,
+    pub playlist: Arc<Vec<PathBuf>>,
+    /// Playlist index of the playing track.
+    pub index: Option<usize>,
+    /// Changes with the playlist and with the tags known.
+    pub revision: u64,
+    /// Tags of the playlist entries already read.
+    tags: Arc<HashMap<PathBuf, TrackMetadata>>,
+    /// `Player::metadata_revision` as of `tags`.
+    tags_revision: u64,
+    pub favorites: Arc<Vec<ui::StoredTrack>>,
+    pub skipped: Arc<Vec<ui::StoredTrack>>,
+    pub favorites_only: bool,
+    /// Filter of the tracks played, as typed.
+    pub filter: Option<String>,
+    /// Whether playback pauses once the current track is over.
+    pub stop_after_current: bool,
+    /// `Player::lists_revision` as of `favorites` and `skipped`, once read.
+    lists_revision: Option<u64>,
+}
+
+impl View {
+    /// Only copies the tags of the playlist when they changed since
+    /// `previous`, and only looks for the playing track in the playlist when
+    /// either changed.
+    fn new(player: &Player, previous: &View) -> View {
+        let track = player.get_current_track().cloned();
+        let playlist = player.shared_playlist();
+        let same_playlist = Arc::ptr_eq(&playlist, &previous.playlist);
+        let tags_revision = player.metadata_revision();
+        let (tags, revision) = match same_playlist && tags_revision == previous.tags_revision {
+            true => (Arc::clone(&previous.tags), previous.revision),
+            false => {
+                let tags = playlist
+                    .iter()
+                    .filter_map(|path| Some((path.clone(), player.cached_metadata(path)?.clone())))
+                    .collect();
+                (Arc::new(tags), previous.revision + 1)
+            }
+        };
+        // Whether their files are still there is only checked when the lists
+        // change.
+        let lists_revision = Some(player.lists_revision());
+        let (favorites, skipped) = match lists_revision == previous.lists_revision {
+            true => (Arc::clone(&previous.favorites), Arc::clone(&previous.skipped)),
+            false => (
+                Arc::new(player.favorites().iter().map(stored_track).collect()),
+                Arc::new(player.skipped().iter().map(stored_track).collect()),
+            ),
+        };
+        let index = match same_playlist && track == previous.track {
+            true => previous.index,
+            false => track.as_ref().and_then(|track| playlist.iter().position(|path| path == track)),
+        };
+        View {
+            is_favorite: track
+                .as_ref()
+                .is_some_and(|track| player.is_favorite(track).unwrap_or(false)),
+            track,
+            playing: player.is_playing(),
+            position: player.get_current_position(),
+            duration: player.total_duration,
+            volume: player.volume(),
+            metadata: player.get_current_metadata().cloned(),
+            technical_info: player.get_current_technical_info().cloned(),
+            art: player.get_current_art().cloned(),
+            chapters: player.chapters(),
+            levels: player.take_levels(),
+            upcoming: player.upcoming(UPCOMING).into_iter().cloned().collect(),
+            priority: player.priority_count(),
+            playlist,
+            index,
+            revision,
+            tags,
+            tags_revision,
+            favorites,
+            skipped,
+            favorites_only: player.favorites_only(),
+            filter: player.filter().map(|filter| filter.to_string()),
+            stop_after_current: player.stop_after_current(),
+            lists_revision,
+        }
+    }
+}
+
+/// How the Favorites and Skipped tabs show `entry`: by the tags it was
+/// added with.
+fn stored_track(entry: &config::TrackEntry) -> ui::StoredTrack {
+    let path = PathBuf::from(&entry.path);
+    let tags = &entry.tags;
+    let label = match (&tags.artist, &tags.title) {
+        (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+        (None, Some(title)) => title.clone(),
+        _ => player::label(&path, None),
+    };
+    ui::StoredTrack {
+        id: entry.id.clone(),
+        missing: !path.is_file(),
+        path,
+        label,
+    }
+}
+
+impl View {
+    /// Whether more than the position and the levels moved since `previous`.
+    fn changed(&self, previous: &View) -> bool {
+        self.track != previous.track
+            || self.playing != previous.playing
+            || self.volume != previous.volume
+            || self.is_favorite != previous.is_favorite
+            || self.art.is_some() != previous.art.is_some()
+            || self.revision != previous.revision
+            || self.index != previous.index
+            || self.upcoming != previous.upcoming
+            || self.priority != previous.priority
+            || self.lists_revision != previous.lists_revision
+            || self.stop_after_current != previous.stop_after_current
+            || self.filter != previous.filter
+    }
+}
+
+impl ui::TrackInfo for View {
+    fn cached_metadata(&self, path: &Path) -> Option<&TrackMetadata> {
+        self.tags.get(path)
+    }
+
+    fn track_label(&self, path: &Path) -> String {
+        player::label(path, self.tags.get(path))
+    }
+}
+
+pub struct Playback {
+    messages: Sender<Message>,
+    view: Arc<Mutex<View>>,
+    thread: Option<JoinHandle<()>>,
+    output: Option<output::Watch>,
+}
+
+impl Playback {
+    /// Starts the thread, with the player `setup` makes there: it must not
+    /// leave it, as the audio output can't. Fails if `setup` does.
+    pub fn start<F>(events: Sender<Event>, setup: F) -> Result<Playback>
+    where
+        F: FnOnce() -> Result<Player> + Send + 'static,
+    {
+        let (messages, receiver) = mpsc::channel();
+        let (ready, started) = mpsc::channel();
+        let view = Arc::new(Mutex::new(View::default()));
+        let shared = Arc::clone(&view);
+        let track_ended = messages.clone();
+        let thread = thread::spawn(move || {
+            let mut player = match setup() {
+                Ok(player) => player,
+                Err(e) => {
+                    let _ = ready.send(Err(e));
+                    return;
+                }
+            };
+            player.notify_track_end(move || {
+                let _ = track_ended.send(Message::TrackEnded);
+            });
+            let mut current = View::new(&player, &View::default());
+            *shared.lock().expect("view lock") = current.clone();
+            let _ = ready.send(Ok(()));
+
+            let send = |update| events.send(Event::Playback(update)).is_ok();
+            let mut over = false;
+            let mut clock = (Instant::now(), SystemTime::now());
+            loop {
+                let mut updates = Vec::new();
+                match receiver.recv_timeout(TICK) {
+                    Ok(Message::Run(job)) => updates.push(match job(&mut player) {
+                        Ok(Some(message)) => Update::Info(message),
+                        Ok(None) => Update::Changed,
+                        Err(e) => Update::Error(e.to_string()),
+                    }),
+                    Ok(Message::OutputChanged(output)) if player.is_playing() => {
+                        player.pause();
+                        updates.push(Update::Info(match output {
+                            Some(_) => "Paused: the audio output changed".to_string(),
+                            None => "Paused: the audio output is gone".to_string(),
+                        }));
+                    }
+                    Ok(Message::TrackEnded | Message::OutputChanged(_)) | Err(RecvTimeoutError::Timeout) => {}
+                    Ok(Message::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+                }
+                // Before the dead output is taken for the end of the track.
+                let now = (Instant::now(), SystemTime::now());
+                let wall = now.1.duration_since(clock.1).unwrap_or_default();
+                if wall.saturating_sub(now.0 - clock.0) >= SLEPT {
+                    let asleep = wall.as_secs();
+                    tracing::info!("Woke up after {}s asleep: opening the audio output again", asleep);
+                    if let Err(e) = player.reopen_output(current.position) {
+                        updates.push(Update::Error(format!("Couldn't open the audio output again: {}", e)));
+                    }
+                }
+                clock = now;
+                if !over {
+                    match player.handle_playback() {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            over = true;
+                            updates.push(Update::Over);
+                        }
+                        Err(e) => updates.push(Update::Error(e.to_string())),
+                    }
+                }
+                let view = View::new(&player, &current);
+                if updates.is_empty() && view.changed(&current) {
+                    updates.push(Update::Changed);
+                }
+                current = view;
+                *shared.lock().expect("view lock") = current.clone();
+
+                updates.extend(player.take_failures().into_iter().map(Update::Error));
+                if !updates.into_iter().all(send) {
+                    return;
+                }
+            }
+        });
+        started.recv().unwrap_or_else(|_| Err(anyhow::anyhow!("The player thread stopped")))?;
+        Ok(Playback {
+            messages,
+            view,
+            thread: Some(thread),
+            output: None,
+        })
+    }
+
+    /// Pauses playback whenever the audio output changes, such as when
+    /// headphones are unplugged.
+    pub fn pause_on_output_change(&mut self) {
+        let messages = self.messages.clone();
+        self.output = Some(output::Watch::start(move |output| {
+            messages.send(Message::OutputChanged(output)).is_ok()
+        }));
+    }
+
+    /// Runs `job` on the player thread, after those sent before.
+    pub fn run(&self, job: impl FnOnce(&mut Player) -> Result<Option<String>> + Send + 'static) {
+        let _ = self.messages.send(Message::Run(Box::new(job)));
+    }
+
+    /// Runs `job` on the player thread and waits for its result. `None` if
+    /// the thread is gone.
+    pub fn call<T: Send + 'static>(&self, job: impl FnOnce(&mut Player) -> T + Send + 'static) -> Option<T> {
+        let (reply, result) = mpsc::channel();
+        self.run(move |player| {
+            let _ = reply.send(job(player));
+            Ok(None)
+        });
+        result.recv().ok()
+    }
+
+    pub fn view(&self) -> View {
+        self.view.lock().expect("view lock").clone()
+    }
+}
+
+impl Drop for Playback {
+    /// Lets the thread finish its jobs, and close the audio output.
+    fn drop(&mut self) {
+        let _ = self.messages.send(Message::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
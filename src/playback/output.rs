@@ -0,0 +1,112 @@
+//! Watching the audio output, for playback to pause when it changes:
+//! headphones unplugged, a Bluetooth speaker gone or another default device
+//! picked, rather than the music going on out of the laptop speakers.
+//!
+//! With PulseAudio or PipeWire, `pactl subscribe` tells when the sinks
+//! change, and the output is the default sink with its active port (the
+//! headphones or the speakers of one sound card). Elsewhere, the name of the
+//! default output device is read again every few seconds.
+
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// How often the default device is read without `pactl`.
+const RECHECK: Duration = Duration::from_secs(3);
+
+/// Stops watching when dropped.
+pub struct Watch {
+    subscription: Option<Child>,
+}
+
+impl Watch {
+    /// Calls `changed` with the new output, or `None` once there is none,
+    /// each time it changes, on a thread of its own until `changed` returns
+    /// false.
+    pub fn start(changed: impl Fn(Option<String>) -> bool + Send + 'static) -> Watch {
+        let mut current = current_output();
+        let mut check = move || {
+            let output = current_output();
+            if output == current {
+                return true;
+            }
+            tracing::info!("The audio output changed from {:?} to {:?}", current, output);
+            current = output.clone();
+            changed(output)
+        };
+        let subscription = match default_sink() {
+            Some(_) => pactl(&["subscribe"]).stdout(Stdio::piped()).spawn().ok(),
+            None => None,
+        };
+        match subscription {
+            Some(mut child) => {
+                let stdout = child.stdout.take().map(BufReader::new);
+                thread::spawn(move || {
+                    // E.g. "Event 'change' on sink #53", or on server for a
+                    // new default sink; not the streams playing into them.
+                    for line in stdout.into_iter().flat_map(|stdout| stdout.lines()).map_while(Result::ok) {
+                        if (line.contains(" on sink #") || line.contains(" on server")) && !check() {
+                            return;
+                        }
+                    }
+                });
+                Watch {
+                    subscription: Some(child),
+                }
+            }
+            None => {
+                thread::spawn(move || loop {
+                    thread::sleep(RECHECK);
+                    if !check() {
+                        return;
+                    }
+                });
+                Watch { subscription: None }
+            }
+        }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.subscription {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// `pactl` with its output in English, to be parsed.
+fn pactl(args: &[&str]) -> Command {
+    let mut command = Command::new("pactl");
+    command.args(args).env("LC_ALL", "C").stdin(Stdio::null()).stderr(Stdio::null());
+    command
+}
+
+/// The default sink of the sound server and its active port, or without
+/// one, the name of the default output device.
+fn current_output() -> Option<String> {
+    default_sink().or_else(|| rodio::cpal::default_host().default_output_device()?.name().ok())
+}
+
+fn default_sink() -> Option<String> {
+    let run = |args: &[&str]| {
+        let output = pactl(args).output().ok().filter(|output| output.status.success())?;
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let info = run(&["info"])?;
+    let sink = info.lines().find_map(|line| line.strip_prefix("Default Sink: "))?.trim().to_string();
+    // The port is in the block of the sink, after its name.
+    let sinks = run(&["list", "sinks"]).unwrap_or_default();
+    let mut in_sink = false;
+    for line in sinks.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("Name: ") {
+            in_sink = name == sink;
+        } else if let (true, Some(port)) = (in_sink, line.strip_prefix("Active Port: ")) {
+            return Some(format!("{} ({})", sink, port));
+        }
+    }
+    Some(sink)
+}
@@ -0,0 +1,287 @@
+//! Network stream of what is playing, for another device in the house to
+//! tune into: with `--stream`, every sink plays into a mixer of ours
+//! instead of straight to the sound card, and what comes out of it is sent
+//! both to the card and to the HTTP listeners, as a never-ending WAV file
+//! (16-bit stereo at 44.1 kHz) that mpv, VLC or a browser can play:
+//!
+//! ```text
+//! mpv http://192.168.1.10:8000/
+//! ```
+//!
+//! The stream follows the volume, pauses and crossfades of the player. A
+//! listener that can't keep up misses what it couldn't take.
+
+use rodio::dynamic_mixer::{self, DynamicMixer, DynamicMixerController};
+use rodio::source::{SeekError, Zero};
+use rodio::{OutputStreamHandle, Sink, Source};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const SAMPLE_RATE: u32 = 44_100;
+const CHANNELS: u16 = 2;
+
+/// Samples sent at once, a tenth of a second.
+const CHUNK_SAMPLES: usize = SAMPLE_RATE as usize * CHANNELS as usize / 10;
+
+/// Chunks a listener may fall behind before missing some, two seconds.
+const BACKLOG: usize = 20;
+
+/// Where the chunks for one listener go.
+#[cfg(not(feature = "tokio"))]
+type Chunks = std::sync::mpsc::SyncSender<Arc<[u8]>>;
+#[cfg(feature = "tokio")]
+type Chunks = tokio::sync::mpsc::Sender<Arc<[u8]>>;
+
+type Listeners = Arc<Mutex<Vec<Chunks>>>;
+
+pub struct Broadcast {
+    mixer: Arc<DynamicMixerController<f32>>,
+    listeners: Listeners,
+}
+
+impl Broadcast {
+    /// Serves the stream on `address` (`host:port`), playing the mix on
+    /// `output` as well.
+    pub fn start(address: &str, output: &OutputStreamHandle) -> Result<Broadcast, io::Error> {
+        let server = bind(address)?;
+        let listeners = Listeners::default();
+        let mixer = mix_into(output, &listeners)?;
+        serve(server, Arc::clone(&listeners));
+        Ok(Broadcast { mixer, listeners })
+    }
+
+    /// Plays the mix on `output` from now on, with a new mixer: the sinks
+    /// of the old one are left playing into the old output.
+    pub fn play_on(&mut self, output: &OutputStreamHandle) -> Result<(), io::Error> {
+        self.mixer = mix_into(output, &self.listeners)?;
+        Ok(())
+    }
+
+    /// A sink that plays into the stream.
+    pub fn sink(&self) -> Sink {
+        let (sink, output) = Sink::new_idle();
+        self.mixer.add(output);
+        sink
+    }
+}
+
+/// A mixer playing on `output`, and copied to the `listeners`.
+fn mix_into(
+    output: &OutputStreamHandle,
+    listeners: &Listeners,
+) -> Result<Arc<DynamicMixerController<f32>>, io::Error> {
+    let (mixer, mix) = dynamic_mixer::mixer(CHANNELS, SAMPLE_RATE);
+    // The mixer ends when it has nothing to play, which would stop the
+    // stream between two tracks.
+    mixer.add(Zero::<f32>::new(CHANNELS, SAMPLE_RATE));
+    output
+        .play_raw(Feed::new(mix, Arc::clone(listeners)))
+        .map_err(io::Error::other)?;
+    Ok(mixer)
+}
+
+const HEADERS: [(&str, &str); 3] = [
+    ("Content-Type", "audio/wav"),
+    ("Cache-Control", "no-cache"),
+    ("icy-name", "ksound"),
+];
+
+#[cfg(not(feature = "tokio"))]
+fn bind(address: &str) -> Result<tiny_http::Server, io::Error> {
+    tiny_http::Server::http(address).map_err(io::Error::other)
+}
+
+#[cfg(not(feature = "tokio"))]
+fn serve(server: tiny_http::Server, listeners: Listeners) {
+    use tiny_http::{Header, Method, Response};
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            if (request.method(), request.url()) != (&Method::Get, "/") {
+                let _ = request.respond(Response::empty(404));
+                continue;
+            }
+            let (sender, chunks) = std::sync::mpsc::sync_channel(BACKLOG);
+            if let Ok(mut listeners) = listeners.lock() {
+                listeners.push(sender);
+            }
+            let headers = HEADERS
+                .iter()
+                .map(|(name, value)| Header::from_bytes(*name, *value).expect("valid header"))
+                .collect();
+            let body = Listener {
+                chunks,
+                chunk: Arc::from(wav_header()),
+                offset: 0,
+            };
+            // Each response lasts as long as the listener stays.
+            std::thread::spawn(move || request.respond(Response::new(200.into(), headers, body, None, None)));
+        }
+    });
+}
+
+#[cfg(feature = "tokio")]
+fn bind(address: &str) -> Result<tokio::net::TcpListener, io::Error> {
+    crate::net::runtime().block_on(tokio::net::TcpListener::bind(address))
+}
+
+/// Each listener is a task, writing the chunks as they come.
+#[cfg(feature = "tokio")]
+fn serve(server: tokio::net::TcpListener, listeners: Listeners) {
+    use crate::net::{self, http};
+    use tokio::io::AsyncWriteExt;
+
+    net::spawn(async move {
+        while let Ok((mut stream, _)) = server.accept().await {
+            let listeners = Arc::clone(&listeners);
+            net::spawn(async move {
+                let Ok(request) = http::read_request(&mut stream, 0).await else {
+                    return;
+                };
+                if (request.method.as_str(), request.url.as_str()) != ("GET", "/") {
+                    let _ = http::respond(&mut stream, 404, "text/plain", b"").await;
+                    return;
+                }
+                let (sender, mut chunks) = tokio::sync::mpsc::channel(BACKLOG);
+                if let Ok(mut listeners) = listeners.lock() {
+                    listeners.push(sender);
+                }
+                if http::write_head(&mut stream, 200, &HEADERS).await.is_err()
+                    || stream.write_all(&wav_header()).await.is_err()
+                {
+                    return;
+                }
+                // Until the listener leaves, or the player is gone.
+                while let Some(chunk) = chunks.recv().await {
+                    if stream.write_all(&chunk).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// The header of a WAV file as long as can be said.
+fn wav_header() -> Vec<u8> {
+    let byte_rate = SAMPLE_RATE * CHANNELS as u32 * 2;
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&CHANNELS.to_le_bytes());
+    header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&byte_rate.to_le_bytes());
+    header.extend_from_slice(&(CHANNELS * 2).to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&(u32::MAX - 36).to_le_bytes());
+    header
+}
+
+/// The body of a listener's response: the chunks sent to it, as they come.
+#[cfg(not(feature = "tokio"))]
+struct Listener {
+    chunks: std::sync::mpsc::Receiver<Arc<[u8]>>,
+    chunk: Arc<[u8]>,
+    /// Bytes of `chunk` already read.
+    offset: usize,
+}
+
+#[cfg(not(feature = "tokio"))]
+impl io::Read for Listener {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.offset == self.chunk.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => (self.chunk, self.offset) = (chunk, 0),
+                // The player is gone: end of the stream.
+                Err(_) => return Ok(0),
+            }
+        }
+        let length = buffer.len().min(self.chunk.len() - self.offset);
+        buffer[..length].copy_from_slice(&self.chunk[self.offset..self.offset + length]);
+        self.offset += length;
+        Ok(length)
+    }
+}
+
+/// The mix on its way to the sound card, copied to the listeners.
+struct Feed {
+    mix: DynamicMixer<f32>,
+    listeners: Listeners,
+    pending: Vec<u8>,
+}
+
+impl Feed {
+    fn new(mix: DynamicMixer<f32>, listeners: Listeners) -> Self {
+        Feed {
+            mix,
+            listeners,
+            pending: Vec::with_capacity(CHUNK_SAMPLES * 2),
+        }
+    }
+
+    /// Sends the pending samples to the listeners, forgetting those who left.
+    fn send(&mut self) {
+        // Never wait on the sound card's thread: the chunk is lost instead.
+        if let Ok(mut listeners) = self.listeners.try_lock() {
+            if !listeners.is_empty() {
+                let chunk: Arc<[u8]> = Arc::from(self.pending.as_slice());
+                listeners.retain(|listener| offer(listener, Arc::clone(&chunk)));
+            }
+        }
+        self.pending.clear();
+    }
+}
+
+/// Sends `chunk` to a listener unless it is behind. False once it left.
+#[cfg(not(feature = "tokio"))]
+fn offer(listener: &Chunks, chunk: Arc<[u8]>) -> bool {
+    !matches!(listener.try_send(chunk), Err(std::sync::mpsc::TrySendError::Disconnected(_)))
+}
+
+#[cfg(feature = "tokio")]
+fn offer(listener: &Chunks, chunk: Arc<[u8]>) -> bool {
+    !matches!(listener.try_send(chunk), Err(tokio::sync::mpsc::error::TrySendError::Closed(_)))
+}
+
+impl Iterator for Feed {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.mix.next()?;
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        self.pending.extend_from_slice(&value.to_le_bytes());
+        if self.pending.len() >= CHUNK_SAMPLES * 2 {
+            self.send();
+        }
+        Some(sample)
+    }
+}
+
+impl Source for Feed {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.mix.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn try_seek(&mut self, _pos: Duration) -> Result<(), SeekError> {
+        Err(SeekError::NotSupported {
+            underlying_source: "the network stream",
+        })
+    }
+}
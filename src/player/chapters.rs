@@ -0,0 +1,62 @@
+//! Chapters of a track: the CHAP frames of its ID3 tag, or in MP4 files
+//! such as M4B audiobooks, the Nero chapter list or the chapter track.
+
+use super::{is_mp4_container, mp4};
+use id3::Tag;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chapter {
+    pub start: Duration,
+    pub title: String,
+}
+
+/// The chapters of `path` in order, untitled ones named by their number;
+/// empty for a file without any, or that can't be read.
+pub fn read(path: &Path) -> Vec<Chapter> {
+    let found = match is_mp4_container(path) {
+        true => mp4::read_chapters(path).unwrap_or_else(|e| {
+            tracing::debug!("Couldn't read the chapters of {}: {}", path.display(), e);
+            Vec::new()
+        }),
+        false => id3_chapters(path),
+    };
+    let mut chapters: Vec<Chapter> = found
+        .into_iter()
+        .map(|(start, title)| Chapter {
+            start,
+            title: title.trim().to_string(),
+        })
+        .collect();
+    chapters.sort_by_key(|chapter| chapter.start);
+    for (index, chapter) in chapters.iter_mut().enumerate() {
+        if chapter.title.is_empty() {
+            chapter.title = format!("Chapter {}", index + 1);
+        }
+    }
+    chapters
+}
+
+/// The start of each CHAP frame, and the title of its TIT2 subframe.
+fn id3_chapters(path: &Path) -> Vec<(Duration, String)> {
+    let Ok(tag) = Tag::read_from_path(path) else {
+        return Vec::new();
+    };
+    tag.chapters()
+        .map(|chapter| {
+            let title = chapter
+                .frames
+                .iter()
+                .find(|frame| frame.id() == "TIT2")
+                .and_then(|frame| frame.content().text())
+                .unwrap_or_default();
+            (Duration::from_millis(chapter.start_time as u64), title.to_string())
+        })
+        .collect()
+}
+
+/// Index of the chapter playing at `position`, if one has started.
+pub fn chapter_at(chapters: &[Chapter], position: Duration) -> Option<usize> {
+    chapters.iter().rposition(|chapter| chapter.start <= position)
+}
@@ -0,0 +1,66 @@
+//! Repair of "mojibake" tags: text that was written in a legacy code page
+//! (cp1251, GBK, ...) but flagged as latin-1, so every byte was decoded as
+//! one latin-1 character.
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+/// Recovers the raw bytes of a string that was decoded as latin-1.
+///
+/// Returns `None` when the text cannot come from latin-1 decoding (it holds
+/// characters above U+00FF) or when it is plain ASCII, which is never garbled.
+fn latin1_bytes(text: &str) -> Option<Vec<u8>> {
+    if text.is_ascii() {
+        return None;
+    }
+    text.chars()
+        .map(|c| u8::try_from(u32::from(c)).ok())
+        .collect()
+}
+
+/// Guesses the real encoding of a set of garbled strings.
+///
+/// All the values are fed to the detector together: a single title is often
+/// too short for a reliable guess, a whole tag is not.
+pub fn detect<'a>(texts: impl IntoIterator<Item = &'a str>) -> Option<&'static Encoding> {
+    let mut detector = EncodingDetector::new();
+    let mut fed = false;
+
+    for text in texts {
+        if let Some(bytes) = latin1_bytes(text) {
+            detector.feed(&bytes, false);
+            detector.feed(b" ", false);
+            fed = true;
+        }
+    }
+    if !fed {
+        return None;
+    }
+    detector.feed(&[], true);
+
+    let encoding = detector.guess(None, true);
+    // windows-1252 means the text really is latin-1: nothing to fix.
+    if encoding == WINDOWS_1252 {
+        None
+    } else {
+        Some(encoding)
+    }
+}
+
+/// Re-decodes `text` with `encoding`, returning the fixed value if it differs.
+pub fn repair(text: &str, encoding: &'static Encoding) -> Option<String> {
+    let bytes = latin1_bytes(text)?;
+    let decoded = if encoding == UTF_8 {
+        String::from_utf8(bytes).ok()?
+    } else {
+        encoding
+            .decode_without_bom_handling_and_without_replacement(&bytes)?
+            .into_owned()
+    };
+
+    if decoded != text {
+        Some(decoded)
+    } else {
+        None
+    }
+}
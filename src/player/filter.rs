@@ -0,0 +1,252 @@
+//! Filters narrowing the playlist to the tracks whose tags match, such as
+//! `genre:ambient year:>2000 rating:>=4`: a track is played when it matches
+//! every term. A word without a field is looked for in the artist, album and
+//! title, a value holding spaces goes in double quotes, and a term after a
+//! `-` keeps the tracks it doesn't match, e.g. `-genre:christmas`.
+
+use super::TrackMetadata;
+use anyhow::Result;
+use std::fmt;
+
+/// Fields a term can name before its colon.
+const FIELDS: [&str; 6] = ["genre", "artist", "album", "title", "year", "rating"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueueFilter {
+    terms: Vec<Term>,
+    /// As typed, to be shown.
+    text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Term {
+    /// Text the field holds, in lowercase, whatever the case; for `None`,
+    /// the artist, the album or the title.
+    Contains(Option<&'static str>, String),
+    /// The year or the rating (0 when unrated), from the first number to
+    /// the second.
+    Between(&'static str, u32, u32),
+    /// The tracks the term doesn't match.
+    Not(Box<Term>),
+}
+
+impl fmt::Display for QueueFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl QueueFilter {
+    /// Fails on an empty filter, unknown fields and years or ratings that
+    /// aren't numbers, compared with `<`, `<=`, `>`, `>=` or given as a
+    /// range such as `1990-1999`.
+    pub fn parse(text: &str) -> Result<QueueFilter> {
+        let mut terms = Vec::new();
+        for word in split_words(text) {
+            let term = match word.strip_prefix('-') {
+                Some(negated) if !negated.is_empty() => Term::Not(Box::new(Term::parse(negated)?)),
+                _ => Term::parse(&word)?,
+            };
+            terms.push(term);
+        }
+        if terms.is_empty() {
+            anyhow::bail!("The filter is empty");
+        }
+        Ok(QueueFilter {
+            terms,
+            text: text.trim().to_string(),
+        })
+    }
+
+    /// Whether a track with these tags is played. Without tags, only the
+    /// terms a missing field meets, such as `rating:0`, match.
+    pub fn matches(&self, metadata: Option<&TrackMetadata>) -> bool {
+        self.terms.iter().all(|term| term.matches(metadata))
+    }
+}
+
+impl Term {
+    /// One word of the filter, its `-` aside.
+    fn parse(word: &str) -> Result<Term> {
+        let (field, value) = match word.split_once(':') {
+            Some((field, value)) => {
+                let field = field.to_lowercase();
+                let field = FIELDS.iter().find(|known| **known == field).ok_or_else(|| {
+                    anyhow::anyhow!("Unknown field \"{}\", expected one of {}", field, FIELDS.join(", "))
+                })?;
+                (Some(*field), value)
+            }
+            None => (None, word),
+        };
+        Ok(match field {
+            Some(field @ ("year" | "rating")) => {
+                let (low, high) = number_range(value).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "\"{}\": expected a number, after <, <=, > or >=, or a range such as 1990-1999",
+                        word
+                    )
+                })?;
+                Term::Between(field, low, high)
+            }
+            _ if value.is_empty() => anyhow::bail!("\"{}\": nothing to look for", word),
+            _ => Term::Contains(field, value.to_lowercase()),
+        })
+    }
+
+    fn matches(&self, metadata: Option<&TrackMetadata>) -> bool {
+        let text = |field: &str| {
+            let value = metadata.and_then(|m| match field {
+                "genre" => m.genre.as_ref(),
+                "artist" => m.artist.as_ref(),
+                "album" => m.album.as_ref(),
+                _ => m.title.as_ref(),
+            });
+            value.map(|value| value.to_lowercase()).unwrap_or_default()
+        };
+        match self {
+            Term::Contains(Some(field), wanted) => text(field).contains(wanted.as_str()),
+            Term::Contains(None, wanted) => {
+                ["artist", "album", "title"].iter().any(|field| text(field).contains(wanted.as_str()))
+            }
+            Term::Between(field, low, high) => {
+                let value = match *field {
+                    "year" => metadata.and_then(|m| year(m.year.as_deref()?)),
+                    _ => Some(metadata.and_then(|m| m.rating).unwrap_or(0) as u32),
+                };
+                value.is_some_and(|value| (*low..=*high).contains(&value))
+            }
+            Term::Not(term) => !term.matches(metadata),
+        }
+    }
+}
+
+/// The words of `text`, split on the spaces outside double quotes, the
+/// quotes left out.
+fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// The numbers `value` stands for, both ends included: "2000", ">2000",
+/// "<=3" or "1990-1999".
+fn number_range(value: &str) -> Option<(u32, u32)> {
+    let number = |text: &str| text.trim().parse::<u32>().ok();
+    if let Some(rest) = value.strip_prefix(">=") {
+        Some((number(rest)?, u32::MAX))
+    } else if let Some(rest) = value.strip_prefix("<=") {
+        Some((0, number(rest)?))
+    } else if let Some(rest) = value.strip_prefix('>') {
+        Some((number(rest)?.checked_add(1)?, u32::MAX))
+    } else if let Some(rest) = value.strip_prefix('<') {
+        Some((0, number(rest)?.checked_sub(1)?))
+    } else if let Some((low, high)) = value.split_once('-') {
+        Some((number(low)?, number(high)?))
+    } else {
+        let value = number(value.strip_prefix('=').unwrap_or(value))?;
+        Some((value, value))
+    }
+}
+
+/// The year a date tag starts with, e.g. 2001 for "2001-05-03".
+fn year(date: &str) -> Option<u32> {
+    let digits = date.trim().split(|c: char| !c.is_ascii_digit()).next()?;
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(artist: &str, genre: &str, year: &str, rating: Option<u8>) -> TrackMetadata {
+        TrackMetadata {
+            artist: Some(artist.to_string()),
+            genre: Some(genre.to_string()),
+            year: Some(year.to_string()),
+            rating,
+            ..TrackMetadata::default()
+        }
+    }
+
+    fn matches(filter: &str, metadata: &TrackMetadata) -> bool {
+        QueueFilter::parse(filter).unwrap().matches(Some(metadata))
+    }
+
+    #[test]
+    fn parses_number_ranges() {
+        assert_eq!(number_range("2000"), Some((2000, 2000)));
+        assert_eq!(number_range("=2000"), Some((2000, 2000)));
+        assert_eq!(number_range(">2000"), Some((2001, u32::MAX)));
+        assert_eq!(number_range(">=2000"), Some((2000, u32::MAX)));
+        assert_eq!(number_range("<3"), Some((0, 2)));
+        assert_eq!(number_range("<=3"), Some((0, 3)));
+        assert_eq!(number_range("1990-1999"), Some((1990, 1999)));
+        assert_eq!(number_range("<0"), None);
+        assert_eq!(number_range(&format!(">{}", u32::MAX)), None);
+        assert_eq!(number_range("199x"), None);
+        assert_eq!(number_range("-1999"), None);
+    }
+
+    #[test]
+    fn matches_years_and_ratings() {
+        let track = track("Air", "Electronic", "1998-01-16", Some(4));
+        assert!(matches("year:1990-1999", &track));
+        assert!(matches("year:>=1998 rating:>3", &track));
+        assert!(!matches("year:>1998", &track));
+        assert!(!matches("rating:5", &track));
+        assert!(matches("rating:0", &TrackMetadata::default()));
+        assert!(!matches("year:<2000", &TrackMetadata::default()));
+    }
+
+    #[test]
+    fn negates_terms() {
+        let track = track("Air", "Electronic", "1998", None);
+        assert!(matches("-genre:christmas", &track));
+        assert!(!matches("-genre:electro", &track));
+        assert!(!matches("-air", &track));
+        assert!(matches("-rating:>0 year:1998", &track));
+        // Within a range, the dash is no negation.
+        assert!(matches("year:1990-1999", &track));
+        assert!(QueueFilter::parse("-genre:").is_err());
+        assert!(QueueFilter::parse("-").is_ok());
+    }
+
+    #[test]
+    fn quotes_values_holding_spaces() {
+        assert_eq!(split_words(r#"artist:"daft punk"  year:1997"#), ["artist:daft punk", "year:1997"]);
+        assert_eq!(split_words(r#""  ""#), ["  "]);
+        let track = track("Daft Punk", "House", "1997", None);
+        assert!(matches(r#"artist:"daft punk""#, &track));
+        assert!(!matches(r#"artist:"punk daft""#, &track));
+        assert!(matches("artist:punk daft", &track));
+        assert!(!matches(r#"-"daft punk""#, &track));
+    }
+
+    #[test]
+    fn refuses_bad_filters() {
+        assert!(QueueFilter::parse("").is_err());
+        assert!(QueueFilter::parse("   ").is_err());
+        assert!(QueueFilter::parse("mood:calm").is_err());
+        assert!(QueueFilter::parse("year:soon").is_err());
+        assert!(QueueFilter::parse("rating:>").is_err());
+        assert!(QueueFilter::parse("genre:").is_err());
+        let filter = QueueFilter::parse(" GENRE:Ambient ").unwrap();
+        assert_eq!(filter.to_string(), "GENRE:Ambient");
+        assert!(filter.matches(Some(&track("Eno", "ambient", "1978", None))));
+    }
+}
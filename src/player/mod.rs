@@ -1,76 +1,815 @@
-use crate::config;
-use anyhow::Result;
+mod broadcast;
+mod chapters;
+mod charset;
+mod filter;
+mod mp4;
+mod mpeg;
+mod tap;
+
+pub use chapters::{chapter_at, Chapter};
+pub use filter::QueueFilter;
+
+use crate::cache::{self, Thumbnail};
+use crate::config::{self, RepeatMode};
+use crate::cover_art::{Cover, CoverArt};
+use crate::history::PlayLog;
+use crate::scrobble::{self, Scrobbler};
+use anyhow::{Context, Result};
 use id3::{Tag, TagLike};
+use rand::rngs::StdRng;
+use rand::seq::{IndexedRandom, SliceRandom};
+use rand::{Rng, SeedableRng};
 use rodio::{Decoder, OutputStream, Sink, Source};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
-use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Clone)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct TrackMetadata {
     pub artist: Option<String>,
     pub album: Option<String>,
     pub title: Option<String>,
     pub year: Option<String>,
+    /// Star rating (1-5) read from the POPM frame, `None` when unrated.
+    pub rating: Option<u8>,
+    /// Position on the album, and the disc of the album it is on.
+    #[serde(default)]
+    pub track: Option<u32>,
+    #[serde(default)]
+    pub disc: Option<u32>,
+    #[serde(default)]
+    pub genre: Option<String>,
+    #[serde(default)]
+    pub album_artist: Option<String>,
+}
+
+/// Owner recorded in the POPM frames created by ksound.
+const POPM_USER: &str = "ksound";
+
+/// Converts a POPM rating byte into 1-5 stars, following the usual
+/// Windows Media Player / MediaMonkey ranges.
+fn popm_to_stars(rating: u8) -> Option<u8> {
+    match rating {
+        0 => None,
+        1..=31 => Some(1),
+        32..=95 => Some(2),
+        96..=159 => Some(3),
+        160..=223 => Some(4),
+        _ => Some(5),
+    }
+}
+
+/// Decodes a PCNT counter (big-endian, at least 4 bytes).
+fn decode_play_counter(data: &[u8]) -> u64 {
+    data.iter().fold(0u64, |acc, &b| acc.saturating_mul(256) | b as u64)
+}
+
+fn encode_play_counter(count: u64) -> Vec<u8> {
+    let bytes = count.to_be_bytes();
+    let first = bytes.iter().position(|&b| b != 0).unwrap_or(8).min(4);
+    bytes[first..].to_vec()
+}
+
+fn stars_to_popm(stars: u8) -> u8 {
+    match stars {
+        0 => 0,
+        1 => 1,
+        2 => 64,
+        3 => 128,
+        4 => 196,
+        _ => 255,
+    }
+}
+
+/// Returns the embedded cover picture (front cover preferred) as raw bytes.
+pub fn embedded_cover(path: &Path) -> Option<Vec<u8>> {
+    if is_mp4_container(path) {
+        return mp4::read_cover(path).ok().flatten();
+    }
+
+    let tag = Tag::read_from_path(path).ok()?;
+    let picture = tag
+        .pictures()
+        .find(|p| p.picture_type == id3::frame::PictureType::CoverFront)
+        .or_else(|| tag.pictures().next())?;
+    Some(picture.data.clone())
+}
+
+/// Writes `cover` into its MP3 file as the front cover, unless the file
+/// got one in the meantime.
+fn embed_cover(cover: &Cover) -> Result<()> {
+    let mut tag = Tag::read_from_path(&cover.path).unwrap_or_default();
+    if tag.pictures().next().is_some() {
+        return Ok(());
+    }
+    let mime_type = if cover.image.starts_with(b"\x89PNG") { "image/png" } else { "image/jpeg" };
+    tag.add_frame(id3::frame::Picture {
+        mime_type: mime_type.to_string(),
+        picture_type: id3::frame::PictureType::CoverFront,
+        description: String::new(),
+        data: cover.image.clone(),
+    });
+    tag.write_to_path(&cover.path, id3::Version::Id3v24)?;
+    Ok(())
+}
+
+/// "Artist - Title" for display, or the file name without both tags.
+pub fn label(path: &Path, metadata: Option<&TrackMetadata>) -> String {
+    match metadata {
+        Some(TrackMetadata {
+            artist: Some(artist),
+            title: Some(title),
+            ..
+        }) => format!("{} - {}", artist, title),
+        Some(TrackMetadata {
+            title: Some(title), ..
+        }) => title.clone(),
+        _ => path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string()),
+    }
+}
+
+/// Length of the track as the decoder reports it, without decoding it.
+pub fn probe_duration(path: &Path) -> Option<Duration> {
+    let file = File::open(path).ok()?;
+    Decoder::new(BufReader::new(file)).ok()?.total_duration()
+}
+
+/// Orders the playlist can be sorted in from the Playlists tab.
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Title,
+    Artist,
+    Album,
+    Year,
+    Duration,
+    Path,
+    /// File name, whatever the folder.
+    Name,
+    /// Newest first.
+    #[serde(rename = "mtime")]
+    Modified,
+    /// Folder by folder, in album order: by disc, then track number.
+    Track,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 9] = [
+        SortKey::Title,
+        SortKey::Artist,
+        SortKey::Album,
+        SortKey::Year,
+        SortKey::Duration,
+        SortKey::Path,
+        SortKey::Name,
+        SortKey::Modified,
+        SortKey::Track,
+    ];
+
+    /// The key of `name`, as written in `.ksound.toml`.
+    pub fn parse(name: &str) -> Option<SortKey> {
+        SortKey::ALL.into_iter().find(|key| key.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Title => "title",
+            SortKey::Artist => "artist",
+            SortKey::Album => "album",
+            SortKey::Year => "year",
+            SortKey::Duration => "duration",
+            SortKey::Path => "path",
+            SortKey::Name => "name",
+            SortKey::Modified => "mtime",
+            SortKey::Track => "track",
+        }
+    }
+}
+
+/// Technical details of the playing file, gathered when it is opened.
+#[derive(Clone)]
+pub struct TechnicalInfo {
+    pub codec: String,
+    pub bitrate_kbps: Option<u32>,
+    /// `None` when the container does not tell (e.g. AAC in MP4).
+    pub vbr: Option<bool>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub file_size: u64,
+}
+
+impl TechnicalInfo {
+    fn probe(path: &Path, sample_rate: u32, channels: u16, duration: Option<Duration>) -> Self {
+        let file_size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        let average_kbps = duration
+            .filter(|d| d.as_secs() > 0)
+            .map(|d| (file_size * 8 / d.as_secs() / 1000) as u32);
+
+        if is_mp4_container(path) {
+            return TechnicalInfo {
+                codec: "AAC".to_string(),
+                bitrate_kbps: average_kbps,
+                vbr: None,
+                sample_rate,
+                channels,
+                file_size,
+            };
+        }
+
+        match mpeg::probe(path).ok().flatten() {
+            Some(frame) => TechnicalInfo {
+                codec: match frame.layer {
+                    3 => "MP3".to_string(),
+                    layer => format!("MPEG Layer {}", "I".repeat(layer as usize)),
+                },
+                bitrate_kbps: Some(frame.bitrate_kbps),
+                vbr: Some(frame.vbr),
+                sample_rate,
+                channels,
+                file_size,
+            },
+            None => TechnicalInfo {
+                codec: path
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_uppercase())
+                    .unwrap_or_else(|| "?".to_string()),
+                bitrate_kbps: average_kbps,
+                vbr: None,
+                sample_rate,
+                channels,
+                file_size,
+            },
+        }
+    }
+
+    /// One-line summary, e.g. "MP3 320kbps CBR, 44.1kHz stereo, 7.4 MB".
+    pub fn summary(&self) -> String {
+        let mut codec = self.codec.clone();
+        if let Some(kbps) = self.bitrate_kbps {
+            codec.push_str(&format!(" {}kbps", kbps));
+        }
+        match self.vbr {
+            Some(true) => codec.push_str(" VBR"),
+            Some(false) => codec.push_str(" CBR"),
+            None => {}
+        }
+
+        let channels = match self.channels {
+            1 => "mono".to_string(),
+            2 => "stereo".to_string(),
+            n => format!("{} channels", n),
+        };
+
+        format!(
+            "{}, {}kHz {}, {:.1} MB",
+            codec,
+            self.sample_rate as f32 / 1000.0,
+            channels,
+            self.file_size as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+/// A proposed repair for one garbled tag field.
+#[derive(Clone, PartialEq)]
+pub struct EncodingFix {
+    pub field: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+/// Returns true for files stored in an MP4 container (iTunes-style tags).
+fn is_mp4_container(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| matches!(ext.to_string_lossy().to_lowercase().as_str(), "m4a" | "m4b" | "mp4"))
+        .unwrap_or(false)
 }
 
 impl TrackMetadata {
+    /// The tags of `path`, `None` when it has none or they can't be read.
     pub fn from_path(path: &Path) -> Option<Self> {
-        match Tag::read_from_path(path) {
-            Ok(tag) => Some(TrackMetadata {
-                artist: tag.artist().map(String::from),
-                album: tag.album().map(String::from),
-                title: tag.title().map(String::from),
-                year: tag.date_recorded().map(|y| y.to_string()),
-            }),
-            Err(_) => None,
+        Self::read(path).ok().flatten()
+    }
+
+    /// The tags of `path`, ID3 or MP4: `None` when it has none, an error
+    /// when they can't be read.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        if is_mp4_container(path) {
+            return Ok(Some(Self::from_mp4(path)?));
+        }
+
+        let tag = match Tag::read_from_path(path) {
+            Ok(tag) => tag,
+            Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let metadata = TrackMetadata {
+            artist: tag.artist().map(String::from),
+            album: tag.album().map(String::from),
+            title: tag.title().map(String::from),
+            // TYER, in ID3v2.3 tags.
+            year: tag
+                .date_recorded()
+                .map(|y| y.to_string())
+                .or_else(|| tag.year().map(|y| y.to_string())),
+            rating: tag
+                .frames()
+                .find_map(|frame| frame.content().popularimeter())
+                .and_then(|popm| popm_to_stars(popm.rating)),
+            track: tag.track(),
+            disc: tag.disc(),
+            // Resolving ID3v1 numbers such as "(17)" to their names.
+            genre: tag.genre_parsed().map(String::from),
+            album_artist: tag.album_artist().map(String::from),
+        };
+        Ok(Some(metadata))
+    }
+
+    /// Detects fields holding legacy-encoded text (cp1251, GBK, ...) that was
+    /// read as latin-1, and proposes their UTF-8 form along with the name of
+    /// the detected encoding.
+    pub fn encoding_fixes(&self) -> Option<(&'static str, Vec<EncodingFix>)> {
+        let fields = [
+            ("Artist", self.artist.as_deref()),
+            ("Album", self.album.as_deref()),
+            ("Title", self.title.as_deref()),
+        ];
+        let encoding = charset::detect(fields.iter().filter_map(|(_, value)| *value))?;
+
+        let fixes: Vec<EncodingFix> = fields
+            .iter()
+            .filter_map(|(field, value)| {
+                let before = (*value)?;
+                charset::repair(before, encoding).map(|after| EncodingFix {
+                    field,
+                    before: before.to_string(),
+                    after,
+                })
+            })
+            .collect();
+
+        if fixes.is_empty() {
+            None
+        } else {
+            Some((encoding.name(), fixes))
         }
     }
+
+    fn from_mp4(path: &Path) -> io::Result<Self> {
+        let items = mp4::read_text_items(path)?;
+        let get = |kind: [u8; 4]| {
+            items
+                .iter()
+                .find(|(k, _)| *k == kind)
+                .map(|(_, value)| value.clone())
+        };
+        // "3/12": the third of twelve. 0 is unset.
+        let number = |kind| get(kind)?.split('/').next()?.parse().ok().filter(|number| *number > 0);
+        Ok(TrackMetadata {
+            artist: get(mp4::ARTIST),
+            album: get(mp4::ALBUM),
+            title: get(mp4::TITLE),
+            year: get(mp4::YEAR),
+            rating: None,
+            track: number(mp4::TRACK),
+            disc: number(mp4::DISC),
+            genre: get(mp4::GENRE),
+            album_artist: get(mp4::ALBUM_ARTIST),
+        })
+    }
 }
 
+/// Plays a playlist on the default audio output, and keeps what goes with
+/// it: favorites, skip list, play counts, listening history and scrobbles.
+///
+/// Nothing happens on its own: `handle_playback` has to be called every now
+/// and then, about ten times a second, to move on to the next track.
 pub struct Player {
     sink: Option<Sink>,
     _stream: Option<OutputStream>,
     _stream_handle: Option<rodio::OutputStreamHandle>,
-    playlist: Vec<PathBuf>,
+    /// Shared with `shared_playlist`: changing it copies it if still in use.
+    playlist: Arc<Vec<PathBuf>>,
+    /// The whole playlist while only its favorites, or the tracks matching
+    /// `filter`, are played.
+    all_tracks: Option<Arc<Vec<PathBuf>>>,
+    /// Whether only the favorites of the playlist are played.
+    favorites_only: bool,
+    /// Tags the tracks played match, the others left in `all_tracks`.
+    filter: Option<QueueFilter>,
+    /// Whether the playlist plays in random order, shuffled again each time
+    /// all of it has played.
+    shuffle: bool,
+    /// Whether shuffle draws whole albums rather than tracks.
+    shuffle_albums: bool,
+    /// How many other tracks shuffle puts between two of the same artist;
+    /// 0 for any.
+    artist_spacing: usize,
+    /// Draws the random orders, from a seed given or from the system.
+    rng: StdRng,
+    /// Whether `rng` comes from a seed given, for the orders to be the same
+    /// each time.
+    seeded: bool,
+    /// Whether random tracks are added once the last entry plays, rather
+    /// than stopping or starting over after it.
+    party: bool,
+    /// Finds the tracks party mode draws from, the first time it needs
+    /// them; without it, it draws from the playlist.
+    find_library: Option<Box<dyn FnOnce() -> Vec<PathBuf>>>,
+    library: Vec<PathBuf>,
+    /// Where each track was in the playlist as given, to put it back in
+    /// order when shuffle is turned off.
+    order: HashMap<PathBuf, usize>,
     current_index: usize,
     current_playing: Option<PathBuf>,
-    skip_list: config::SkipList,
-    favorites_list: config::FavoritesList,
+    /// Tracks played before the current one, the latest last, for
+    /// `play_previous` to go back in the order they played.
+    played: Vec<PathBuf>,
+    /// Tracks put after the current one with `play_after_current`, not
+    /// started yet, in the order they were.
+    priority: Vec<PathBuf>,
+    skip_list: config::TrackStore,
+    /// Artists and albums left out of the playlist, like the skip list.
+    bans: config::Bans,
+    favorites_list: config::TrackStore,
+    /// Bumped whenever a track is added to or taken out of the favorites
+    /// or the skip list.
+    lists_revision: u64,
+    /// What `undo` can take back, the latest last.
+    undo: Vec<Undo>,
+    play_counts: config::PlayCounts,
+    /// Where the long tracks were left.
+    positions: config::Positions,
+    bookmarks: config::Bookmarks,
+    /// Tracks at least this long start again where they were left; zero for
+    /// none.
+    remember_position: Duration,
+    play_log: PlayLog,
+    metadata_cache: cache::MetadataCache,
+    write_play_counts: bool,
     pub total_duration: Option<Duration>,
     start_time: Option<Instant>,
     paused_duration: Duration,
     pause_start: Option<Instant>,
     pub current_metadata: Option<TrackMetadata>,
+    current_technical_info: Option<TechnicalInfo>,
+    current_art: Option<Thumbnail>,
+    /// Chapters of the current track, in order.
+    chapters: Arc<Vec<Chapter>>,
+    levels: Arc<tap::Levels>,
+    /// Tracks that could not be played, and covers that could not be found
+    /// or embedded, since the last `take_failures`.
+    failures: Vec<String>,
+    /// Volume of every new track, 1.0 being the file's own level.
+    volume: f32,
+    /// Change of `volume` per key press.
+    volume_step: f32,
+    /// The volume `volume` is being raised to, since when, and over how long.
+    ramp: Option<(f32, Instant, Duration)>,
+    repeat: RepeatMode,
+    /// Pause on the next track once the current one is over.
+    stop_after_current: bool,
+    /// Load the next track paused.
+    start_paused: bool,
+    crossfade: Duration,
+    crossfade_policy: CrossfadePolicy,
+    /// Whether the current track was found not to fade into the next one,
+    /// which then follows it without a gap if it can.
+    crossfade_skipped: bool,
+    /// Whether deleted files go to the trash.
+    trash: bool,
+    /// The previous track while it fades out, and when it started to.
+    fading_out: Option<(Sink, Instant)>,
+    /// Playback speed of the current track, from its folder settings.
+    speed: f32,
+    /// Whether the current track is to run into the next one without a gap.
+    gapless: bool,
+    /// The next track, already appended to the sink behind the current one.
+    queued: Option<QueuedTrack>,
+    /// Settings of the `.ksound.toml` files, by folder of the tracks played.
+    folders: HashMap<PathBuf, config::FolderSettings>,
+    scrobbler: Option<Scrobbler>,
+    /// The current track, while it can be scrobbled.
+    listen: Option<scrobble::Listen>,
+    cover_art: Option<CoverArt>,
+    /// Whether the covers found are written into the files.
+    embed_covers: bool,
+    /// Covers to embed once their track is no longer playing.
+    pending_covers: Vec<Cover>,
+    /// The network stream, which the sinks then play into.
+    broadcast: Option<broadcast::Broadcast>,
+    on_track_end: Option<tap::OnEnd>,
+}
+
+/// Sorts `playlist` by `key`, tracks missing the field last, and merges the
+/// repeated entries.
+fn sort_tracks(playlist: &mut Arc<Vec<PathBuf>>, key: SortKey, cache: &mut cache::MetadataCache) {
+    let playlist = Arc::make_mut(playlist);
+    playlist.sort_by_cached_key(|path| {
+        let field = match key {
+            SortKey::Title => cache.metadata(path).and_then(|m| m.title),
+            SortKey::Artist => cache.metadata(path).and_then(|m| m.artist),
+            SortKey::Album => cache.metadata(path).and_then(|m| m.album),
+            SortKey::Year => cache.metadata(path).and_then(|m| m.year),
+            // Zero-padded so that the text order is the numeric one.
+            SortKey::Duration => cache.duration(path).map(|d| format!("{:020}", d.as_millis())),
+            SortKey::Path => None,
+            SortKey::Name => path.file_name().map(|name| name.to_string_lossy().into_owned()),
+            SortKey::Modified => fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|age| format!("{:020}", u64::MAX - age.as_secs())),
+            // Without a disc number a track is on the first one, without
+            // a track number it goes last.
+            SortKey::Track => {
+                let metadata = cache.metadata(path);
+                Some(format!(
+                    "{}\0{:010}\0{:010}",
+                    path.parent().unwrap_or(path).display(),
+                    metadata.as_ref().and_then(|m| m.disc).unwrap_or(1),
+                    metadata.as_ref().and_then(|m| m.track).unwrap_or(u32::MAX),
+                ))
+            }
+        };
+        (field.is_none(), field.map(|value| value.to_lowercase()), path.clone())
+    });
+    playlist.dedup();
+}
+
+/// A change to the favorites or the playlist that `Player::undo` can take
+/// back.
+enum Undo {
+    /// The favorite added, by identifier.
+    Favorite(PathBuf, String),
+    /// The entries taken out of the favorites.
+    Unfavorite(PathBuf, Vec<config::TrackEntry>),
+    /// A track taken out of the playlist, and put in the skip list unless
+    /// `id` is `None`.
+    Skip {
+        track: PathBuf,
+        id: Option<String>,
+        /// Where it was in the playlist, and in the whole playlist while
+        /// only the favorites are played.
+        index: Option<usize>,
+        stash_index: Option<usize>,
+        /// Where it was at, if it was playing and playback moved on.
+        position: Option<Duration>,
+    },
+    /// A track whose file was moved to the trash, with where it was in the
+    /// playlist.
+    Delete {
+        track: PathBuf,
+        /// Its canonical path, the one the trash keeps.
+        trashed: PathBuf,
+        index: Option<usize>,
+        stash_index: Option<usize>,
+    },
+}
+
+/// How many changes `Player::undo` can take back.
+const UNDO_STEPS: usize = 20;
+
+/// How many tracks played `Player::play_previous` can go back through.
+const PLAYED_KEPT: usize = 100;
+
+/// Moves `track`, a canonical path as the trash keeps, back from the trash
+/// to where it was, the latest file trashed from there if there are several.
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+fn restore_from_trash(track: &Path) -> Result<()> {
+    let item = trash::os_limited::list()?
+        .into_iter()
+        .filter(|item| item.original_path() == track)
+        .max_by_key(|item| item.time_deleted)
+        .with_context(|| format!("{} is no longer in the trash", track.display()))?;
+    trash::os_limited::restore_all([item])?;
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+)))]
+fn restore_from_trash(_track: &Path) -> Result<()> {
+    anyhow::bail!("Files can't be restored from the trash on this system")
+}
+
+/// Sets the star rating (1-5, 0 to clear) of `track` in its POPM frame.
+/// MP4 files have no POPM equivalent and are left untouched.
+pub fn write_rating(track: &Path, stars: u8) -> Result<()> {
+    if is_mp4_container(track) {
+        return Ok(());
+    }
+    let mut tag = Tag::read_from_path(track).unwrap_or_else(|_| Tag::new());
+    let mut popm = tag
+        .frames()
+        .find_map(|frame| frame.content().popularimeter())
+        .cloned()
+        .unwrap_or(id3::frame::Popularimeter {
+            user: POPM_USER.to_string(),
+            rating: 0,
+            counter: 0,
+        });
+    popm.rating = stars_to_popm(stars);
+    tag.add_frame(popm);
+    tag.write_to_path(track, id3::Version::Id3v24)?;
+    Ok(())
+}
+
+/// Leaving a track with Next before this counts as an early skip.
+const EARLY_SKIP: Duration = Duration::from_secs(30);
+
+/// How many tracks party mode adds at a time.
+const PARTY_TRACKS: usize = 10;
+
+/// How far down the shuffled tracks one of another artist is looked for,
+/// when the next one was by an artist played too recently.
+const ARTIST_LOOK_AHEAD: usize = 500;
+
+/// Plays older than this don't make a track come up later in random mode.
+const RECENT_PLAY: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// How strongly random mode favors a track: 1 for any track, plus 1 for a
+/// favorite and 1 per star above three, scaled down to a tenth for a track
+/// just played, back to full over `RECENT_PLAY`.
+fn shuffle_weight(favorite: bool, rating: Option<u8>, played: Option<Duration>) -> f64 {
+    let weight = 1.0 + favorite as u8 as f64 + rating.unwrap_or(0).saturating_sub(3) as f64;
+    match played {
+        Some(age) => weight * (age.as_secs_f64() / RECENT_PLAY.as_secs_f64()).clamp(0.1, 1.0),
+        None => weight,
+    }
+}
+
+/// The position of each track in `playlist`, the first one for a track
+/// found twice.
+fn order_of(playlist: &[PathBuf]) -> HashMap<PathBuf, usize> {
+    let mut order = HashMap::with_capacity(playlist.len());
+    for (index, track) in playlist.iter().enumerate() {
+        order.entry(track.clone()).or_insert(index);
+    }
+    order
+}
+
+/// A track waiting in the sink to follow the playing one (gapless playback).
+struct QueuedTrack {
+    path: PathBuf,
+    total_duration: Option<Duration>,
+    sample_rate: u32,
+    channels: u16,
+}
+
+/// Which changes of track fade, when a crossfade is set.
+#[derive(Clone, Copy)]
+pub struct CrossfadePolicy {
+    /// Going to the next track before the end of this one, with Next.
+    pub on_next: bool,
+    /// A track ending; otherwise the next one follows it without a gap.
+    pub on_end: bool,
+    /// Between two tracks of the same album; otherwise they follow each
+    /// other without a gap, or cut on Next.
+    pub within_albums: bool,
+}
+
+/// How long before the end of a gapless track the next one is queued.
+const GAPLESS_PRELOAD: Duration = Duration::from_secs(5);
+
+/// How far into a chapter `previous_chapter` goes back to its start rather
+/// than to the chapter before.
+const CHAPTER_RESTART: Duration = Duration::from_secs(3);
+
+/// A long track left this close to its start or end starts over next time.
+const POSITION_MARGIN: Duration = Duration::from_secs(30);
+
+fn open_source(path: &Path) -> Result<Decoder<BufReader<File>>> {
+    Ok(Decoder::new(BufReader::new(File::open(path)?))?)
 }
 
 impl Player {
-    pub fn new() -> Result<Self> {
+    /// `library` holds the folders favorites and skipped tracks are kept
+    /// relative to (see `config::TrackStore`).
+    pub fn new(library: &[PathBuf]) -> Result<Self> {
         let (stream, stream_handle) = OutputStream::try_default()?;
-        let skip_list = config::SkipList::new()?;
-        let favorites_list = config::FavoritesList::new()?;
+        let skip_list = config::TrackStore::skip_list(library)?;
+        let bans = config::Bans::open()?;
+        let favorites_list = config::TrackStore::favorites(library)?;
+        let play_counts = config::PlayCounts::new()?;
+        let positions = config::Positions::open()?;
+        let bookmarks = config::Bookmarks::open()?;
+        let play_log = PlayLog::new()?;
+        let metadata_cache = cache::MetadataCache::new()?;
 
         Ok(Player {
             sink: None,
             _stream: Some(stream),
             _stream_handle: Some(stream_handle),
-            playlist: Vec::new(),
+            playlist: Arc::default(),
+            all_tracks: None,
+            favorites_only: false,
+            filter: None,
+            shuffle: false,
+            shuffle_albums: false,
+            artist_spacing: 0,
+            rng: StdRng::from_os_rng(),
+            seeded: false,
+            party: false,
+            find_library: None,
+            library: Vec::new(),
+            order: HashMap::new(),
             current_index: 0,
             current_playing: None,
+            played: Vec::new(),
+            priority: Vec::new(),
             skip_list,
+            bans,
             favorites_list,
+            lists_revision: 0,
+            undo: Vec::new(),
+            play_counts,
+            positions,
+            bookmarks,
+            remember_position: Duration::ZERO,
+            play_log,
+            metadata_cache,
+            write_play_counts: false,
             total_duration: None,
             start_time: None,
             paused_duration: Duration::ZERO,
             pause_start: None,
             current_metadata: None,
+            current_technical_info: None,
+            current_art: None,
+            chapters: Arc::default(),
+            levels: Arc::default(),
+            failures: Vec::new(),
+            volume: 1.0,
+            volume_step: 0.1,
+            ramp: None,
+            repeat: RepeatMode::default(),
+            crossfade: Duration::ZERO,
+            crossfade_policy: CrossfadePolicy {
+                on_next: false,
+                on_end: true,
+                within_albums: true,
+            },
+            crossfade_skipped: false,
+            trash: true,
+            fading_out: None,
+            speed: 1.0,
+            gapless: false,
+            stop_after_current: false,
+            start_paused: false,
+            queued: None,
+            folders: HashMap::new(),
+            scrobbler: None,
+            listen: None,
+            cover_art: None,
+            embed_covers: false,
+            pending_covers: Vec::new(),
+            broadcast: None,
+            on_track_end: None,
         })
     }
 
-    /// Modifie les tags d'un fichier MP3 (artist, album, title, year).
+    /// Streams what is played over HTTP on `address` (see `broadcast`), from
+    /// the next track on.
+    pub fn stream_to(&mut self, address: &str) -> Result<(), io::Error> {
+        if let Some(stream_handle) = &self._stream_handle {
+            self.broadcast = Some(broadcast::Broadcast::start(address, stream_handle)?);
+        }
+        Ok(())
+    }
+
+    /// Calls `notify`, from the audio thread, whenever a track has been
+    /// played to its end: `handle_playback` then has something to do.
+    pub fn notify_track_end(&mut self, notify: impl Fn() + Send + Sync + 'static) {
+        self.on_track_end = Some(Arc::new(notify));
+    }
+
+    /// Submits the tracks played to the scrobbling services of `scrobbler`.
+    pub fn set_scrobbler(&mut self, scrobbler: Scrobbler) {
+        self.scrobbler = Some(scrobbler);
+    }
+
+    /// Looks for the covers missing from the tracks played with
+    /// `cover_art`, and writes them into the files if `embed`.
+    pub fn set_cover_art(&mut self, cover_art: CoverArt, embed: bool) {
+        self.cover_art = Some(cover_art);
+        self.embed_covers = embed;
+    }
+
+    /// Modifie les tags d'un fichier MP3 ou MP4 (artist, album, title, year).
     /// Si un champ est None, il n'est pas modifié.
     pub fn edit_tags(
         &self,
@@ -80,6 +819,20 @@ impl Player {
         title: Option<String>,
         year: Option<String>,
     ) -> Result<(), anyhow::Error> {
+        if is_mp4_container(path) {
+            let updates: Vec<([u8; 4], String)> = [
+                (mp4::ARTIST, artist),
+                (mp4::ALBUM, album),
+                (mp4::TITLE, title),
+                (mp4::YEAR, year),
+            ]
+            .into_iter()
+            .filter_map(|(kind, value)| value.map(|v| (kind, v)))
+            .collect();
+            mp4::write_text_items(path, &updates)?;
+            return Ok(());
+        }
+
         let mut tag = match id3::Tag::read_from_path(path) {
             Ok(tag) => tag,
             Err(_) => id3::Tag::new(),
@@ -107,181 +860,1594 @@ impl Player {
         Ok(())
     }
 
-    pub fn set_playlist(&mut self, playlist: Vec<PathBuf>, random: bool) -> Result<()> {
-        let mut filtered_playlist = self.filter_skipped_tracks(playlist)?;
-        if random {
-            filtered_playlist = self.add_favorites_twice(filtered_playlist)?;
-        }
-        self.playlist = filtered_playlist;
-        self.current_index = 0;
-        Ok(())
+    /// Rewrites the repaired fields as UTF-8 text.
+    pub fn apply_encoding_fixes(&self, path: &Path, fixes: &[EncodingFix]) -> Result<()> {
+        let value = |field: &str| {
+            fixes
+                .iter()
+                .find(|fix| fix.field == field)
+                .map(|fix| fix.after.clone())
+        };
+        self.edit_tags(path, value("Artist"), value("Album"), value("Title"), None)
     }
 
-    fn add_favorites_twice(&mut self, playlist: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
-        let mut extended_playlist = Vec::with_capacity(playlist.len() * 2);
+    /// Sets the star rating (1-5, 0 to clear) of the current track in its POPM frame.
+    /// MP4 files have no POPM equivalent and are left untouched.
+    pub fn set_rating(&mut self, stars: u8) -> Result<()> {
+        let Some(track) = self.current_playing.clone() else {
+            return Ok(());
+        };
+        write_rating(&track, stars)?;
+        self.current_metadata = TrackMetadata::from_path(&track);
+        Ok(())
+    }
 
-        for path in &playlist {
-            extended_playlist.push(path.clone());
-            if self.favorites_list.is_favorite(path)? {
-                extended_playlist.push(path.clone());
-            }
+    /// Sets the volume, in percent of the file's level (0 to 200).
+    pub fn set_volume(&mut self, percent: u16) {
+        self.ramp = None;
+        self.volume = (percent.min(200) as f32) / 100.0;
+        if let Some(sink) = &self.sink {
+            sink.set_volume(self.volume);
         }
+    }
 
-        Ok(extended_playlist)
+    /// The volume, in percent of the file's level.
+    pub fn volume(&self) -> u16 {
+        (self.volume * 100.0).round() as u16
     }
 
-    pub fn mark_favorite(&mut self) -> Result<()> {
-        if let Some(track) = &self.current_playing {
-            if self.favorites_list.is_favorite(track)? {
-                self.favorites_list.remove(track)?;
-            } else {
-                self.favorites_list.add(track)?;
-            }
+    /// Raises the volume from silence to where it is over `length`, from now,
+    /// unless it is changed in between.
+    pub fn ramp_volume(&mut self, length: Duration) {
+        if length.is_zero() {
+            return;
         }
-        Ok(())
+        self.ramp = Some((self.volume, Instant::now(), length));
+        self.volume = 0.0;
+        self.apply_volume();
     }
 
-    fn filter_skipped_tracks(&mut self, playlist: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
-        let mut filtered = Vec::with_capacity(playlist.len());
+    /// Sets the volume change of one key press, in percent.
+    pub fn set_volume_step(&mut self, percent: u16) {
+        self.volume_step = percent as f32 / 100.0;
+    }
 
-        for path in playlist {
-            if !self.skip_list.is_skipped(&path)? {
-                filtered.push(path);
-            }
-        }
+    /// What happens at the end of a track and of the playlist.
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+    }
 
-        Ok(filtered)
+    /// Whether `delete_current_track` moves files to the trash (the default)
+    /// or deletes them for good.
+    pub fn set_trash(&mut self, trash: bool) {
+        self.trash = trash;
     }
 
-    pub fn play_next(&mut self) -> Result<()> {
-        if self.playlist.is_empty() {
+    /// How long a track fades into the next one, zero for none.
+    pub fn set_crossfade(&mut self, crossfade: Duration) {
+        self.crossfade = crossfade;
+    }
+
+    /// Which changes of track the crossfade applies to.
+    pub fn set_crossfade_policy(&mut self, policy: CrossfadePolicy) {
+        self.crossfade_policy = policy;
+    }
+
+    /// Tracks at least `length` long start again where they were left the
+    /// last time; zero for none.
+    pub fn set_remember_position(&mut self, length: Duration) {
+        self.remember_position = length;
+    }
+
+    /// Also store play counts in the files themselves (PCNT, and the POPM
+    /// counter when present) each time a track completes.
+    pub fn set_write_play_counts(&mut self, enabled: bool) {
+        self.write_play_counts = enabled;
+    }
+
+    fn record_completed_play(&mut self) -> Result<()> {
+        self.finish_listen()?;
+        let Some(track) = self.current_playing.clone() else {
             return Ok(());
+        };
+        let count = self.play_counts.increment(&track)?;
+        self.play_log.record(&track, self.tag_snapshot())?;
+        if self.write_play_counts && !is_mp4_container(&track) {
+            Self::write_play_count(&track, count)?;
         }
+        Ok(())
+    }
 
-        let playlist_len = self.playlist.len();
-        let mut attempts = 0;
+    /// Writes the play count into the file, never lowering a count that
+    /// another player may already have stored there.
+    fn write_play_count(path: &Path, tracked: u64) -> Result<()> {
+        let mut tag = Tag::read_from_path(path).unwrap_or_else(|_| Tag::new());
+        let in_file = tag
+            .get("PCNT")
+            .and_then(|frame| frame.content().to_unknown().ok())
+            .map(|unknown| decode_play_counter(&unknown.data))
+            .unwrap_or(0);
+        let count = tracked.max(in_file.saturating_add(1));
 
-        while attempts < playlist_len {
-            if self.current_index >= self.playlist.len() {
-                self.current_index = 0;
-            }
+        // Frames of unknown content never replace each other: added as is,
+        // a new PCNT would come after the old one, which is read first.
+        tag.remove("PCNT");
+        tag.add_frame(id3::Frame::with_content(
+            "PCNT",
+            id3::Content::Unknown(id3::frame::Unknown {
+                data: encode_play_counter(count),
+                version: id3::Version::Id3v24,
+            }),
+        ));
+        let popm = tag
+            .frames()
+            .find_map(|frame| frame.content().popularimeter())
+            .cloned();
+        if let Some(mut popm) = popm {
+            popm.counter = count;
+            tag.add_frame(popm);
+        }
 
-            let path = self.playlist[self.current_index].clone();
+        tag.write_to_path(path, id3::Version::Id3v24)?;
+        Ok(())
+    }
 
-            match self.play_file(&path) {
-                Ok(_) => {
-                    self.current_playing = Some(path);
-                    self.current_index = (self.current_index + 1) % self.playlist.len();
-                    return Ok(());
-                }
-                Err(_e) => {
-                    // Could not play file, skip to next
-                    self.current_index = (self.current_index + 1) % self.playlist.len();
-                    attempts += 1;
-                    continue;
-                }
-            }
+    /// Replaces the playlist, leaving out the skipped tracks. In `random` mode
+    /// it is shuffled, the favorites, the tracks rated 4 or 5 stars and those
+    /// not played lately coming up earlier (see `shuffle_weight`), or album
+    /// by album with `set_shuffle_albums`, and shuffled again once all of it
+    /// has played. Call `play_next` to start.
+    ///
+    /// The tags of every entry are read here, in parallel, for the queue and
+    /// search to show titles rather than file names.
+    pub fn set_playlist(&mut self, playlist: Vec<PathBuf>, random: bool) -> Result<()> {
+        let mut filtered_playlist = self.filter_skipped_tracks(playlist)?;
+        self.metadata_cache.preload(&filtered_playlist);
+        self.order = order_of(&filtered_playlist);
+        self.shuffle = random;
+        if random {
+            filtered_playlist = self.shuffled(filtered_playlist)?;
         }
-
-        // No playable files found in playlist
+        self.playlist = Arc::new(filtered_playlist);
+        self.all_tracks = None;
+        self.favorites_only = false;
+        self.filter = None;
+        self.current_index = 0;
+        self.priority.clear();
+        self.metadata_cache.save()?;
         Ok(())
     }
 
-    pub fn play_previous(&mut self) -> Result<()> {
-        if self.playlist.is_empty() {
+    /// Plays only the favorites of the playlist, or the whole of it again.
+    /// Playback goes on with the track after the current one. Fails,
+    /// changing nothing, when the playlist has no favorites.
+    pub fn set_favorites_only(&mut self, on: bool) -> Result<()> {
+        if on == self.favorites_only {
             return Ok(());
         }
+        self.narrow(on, self.filter.clone())
+    }
 
-        let playlist_len = self.playlist.len();
-        let mut attempts = 0;
-
-        while attempts < playlist_len {
-            if self.current_index == 0 {
-                self.current_index = self.playlist.len().saturating_sub(1);
-            } else {
-                self.current_index = self.current_index.saturating_sub(1);
-            }
+    /// Plays only the tracks of the playlist whose tags match `filter`, or
+    /// all of them again for `None`, favorites-only still applying.
+    /// Playback goes on with the track after the current one. Fails,
+    /// changing nothing, when no track matches.
+    pub fn set_filter(&mut self, filter: Option<QueueFilter>) -> Result<()> {
+        if filter == self.filter {
+            return Ok(());
+        }
+        self.narrow(self.favorites_only, filter)
+    }
 
-            let path = self.playlist[self.current_index].clone();
+    pub fn filter(&self) -> Option<&QueueFilter> {
+        self.filter.as_ref()
+    }
 
-            match self.play_file(&path) {
-                Ok(_) => {
-                    self.current_playing = Some(path);
-                    return Ok(());
-                }
-                Err(_e) => {
-                    // Could not play file, skip to previous
-                    attempts += 1;
+    /// Plays the tracks of the whole playlist that `favorites_only` and
+    /// `filter` keep, in the order of the whole playlist.
+    fn narrow(&mut self, favorites_only: bool, filter: Option<QueueFilter>) -> Result<()> {
+        let whole = self.all_tracks.clone().unwrap_or_else(|| Arc::clone(&self.playlist));
+        if favorites_only || filter.is_some() {
+            let mut kept = Vec::new();
+            for path in whole.iter() {
+                if favorites_only && !self.favorites_list.contains(path)? {
                     continue;
                 }
+                if let Some(filter) = &filter {
+                    if !filter.matches(self.metadata_cache.metadata(path).as_ref()) {
+                        continue;
+                    }
+                }
+                kept.push(path.clone());
+            }
+            if kept.is_empty() {
+                match filter.is_some() {
+                    true => anyhow::bail!("No track of the playlist matches"),
+                    false => anyhow::bail!("No favorites in the playlist"),
+                }
             }
+            self.playlist = Arc::new(kept);
+            self.all_tracks = Some(whole);
+        } else {
+            self.playlist = whole;
+            self.all_tracks = None;
         }
-
-        // No playable files found in playlist
+        self.favorites_only = favorites_only;
+        self.filter = filter;
+        self.follow_current();
         Ok(())
     }
 
-    pub fn play_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        if let Some(stream_handle) = &self._stream_handle {
-            let file = File::open(&path)?;
-            let reader = BufReader::new(file);
-            let source = Decoder::new(reader)?;
-
-            self.total_duration = source.total_duration();
-            self.start_time = Some(Instant::now());
-            self.paused_duration = Duration::ZERO;
-            self.pause_start = None;
-
-            self.current_metadata = TrackMetadata::from_path(path.as_ref());
-
-            let sink = Sink::try_new(stream_handle)?;
-            sink.append(source);
-            self.sink = Some(sink);
+    /// Plays the rest of the playlist in random order, then all of it again
+    /// in another one each time, or puts it back in order, the tracks to
+    /// play next included. Playback goes on with the track after the
+    /// current one.
+    pub fn set_shuffle(&mut self, on: bool) -> Result<()> {
+        if on == self.shuffle {
+            return Ok(());
         }
-
+        self.shuffle = on;
+        if on {
+            return self.shuffle_from(self.current_index);
+        }
+        let order = &self.order;
+        let position = |track: &PathBuf| order.get(track).copied().unwrap_or(usize::MAX);
+        Arc::make_mut(&mut self.playlist).sort_by_key(position);
+        if let Some(all_tracks) = &mut self.all_tracks {
+            Arc::make_mut(all_tracks).sort_by_key(position);
+        }
+        self.priority.clear();
+        self.follow_current();
         Ok(())
     }
 
-    pub fn get_current_metadata(&self) -> Option<&TrackMetadata> {
-        self.current_metadata.as_ref()
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
     }
 
-    pub fn pause(&mut self) {
-        if let Some(sink) = &self.sink {
-            sink.pause();
-            self.pause_start = Some(Instant::now());
+    /// Draws the random orders from `seed` from now on: the same tracks
+    /// shuffle the same way each time, by the same settings. The listening
+    /// history is left out of the weights then, as it changes from one run
+    /// to the next.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.seeded = true;
+    }
+
+    /// Makes shuffle draw whole albums, each played through in order, or
+    /// single tracks again. While shuffling, applies to the tracks not
+    /// played yet.
+    pub fn set_shuffle_albums(&mut self, on: bool) -> Result<()> {
+        if on == self.shuffle_albums {
+            return Ok(());
+        }
+        self.shuffle_albums = on;
+        match self.shuffle {
+            true => self.shuffle_from(self.current_index),
+            false => Ok(()),
         }
     }
 
-    pub fn play(&mut self) {
-        if let Some(sink) = &self.sink {
-            sink.play();
-            if let Some(pause_start) = self.pause_start.take() {
-                self.paused_duration += pause_start.elapsed();
-            }
+    /// Makes shuffle put at least `spacing` other tracks between two of the
+    /// same artist where it can, so that an artist with many tracks doesn't
+    /// fill stretches of the queue; 0 for no spacing. While shuffling,
+    /// applies to the tracks not played yet.
+    pub fn set_artist_spacing(&mut self, spacing: usize) -> Result<()> {
+        if spacing == self.artist_spacing {
+            return Ok(());
+        }
+        self.artist_spacing = spacing;
+        match self.shuffle && !self.shuffle_albums {
+            true => self.shuffle_from(self.current_index),
+            false => Ok(()),
         }
     }
 
-    pub fn get_current_track(&mut self) -> Option<&PathBuf> {
-        self.current_playing.as_ref()
+    /// Shuffles again the entries not played yet in this round, the tracks
+    /// to play next staying first, for a stretch of the queue that doesn't
+    /// appeal. The entries already played keep their place, and the current
+    /// track plays on. Past the last entry, shuffles the next round.
+    pub fn reshuffle_rest(&mut self) -> Result<()> {
+        let start = if self.current_index == 0 && self.current_playing.is_some() {
+            0
+        } else if self.playlist.get(self.current_index) == self.current_playing.as_ref() {
+            // After going back, current_index still points at the playing track.
+            self.current_index + 1
+        } else {
+            self.current_index
+        };
+        self.shuffle_from(start)
     }
 
-    pub fn handle_playback(&mut self) -> Result<bool> {
-        if let Some(sink) = &self.sink {
-            if sink.empty() && !self.playlist.is_empty() {
-                if self.current_index >= self.playlist.len() {
-                    // End of playlist reached
+    /// Shuffles the playlist entries from `start` on: those before have
+    /// played in this round.
+    fn shuffle_from(&mut self, start: usize) -> Result<()> {
+        let mut tracks = self.playlist.to_vec();
+        let mut rest = tracks.split_off(start.min(tracks.len()));
+        // The tracks to play next stay first.
+        let first = rest.iter().take_while(|track| self.priority.contains(track)).count();
+        tracks.extend(rest.drain(..first));
+        let start = tracks.len();
+        if self.shuffle_albums {
+            let playing = self.current_playing.clone().map(|track| self.album_key(&track));
+            if start > 0 {
+                // The rest of the album playing goes on first.
+                let (album, others): (Vec<PathBuf>, Vec<PathBuf>) =
+                    rest.into_iter().partition(|track| Some(self.album_key(track)) == playing);
+                tracks.extend(self.album_shuffle(album));
+                tracks.extend(self.album_shuffle(others));
+            } else {
+                let mut shuffled = self.album_shuffle(rest);
+                // Not the album just over again straight away.
+                let mut over = |track: &PathBuf| Some(self.album_key(track)) == playing;
+                let first = shuffled.iter().take_while(|track| over(track)).count();
+                shuffled.rotate_left(first);
+                tracks.extend(shuffled);
+            }
+        } else {
+            tracks.extend(self.weighted_shuffle(rest)?);
+            // Not the track playing again straight away.
+            if tracks.len() > start + 1 && tracks.get(start) == self.current_playing.as_ref() {
+                let track = tracks.remove(start);
+                tracks.push(track);
+            }
+        }
+        self.playlist = Arc::new(tracks);
+        Ok(())
+    }
+
+    /// `tracks` in random order, by track or by album.
+    fn shuffled(&mut self, tracks: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        match self.shuffle_albums {
+            true => Ok(self.album_shuffle(tracks)),
+            false => self.weighted_shuffle(tracks),
+        }
+    }
+
+    /// The albums of `tracks` in random order, the tracks of each by disc
+    /// and track number.
+    fn album_shuffle(&mut self, mut tracks: Vec<PathBuf>) -> Vec<PathBuf> {
+        // In a known order first, for a seed to give the same order whatever
+        // the order the tracks were found in.
+        tracks.sort_unstable();
+        let mut albums: Vec<Vec<PathBuf>> = Vec::new();
+        let mut found = HashMap::new();
+        for track in tracks {
+            let index = *found.entry(self.album_key(&track)).or_insert_with(|| {
+                albums.push(Vec::new());
+                albums.len() - 1
+            });
+            albums[index].push(track);
+        }
+        albums.shuffle(&mut self.rng);
+        let cache = &mut self.metadata_cache;
+        for album in &mut albums {
+            album.sort_by_cached_key(|track| {
+                let metadata = cache.metadata(track);
+                // Without a disc number a track is on the first one, without
+                // a track number it goes last.
+                let disc = metadata.as_ref().and_then(|m| m.disc).unwrap_or(1);
+                (disc, metadata.and_then(|m| m.track).unwrap_or(u32::MAX), track.clone())
+            });
+        }
+        albums.into_iter().flatten().collect()
+    }
+
+    /// What the tracks of an album share: their album tag and the folder
+    /// above their own, which holds the discs of an album in folders of their
+    /// own. Without the tag, their folder.
+    fn album_key(&mut self, track: &Path) -> (Option<String>, PathBuf) {
+        let folder = track.parent().unwrap_or(track);
+        match self.metadata_cache.metadata(track).and_then(|m| m.album) {
+            Some(album) => (Some(album.to_lowercase()), folder.parent().unwrap_or(folder).to_path_buf()),
+            None => (None, folder.to_path_buf()),
+        }
+    }
+
+    /// Moves on to the entry after the one just started, shuffling the
+    /// playlist again once all of it has played.
+    fn advance(&mut self) -> Result<()> {
+        self.current_index = (self.current_index + 1) % self.playlist.len();
+        if self.current_index == 0 && self.party && self.repeat != RepeatMode::One {
+            self.add_party_tracks()?;
+        }
+        if self.current_index == 0 && self.shuffle {
+            self.shuffle_from(0)?;
+        }
+        Ok(())
+    }
+
+    /// Adds random tracks of the library once the last playlist entry
+    /// plays, rather than stopping or starting over after it. Repeating one
+    /// track still does.
+    pub fn set_party(&mut self, on: bool) -> Result<()> {
+        self.party = on;
+        // Already on the last entry.
+        let last = self.current_index == 0 && self.current_playing.is_some();
+        if on && last && self.repeat != RepeatMode::One {
+            self.add_party_tracks()?;
+        }
+        Ok(())
+    }
+
+    /// What party mode draws from, found by `find` the first time it is
+    /// needed, rather than the tracks of the playlist.
+    pub fn set_library(&mut self, find: impl FnOnce() -> Vec<PathBuf> + 'static) {
+        self.find_library = Some(Box::new(find));
+        self.library.clear();
+    }
+
+    /// Enqueues `PARTY_TRACKS` random tracks of the library, favoring those
+    /// not in the playlist yet, then like shuffle does (see
+    /// `shuffle_weight`).
+    fn add_party_tracks(&mut self) -> Result<()> {
+        if let Some(find) = self.find_library.take() {
+            self.library = find();
+            tracing::info!("Party mode: {} tracks in the library", self.library.len());
+        }
+        // The fields rather than all_tracks(), for the rng to be borrowed below.
+        let all_tracks = self.all_tracks.as_deref().unwrap_or(&self.playlist);
+        let pool = match self.library.is_empty() {
+            true => all_tracks,
+            false => &self.library,
+        };
+        let playlist: HashSet<&PathBuf> = all_tracks.iter().collect();
+        let mut candidates: Vec<&PathBuf> = pool.iter().filter(|track| !playlist.contains(track)).collect();
+        if candidates.is_empty() {
+            candidates = pool.iter().collect();
+        }
+        candidates.sort_unstable();
+        // Only some are weighed, for their tags to be read.
+        let drawn = candidates.choose_multiple(&mut self.rng, PARTY_TRACKS * 10).map(|&track| track.clone());
+        let mut tracks = self.weighted_shuffle(drawn.collect())?;
+        tracks.truncate(PARTY_TRACKS);
+        let added = self.enqueue(tracks)?;
+        tracing::info!("Party mode: {} tracks added", added);
+        Ok(())
+    }
+
+    /// Once the current track is over, waits paused on the next one instead
+    /// of playing it. It is turned off then.
+    pub fn set_stop_after_current(&mut self, on: bool) {
+        self.stop_after_current = on;
+    }
+
+    pub fn stop_after_current(&self) -> bool {
+        self.stop_after_current
+    }
+
+    /// Whether only the favorites of the playlist are played.
+    pub fn favorites_only(&self) -> bool {
+        self.favorites_only
+    }
+
+    /// The whole playlist, with the tracks favorites-only play and the
+    /// filter leave out.
+    pub fn all_tracks(&self) -> &[PathBuf] {
+        self.all_tracks.as_deref().unwrap_or(&self.playlist)
+    }
+
+    /// Points playback at the entry after the current track, in a playlist
+    /// that just changed.
+    fn follow_current(&mut self) {
+        self.current_index = self
+            .current_playing
+            .as_ref()
+            .and_then(|current| self.playlist.iter().position(|path| path == current))
+            .map_or(0, |index| (index + 1) % self.playlist.len());
+    }
+
+    /// Reorders the playlist by `key`, tracks missing the field last, and
+    /// merges the repeated entries, turning shuffle off; the tracks to play
+    /// next go back in order too. Playback goes on with the track after the
+    /// current one in the new order.
+    pub fn sort_playlist(&mut self, key: SortKey) -> Result<()> {
+        let cache = &mut self.metadata_cache;
+        sort_tracks(&mut self.playlist, key, cache);
+        // For the order to stay once all of it is played again.
+        if let Some(all_tracks) = &mut self.all_tracks {
+            sort_tracks(all_tracks, key, cache);
+        }
+        self.shuffle = false;
+        self.order = order_of(self.all_tracks());
+        self.priority.clear();
+        self.follow_current();
+        self.metadata_cache.save()?;
+        Ok(())
+    }
+
+    /// Keeps only the tracks rated at least `min_rating` stars.
+    pub fn filter_by_min_rating(&mut self, playlist: Vec<PathBuf>, min_rating: u8) -> Vec<PathBuf> {
+        self.metadata_cache.preload(&playlist);
+        playlist
+            .into_iter()
+            .filter(|path| {
+                self.metadata_cache
+                    .metadata(path)
+                    .and_then(|metadata| metadata.rating)
+                    .is_some_and(|rating| rating >= min_rating)
+            })
+            .collect()
+    }
+
+    /// Shuffles `playlist` so that each track comes before the others in
+    /// proportion to its `shuffle_weight` (Efraimidis-Spirakis sampling).
+    fn weighted_shuffle(&mut self, mut playlist: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        let last_played = match self.seeded {
+            true => HashMap::new(),
+            false => self.play_log.last_played().unwrap_or_else(|e| {
+                tracing::warn!("Couldn't read the listening history: {}", e);
+                HashMap::new()
+            }),
+        };
+        // As in album_shuffle.
+        playlist.sort_unstable();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+        let mut keyed = Vec::with_capacity(playlist.len());
+        for path in playlist {
+            let favorite = self.favorites_list.contains(&path)?;
+            let rating = self.metadata_cache.metadata(&path).and_then(|m| m.rating);
+            // The history has canonical paths, which those found under an
+            // absolute folder already are, short of symbolic links.
+            let canonical = match path.is_absolute() {
+                true => None,
+                false => path.canonicalize().ok(),
+            };
+            let played = last_played
+                .get(canonical.as_ref().unwrap_or(&path))
+                .map(|&time| Duration::from_secs(now.saturating_sub(time)));
+            let weight = shuffle_weight(favorite, rating, played);
+            // 1 - random() is in (0, 1]: ln never sees 0.
+            let key = -(1.0 - self.rng.random::<f64>()).ln() / weight;
+            keyed.push((key, path));
+        }
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(self.space_artists(keyed.into_iter().map(|(_, path)| path).collect()))
+    }
+
+    /// `tracks` in the same order, but for those whose artist played less
+    /// than `artist_spacing` tracks before, counting the tracks played
+    /// already: each goes after the next ones of other artists if any come
+    /// soon enough, or else back among the tracks before where it is far
+    /// enough from its artist. Tracks without an artist tag stay in place.
+    fn space_artists(&mut self, tracks: Vec<PathBuf>) -> Vec<PathBuf> {
+        let spacing = self.artist_spacing;
+        if spacing == 0 {
+            return tracks;
+        }
+        // Artists by number, cheaper to compare.
+        let mut numbers: HashMap<String, usize> = HashMap::new();
+        let cache = &mut self.metadata_cache;
+        let mut artist = |track: &Path| {
+            let name = cache.metadata(track)?.artist?.trim().to_lowercase();
+            let next = numbers.len();
+            (!name.is_empty()).then(|| *numbers.entry(name).or_insert(next))
+        };
+        let played: Vec<&PathBuf> = self.played.iter().chain(&self.current_playing).collect();
+        // The artists of the last tracks played, then of `spaced`.
+        let mut artists: Vec<Option<usize>> =
+            played[played.len().saturating_sub(spacing)..].iter().map(|track| artist(track)).collect();
+        let before = artists.len();
+        let mut left: VecDeque<(Option<usize>, PathBuf)> =
+            tracks.into_iter().map(|track| (artist(&track), track)).collect();
+        let mut spaced = Vec::with_capacity(left.len());
+        // Whether `artist` is far enough from its other tracks at `at`.
+        let fits = |artists: &[Option<usize>], at: usize, artist: &Option<usize>| {
+            let around = &artists[at.saturating_sub(spacing)..(at + spacing).min(artists.len())];
+            artist.is_none() || !around.contains(artist)
+        };
+        while !left.is_empty() {
+            let end = artists.len();
+            let mut next = left.iter().take(ARTIST_LOOK_AHEAD);
+            let (index, at) = match next.position(|(artist, _)| fits(&artists, end, artist)) {
+                Some(index) => (index, end),
+                None => {
+                    let earliest = end.saturating_sub(ARTIST_LOOK_AHEAD).max(before);
+                    (0, (earliest..end).rev().find(|&at| fits(&artists, at, &left[0].0)).unwrap_or(end))
+                }
+            };
+            let Some((artist, track)) = left.remove(index) else {
+                break;
+            };
+            artists.insert(at, artist);
+            spaced.insert(at - before, track);
+        }
+        spaced
+    }
+
+    /// Adds the current track to the favorites, or takes it out.
+    pub fn mark_favorite(&mut self) -> Result<()> {
+        if let Some(track) = self.current_playing.clone() {
+            if self.favorites_list.contains(&track)? {
+                let removed = self.favorites_list.remove(&track)?;
+                self.remember(Undo::Unfavorite(track, removed));
+            } else {
+                let tags = self.tag_snapshot();
+                if let Some(id) = self.favorites_list.add(&track, "marked as favorite", tags)? {
+                    self.remember(Undo::Favorite(track, id));
+                }
+            }
+            self.lists_revision += 1;
+        }
+        Ok(())
+    }
+
+    fn remember(&mut self, change: Undo) {
+        if self.undo.len() == UNDO_STEPS {
+            self.undo.remove(0);
+        }
+        self.undo.push(change);
+    }
+
+    /// Takes back the latest favorite marked or unmarked, track skipped
+    /// (for good or until quitting) or file moved to the trash: a skipped
+    /// track goes back to its place in the playlist, and plays again from
+    /// where it was if it was playing; a trashed file is restored from the
+    /// trash and goes back to its place too. Returns what was undone, if
+    /// anything was left to.
+    pub fn undo(&mut self) -> Result<Option<String>> {
+        let Some(change) = self.undo.pop() else {
+            return Ok(None);
+        };
+        let message = match change {
+            Undo::Favorite(track, id) => {
+                self.favorites_list.remove_id(&id)?;
+                format!("{} is no longer a favorite", self.track_label(&track))
+            }
+            Undo::Unfavorite(track, entries) => {
+                self.favorites_list.restore(entries)?;
+                format!("{} is a favorite again", self.track_label(&track))
+            }
+            Undo::Skip {
+                track,
+                id,
+                index,
+                stash_index,
+                position,
+            } => {
+                if let Some(id) = &id {
+                    self.skip_list.remove_id(id)?;
+                }
+                let index = self.reinsert(&track, index, stash_index);
+                if let (Some(index), Some(position)) = (index, position) {
+                    self.play_index(index)?;
+                    if self.current_playing.as_ref() == Some(&track) {
+                        self.seek_to(position)?;
+                    }
+                }
+                match id {
+                    Some(_) => format!("{} is no longer skipped", self.track_label(&track)),
+                    None => format!("{} is back in the playlist", self.track_label(&track)),
+                }
+            }
+            Undo::Delete {
+                track,
+                trashed,
+                index,
+                stash_index,
+            } => {
+                if let Err(e) = restore_from_trash(&trashed) {
+                    self.remember(Undo::Delete {
+                        track,
+                        trashed,
+                        index,
+                        stash_index,
+                    });
+                    return Err(e);
+                }
+                self.reinsert(&track, index, stash_index);
+                format!("{} is back from the trash", self.track_label(&track))
+            }
+        };
+        self.lists_revision += 1;
+        Ok(Some(message))
+    }
+
+    /// Puts `track` back where it was in the playlist, and in the whole
+    /// playlist while only the favorites are played. Returns its place in
+    /// the playlist, if it was in it.
+    fn reinsert(&mut self, track: &Path, index: Option<usize>, stash_index: Option<usize>) -> Option<usize> {
+        if let (Some(all_tracks), Some(index)) = (&mut self.all_tracks, stash_index) {
+            let all_tracks = Arc::make_mut(all_tracks);
+            all_tracks.insert(index.min(all_tracks.len()), track.to_path_buf());
+        }
+        let index = index?.min(self.playlist.len());
+        Arc::make_mut(&mut self.playlist).insert(index, track.to_path_buf());
+        if index < self.current_index {
+            self.current_index += 1;
+        }
+        Some(index)
+    }
+
+    /// Takes the favorite with this id out, even if its file is gone.
+    pub fn remove_favorite(&mut self, id: &str) -> Result<()> {
+        self.favorites_list.remove_id(id)?;
+        self.lists_revision += 1;
+        Ok(())
+    }
+
+    /// Takes the skipped track with this id out of the skip list, even if
+    /// its file is gone. It can then be played and enqueued again.
+    pub fn unskip(&mut self, id: &str) -> Result<()> {
+        self.skip_list.remove_id(id)?;
+        self.lists_revision += 1;
+        Ok(())
+    }
+
+    /// The favorites, oldest first, those whose file is gone included.
+    pub fn favorites(&self) -> &[config::TrackEntry] {
+        self.favorites_list.entries()
+    }
+
+    /// The skipped tracks, oldest first, those whose file is gone included.
+    pub fn skipped(&self) -> &[config::TrackEntry] {
+        self.skip_list.entries()
+    }
+
+    /// Changes whenever a track is added to or taken out of the favorites
+    /// or the skip list, for whoever keeps `favorites` and `skipped` to know
+    /// when to look again.
+    pub fn lists_revision(&self) -> u64 {
+        self.lists_revision
+    }
+
+    /// Adds `tracks` at the end of the playlist, leaving out the skipped
+    /// ones. They play even when only the favorites do. Returns how many
+    /// were added.
+    pub fn enqueue(&mut self, tracks: Vec<PathBuf>) -> Result<usize> {
+        let tracks = self.filter_skipped_tracks(tracks)?;
+        self.metadata_cache.preload(&tracks);
+        self.metadata_cache.save()?;
+        // Past the last entry, play_next has wrapped the index around: the
+        // new tracks come next instead.
+        if self.current_index == 0 && self.current_playing.is_some() {
+            self.current_index = self.playlist.len();
+        }
+        let count = tracks.len();
+        for track in &tracks {
+            let next = self.order.len();
+            self.order.entry(track.clone()).or_insert(next);
+        }
+        if let Some(all_tracks) = &mut self.all_tracks {
+            Arc::make_mut(all_tracks).extend(tracks.iter().cloned());
+        }
+        Arc::make_mut(&mut self.playlist).extend(tracks);
+        Ok(count)
+    }
+
+    fn filter_skipped_tracks(&mut self, playlist: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        let mut filtered = Vec::with_capacity(playlist.len());
+
+        for path in playlist {
+            if self.skip_list.contains(&path)? {
+                tracing::debug!("In the skip list: {}", path.display());
+            } else if let Some(ban) = self.banned(&path) {
+                tracing::debug!("Banned ({}): {}", ban.describe(), path.display());
+            } else {
+                filtered.push(path);
+            }
+        }
+
+        Ok(filtered)
+    }
+
+    /// Plays the next playlist entry, wrapping around, and skips those that
+    /// can't be played.
+    pub fn play_next(&mut self) -> Result<()> {
+        if self.playlist.is_empty() {
+            return Ok(());
+        }
+
+        let playlist_len = self.playlist.len();
+        let mut attempts = 0;
+
+        while attempts < playlist_len {
+            if self.current_index >= self.playlist.len() {
+                self.current_index = 0;
+            }
+
+            let path = self.playlist[self.current_index].clone();
+
+            match self.play_file(&path) {
+                Ok(_) => {
+                    self.current_playing = Some(path);
+                    return self.advance();
+                }
+                Err(e) => {
+                    // Could not play file, skip to next
+                    self.report_failure(&path, &e);
+                    self.current_index = (self.current_index + 1) % self.playlist.len();
+                    attempts += 1;
+                    continue;
+                }
+            }
+        }
+
+        // No playable files found in playlist
+        Ok(())
+    }
+
+    /// Jumps playback to the playlist entry at `index`.
+    pub fn play_index(&mut self, index: usize) -> Result<()> {
+        if index < self.playlist.len() {
+            self.current_index = index;
+            self.play_next()?;
+        }
+        Ok(())
+    }
+
+    /// Plays `track` of the queue straight away: it moves up to the first
+    /// entry of the queue, so that those it passes over still come next,
+    /// in the same order.
+    pub fn play_upcoming(&mut self, track: &Path) -> Result<()> {
+        let len = self.playlist.len();
+        let next = match len {
+            0 => 0,
+            _ => self.next_index(),
+        };
+        let mut queue = (0..len.saturating_sub(1)).map(|offset| (next + offset) % len);
+        let Some(index) = queue.find(|&index| self.playlist[index] == track) else {
+            anyhow::bail!("{}: no longer in the queue", track.display());
+        };
+        let playlist = Arc::make_mut(&mut self.playlist);
+        let entry = playlist.remove(index);
+        // Taken from before the queue, after a wrap, it leaves a gap there.
+        let at = if index < next { next - 1 } else { next };
+        playlist.insert(at, entry);
+        self.play_index(at)
+    }
+
+    /// Puts `track` right after the current one, after the others put there
+    /// and not played yet: moved there if the queue has it, added to the
+    /// playlist otherwise. Returns false, changing nothing, for a track in
+    /// the skip list.
+    pub fn play_after_current(&mut self, track: &Path) -> Result<bool> {
+        let len = self.playlist.len();
+        let next = match len {
+            0 => 0,
+            _ => self.next_index(),
+        };
+        let mut queue = (0..len.saturating_sub(1)).map(|offset| (next + offset) % len);
+        match queue.find(|&index| self.playlist[index] == track) {
+            Some(index) => {
+                Arc::make_mut(&mut self.playlist).remove(index);
+                if index < self.current_index {
+                    self.current_index -= 1;
+                }
+            }
+            None => {
+                if self.filter_skipped_tracks(vec![track.to_path_buf()])?.is_empty() {
                     return Ok(false);
                 }
+                self.metadata_cache.preload(&[track.to_path_buf()]);
+                self.metadata_cache.save()?;
+                let next = self.order.len();
+                self.order.entry(track.to_path_buf()).or_insert(next);
+                if let Some(all_tracks) = &mut self.all_tracks {
+                    Arc::make_mut(all_tracks).push(track.to_path_buf());
+                }
+            }
+        }
+        // Past the last entry, play_next has wrapped the index around: the
+        // track goes at the end instead, like an enqueued one.
+        let mut at = if self.current_index == 0 && self.current_playing.is_some() {
+            self.current_index = self.playlist.len();
+            self.current_index
+        } else if self.playlist.is_empty() {
+            0
+        } else {
+            self.next_index()
+        };
+        let playlist = Arc::make_mut(&mut self.playlist);
+        while playlist.get(at).is_some_and(|entry| self.priority.contains(entry)) {
+            at += 1;
+        }
+        playlist.insert(at, track.to_path_buf());
+        self.priority.push(track.to_path_buf());
+        Ok(true)
+    }
+
+    /// How many of the next tracks were put there with
+    /// `play_after_current`.
+    pub fn priority_count(&self) -> usize {
+        let upcoming = self.upcoming(self.priority.len());
+        upcoming.iter().take_while(|track| self.priority.contains(track)).count()
+    }
+
+    /// Plays `track`, added at the end of the playlist first if it isn't in it.
+    pub fn play_track(&mut self, track: &Path) -> Result<()> {
+        if !track.is_file() {
+            anyhow::bail!("{}: not found", track.display());
+        }
+        let index = match self.playlist.iter().position(|path| path == track) {
+            Some(index) => index,
+            None => {
+                if self.enqueue(vec![track.to_path_buf()])? == 0 {
+                    anyhow::bail!("{}: in the skip list", track.display());
+                }
+                self.playlist.len() - 1
+            }
+        };
+        self.play_index(index)
+    }
+
+    /// Goes back to the track played before the current one, shuffled or
+    /// not, passing over those no longer in the playlist. Before any, plays
+    /// the previous playlist entry, wrapping around.
+    pub fn play_previous(&mut self) -> Result<()> {
+        if self.playlist.is_empty() {
+            return Ok(());
+        }
+
+        while let Some(track) = self.played.pop() {
+            // Skipped, deleted or left out since.
+            let Some(index) = self.playlist.iter().position(|entry| *entry == track) else {
+                continue;
+            };
+            // Going back doesn't count the track left as played before.
+            let played = self.played.len();
+            let result = self.play_file(&track);
+            self.played.truncate(played);
+            match result {
+                Ok(_) => {
+                    self.current_playing = Some(track);
+                    self.current_index = (index + 1) % self.playlist.len();
+                    return Ok(());
+                }
+                Err(e) => self.report_failure(&track, &e),
+            }
+        }
+
+        let playlist_len = self.playlist.len();
+        let mut attempts = 0;
+
+        while attempts < playlist_len {
+            if self.current_index == 0 {
+                self.current_index = self.playlist.len().saturating_sub(1);
+            } else {
+                self.current_index = self.current_index.saturating_sub(1);
+            }
+
+            let path = self.playlist[self.current_index].clone();
+
+            match self.play_file(&path) {
+                Ok(_) => {
+                    self.current_playing = Some(path);
+                    return Ok(());
+                }
+                Err(e) => {
+                    // Could not play file, skip to previous
+                    self.report_failure(&path, &e);
+                    attempts += 1;
+                    continue;
+                }
+            }
+        }
+
+        // No playable files found in playlist
+        Ok(())
+    }
 
-                self.play_next()?;
-                return Ok(true);
+    /// Plays `path` at once, whether it is in the playlist or not.
+    pub fn play_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        // Jumping to another track cuts a fade short, and drops the queued one.
+        self.fading_out = None;
+        self.queued = None;
+        if self._stream_handle.is_some() {
+            let source = open_source(path.as_ref())?;
+            self.start_track(
+                path.as_ref(),
+                source.total_duration(),
+                source.sample_rate(),
+                source.channels(),
+            )?;
+
+            if let Some(sink) = self.new_sink(source, self.start_paused)? {
+                self.sink = Some(sink);
+            }
+            if std::mem::take(&mut self.start_paused) {
+                self.pause();
+            }
+            if let Some(position) = self.long_track().then(|| self.positions.get(path.as_ref())).flatten() {
+                // Formats that cannot seek start over.
+                if let Err(e) = self.seek_to(position) {
+                    let path = path.as_ref().display();
+                    tracing::info!("Couldn't resume {} at {:.0}s: {:#}", path, position.as_secs_f64(), e);
+                }
             }
         }
 
+        Ok(())
+    }
+
+    /// A sink playing `source` at the volume and speed of the player, paused
+    /// if `paused`. `None` without an audio output.
+    fn new_sink(&self, source: Decoder<BufReader<File>>, paused: bool) -> Result<Option<Sink>> {
+        let Some(stream_handle) = &self._stream_handle else {
+            return Ok(None);
+        };
+        let sink = match &self.broadcast {
+            Some(broadcast) => broadcast.sink(),
+            None => Sink::try_new(stream_handle)?,
+        };
+        sink.set_volume(self.volume);
+        sink.set_speed(self.speed);
+        if paused {
+            sink.pause();
+        }
+        sink.append(tap::Tap::new(source, Arc::clone(&self.levels), self.on_track_end.clone()));
+        Ok(Some(sink))
+    }
+
+    /// Opens the audio output again, as the computer wakes up: the one
+    /// opened before sleeping is often dead. The current track goes on from
+    /// `position`, the last one known before sleeping, paused if it was.
+    pub fn reopen_output(&mut self, position: Option<Duration>) -> Result<()> {
+        let (stream, stream_handle) = OutputStream::try_default()?;
+        if let Some(broadcast) = &mut self.broadcast {
+            broadcast.play_on(&stream_handle)?;
+        }
+        self.fading_out = None;
+        self.queued = None;
+        let previous = self.sink.take();
+        self._stream = Some(stream);
+        self._stream_handle = Some(stream_handle);
+        let (Some(track), Some(previous)) = (self.current_playing.clone(), previous) else {
+            return Ok(());
+        };
+        let paused = previous.is_paused();
+        drop(previous);
+        self.sink = self.new_sink(open_source(&track)?, paused)?;
+
+        let now = Instant::now();
+        self.start_time = Some(now);
+        self.paused_duration = Duration::ZERO;
+        self.pause_start = paused.then_some(now);
+        if let Some(position) = position.filter(|position| !position.is_zero()) {
+            // Formats that cannot seek start over.
+            if let Err(e) = self.seek_to(position) {
+                let seconds = position.as_secs_f64();
+                tracing::info!("Couldn't go back to {:.0}s of {}: {:#}", seconds, track.display(), e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the current track is long enough for its position to be
+    /// remembered.
+    fn long_track(&self) -> bool {
+        !self.remember_position.is_zero()
+            && self.total_duration.is_some_and(|total| total >= self.remember_position)
+    }
+
+    /// Resets the clock and loads the details of `path`, which starts playing.
+    fn start_track(
+        &mut self,
+        path: &Path,
+        total_duration: Option<Duration>,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<()> {
+        self.finish_listen()?;
+        if let Some(previous) = self.current_playing.clone().filter(|previous| previous != path) {
+            if self.played.len() == PLAYED_KEPT {
+                self.played.remove(0);
+            }
+            self.played.push(previous);
+        }
+        tracing::info!("Playing {}", path.display());
+        if let Some(index) = self.priority.iter().position(|track| track == path) {
+            self.priority.remove(index);
+        }
+        self.total_duration = total_duration;
+        self.start_time = Some(Instant::now());
+        self.paused_duration = Duration::ZERO;
+        self.pause_start = None;
+
+        let settings = self.folder_settings(path);
+        self.speed = settings.speed.unwrap_or(1.0);
+        self.gapless = settings.gapless.unwrap_or(false);
+        self.crossfade_skipped = false;
+
+        self.embed_pending_covers(path);
+        self.current_metadata = self.metadata_cache.metadata(path);
+        self.current_art = self.metadata_cache.thumbnail(path);
+        self.request_cover(path);
+        self.metadata_cache.save()?;
+        self.current_technical_info =
+            Some(TechnicalInfo::probe(path, sample_rate, channels, total_duration));
+        self.chapters = Arc::new(chapters::read(path));
+
+        if let Some(scrobbler) = &self.scrobbler {
+            let metadata = self.current_metadata.as_ref();
+            let track = scrobble::Track::new(
+                metadata.and_then(|m| m.artist.as_deref()),
+                metadata.and_then(|m| m.title.as_deref()),
+                metadata.and_then(|m| m.album.as_deref()),
+                total_duration,
+            );
+            if let Some(track) = track {
+                scrobbler.now_playing(&track);
+                self.listen = Some(scrobble::Listen::new(track, self.speed));
+            }
+        }
+        Ok(())
+    }
+
+    /// Asks for the cover of `path` if it has none. With `embed_covers`, a
+    /// cover found earlier but not embedded yet is asked for again.
+    fn request_cover(&self, path: &Path) {
+        let Some(cover_art) = &self.cover_art else {
+            return;
+        };
+        let missing = self.current_art.is_none()
+            || (self.embed_covers && !is_mp4_container(path) && embedded_cover(path).is_none());
+        let metadata = self.current_metadata.as_ref();
+        let artist = metadata.and_then(|m| m.artist.as_deref());
+        let album = metadata.and_then(|m| m.album.as_deref());
+        if let (true, Some(artist), Some(album)) = (missing, artist, album) {
+            cover_art.request(path.to_path_buf(), artist, album);
+        }
+    }
+
+    /// Shows the covers found for the current track, and keeps them all for
+    /// the next time their track plays, or to embed them.
+    fn receive_covers(&mut self) {
+        let Some(cover_art) = &self.cover_art else {
+            return;
+        };
+        for cover in cover_art.take() {
+            match cover {
+                Ok(cover) => {
+                    let thumbnail = self.metadata_cache.store_thumbnail(&cover.path, &cover.image);
+                    if self.current_playing.as_ref() == Some(&cover.path) {
+                        self.current_art = thumbnail;
+                    }
+                    if self.embed_covers && !is_mp4_container(&cover.path) {
+                        self.pending_covers.push(cover);
+                    }
+                }
+                Err(e) => self.failures.push(e),
+            }
+        }
+    }
+
+    /// Writes the covers waiting to be embedded into their files, except
+    /// into `playing`, which is still being read.
+    fn embed_pending_covers(&mut self, playing: &Path) {
+        let (waiting, ready) = std::mem::take(&mut self.pending_covers)
+            .into_iter()
+            .partition(|cover| cover.path == playing);
+        self.pending_covers = waiting;
+        for cover in ready {
+            if let Err(e) = embed_cover(&cover) {
+                self.failures.push(format!("Couldn't embed the cover in {}: {}", cover.path.display(), e));
+            }
+        }
+    }
+
+    /// Scrobbles the track being left if it was listened to long enough.
+    /// Where a long track was left is kept, or forgotten if it was near its
+    /// start or end.
+    pub fn finish_listen(&mut self) -> Result<(), io::Error> {
+        if let (Some(track), Some(position), Some(total), true) = (
+            self.current_playing.clone(),
+            self.get_current_position(),
+            self.total_duration,
+            self.long_track(),
+        ) {
+            let result = match position < POSITION_MARGIN || position + POSITION_MARGIN > total {
+                true => self.positions.forget(&track),
+                false => self.positions.set(&track, position),
+            };
+            if let Err(e) = result {
+                self.failures.push(format!("Couldn't save the position in {}: {}", track.display(), e));
+            }
+        }
+        if let (Some(scrobbler), Some(listen)) = (&self.scrobbler, self.listen.take()) {
+            if listen.counts() {
+                scrobbler.scrobble(&listen.track)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The `.ksound.toml` settings applying to `track`. A broken file is
+    /// reported once and ignored.
+    fn folder_settings(&mut self, track: &Path) -> config::FolderSettings {
+        let folder = track.parent().unwrap_or(track).to_path_buf();
+        if let Some(settings) = self.folders.get(&folder) {
+            return *settings;
+        }
+        let settings = config::FolderSettings::find(&folder).unwrap_or_else(|e| {
+            self.failures.push(format!("Ignored folder settings: {}", e));
+            config::FolderSettings::default()
+        });
+        self.folders.insert(folder, settings);
+        settings
+    }
+
+    /// Tags of the current track.
+    pub fn get_current_metadata(&self) -> Option<&TrackMetadata> {
+        self.current_metadata.as_ref()
+    }
+
+    /// Format, sample rate, channels, ... of the current track.
+    pub fn get_current_technical_info(&self) -> Option<&TechnicalInfo> {
+        self.current_technical_info.as_ref()
+    }
+
+    /// Peak level of the left and right channels since the last call, 0.0 to 1.0.
+    pub fn take_levels(&self) -> [f32; 2] {
+        self.levels.take()
+    }
+
+    /// Every entry, in play order.
+    pub fn playlist(&self) -> &[PathBuf] {
+        &self.playlist
+    }
+
+    /// The playlist as it is now, without copying it: it stays the same
+    /// `Arc` until the playlist changes.
+    pub fn shared_playlist(&self) -> Arc<Vec<PathBuf>> {
+        Arc::clone(&self.playlist)
+    }
+
+    /// Changes whenever tags are read or read again, for whoever keeps
+    /// `cached_metadata` results to know when to look again.
+    pub fn metadata_revision(&self) -> u64 {
+        self.metadata_cache.revision()
+    }
+
+    /// The next `count` entries of the queue, in play order.
+    pub fn upcoming(&self, count: usize) -> Vec<&PathBuf> {
+        let len = self.playlist.len();
+        if len == 0 {
+            return Vec::new();
+        }
+        let start = self.next_index();
+        (0..count.min(len - 1)).map(|offset| &self.playlist[(start + offset) % len]).collect()
+    }
+
+    /// Index of the first entry of the queue; the playlist can't be empty.
+    fn next_index(&self) -> usize {
+        let len = self.playlist.len();
+        // After going back, current_index still points at the playing track.
+        if self.current_playing.as_ref() == self.playlist.get(self.current_index % len) {
+            (self.current_index + 1) % len
+        } else {
+            self.current_index % len
+        }
+    }
+
+    /// Tags of `path` if they were already read, without touching the file.
+    pub fn cached_metadata(&self, path: &Path) -> Option<&TrackMetadata> {
+        self.metadata_cache.peek(path)
+    }
+
+    /// "Artist - Title" for display, from the tag cache, or the file name
+    /// when the tags are unknown or not loaded yet.
+    pub fn track_label(&self, path: &Path) -> String {
+        label(path, self.metadata_cache.peek(path))
+    }
+
+    /// Cover of the current track, embedded or downloaded.
+    pub fn get_current_art(&self) -> Option<&Thumbnail> {
+        self.current_art.as_ref()
+    }
+
+    /// Pauses the current track, if any.
+    pub fn pause(&mut self) {
+        self.end_crossfade();
+        if let Some(sink) = &self.sink {
+            sink.pause();
+            self.pause_start = Some(Instant::now());
+        }
+        if let Some(listen) = &mut self.listen {
+            listen.pause();
+        }
+    }
+
+    /// Resumes the current track, if any.
+    pub fn play(&mut self) {
+        if let Some(sink) = &self.sink {
+            sink.play();
+            if let Some(pause_start) = self.pause_start.take() {
+                self.paused_duration += pause_start.elapsed();
+            }
+        }
+        if let Some(listen) = &mut self.listen {
+            listen.resume();
+        }
+    }
+
+    /// Moves the playback position by `seconds` (backwards if negative),
+    /// staying within the track.
+    pub fn seek_by(&mut self, seconds: i64) -> Result<()> {
+        let Some(position) = self.get_current_position() else {
+            return Ok(());
+        };
+        let offset = Duration::from_secs(seconds.unsigned_abs());
+        let target = if seconds < 0 {
+            position.saturating_sub(offset)
+        } else {
+            position + offset
+        };
+        self.seek_to(target)
+    }
+
+    /// Moves the playback position to `target`, staying within the track.
+    pub fn seek_to(&mut self, mut target: Duration) -> Result<()> {
+        let Some(sink) = &self.sink else {
+            return Ok(());
+        };
+        if let Some(total) = self.total_duration {
+            target = target.min(total);
+        }
+        sink.try_seek(target).map_err(|e| anyhow::anyhow!("Cannot seek: {}", e))?;
+
+        // Restart the clock at the new position.
+        let now = Instant::now();
+        let elapsed = target.div_f32(self.speed);
+        self.start_time = Some(now.checked_sub(elapsed).unwrap_or(now));
+        self.paused_duration = Duration::ZERO;
+        if self.pause_start.is_some() {
+            self.pause_start = Some(now);
+        }
+        Ok(())
+    }
+
+    /// The chapters of the current track, in order.
+    pub fn chapters(&self) -> Arc<Vec<Chapter>> {
+        Arc::clone(&self.chapters)
+    }
+
+    /// Seeks to the start of the next chapter. Returns it, or `None` in the
+    /// last chapter and without chapters.
+    pub fn next_chapter(&mut self) -> Result<Option<Chapter>> {
+        let position = self.get_current_position().unwrap_or_default();
+        let Some(chapter) = self.chapters.iter().find(|chapter| chapter.start > position).cloned() else {
+            return Ok(None);
+        };
+        self.seek_to(chapter.start)?;
+        Ok(Some(chapter))
+    }
+
+    /// Seeks to the start of the current chapter, or of the one before in
+    /// the first seconds of a chapter. Returns it, or `None` without
+    /// chapters.
+    pub fn previous_chapter(&mut self) -> Result<Option<Chapter>> {
+        let position = self.get_current_position().unwrap_or_default();
+        let index = match chapter_at(&self.chapters, position) {
+            Some(index) if index > 0 && position < self.chapters[index].start + CHAPTER_RESTART => index - 1,
+            Some(index) => index,
+            None if self.chapters.is_empty() => return Ok(None),
+            // Before the first one.
+            None => 0,
+        };
+        let chapter = self.chapters[index].clone();
+        self.seek_to(chapter.start)?;
+        Ok(Some(chapter))
+    }
+
+    /// The bookmarks of `track`, in the order of their positions.
+    pub fn bookmarks(&mut self, track: &Path) -> Result<Vec<config::Bookmark>> {
+        Ok(self.bookmarks.list(track)?)
+    }
+
+    /// Bookmarks a place in `track`, in place of a bookmark of the same name.
+    pub fn add_bookmark(&mut self, track: &Path, bookmark: &config::Bookmark) -> Result<()> {
+        Ok(self.bookmarks.add(track, &bookmark.name, bookmark.position)?)
+    }
+
+    /// Returns whether `track` had a bookmark named `name`.
+    pub fn remove_bookmark(&mut self, track: &Path, name: &str) -> Result<bool> {
+        Ok(self.bookmarks.remove(track, name)?)
+    }
+
+    /// The track playing, or paused.
+    pub fn get_current_track(&self) -> Option<&PathBuf> {
+        self.current_playing.as_ref()
+    }
+
+    /// Moves on when the track ends, or when it is time to fade into the
+    /// next one. Returns false once the playlist is over (repeat off).
+    pub fn handle_playback(&mut self) -> Result<bool> {
+        self.receive_covers();
+        self.update_crossfade();
+        self.update_ramp();
+        let Some(sink) = &self.sink else {
+            return Ok(true);
+        };
+        if self.playlist.is_empty() {
+            return Ok(true);
+        }
+
+        // The queued track has taken over in the sink.
+        if self.queued.is_some() && sink.len() <= 1 {
+            self.record_completed_play()?;
+            self.follow_queued()?;
+            return Ok(true);
+        }
+
+        let finished = sink.empty();
+        // After the last entry, play_next has wrapped the index around.
+        let last = self.repeat == RepeatMode::Off && self.current_index == 0;
+        let moving_on =
+            !finished && !last && self.repeat != RepeatMode::One && !self.stop_after_current;
+        if moving_on && self.gapless {
+            if self.queued.is_none() && self.time_left().is_some_and(|left| left <= GAPLESS_PRELOAD) {
+                self.queue_next(true);
+            }
+            return Ok(true);
+        }
+        let fade = moving_on && self.crossfade_due();
+        if fade && !self.fades_into_next(self.crossfade_policy.on_end) {
+            self.crossfade_skipped = true;
+            tracing::debug!("No crossfade into the next track, by the crossfade policy");
+            // Runs into the next one instead, or else ends the usual way.
+            self.queue_next(false);
+            return Ok(true);
+        }
+        if !finished && !fade {
+            return Ok(true);
+        }
+
+        self.record_completed_play()?;
+        if last {
+            // End of playlist reached
+            return Ok(false);
+        }
+        if std::mem::take(&mut self.stop_after_current) {
+            self.start_paused = true;
+            let result = self.play_next();
+            self.start_paused = false;
+            return result.map(|_| true);
+        }
+        match self.repeat {
+            RepeatMode::One => {
+                if let Some(track) = self.current_playing.clone() {
+                    if let Err(e) = self.play_file(&track) {
+                        self.report_failure(&track, &e);
+                        self.play_next()?;
+                    }
+                }
+            }
+            _ => self.play_next_fading(fade)?,
+        }
         Ok(true)
     }
 
+    /// Plays the next entry before the end of the current track, as Next
+    /// does: fading into it if the crossfade policy says so.
+    pub fn skip_to_next(&mut self) -> Result<()> {
+        let playing = self.sink.as_ref().is_some_and(|sink| !sink.is_paused() && !sink.empty());
+        let on_next = self.crossfade_policy.on_next;
+        let fade = playing && !self.crossfade.is_zero() && self.fades_into_next(on_next);
+        self.play_next_fading(fade)
+    }
+
+    /// Plays the next entry, the current track fading out meanwhile if
+    /// `fade`.
+    fn play_next_fading(&mut self, fade: bool) -> Result<()> {
+        let previous = self.sink.take();
+        self.play_next()?;
+        if let (true, Some(previous), Some(sink)) = (fade, previous, &self.sink) {
+            sink.set_volume(0.0);
+            self.fading_out = Some((previous, Instant::now()));
+        }
+        Ok(())
+    }
+
+    /// Whether the current track fades into the next entry, `on` telling
+    /// whether the policy fades on this change of track: never between two
+    /// tracks of the same album, by their tags, unless it fades within albums.
+    fn fades_into_next(&mut self, on: bool) -> bool {
+        if !on || self.crossfade_policy.within_albums {
+            return on;
+        }
+        let current = self.current_playing.clone();
+        let next = self.playlist.get(self.current_index).cloned();
+        let (Some(current), Some(next)) = (current, next) else {
+            return true;
+        };
+        // Loose tracks without an album tag, even in one folder, still fade.
+        match (self.album_key(&current), self.album_key(&next)) {
+            (album @ (Some(_), _), next_album) => album != next_album,
+            _ => true,
+        }
+    }
+
+    fn time_left(&self) -> Option<Duration> {
+        Some(self.total_duration?.saturating_sub(self.get_current_position()?))
+    }
+
+    /// Whether the playing track is within the crossfade time of its end.
+    fn crossfade_due(&self) -> bool {
+        let started = self.fading_out.is_some() || self.queued.is_some();
+        if self.crossfade.is_zero() || started || self.crossfade_skipped {
+            return false;
+        }
+        self.time_left().is_some_and(|left| left <= self.crossfade)
+    }
+
+    /// Appends the next playlist entry to the sink, if it plays at the same
+    /// speed and, for `gapless_only`, is gapless too. Otherwise, or if it
+    /// can't be opened, the current track ends the usual way.
+    fn queue_next(&mut self, gapless_only: bool) {
+        self.gapless = false;
+        let Some(path) = self.playlist.get(self.current_index).cloned() else {
+            return;
+        };
+        let settings = self.folder_settings(&path);
+        let gapless = settings.gapless.unwrap_or(false) || !gapless_only;
+        if !gapless || settings.speed.unwrap_or(1.0) != self.speed {
+            return;
+        }
+        let (Ok(source), Some(sink)) = (open_source(&path), &self.sink) else {
+            return;
+        };
+        self.queued = Some(QueuedTrack {
+            path,
+            total_duration: source.total_duration(),
+            sample_rate: source.sample_rate(),
+            channels: source.channels(),
+        });
+        sink.append(tap::Tap::new(source, Arc::clone(&self.levels), self.on_track_end.clone()));
+    }
+
+    /// Makes the queued track the current one once the sink has reached it.
+    fn follow_queued(&mut self) -> Result<()> {
+        let Some(next) = self.queued.take() else {
+            return Ok(());
+        };
+        let in_order = self.playlist.get(self.current_index) == Some(&next.path);
+        self.start_track(&next.path, next.total_duration, next.sample_rate, next.channels)?;
+        self.current_playing = Some(next.path);
+        if in_order {
+            self.advance()?;
+        }
+        Ok(())
+    }
+
+    /// Lowers the fading track and raises the new one along the crossfade.
+    fn update_crossfade(&mut self) {
+        let Some((previous, started)) = &self.fading_out else {
+            return;
+        };
+        let progress = started.elapsed().as_secs_f32() / self.crossfade.as_secs_f32();
+        if progress >= 1.0 || previous.empty() {
+            self.end_crossfade();
+            return;
+        }
+        previous.set_volume(self.volume * (1.0 - progress));
+        if let Some(sink) = &self.sink {
+            sink.set_volume(self.volume * progress);
+        }
+    }
+
+    fn end_crossfade(&mut self) {
+        if self.fading_out.take().is_some() {
+            if let Some(sink) = &self.sink {
+                sink.set_volume(self.volume);
+            }
+        }
+    }
+
+    fn update_ramp(&mut self) {
+        let Some((target, started, length)) = self.ramp else {
+            return;
+        };
+        let progress = started.elapsed().as_secs_f32() / length.as_secs_f32();
+        if progress >= 1.0 {
+            self.ramp = None;
+        }
+        self.volume = target * progress.min(1.0);
+        self.apply_volume();
+    }
+
+    /// Whether a track is playing, and not paused.
     pub fn is_playing(&self) -> bool {
         if let Some(sink) = &self.sink {
             !sink.is_paused()
@@ -290,27 +2456,59 @@ impl Player {
         }
     }
 
-    pub fn increase_volume(&self) {
-        if let Some(sink) = &self.sink {
-            let current_volume = sink.volume();
-            let new_volume = (current_volume + 0.1).min(2.0);
-            sink.set_volume(new_volume);
-        }
+    /// Raises the volume by its step, up to 200%.
+    pub fn increase_volume(&mut self) {
+        self.ramp = None;
+        self.volume = (self.volume + self.volume_step).min(2.0);
+        self.apply_volume();
     }
 
-    pub fn decrease_volume(&self) {
-        if let Some(sink) = &self.sink {
-            let current_volume = sink.volume();
-            let new_volume = (current_volume - 0.1).max(0.0);
-            sink.set_volume(new_volume);
+    /// Lowers the volume by its step.
+    pub fn decrease_volume(&mut self) {
+        self.ramp = None;
+        self.volume = (self.volume - self.volume_step).max(0.0);
+        self.apply_volume();
+    }
+
+    fn apply_volume(&self) {
+        // A fade in progress picks the new volume up at its next step.
+        if let (Some(sink), None) = (&self.sink, &self.fading_out) {
+            sink.set_volume(self.volume);
         }
     }
 
+    /// Adds the current track to the skip list, takes it out of the playlist
+    /// and plays the next one.
     pub fn mark_skip(&mut self) -> Result<()> {
         if let Some(track) = &self.current_playing {
-            self.skip_list.add(track)?;
-            self.remove_current_from_playlist();
-            
+            let tags = self.tag_snapshot();
+            let id = self.skip_list.add(track, "skipped from the player", tags)?;
+            self.lists_revision += 1;
+            self.leave_current(id)?;
+        }
+        Ok(())
+    }
+
+    /// Takes the current track out of the playlist for this session only,
+    /// and plays the next one.
+    pub fn drop_current(&mut self) -> Result<()> {
+        self.leave_current(None)
+    }
+
+    /// Takes the current track out of the playlist and plays the next one.
+    /// `undo` puts it back, and takes the skip list entry `id` out.
+    fn leave_current(&mut self, id: Option<String>) -> Result<()> {
+        if let Some(track) = self.current_playing.clone() {
+            let position = self.get_current_position();
+            let (index, stash_index) = self.remove_from_playlist(&track);
+            self.remember(Undo::Skip {
+                track,
+                id,
+                index,
+                stash_index,
+                position,
+            });
+
             // After removing current track, current_index points to the next track
             // We need to play it without incrementing the index again
             if !self.playlist.is_empty() {
@@ -323,8 +2521,9 @@ impl Player {
                         self.current_playing = Some(path);
                         self.current_index = (self.current_index + 1) % self.playlist.len();
                     }
-                    Err(_) => {
+                    Err(e) => {
                         // If this fails, try play_next as fallback
+                        self.report_failure(&path, &e);
                         self.play_next()?;
                     }
                 }
@@ -333,30 +2532,253 @@ impl Player {
         Ok(())
     }
 
-    fn remove_current_from_playlist(&mut self) {
-        if let Some(current) = &self.current_playing {
-            if let Some(index) = self.playlist.iter().position(|path| path == current) {
-                self.playlist.remove(index);
+    /// The ban `track` falls under, if any. Its tags are only read when
+    /// there are bans.
+    fn banned(&mut self, track: &Path) -> Option<&config::Ban> {
+        if self.bans.is_empty() {
+            return None;
+        }
+        let metadata = self.metadata_cache.metadata(track)?;
+        self.bans.matching(metadata.artist.as_deref(), metadata.album.as_deref())
+    }
 
-                if index <= self.current_index && self.current_index > 0 {
-                    self.current_index -= 1;
-                }
+    /// Bans every track of `artist`, or the album `album` (by `artist` if
+    /// given), and takes their tracks out of the playlist: if the current
+    /// track is one of them, the next one plays. Returns what was banned and
+    /// how many tracks were taken out.
+    pub fn ban(&mut self, artist: Option<&str>, album: Option<&str>) -> Result<(String, usize)> {
+        let Some(ban) = self.bans.add(artist, album)?.cloned() else {
+            anyhow::bail!("Already banned");
+        };
+        let mut tracks: Vec<PathBuf> = self.playlist.to_vec();
+        tracks.extend(self.all_tracks.iter().flat_map(|all_tracks| all_tracks.iter().cloned()));
+        tracks.sort();
+        tracks.dedup();
+        let banned: Vec<PathBuf> = tracks
+            .into_iter()
+            .filter(|track| {
+                self.metadata_cache
+                    .metadata(track)
+                    .is_some_and(|metadata| ban.matches(metadata.artist.as_deref(), metadata.album.as_deref()))
+            })
+            .collect();
+        let current = self.current_playing.clone().filter(|track| banned.contains(track));
+        for track in &banned {
+            self.remove_from_playlist(track);
+        }
+        // The index already points to the track after the current one.
+        if current.is_some() {
+            self.play_next()?;
+        }
+        Ok((ban.describe(), banned.len()))
+    }
+
+    /// Adds `track` to the skip list, giving `reason`, and takes it out of
+    /// the playlist. If it is the current track, it goes on playing until
+    /// the next.
+    pub fn skip_track(&mut self, track: &Path, reason: &str) -> Result<()> {
+        let tags = match self.current_playing.as_deref() == Some(track) {
+            true => self.tag_snapshot(),
+            false => self
+                .metadata_cache
+                .peek(track)
+                .map(|metadata| config::TagSnapshot {
+                    artist: metadata.artist.clone(),
+                    album: metadata.album.clone(),
+                    title: metadata.title.clone(),
+                })
+                .unwrap_or_default(),
+        };
+        let id = self.skip_list.add(track, reason, tags)?;
+        self.lists_revision += 1;
+        let (index, stash_index) = self.remove_from_playlist(track);
+        self.remember(Undo::Skip {
+            track: track.to_path_buf(),
+            id,
+            index,
+            stash_index,
+            position: None,
+        });
+        Ok(())
+    }
+
+    /// Logs that the current track is left with Next, if that is within
+    /// `EARLY_SKIP` of its start. Returns the track and how many times it
+    /// was left early since it was last played to the end.
+    pub fn record_early_skip(&mut self) -> Result<Option<(PathBuf, usize)>> {
+        let (Some(track), Some(position)) = (self.current_playing.clone(), self.get_current_position()) else {
+            return Ok(None);
+        };
+        if position >= EARLY_SKIP {
+            return Ok(None);
+        }
+        let count = self.play_log.record_early_skip(&track, self.tag_snapshot())?;
+        tracing::debug!("Left early ({} times): {}", count, track.display());
+        Ok(Some((track, count)))
+    }
+
+    /// Returns where the track was in the playlist, and in the whole
+    /// playlist while only the favorites are played.
+    fn remove_from_playlist(&mut self, track: &Path) -> (Option<usize>, Option<usize>) {
+        let mut stash_index = None;
+        if let Some(all_tracks) = &mut self.all_tracks {
+            stash_index = all_tracks.iter().position(|path| path == track);
+            Arc::make_mut(all_tracks).retain(|path| path != track);
+        }
+        let index = self.playlist.iter().position(|path| path == track);
+        if let Some(index) = index {
+            Arc::make_mut(&mut self.playlist).remove(index);
+
+            if index <= self.current_index && self.current_index > 0 {
+                self.current_index -= 1;
             }
         }
+        (index, stash_index)
     }
 
-    pub fn delete_current_track(&mut self) -> Result<(), io::Error> {
-        if let Some(track) = &self.current_playing {
-            fs::remove_file(track)?;
-            self.remove_current_from_playlist();
+    /// Moves the file of the current track to the trash, or deletes it for
+    /// good if `set_trash(false)` was called, and takes it out of the
+    /// playlist. It keeps playing from memory until the next track. A file
+    /// moved to the trash can be brought back with `undo`.
+    pub fn delete_current_track(&mut self) -> Result<()> {
+        let Some(track) = self.current_playing.clone() else {
+            return Ok(());
+        };
+        if !self.trash {
+            fs::remove_file(&track)?;
+            self.remove_from_playlist(&track);
+            return Ok(());
         }
+        // The trash keeps the canonical path, which undo looks for.
+        let trashed = track.canonicalize().with_context(|| format!("{}: not found", track.display()))?;
+        trash::delete(&trashed)?;
+        let (index, stash_index) = self.remove_from_playlist(&track);
+        self.remember(Undo::Delete {
+            track,
+            trashed,
+            index,
+            stash_index,
+        });
         Ok(())
     }
 
-    pub fn is_favorite(&self, track: &PathBuf) -> Result<bool, io::Error> {
-        self.favorites_list.is_favorite(track)
+    /// Moves the file of `track` into `folder`, made if missing, and points
+    /// the playlist, the favorites, the skip list and the play counts to its
+    /// new path, which is returned. A file of the same name already there
+    /// is left alone and the move refused. A playing track keeps playing.
+    pub fn move_track(&mut self, track: &Path, folder: &Path) -> Result<PathBuf> {
+        let name = track.file_name().with_context(|| format!("{}: not a file", track.display()))?;
+        fs::create_dir_all(folder).with_context(|| format!("Couldn't make {}", folder.display()))?;
+        let destination = folder.join(name);
+        let from = track.canonicalize().with_context(|| format!("{}: not found", track.display()))?;
+        if destination.canonicalize().is_ok_and(|destination| destination == from) {
+            anyhow::bail!("{} is in {} already", self.track_label(track), folder.display());
+        }
+        if destination.exists() {
+            anyhow::bail!("{} exists already", destination.display());
+        }
+        config::move_file(track, &destination)
+            .with_context(|| format!("Couldn't move {} to {}", track.display(), folder.display()))?;
+        self.relocated(track, &from, destination)
     }
 
+    /// Renames the file of `track` to `name`, in the same folder, the way
+    /// `move_track` moves it. Without an extension, `name` gets the one of
+    /// the file.
+    pub fn rename_track(&mut self, track: &Path, name: &str) -> Result<PathBuf> {
+        let name = name.trim();
+        if name.is_empty() || name == "." || name == ".." || name.contains(std::path::is_separator) {
+            anyhow::bail!("\"{}\" isn't a file name", name);
+        }
+        let mut destination = track.with_file_name(name);
+        if destination.extension().is_none() {
+            if let Some(extension) = track.extension() {
+                destination.set_extension(extension);
+            }
+        }
+        if destination == track {
+            anyhow::bail!("{} is named so already", self.track_label(track));
+        }
+        let from = track.canonicalize().with_context(|| format!("{}: not found", track.display()))?;
+        // A change of case only, on a file system ignoring case, finds the
+        // file itself there.
+        if destination.exists() && destination.canonicalize().ok().as_deref() != Some(&from) {
+            anyhow::bail!("{} exists already", destination.display());
+        }
+        fs::rename(track, &destination)
+            .with_context(|| format!("Couldn't rename {} to {}", track.display(), name))?;
+        self.relocated(track, &from, destination)
+    }
+
+    /// Points the playlist, the favorites, the skip list and the play counts
+    /// to `destination`, where the file of `track` now is.
+    /// `from` is the canonical path `track` had.
+    fn relocated(&mut self, track: &Path, from: &Path, destination: PathBuf) -> Result<PathBuf> {
+        let repoint = |tracks: &mut Arc<Vec<PathBuf>>| {
+            if tracks.iter().any(|path| path == track) {
+                for path in Arc::make_mut(tracks).iter_mut().filter(|path| *path == track) {
+                    *path = destination.clone();
+                }
+            }
+        };
+        repoint(&mut self.playlist);
+        if let Some(all_tracks) = &mut self.all_tracks {
+            repoint(all_tracks);
+        }
+        if self.current_playing.as_deref() == Some(track) {
+            self.current_playing = Some(destination.clone());
+        }
+        for path in self.priority.iter_mut().chain(&mut self.played).filter(|path| *path == track) {
+            *path = destination.clone();
+        }
+        if let Some(position) = self.order.remove(track) {
+            self.order.insert(destination.clone(), position);
+        }
+        let favorite = self.favorites_list.moved(from, &destination)?;
+        let skipped = self.skip_list.moved(from, &destination)?;
+        if favorite || skipped {
+            self.lists_revision += 1;
+        }
+        self.play_counts.moved(from, &destination)?;
+        // For `track_label` and the lists, which only peek at the cache.
+        self.metadata_cache.metadata(&destination);
+        Ok(destination)
+    }
+
+    fn report_failure(&mut self, path: &Path, error: &anyhow::Error) {
+        tracing::info!("Couldn't decode {}: {:#}", path.display(), error);
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+        self.failures.push(format!("Couldn't decode {}, skipped ({})", name, error));
+    }
+
+    /// Messages about the tracks skipped because they could not be played,
+    /// and about failed scrobbles and cover downloads.
+    pub fn take_failures(&mut self) -> Vec<String> {
+        let mut failures = std::mem::take(&mut self.failures);
+        if let Some(scrobbler) = &self.scrobbler {
+            failures.extend(scrobbler.take_errors());
+        }
+        failures
+    }
+
+    /// Whether `track` is one of the favorites.
+    pub fn is_favorite(&self, track: &Path) -> Result<bool, io::Error> {
+        self.favorites_list.contains(track)
+    }
+
+    /// Tags of the current track, recorded with skips and favorites.
+    fn tag_snapshot(&self) -> config::TagSnapshot {
+        match &self.current_metadata {
+            Some(metadata) => config::TagSnapshot {
+                artist: metadata.artist.clone(),
+                album: metadata.album.clone(),
+                title: metadata.title.clone(),
+            },
+            None => config::TagSnapshot::default(),
+        }
+    }
+
+    /// How far the current track has played.
     pub fn get_current_position(&self) -> Option<Duration> {
         if let Some(start_time) = self.start_time {
             let elapsed = start_time.elapsed();
@@ -365,8 +2787,31 @@ impl Player {
             } else {
                 self.paused_duration
             };
-            return Some(elapsed - pause_duration);
+            // The clock runs at normal speed, the track at `speed`.
+            return Some((elapsed - pause_duration).mul_f32(self.speed));
         }
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn play_count_replaces_the_pcnt_frame() {
+        let path = std::env::temp_dir().join(format!("ksound-pcnt-{}.mp3", std::process::id()));
+        fs::write(&path, [0xff, 0xfb, 0x90, 0x00]).unwrap();
+        Player::write_play_count(&path, 3).unwrap();
+        Player::write_play_count(&path, 3).unwrap();
+
+        let tag = Tag::read_from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let counts: Vec<u64> = tag
+            .frames()
+            .filter(|frame| frame.id() == "PCNT")
+            .map(|frame| decode_play_counter(&frame.content().to_unknown().unwrap().data))
+            .collect();
+        assert_eq!(counts, [4]);
+    }
+}
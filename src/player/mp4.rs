@@ -0,0 +1,688 @@
+//! Minimal reader/writer for iTunes-style MP4 metadata (`moov/udta/meta/ilst`),
+//! and reader of the chapters of M4B audiobooks.
+//!
+//! Only the handful of text atoms the tag editor knows about are handled; every
+//! other atom is copied through untouched.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+pub const ARTIST: [u8; 4] = *b"\xa9ART";
+pub const ALBUM: [u8; 4] = *b"\xa9alb";
+pub const TITLE: [u8; 4] = *b"\xa9nam";
+pub const YEAR: [u8; 4] = *b"\xa9day";
+pub const GENRE: [u8; 4] = *b"\xa9gen";
+pub const ALBUM_ARTIST: [u8; 4] = *b"aART";
+/// Binary pairs, read as "number/total" text.
+pub const TRACK: [u8; 4] = *b"trkn";
+pub const DISC: [u8; 4] = *b"disk";
+
+/// Well-known type indicator for UTF-8 text in a `data` atom.
+const DATA_TYPE_UTF8: u32 = 1;
+
+/// Type indicator of the `trkn` and `disk` pairs, whose type is implied.
+const DATA_TYPE_IMPLICIT: u32 = 0;
+
+/// Container atoms that can hold `stco`/`co64` chunk offset tables.
+const OFFSET_CONTAINERS: [&[u8; 4]; 6] = [b"moov", b"trak", b"mdia", b"minf", b"stbl", b"edts"];
+
+#[derive(Debug, Clone, Copy)]
+struct Atom {
+    kind: [u8; 4],
+    start: usize,
+    header: usize,
+    end: usize,
+}
+
+impl Atom {
+    fn payload<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.start + self.header..self.end]
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn read_u64(data: &[u8], at: usize) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&data[at..at + 8]);
+    u64::from_be_bytes(buf)
+}
+
+/// Lists the atoms found in `data[start..end]`.
+fn atoms(data: &[u8], start: usize, end: usize) -> io::Result<Vec<Atom>> {
+    let mut result = Vec::new();
+    let mut pos = start;
+
+    while pos + 8 <= end {
+        let size32 = read_u32(data, pos) as usize;
+        let mut kind = [0u8; 4];
+        kind.copy_from_slice(&data[pos + 4..pos + 8]);
+
+        let (size, header) = match size32 {
+            0 => (end - pos, 8),
+            1 => {
+                if pos + 16 > end {
+                    return Err(invalid("truncated 64-bit atom header"));
+                }
+                let size = usize::try_from(read_u64(data, pos + 8));
+                (size.map_err(|_| invalid("atom size out of bounds"))?, 16)
+            }
+            n => (n, 8),
+        };
+
+        let atom_end = match pos.checked_add(size) {
+            Some(atom_end) if size >= header && atom_end <= end => atom_end,
+            _ => return Err(invalid("atom size out of bounds")),
+        };
+
+        result.push(Atom {
+            kind,
+            start: pos,
+            header,
+            end: atom_end,
+        });
+        pos = atom_end;
+    }
+
+    Ok(result)
+}
+
+fn find(list: &[Atom], kind: &[u8; 4]) -> Option<Atom> {
+    list.iter().find(|a| &a.kind == kind).copied()
+}
+
+fn build_atom(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 8);
+    out.extend_from_slice(&((payload.len() + 8) as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(payload);
+    out
+}
+
+fn build_text_item(kind: &[u8; 4], text: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(text.len() + 8);
+    data.extend_from_slice(&DATA_TYPE_UTF8.to_be_bytes());
+    data.extend_from_slice(&[0, 0, 0, 0]); // locale
+    data.extend_from_slice(text.as_bytes());
+    build_atom(kind, &build_atom(b"data", &data))
+}
+
+/// Handler reference required by iTunes for the `meta` atom.
+fn build_mdir_handler() -> Vec<u8> {
+    let mut payload = vec![0u8; 8]; // version/flags + pre_defined
+    payload.extend_from_slice(b"mdirappl");
+    payload.extend_from_slice(&[0u8; 9]); // reserved + empty name
+    build_atom(b"hdlr", &payload)
+}
+
+/// Locates the `ilst` atom (if any) below `moov`.
+fn find_ilst(data: &[u8], moov: Atom) -> io::Result<Option<Atom>> {
+    let moov_children = atoms(data, moov.start + moov.header, moov.end)?;
+    let Some(udta) = find(&moov_children, b"udta") else {
+        return Ok(None);
+    };
+    let udta_children = atoms(data, udta.start + udta.header, udta.end)?;
+    let Some(meta) = find(&udta_children, b"meta") else {
+        return Ok(None);
+    };
+    // `meta` is a full box: skip its version/flags before the children.
+    let meta_children = atoms(data, meta.start + meta.header + 4, meta.end)?;
+    Ok(find(&meta_children, b"ilst"))
+}
+
+fn top_level(data: &[u8]) -> io::Result<(Vec<Atom>, Atom)> {
+    let top = atoms(data, 0, data.len())?;
+    let moov = find(&top, b"moov").ok_or_else(|| invalid("no moov atom found"))?;
+    Ok((top, moov))
+}
+
+/// One `ilst` entry: the item atom, the type indicator of its `data` and the payload.
+struct Item {
+    kind: [u8; 4],
+    data_type: u32,
+    value: Vec<u8>,
+}
+
+fn read_items(path: &Path) -> io::Result<Vec<Item>> {
+    let data = fs::read(path)?;
+    let (_, moov) = top_level(&data)?;
+    let Some(ilst) = find_ilst(&data, moov)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut items = Vec::new();
+    for item in atoms(&data, ilst.start + ilst.header, ilst.end)? {
+        let children = atoms(&data, item.start + item.header, item.end)?;
+        if let Some(value) = find(&children, b"data") {
+            let payload = value.payload(&data);
+            if payload.len() >= 8 {
+                items.push(Item {
+                    kind: item.kind,
+                    data_type: read_u32(payload, 0),
+                    value: payload[8..].to_vec(),
+                });
+            }
+        }
+    }
+    Ok(items)
+}
+
+/// Reads every UTF-8 text item stored in the file's `ilst`, and the track
+/// and disc numbers as "number/total".
+pub fn read_text_items(path: &Path) -> io::Result<Vec<([u8; 4], String)>> {
+    Ok(read_items(path)?
+        .into_iter()
+        .filter_map(|item| match item.data_type {
+            DATA_TYPE_UTF8 => Some((item.kind, String::from_utf8_lossy(&item.value).into_owned())),
+            // Two reserved bytes, the number, the total.
+            DATA_TYPE_IMPLICIT if (item.kind == TRACK || item.kind == DISC) && item.value.len() >= 6 => {
+                let number = u16::from_be_bytes([item.value[2], item.value[3]]);
+                let total = u16::from_be_bytes([item.value[4], item.value[5]]);
+                Some((item.kind, format!("{}/{}", number, total)))
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+/// Returns the raw bytes (JPEG or PNG) of the first cover stored in `covr`.
+pub fn read_cover(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    Ok(read_items(path)?
+        .into_iter()
+        .find(|item| &item.kind == b"covr")
+        .map(|item| item.value))
+}
+
+/// The start and title of each chapter, from the Nero `chpl` list or else
+/// the QuickTime chapter track; empty without either. Only `moov` and the
+/// chapter titles are read, not the whole file.
+pub fn read_chapters(path: &Path) -> io::Result<Vec<(Duration, String)>> {
+    let mut file = File::open(path)?;
+    let data = read_moov(&mut file)?;
+    let (_, moov) = top_level(&data)?;
+    let children = atoms(&data, moov.start + moov.header, moov.end)?;
+    if let Some(chapters) = nero_chapters(&data, &children)? {
+        return Ok(chapters);
+    }
+    track_chapters(&mut file, &data, &children)
+}
+
+/// The `moov` atom alone, read without loading the media data.
+fn read_moov(file: &mut File) -> io::Result<Vec<u8>> {
+    let length = file.metadata()?.len();
+    let mut pos = 0;
+    while pos + 8 <= length {
+        let mut header = [0u8; 16];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut header[..8])?;
+        let size = match read_u32(&header, 0) {
+            0 => length - pos,
+            1 => {
+                file.read_exact(&mut header[8..])?;
+                read_u64(&header, 8)
+            }
+            n => n as u64,
+        };
+        if size < 8 || size > length - pos {
+            return Err(invalid("atom size out of bounds"));
+        }
+        if &header[4..8] == b"moov" {
+            let mut data = vec![0; size as usize];
+            file.seek(SeekFrom::Start(pos))?;
+            file.read_exact(&mut data)?;
+            return Ok(data);
+        }
+        pos += size;
+    }
+    Err(invalid("no moov atom found"))
+}
+
+/// The big-endian number of `size` bytes at `at`, if `data` holds it.
+fn number_at(data: &[u8], at: usize, size: usize) -> Option<u64> {
+    let bytes = data.get(at..at.checked_add(size)?)?;
+    Some(bytes.iter().fold(0, |number, &byte| number << 8 | byte as u64))
+}
+
+/// The `moov/udta/chpl` list, with starts in units of 100 ns.
+fn nero_chapters(data: &[u8], moov_children: &[Atom]) -> io::Result<Option<Vec<(Duration, String)>>> {
+    let Some(udta) = find(moov_children, b"udta") else {
+        return Ok(None);
+    };
+    let Some(chpl) = find(&atoms(data, udta.start + udta.header, udta.end)?, b"chpl") else {
+        return Ok(None);
+    };
+    let payload = chpl.payload(data);
+    // Version and flags, then four reserved bytes from version 1 on.
+    let mut pos = if payload.first().is_some_and(|&version| version > 0) { 8 } else { 4 };
+    let count = number_at(payload, pos, 1).unwrap_or(0);
+    pos += 1;
+    let mut chapters = Vec::new();
+    for _ in 0..count {
+        let (Some(start), Some(length)) = (number_at(payload, pos, 8), number_at(payload, pos + 8, 1)) else {
+            break;
+        };
+        let Some(title) = payload.get(pos + 9..pos + 9 + length as usize) else {
+            break;
+        };
+        chapters.push((Duration::from_nanos(start * 100), String::from_utf8_lossy(title).into_owned()));
+        pos += 9 + length as usize;
+    }
+    Ok(Some(chapters))
+}
+
+/// The text track named by the `tref/chap` of another track: one sample
+/// per chapter, each a 16-bit length and the title.
+fn track_chapters(
+    file: &mut File,
+    data: &[u8],
+    moov_children: &[Atom],
+) -> io::Result<Vec<(Duration, String)>> {
+    let children = |atom: Atom| atoms(data, atom.start + atom.header, atom.end);
+    let traks: Vec<Atom> = moov_children.iter().filter(|atom| &atom.kind == b"trak").copied().collect();
+    let mut chapter_id = None;
+    for trak in &traks {
+        if let Some(tref) = find(&children(*trak)?, b"tref") {
+            if let Some(chap) = find(&children(tref)?, b"chap") {
+                chapter_id = number_at(chap.payload(data), 0, 4);
+            }
+        }
+    }
+    let Some(chapter_id) = chapter_id else {
+        return Ok(Vec::new());
+    };
+
+    for trak in traks {
+        let trak_children = children(trak)?;
+        let Some(tkhd) = find(&trak_children, b"tkhd") else {
+            continue;
+        };
+        // Version 1 has 64-bit creation and modification times.
+        let tkhd = tkhd.payload(data);
+        let wide = tkhd.first().is_some_and(|&version| version == 1);
+        if number_at(tkhd, if wide { 20 } else { 12 }, 4) != Some(chapter_id) {
+            continue;
+        }
+        let Some(mdia) = find(&trak_children, b"mdia") else {
+            break;
+        };
+        let mdia_children = children(mdia)?;
+        let (Some(mdhd), Some(minf)) = (find(&mdia_children, b"mdhd"), find(&mdia_children, b"minf")) else {
+            break;
+        };
+        let mdhd = mdhd.payload(data);
+        let wide = mdhd.first().is_some_and(|&version| version == 1);
+        let timescale = number_at(mdhd, if wide { 20 } else { 12 }, 4).filter(|&scale| scale > 0);
+        let Some(stbl) = find(&children(minf)?, b"stbl") else {
+            break;
+        };
+        let table = children(stbl)?;
+        let payload = |kind: &[u8; 4]| find(&table, kind).map(|atom| atom.payload(data));
+        let (Some(timescale), Some(stts), Some(stsz), Some(stsc)) =
+            (timescale, payload(b"stts"), payload(b"stsz"), payload(b"stsc"))
+        else {
+            break;
+        };
+        let offsets = match (payload(b"stco"), payload(b"co64")) {
+            (Some(stco), _) => entries(stco, 4),
+            (None, Some(co64)) => entries(co64, 8),
+            (None, None) => break,
+        };
+
+        let starts = sample_starts(stts);
+        let sizes = sample_sizes(stsz);
+        let mut chapters = Vec::new();
+        for (offset, size) in sample_offsets(stsc, &offsets, &sizes).into_iter().zip(sizes) {
+            let Some(&start) = starts.get(chapters.len()) else {
+                break;
+            };
+            let mut sample = vec![0; size.min(2 + u16::MAX as u64) as usize];
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut sample)?;
+            let length = number_at(&sample, 0, 2).unwrap_or(0) as usize;
+            let title = sample.get(2..2 + length).unwrap_or_default();
+            let title = match title.strip_prefix(&[0xfe, 0xff]) {
+                Some(utf16) => {
+                    let units: Vec<u16> =
+                        utf16.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+                    String::from_utf16_lossy(&units)
+                }
+                None => String::from_utf8_lossy(title).into_owned(),
+            };
+            let start = Duration::from_secs_f64(start as f64 / timescale as f64);
+            chapters.push((start, title));
+        }
+        return Ok(chapters);
+    }
+    Ok(Vec::new())
+}
+
+/// The entries of a table with a count after version and flags, each of
+/// `size` bytes.
+fn entries(payload: &[u8], size: usize) -> Vec<u64> {
+    let count = number_at(payload, 4, 4).unwrap_or(0) as usize;
+    (0..count).map_while(|i| number_at(payload, 8 + i * size, size)).collect()
+}
+
+/// The start of each sample by `stts`, in units of the timescale.
+fn sample_starts(stts: &[u8]) -> Vec<u64> {
+    let pairs = entries(stts, 8);
+    let mut starts = Vec::new();
+    let mut time = 0;
+    for pair in pairs {
+        let (count, delta) = (pair >> 32, pair & 0xffff_ffff);
+        for _ in 0..count.min(u16::MAX as u64) {
+            starts.push(time);
+            time += delta;
+        }
+    }
+    starts
+}
+
+/// The size of each sample by `stsz`: one for all, or one each.
+fn sample_sizes(stsz: &[u8]) -> Vec<u64> {
+    let size = number_at(stsz, 4, 4).unwrap_or(0);
+    let count = number_at(stsz, 8, 4).unwrap_or(0).min(u16::MAX as u64) as usize;
+    match size {
+        0 => (0..count).map_while(|i| number_at(stsz, 12 + i * 4, 4)).collect(),
+        size => vec![size; count],
+    }
+}
+
+/// Where each sample is, by the chunks of `stsc` and their `offsets`.
+fn sample_offsets(stsc: &[u8], offsets: &[u64], sizes: &[u64]) -> Vec<u64> {
+    // First chunk (from 1) and samples per chunk, in order.
+    let runs: Vec<(u64, u64)> = (0..number_at(stsc, 4, 4).unwrap_or(0) as usize)
+        .map_while(|i| Some((number_at(stsc, 8 + i * 12, 4)?, number_at(stsc, 12 + i * 12, 4)?)))
+        .collect();
+    let mut result = Vec::new();
+    for (chunk, &offset) in offsets.iter().enumerate() {
+        let per_chunk = runs
+            .iter()
+            .rev()
+            .find(|(first, _)| *first <= chunk as u64 + 1)
+            .map_or(0, |(_, samples)| *samples);
+        let mut offset = offset;
+        for _ in 0..per_chunk {
+            let Some(size) = sizes.get(result.len()) else {
+                return result;
+            };
+            result.push(offset);
+            offset += size;
+        }
+    }
+    result
+}
+
+/// Replaces (or inserts) the given text atoms and writes the file back.
+///
+/// The chunk offsets pointing past `moov` are shifted by the size difference,
+/// so the audio samples stay addressable. The file is replaced whole, by a
+/// copy written next to it: a crash or a full disk leaves the old one.
+pub fn write_text_items(path: &Path, updates: &[([u8; 4], String)]) -> io::Result<()> {
+    if updates.is_empty() {
+        return Ok(());
+    }
+
+    // The file a link points to is the one rewritten, not the link.
+    let path = path.canonicalize()?;
+    let data = fs::read(&path)?;
+    let (_, moov) = top_level(&data)?;
+
+    let mut new_moov = rebuild_moov(&data, moov, updates)?;
+
+    let delta = new_moov.len() as i64 - (moov.end - moov.start) as i64;
+    if delta != 0 {
+        let len = new_moov.len();
+        shift_chunk_offsets(&mut new_moov, 0, len, moov.end as u64, delta)?;
+    }
+
+    let mut out = Vec::with_capacity(data.len() + new_moov.len());
+    out.extend_from_slice(&data[..moov.start]);
+    out.extend_from_slice(&new_moov);
+    out.extend_from_slice(&data[moov.end..]);
+    crate::config::write_whole(&path, out)
+}
+
+fn rebuild_moov(data: &[u8], moov: Atom, updates: &[([u8; 4], String)]) -> io::Result<Vec<u8>> {
+    // ilst: keep unrelated items, replace the updated ones
+    let mut ilst_payload = Vec::new();
+    if let Some(ilst) = find_ilst(data, moov)? {
+        for item in atoms(data, ilst.start + ilst.header, ilst.end)? {
+            if !updates.iter().any(|(kind, _)| *kind == item.kind) {
+                ilst_payload.extend_from_slice(&data[item.start..item.end]);
+            }
+        }
+    }
+    for (kind, value) in updates {
+        ilst_payload.extend_from_slice(&build_text_item(kind, value));
+    }
+    let new_ilst = build_atom(b"ilst", &ilst_payload);
+
+    let moov_children = atoms(data, moov.start + moov.header, moov.end)?;
+    let udta = find(&moov_children, b"udta");
+    let udta_children = match udta {
+        Some(udta) => atoms(data, udta.start + udta.header, udta.end)?,
+        None => Vec::new(),
+    };
+    let meta = find(&udta_children, b"meta");
+
+    // meta: full box with an `mdir` handler in front of ilst
+    let new_meta = match meta {
+        Some(meta) => {
+            let body = meta.start + meta.header;
+            if body + 4 > meta.end {
+                return Err(invalid("truncated meta atom"));
+            }
+            let children = atoms(data, body + 4, meta.end)?;
+            let mut payload = data[body..body + 4].to_vec();
+            payload.extend_from_slice(&replace_or_append(data, &children, b"ilst", &new_ilst));
+            build_atom(b"meta", &payload)
+        }
+        None => {
+            let mut payload = vec![0u8; 4];
+            payload.extend_from_slice(&build_mdir_handler());
+            payload.extend_from_slice(&new_ilst);
+            build_atom(b"meta", &payload)
+        }
+    };
+
+    let new_udta = build_atom(
+        b"udta",
+        &replace_or_append(data, &udta_children, b"meta", &new_meta),
+    );
+
+    let moov_payload = replace_or_append(data, &moov_children, b"udta", &new_udta);
+    Ok(build_atom(b"moov", &moov_payload))
+}
+
+fn replace_or_append(data: &[u8], children: &[Atom], kind: &[u8; 4], replacement: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    let mut replaced = false;
+    for child in children {
+        if &child.kind == kind {
+            payload.extend_from_slice(replacement);
+            replaced = true;
+        } else {
+            payload.extend_from_slice(&data[child.start..child.end]);
+        }
+    }
+    if !replaced {
+        payload.extend_from_slice(replacement);
+    }
+    payload
+}
+
+/// Shifts the `stco`/`co64` entries found below the atoms in `buf[start..end]`
+/// that point at `from` or past it, the media after `moov`; those before it
+/// stay.
+///
+/// Fails, rather than writing a broken table, on a count running past its
+/// atom and on offsets the shift takes out of range.
+fn shift_chunk_offsets(buf: &mut [u8], start: usize, end: usize, from: u64, delta: i64) -> io::Result<()> {
+    for atom in atoms(buf, start, end)? {
+        let body = atom.start + atom.header;
+        let width = match &atom.kind {
+            b"stco" => 4,
+            b"co64" => 8,
+            kind if OFFSET_CONTAINERS.contains(&kind) => {
+                shift_chunk_offsets(buf, body, atom.end, from, delta)?;
+                continue;
+            }
+            _ => continue,
+        };
+        if body + 8 > atom.end {
+            return Err(invalid("truncated chunk offset table"));
+        }
+        let count = read_u32(buf, body + 4) as usize;
+        let table_end = count.checked_mul(width).and_then(|size| (body + 8).checked_add(size));
+        if table_end.is_none_or(|table_end| table_end > atom.end) {
+            return Err(invalid("chunk offset table out of bounds"));
+        }
+        let out_of_range = |_| invalid("chunk offset out of range");
+        for i in 0..count {
+            let at = body + 8 + i * width;
+            let value = match width {
+                4 => read_u32(buf, at) as u64,
+                _ => read_u64(buf, at),
+            };
+            if value < from {
+                continue;
+            }
+            let shifted = i64::try_from(value).map_err(out_of_range)?.checked_add(delta);
+            let shifted = shifted.ok_or_else(|| invalid("chunk offset out of range"))?;
+            let entry = &mut buf[at..at + width];
+            match width {
+                4 => entry.copy_from_slice(&u32::try_from(shifted).map_err(out_of_range)?.to_be_bytes()),
+                _ => entry.copy_from_slice(&u64::try_from(shifted).map_err(out_of_range)?.to_be_bytes()),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `stco` atom holding `offsets`, with `count` as its entry count.
+    fn stco(count: u32, offsets: &[u32]) -> Vec<u8> {
+        let mut payload = vec![0u8; 4]; // version/flags
+        payload.extend_from_slice(&count.to_be_bytes());
+        for offset in offsets {
+            payload.extend_from_slice(&offset.to_be_bytes());
+        }
+        build_atom(b"stco", &payload)
+    }
+
+    #[test]
+    fn atoms_round_trip() {
+        let mut data = build_atom(b"ftyp", b"M4A ");
+        data.extend_from_slice(&build_atom(b"moov", &build_atom(b"udta", b"")));
+        data.extend_from_slice(&build_atom(b"mdat", &[1, 2, 3]));
+
+        let list = atoms(&data, 0, data.len()).unwrap();
+        let kinds: Vec<_> = list.iter().map(|atom| atom.kind).collect();
+        assert_eq!(kinds, [*b"ftyp", *b"moov", *b"mdat"]);
+        assert_eq!(list[0].payload(&data), b"M4A ");
+        assert_eq!(list[2].payload(&data), [1, 2, 3]);
+        let moov = list[1];
+        let children = atoms(&data, moov.start + moov.header, moov.end).unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(&children[0].kind, b"udta");
+        assert_eq!(list[2].end, data.len());
+    }
+
+    #[test]
+    fn atoms_rejects_sizes_out_of_bounds() {
+        let mut data = build_atom(b"free", b"");
+        data[3] = 200;
+        assert!(atoms(&data, 0, data.len()).is_err());
+
+        // A 64-bit size that would overflow the end position.
+        let mut data = 1u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert!(atoms(&data, 0, data.len()).is_err());
+
+        // Smaller than its own header.
+        let mut data = 4u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"free");
+        assert!(atoms(&data, 0, data.len()).is_err());
+    }
+
+    #[test]
+    fn shifts_chunk_offsets() {
+        let mut buf = build_atom(b"trak", &build_atom(b"stbl", &stco(2, &[100, 200])));
+        let len = buf.len();
+        shift_chunk_offsets(&mut buf, 0, len, 0, 16).unwrap();
+        assert_eq!(buf, build_atom(b"trak", &build_atom(b"stbl", &stco(2, &[116, 216]))));
+    }
+
+    #[test]
+    fn keeps_chunk_offsets_before_moov() {
+        let mut buf = stco(2, &[40, 200]);
+        let len = buf.len();
+        shift_chunk_offsets(&mut buf, 0, len, 100, 16).unwrap();
+        assert_eq!(buf, stco(2, &[40, 216]));
+    }
+
+    #[test]
+    fn refuses_a_truncated_meta_atom() {
+        let data = build_atom(b"moov", &build_atom(b"udta", &build_atom(b"meta", b"")));
+        let moov = atoms(&data, 0, data.len()).unwrap()[0];
+        assert!(rebuild_moov(&data, moov, &[(TITLE, "Title".to_string())]).is_err());
+    }
+
+    #[test]
+    fn writes_text_items() {
+        let moov = |offset: u32| {
+            let stbl = build_atom(b"stbl", &stco(1, &[offset]));
+            build_atom(b"moov", &build_atom(b"trak", &stbl))
+        };
+        let mut data = build_atom(b"ftyp", b"M4A ");
+        let audio = data.len() + moov(0).len() + 8;
+        data.extend_from_slice(&moov(audio as u32));
+        data.extend_from_slice(&build_atom(b"mdat", b"audio"));
+        let path = std::env::temp_dir().join(format!("ksound-mp4-{}.m4a", std::process::id()));
+        fs::write(&path, &data).unwrap();
+
+        write_text_items(&path, &[(TITLE, "Title".to_string())]).unwrap();
+        let items = read_text_items(&path);
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(items.unwrap(), [(TITLE, "Title".to_string())]);
+        // The chunk offset still points at the audio, moved by the new tags.
+        let at = data.windows(4).position(|window| window == b"stco").unwrap() + 12;
+        let offset = read_u32(&data, at) as usize;
+        assert_eq!(&data[offset..offset + 5], b"audio");
+    }
+
+    #[test]
+    fn refuses_malformed_chunk_offset_tables() {
+        // The count runs past the atom.
+        let mut buf = stco(1000, &[100]);
+        let len = buf.len();
+        assert!(shift_chunk_offsets(&mut buf, 0, len, 0, 16).is_err());
+
+        // Too short to hold a count.
+        let mut buf = build_atom(b"stco", &[0, 0]);
+        let len = buf.len();
+        assert!(shift_chunk_offsets(&mut buf, 0, len, 0, 16).is_err());
+
+        // Offsets the shift takes below 0 or past 4 GiB.
+        let mut buf = stco(1, &[8]);
+        let len = buf.len();
+        assert!(shift_chunk_offsets(&mut buf, 0, len, 0, -16).is_err());
+        let mut buf = stco(1, &[u32::MAX - 8]);
+        let len = buf.len();
+        assert!(shift_chunk_offsets(&mut buf, 0, len, 0, 16).is_err());
+    }
+}
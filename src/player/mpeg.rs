@@ -0,0 +1,153 @@
+//! MPEG audio frame header probing, used to report bitrate and CBR/VBR.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// How far past the ID3v2 tag we look for the first frame.
+const PROBE_SIZE: usize = 64 * 1024;
+
+// Bitrates in kbps, indexed by [version 1 / 2 and 2.5][layer I, II, III][index]
+const BITRATES: [[[u32; 15]; 3]; 2] = [
+    [
+        [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+        [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+        [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    ],
+    [
+        [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ],
+];
+
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+pub struct FrameInfo {
+    pub layer: u8,
+    /// Nominal bitrate for CBR, average bitrate for VBR (when the header says so).
+    pub bitrate_kbps: u32,
+    pub vbr: bool,
+}
+
+/// Size of the ID3v2 tag at the start of `data`, footer included.
+fn id3v2_size(data: &[u8]) -> usize {
+    if data.len() < 10 || &data[..3] != b"ID3" {
+        return 0;
+    }
+    let size = data[6..10]
+        .iter()
+        .fold(0usize, |acc, &b| (acc << 7) | (b & 0x7f) as usize);
+    let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    let bytes = data.get(at..at + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Reads the first MPEG audio frame of the file and its Xing/Info/VBRI header.
+pub fn probe(path: &Path) -> io::Result<Option<FrameInfo>> {
+    let mut file = File::open(path)?;
+    let mut head = vec![0u8; 10];
+    let read = file.read(&mut head)?;
+    head.truncate(read);
+
+    // Skip the tag without loading it: embedded pictures can be large.
+    let skip = id3v2_size(&head);
+    let mut data = Vec::with_capacity(PROBE_SIZE);
+    if skip > 0 {
+        io::copy(&mut (&mut file).take(skip.saturating_sub(head.len()) as u64), &mut io::sink())?;
+    } else {
+        data.extend_from_slice(&head);
+    }
+    file.take(PROBE_SIZE as u64).read_to_end(&mut data)?;
+
+    Ok(find_frame(&data))
+}
+
+fn find_frame(data: &[u8]) -> Option<FrameInfo> {
+    let mut pos = 0;
+    while pos + 4 <= data.len() {
+        if data[pos] == 0xff && data[pos + 1] & 0xe0 == 0xe0 {
+            if let Some(info) = parse_frame(data, pos) {
+                return Some(info);
+            }
+        }
+        pos += 1;
+    }
+    None
+}
+
+/// Average bitrate from the stream size and frame count of a VBR header.
+fn average_kbps(bytes: u32, frames: u32, mpeg1: bool, sample_rate: u32) -> Option<u32> {
+    let samples_per_frame: u64 = if mpeg1 { 1152 } else { 576 };
+    let samples = frames as u64 * samples_per_frame;
+    let bits_per_second = (bytes as u64 * 8 * sample_rate as u64).checked_div(samples)?;
+    Some((bits_per_second / 1000) as u32)
+}
+
+fn parse_frame(data: &[u8], pos: usize) -> Option<FrameInfo> {
+    let header = read_u32(data, pos)?;
+
+    let version_bits = (header >> 19) & 0b11; // 00 = 2.5, 10 = 2, 11 = 1
+    let layer_bits = (header >> 17) & 0b11; // 01 = III, 10 = II, 11 = I
+    let bitrate_index = ((header >> 12) & 0b1111) as usize;
+    let rate_index = ((header >> 10) & 0b11) as usize;
+    let channel_mode = (header >> 6) & 0b11;
+
+    if version_bits == 0b01 || layer_bits == 0 || bitrate_index == 0 || bitrate_index == 15 || rate_index == 3 {
+        return None;
+    }
+
+    let mpeg1 = version_bits == 0b11;
+    let layer = (4 - layer_bits) as u8;
+    let mut bitrate_kbps = BITRATES[usize::from(!mpeg1)][layer as usize - 1][bitrate_index];
+    let sample_rate = match version_bits {
+        0b11 => SAMPLE_RATES[rate_index],
+        0b10 => SAMPLE_RATES[rate_index] / 2,
+        _ => SAMPLE_RATES[rate_index] / 4,
+    };
+    let mono = channel_mode == 0b11;
+
+    // Layer III frames can carry a Xing/Info header (LAME) right after the side info,
+    // or a Fraunhofer VBRI header at a fixed offset.
+    let side_info = match (mpeg1, mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let xing_at = pos + 4 + side_info;
+    let mut vbr = false;
+
+    match data.get(xing_at..xing_at + 4) {
+        Some(b"Xing") | Some(b"Info") => {
+            vbr = &data[xing_at..xing_at + 4] == b"Xing";
+            let flags = read_u32(data, xing_at + 4)?;
+            if vbr && flags & 0b11 == 0b11 {
+                let frames = read_u32(data, xing_at + 8)?;
+                let bytes = read_u32(data, xing_at + 12)?;
+                if let Some(kbps) = average_kbps(bytes, frames, mpeg1, sample_rate) {
+                    bitrate_kbps = kbps;
+                }
+            }
+        }
+        _ => {
+            if data.get(pos + 36..pos + 40) == Some(b"VBRI") {
+                vbr = true;
+                let bytes = read_u32(data, pos + 36 + 10)?;
+                let frames = read_u32(data, pos + 36 + 14)?;
+                if let Some(kbps) = average_kbps(bytes, frames, mpeg1, sample_rate) {
+                    bitrate_kbps = kbps;
+                }
+            }
+        }
+    }
+
+    Some(FrameInfo {
+        layer,
+        bitrate_kbps,
+        vbr,
+    })
+}
@@ -0,0 +1,109 @@
+//! Audio tap: a pass-through source that measures what is being played, for
+//! the level meters, and tells when it is over.
+
+use rodio::source::SeekError;
+use rodio::{Sample, Source};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Peak level of the left and right channels, shared between the audio
+/// thread and the UI. Levels are stored as `f32` bits: for positive floats
+/// the bit patterns sort like the values, so `fetch_max` works on them.
+#[derive(Default)]
+pub struct Levels {
+    peaks: [AtomicU32; 2],
+}
+
+impl Levels {
+    /// Peaks (0.0 to 1.0) seen since the previous call.
+    pub fn take(&self) -> [f32; 2] {
+        [0, 1].map(|channel| f32::from_bits(self.peaks[channel].swap(0, Ordering::Relaxed)))
+    }
+
+    fn record(&self, channel: usize, value: f32) {
+        self.peaks[channel].fetch_max(value.abs().to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Called on the audio thread when a track has been played to its end.
+pub type OnEnd = Arc<dyn Fn() + Send + Sync>;
+
+pub struct Tap<S> {
+    inner: S,
+    levels: Arc<Levels>,
+    /// Channel of the next sample.
+    channel: u16,
+    /// Called once, at the end, then dropped.
+    on_end: Option<OnEnd>,
+}
+
+impl<S> Tap<S> {
+    pub fn new(inner: S, levels: Arc<Levels>, on_end: Option<OnEnd>) -> Self {
+        Tap {
+            inner,
+            levels,
+            channel: 0,
+            on_end,
+        }
+    }
+}
+
+impl<S> Iterator for Tap<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        let Some(sample) = self.inner.next() else {
+            if let Some(on_end) = self.on_end.take() {
+                on_end();
+            }
+            return None;
+        };
+        let channels = self.inner.channels().max(1);
+        let value = sample.to_f32();
+        if channels == 1 {
+            self.levels.record(0, value);
+            self.levels.record(1, value);
+        } else if self.channel < 2 {
+            self.levels.record(self.channel as usize, value);
+        }
+        self.channel = (self.channel + 1) % channels;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S> Source for Tap<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.channel = 0;
+        Ok(())
+    }
+}
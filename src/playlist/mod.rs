@@ -0,0 +1,154 @@
+//! Building playlists, from a folder, a playlist file or a list of paths
+//! piped in, for `player::Player::set_playlist`.
+
+use anyhow::Result;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use glob::{MatchOptions, Pattern};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// The paths of a playlist file, one per line. In M3U files, lines starting
+/// with `#` are left out and paths are relative to the file.
+pub fn from_file(path: &str) -> Result<Vec<PathBuf>> {
+    let playlist = from_reader(BufReader::new(File::open(path)?))?;
+    if !is_m3u(Path::new(path)) {
+        return Ok(playlist);
+    }
+    let folder = Path::new(path).parent().unwrap_or(Path::new(""));
+    Ok(playlist
+        .into_iter()
+        .filter(|entry| !entry.to_string_lossy().starts_with('#'))
+        .map(|entry| folder.join(entry))
+        .collect())
+}
+
+/// Whether `path` is an M3U playlist, by its extension.
+pub fn is_m3u(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| ["m3u", "m3u8"].iter().any(|m3u| extension.eq_ignore_ascii_case(m3u)))
+}
+
+/// The paths read from `reader`, one per line, such as the output of `find`
+/// on standard input.
+pub fn from_reader(reader: impl BufRead) -> Result<Vec<PathBuf>> {
+    let mut playlist = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            playlist.push(PathBuf::from(line.trim()));
+        }
+    }
+    Ok(playlist)
+}
+
+/// Files under `path` whose extension is one of `extensions` (any case).
+pub fn from_path(path: &str, extensions: &[String]) -> Result<Vec<PathBuf>> {
+    Scan::new(extensions).files(path)
+}
+
+/// What a folder scan picks up: the files with one of its extensions (any
+/// case), but for those and the folders its exclude patterns match.
+#[derive(Clone)]
+pub struct Scan {
+    extensions: Vec<String>,
+    exclude: Vec<Exclusion>,
+    max_depth: Option<usize>,
+}
+
+#[derive(Clone)]
+struct Exclusion {
+    pattern: Pattern,
+    /// The pattern ended with `/`: it only matches folders.
+    folders_only: bool,
+    /// The pattern has a `/` before its end: it matches the path below the
+    /// folder scanned rather than a file or folder name at any depth.
+    anchored: bool,
+}
+
+/// `*` and `?` stop at `/`, which only `**` crosses.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl Scan {
+    pub fn new(extensions: &[String]) -> Scan {
+        Scan {
+            extensions: extensions.to_vec(),
+            exclude: Vec::new(),
+            max_depth: None,
+        }
+    }
+
+    /// Only goes `depth` folders down: 1 only picks up the files directly in
+    /// the folder scanned. `None` goes all the way.
+    pub fn max_depth(mut self, depth: Option<usize>) -> Scan {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Also leaves out what one of the glob `patterns` matches, like
+    /// `ringtones/` (a folder anywhere), `*-demo.mp3` (files anywhere) or
+    /// `/live/**/*.mp3` (below the `live` folder of the folder scanned).
+    /// Fails on an invalid pattern.
+    pub fn excluding(mut self, patterns: &[String]) -> Result<Scan> {
+        for pattern in patterns {
+            let folders_only = pattern.ends_with('/');
+            let trimmed = pattern.trim_end_matches('/');
+            let anchored = trimmed.contains('/');
+            let pattern = Pattern::new(trimmed.trim_start_matches('/'))
+                .map_err(|e| anyhow::anyhow!("Invalid exclude pattern \"{}\": {}", pattern, e))?;
+            self.exclude.push(Exclusion {
+                pattern,
+                folders_only,
+                anchored,
+            });
+        }
+        Ok(self)
+    }
+
+    /// The files under `path`, or `path` itself if it is one of them.
+    pub fn files(&self, path: &str) -> Result<Vec<PathBuf>> {
+        let root = Path::new(path);
+        let mut playlist = Vec::new();
+
+        // Excluded folders are not even read.
+        let entries = WalkDir::new(path)
+            .max_depth(self.max_depth.unwrap_or(usize::MAX))
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !self.excluded(root, entry.path()));
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    tracing::warn!("Skipped while looking for tracks: {}", e);
+                    continue;
+                }
+            };
+            let path = entry.path();
+            if path.is_file() {
+                if let Some(extension) = path.extension() {
+                    let extension = extension.to_string_lossy();
+                    if self.extensions.iter().any(|wanted| wanted.eq_ignore_ascii_case(&extension)) {
+                        playlist.push(path.to_path_buf());
+                    }
+                }
+            }
+        }
+
+        Ok(playlist)
+    }
+
+    /// Whether `path`, found under `root`, is left out.
+    fn excluded(&self, root: &Path, path: &Path) -> bool {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let name = path.file_name().map(Path::new).unwrap_or(relative);
+        self.exclude.iter().any(|exclusion| {
+            let target = if exclusion.anchored { relative } else { name };
+            exclusion.pattern.matches_path_with(target, MATCH_OPTIONS)
+                && (!exclusion.folders_only || path.is_dir())
+        })
+    }
+}
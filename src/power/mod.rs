@@ -0,0 +1,53 @@
+//! Whether the computer runs on battery, for the main loop to tick slower
+//! while paused. Read from `/sys/class/power_supply` on Linux; elsewhere the
+//! computer is taken as plugged in.
+
+use std::time::{Duration, Instant};
+
+/// How long an answer is kept before reading the power supplies again.
+const RECHECK: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+pub struct Power {
+    /// The last answer, and when it was read.
+    last: Option<(bool, Instant)>,
+}
+
+impl Power {
+    pub fn on_battery(&mut self) -> bool {
+        match self.last {
+            Some((on_battery, read_at)) if read_at.elapsed() < RECHECK => on_battery,
+            _ => {
+                let on_battery = read();
+                self.last = Some((on_battery, Instant::now()));
+                on_battery
+            }
+        }
+    }
+}
+
+/// On battery when a battery discharges, as long as no charger is online.
+#[cfg(target_os = "linux")]
+fn read() -> bool {
+    use std::fs;
+
+    let Ok(supplies) = fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+    let mut discharging = false;
+    for supply in supplies.flatten() {
+        let path = supply.path();
+        let field = |name: &str| fs::read_to_string(path.join(name)).unwrap_or_default();
+        match field("type").trim() {
+            "Mains" | "USB" if field("online").trim() == "1" => return false,
+            "Battery" if field("status").trim() == "Discharging" => discharging = true,
+            _ => {}
+        }
+    }
+    discharging
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read() -> bool {
+    false
+}
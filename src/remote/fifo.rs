@@ -0,0 +1,71 @@
+//! A named pipe, `ctl` next to the socket, that takes the socket's command
+//! lines without answering them, for systems without `socat` or `nc`:
+//!
+//! ```text
+//! echo next > $XDG_RUNTIME_DIR/ksound/ctl
+//! ```
+//!
+//! Errors, having nobody to go back to, are shown on the player's status
+//! line.
+
+use super::{submit, Command, Request};
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+/// Makes the pipe at `path`, replacing the one a player that didn't quit
+/// cleanly left, and reads commands from it on a thread of its own.
+pub fn start(path: PathBuf, sender: Sender<Request>, errors: Sender<String>) -> Result<(), io::Error> {
+    match std::fs::symlink_metadata(&path) {
+        Ok(metadata) if metadata.file_type().is_fifo() => std::fs::remove_file(&path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a named pipe", path.display()),
+            ))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    make_fifo(&path)?;
+    thread::spawn(move || run(&path, &sender, &errors));
+    Ok(())
+}
+
+fn make_fifo(path: &Path) -> Result<(), io::Error> {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)?;
+    // Only the user may drive the player.
+    if unsafe { libc::mkfifo(path.as_ptr(), 0o600) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn run(path: &Path, sender: &Sender<Request>, errors: &Sender<String>) {
+    loop {
+        // Blocks until a writer opens the pipe, and reads until the last
+        // one closes it: each `echo` is a writer of its own.
+        let Ok(pipe) = File::open(path) else {
+            return;
+        };
+        for line in BufReader::new(pipe).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Err(e) = Command::parse(&line).and_then(|command| submit(sender, command)) {
+                if errors.send(format!("{}: {}", line.trim(), e)).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
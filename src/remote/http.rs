@@ -0,0 +1,161 @@
+//! The HTTP API, for home automation and the like, and a remote control
+//! page for phones at `/`, which uses it. Answers are JSON:
+//!
+//! ```text
+//! GET  /status                      the current track, as the `status` command
+//! GET  /queue                       the next tracks
+//! POST /play, /pause, /toggle, /next, /prev
+//! POST /volume   {"volume": 80}
+//! POST /enqueue  {"path": "/music/album"}
+//! ```
+//!
+//! Errors come with a 4xx status and `{"error": "..."}`. There is no
+//! authentication: listen on a loopback address, or behind a proxy that
+//! checks who is calling. POST requests have to say `Content-Type:
+//! application/json`, which a browser doesn't send to another site without
+//! asking it first, so that the web pages the user visits can't drive the
+//! player.
+
+use super::{submit, Command, Request};
+use serde_json::{json, Value};
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+
+/// Largest request body read, plenty for a path.
+const MAX_BODY: u64 = 64 * 1024;
+
+/// The remote control page, self-contained.
+const PAGE: &str = include_str!("remote.html");
+
+#[cfg(not(feature = "tokio"))]
+pub fn start(address: &str, sender: Sender<Request>) -> Result<(), io::Error> {
+    use std::io::Read;
+    use tiny_http::{Header, Response, Server};
+
+    let server = Server::http(address).map_err(io::Error::other)?;
+    std::thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            // A body that can't be read is answered as invalid JSON.
+            let mut body = Vec::new();
+            let _ = request.as_reader().take(MAX_BODY).read_to_end(&mut body);
+            let method = request.method().as_str();
+            let content_type = request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Content-Type"))
+                .map(|header| header.value.as_str());
+            let (status, body, content_type) = respond(method, request.url(), content_type, &body, &sender);
+            let content_type = Header::from_bytes("Content-Type", content_type).expect("valid header");
+            let response = Response::from_string(body)
+                .with_status_code(status)
+                .with_header(content_type);
+            let _ = request.respond(response);
+        }
+    });
+    Ok(())
+}
+
+/// Each connection is a task; the commands wait for the main loop on the
+/// blocking pool.
+#[cfg(feature = "tokio")]
+pub fn start(address: &str, sender: Sender<Request>) -> Result<(), io::Error> {
+    use ksound::net::{self, http};
+
+    let listener = net::runtime().block_on(tokio::net::TcpListener::bind(address))?;
+    net::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let sender = sender.clone();
+            net::spawn(async move {
+                let Ok(request) = http::read_request(&mut stream, MAX_BODY).await else {
+                    return;
+                };
+                let answer = move || {
+                    let content_type = request.content_type.as_deref();
+                    respond(&request.method, &request.url, content_type, &request.body, &sender)
+                };
+                if let Ok((status, body, content_type)) = net::blocking(answer).await {
+                    let _ = http::respond(&mut stream, status, content_type, body.as_bytes()).await;
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Status code, body and content type of the answer to a request, whose
+/// body is of `content_type`.
+fn respond(
+    method: &str,
+    url: &str,
+    content_type: Option<&str>,
+    body: &[u8],
+    sender: &Sender<Request>,
+) -> (u16, String, &'static str) {
+    match (method, url) {
+        ("GET", "/") => (200, PAGE.to_string(), "text/html; charset=utf-8"),
+        _ => {
+            let (status, body) = answer(method, url, content_type, body, sender);
+            (status, body.to_string(), "application/json")
+        }
+    }
+}
+
+/// Status code and body of the answer to an API request.
+fn answer(
+    method: &str,
+    url: &str,
+    content_type: Option<&str>,
+    body: &[u8],
+    sender: &Sender<Request>,
+) -> (u16, Value) {
+    let path = url.split('?').next().unwrap_or_default();
+    let reading = match path {
+        "/status" | "/queue" => true,
+        "/play" | "/pause" | "/toggle" | "/next" | "/prev" | "/volume" | "/enqueue" => false,
+        _ => return (404, json!({ "error": format!("no such endpoint {}", path) })),
+    };
+    let expected = if reading { "GET" } else { "POST" };
+    if method != expected {
+        return (405, json!({ "error": format!("{} only takes {}", path, expected) }));
+    }
+    // A form or a text/plain body, which any web page can send, is refused.
+    let media_type = content_type.and_then(|value| value.split(';').next()).unwrap_or_default();
+    if !reading && !media_type.trim().eq_ignore_ascii_case("application/json") {
+        return (415, json!({ "error": "POST requests take Content-Type: application/json" }));
+    }
+    let command = match path {
+        "/status" => Command::Status,
+        "/queue" => Command::Queue,
+        "/play" => Command::Play,
+        "/pause" => Command::Pause,
+        "/toggle" => Command::Toggle,
+        "/next" => Command::Next,
+        "/prev" => Command::Previous,
+        _ => match body_command(body, path) {
+            Ok(command) => command,
+            Err(e) => return (400, json!({ "error": e })),
+        },
+    };
+    match submit(sender, command) {
+        Ok(Value::Null) => (200, json!({ "ok": true })),
+        Ok(answer) => (200, answer),
+        Err(e) => (400, json!({ "error": e })),
+    }
+}
+
+/// The command of a `/volume` or `/enqueue` request, from its JSON body.
+fn body_command(body: &[u8], path: &str) -> Result<Command, String> {
+    let body: Value = serde_json::from_slice(body).map_err(|e| format!("invalid JSON: {}", e))?;
+    if path == "/volume" {
+        match body["volume"].as_u64() {
+            Some(percent) if percent <= 200 => Ok(Command::Volume(percent as u16)),
+            _ => Err("\"volume\" must be a number from 0 to 200".to_string()),
+        }
+    } else {
+        match body["path"].as_str().map(PathBuf::from) {
+            Some(path) if path.is_absolute() => Ok(Command::Enqueue(path)),
+            _ => Err("\"path\" must be an absolute path".to_string()),
+        }
+    }
+}
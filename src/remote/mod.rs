@@ -0,0 +1,281 @@
+//! Remote control of a running player through a Unix socket, `control.sock`
+//! in `config::runtime_dir`, for window manager key bindings and scripts,
+//! through a named pipe next to it (see `fifo`), through an HTTP API when
+//! `--listen` is given (see `http`), and through a subset of the MPD protocol
+//! when `--mpd` is (see `mpd`).
+//!
+//! The socket protocol is line based: each command line gets its answer
+//! lines, if any, then `OK`, or a single `ERR <message>` line:
+//!
+//! ```text
+//! play | pause | toggle | next | prev | status | queue | volume <0-200> | enqueue <path>
+//! ```
+//!
+//! Connections are served by threads of their own; the commands reach the
+//! main loop as `Request`s, which it answers with a JSON object,
+//! shown as `key: value` lines on the socket. `send` is the other end, for
+//! the `ksound next`, `ksound status`, ... subcommands.
+
+#[cfg(unix)]
+mod fifo;
+mod http;
+mod mpd;
+
+use crate::config;
+use serde_json::Value;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+
+/// Name of the socket in `config::runtime_dir`.
+const SOCKET_NAME: &str = "control.sock";
+
+/// Name of the named pipe in `config::runtime_dir`.
+#[cfg(unix)]
+const FIFO_NAME: &str = "ctl";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Play,
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    Status,
+    /// The next tracks to play.
+    Queue,
+    /// In percent, 0 to 200.
+    Volume(u16),
+    /// Absolute path of a file or directory to add to the playlist.
+    Enqueue(PathBuf),
+    /// Jump to the playlist entry at this index.
+    PlayIndex(usize),
+    /// The whole playlist, with the tags known so far.
+    Playlist,
+    /// Quit, saving the session, as `q` would.
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let line = line.trim();
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim())),
+            None => (line, None),
+        };
+        match (name, argument) {
+            ("play", None) => Ok(Command::Play),
+            ("pause", None) => Ok(Command::Pause),
+            ("toggle", None) => Ok(Command::Toggle),
+            ("next", None) => Ok(Command::Next),
+            ("prev", None) => Ok(Command::Previous),
+            ("status", None) => Ok(Command::Status),
+            ("queue", None) => Ok(Command::Queue),
+            ("quit", None) => Ok(Command::Quit),
+            ("volume", Some(percent)) => match percent.parse() {
+                Ok(percent) if percent <= 200 => Ok(Command::Volume(percent)),
+                _ => Err(format!("invalid volume \"{}\" (0 to 200)", percent)),
+            },
+            ("enqueue", Some(path)) if PathBuf::from(path).is_absolute() => {
+                Ok(Command::Enqueue(PathBuf::from(path)))
+            }
+            ("enqueue", Some(_)) => Err("enqueue needs an absolute path".to_string()),
+            ("volume" | "enqueue", None) => Err(format!("{} needs an argument", name)),
+            ("play" | "pause" | "toggle" | "next" | "prev" | "status" | "queue" | "quit", Some(_)) => {
+                Err(format!("{} takes no argument", name))
+            }
+            _ => Err(format!("unknown command \"{}\"", name)),
+        }
+    }
+}
+
+/// A command waiting for the main loop.
+pub struct Request {
+    pub command: Command,
+    reply: Sender<Result<Value, String>>,
+}
+
+impl Request {
+    /// Sends the answer: an object (`null` when there is nothing to say), or
+    /// the error message.
+    pub fn answer(self, reply: Result<Value, String>) {
+        let _ = self.reply.send(reply);
+    }
+}
+
+/// Hands `command` to the main loop and waits for its answer.
+fn submit(sender: &Sender<Request>, command: Command) -> Result<Value, String> {
+    let (reply, answer) = mpsc::channel();
+    let quitting = || "the player is quitting".to_string();
+    sender.send(Request { command, reply }).map_err(|_| quitting())?;
+    answer.recv().unwrap_or_else(|_| Err(quitting()))
+}
+
+/// `key: value` lines of an answer, one per item for lists.
+fn text(answer: &Value) -> String {
+    let Value::Object(fields) = answer else {
+        return String::new();
+    };
+    let mut lines = Vec::new();
+    for (key, value) in fields {
+        let values = match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Null => {}
+                Value::String(value) => lines.push(format!("{}: {}", key, value)),
+                value => lines.push(format!("{}: {}", key, value)),
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// Where a running player listens.
+pub fn socket_path() -> Result<PathBuf, io::Error> {
+    Ok(config::runtime_dir()?.join(SOCKET_NAME))
+}
+
+/// Sends one command line to the running player and returns its answer
+/// lines, without the final `OK`.
+#[cfg(unix)]
+pub fn send(line: &str) -> anyhow::Result<String> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let path = socket_path()?;
+    let mut stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused) => {
+            let profile = config::profile()
+                .map(|name| format!(" with profile {}", name))
+                .unwrap_or_default();
+            anyhow::bail!("No ksound is running{} (nothing listens on {})", profile, path.display());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    writeln!(stream, "{}", line)?;
+
+    let mut answer = Vec::new();
+    for reply in BufReader::new(stream).lines() {
+        let reply = reply?;
+        if reply == "OK" {
+            return Ok(answer.join("\n"));
+        }
+        if let Some(message) = reply.strip_prefix("ERR ") {
+            anyhow::bail!("{}", message);
+        }
+        answer.push(reply);
+    }
+    anyhow::bail!("The player closed the connection without answering")
+}
+
+#[cfg(not(unix))]
+pub fn send(_line: &str) -> anyhow::Result<String> {
+    anyhow::bail!("Remote control is only available on Unix systems")
+}
+
+pub struct Control {
+    sender: Sender<Request>,
+    /// The socket listened on, removed on drop.
+    socket: Option<PathBuf>,
+    /// The named pipe read from, removed on drop.
+    fifo: Option<PathBuf>,
+    /// Where what goes wrong with the commands of the named pipe is sent.
+    errors: Sender<String>,
+}
+
+impl Control {
+    /// Sends the commands to `requests`, and the errors of the named pipe to
+    /// `errors`.
+    pub fn new(requests: Sender<Request>, errors: Sender<String>) -> Control {
+        Control {
+            sender: requests,
+            socket: None,
+            fifo: None,
+            errors,
+        }
+    }
+
+    /// Listens on the socket and reads the named pipe. Does nothing when
+    /// another player already does, or on platforms without Unix sockets.
+    #[cfg(unix)]
+    pub fn listen_socket(&mut self) -> Result<(), io::Error> {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let path = socket_path()?;
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Ok(());
+            }
+            // Left over by a player that didn't quit cleanly.
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                std::thread::spawn(move || serve(stream, sender));
+            }
+        });
+        self.socket = Some(path);
+
+        let path = config::runtime_dir()?.join(FIFO_NAME);
+        fifo::start(path.clone(), self.sender.clone(), self.errors.clone())?;
+        self.fifo = Some(path);
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen_socket(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    /// Serves the HTTP API on `address` (`host:port`).
+    pub fn listen_http(&self, address: &str) -> Result<(), io::Error> {
+        http::start(address, self.sender.clone())
+    }
+
+    /// Serves MPD clients on `address` (`host:port`).
+    pub fn listen_mpd(&self, address: &str) -> Result<(), io::Error> {
+        mpd::start(address, self.sender.clone())
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        for path in self.socket.iter().chain(&self.fifo) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Answers the commands of one connection until it is closed.
+#[cfg(unix)]
+fn serve(stream: std::os::unix::net::UnixStream, sender: Sender<Request>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = Command::parse(&line).and_then(|command| submit(&sender, command));
+        let written = match reply.map(|answer| text(&answer)) {
+            Ok(text) if text.is_empty() => writeln!(writer, "OK"),
+            Ok(text) => writeln!(writer, "{}\nOK", text),
+            Err(e) => writeln!(writer, "ERR {}", e),
+        };
+        if written.is_err() {
+            return;
+        }
+    }
+}
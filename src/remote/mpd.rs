@@ -0,0 +1,362 @@
+//! The part of the MPD protocol that remote control clients use, so that
+//! `mpc`, status bar widgets and phone apps work with ksound:
+//! https://mpd.readthedocs.io/en/latest/protocol.html
+//!
+//! Supported: `status`, `currentsong`, `playlistinfo`, `playlistid`, `play`,
+//! `playid`, `pause`, `stop` (pauses), `next`, `previous`, `setvol`, `getvol`,
+//! `stats`, `idle`, `noidle`, `ping`, `close` and command lists. The database
+//! and stored playlist commands answer with nothing, so that clients asking
+//! for them at startup carry on. Song ids are playlist positions.
+
+use super::{submit, Command, Request};
+use serde_json::Value;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// Protocol version announced; clients check features against it.
+const VERSION: &str = "0.21.0";
+
+/// How often `idle` checks the player for changes.
+const IDLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Commands listed by `commands`.
+const COMMANDS: &[&str] = &[
+    "close", "commands", "currentsong", "getvol", "idle", "next", "noidle", "notcommands", "pause",
+    "ping", "play", "playid", "playlistid", "playlistinfo", "previous", "setvol", "stats", "status",
+    "stop",
+];
+
+/// Commands answered with nothing: ksound has no database, stored playlists
+/// or outputs to show.
+const EMPTY_COMMANDS: &[&str] = &[
+    "channels", "decoders", "listplaylists", "notcommands", "outputs", "readmessages", "tagtypes",
+    "urlhandlers",
+];
+
+/// Error codes of `ACK` lines.
+const ACK_ERROR_ARG: u8 = 2;
+const ACK_ERROR_UNKNOWN: u8 = 5;
+const ACK_ERROR_NO_EXIST: u8 = 50;
+const ACK_ERROR_SYSTEM: u8 = 52;
+
+struct Ack {
+    code: u8,
+    message: String,
+}
+
+impl Ack {
+    fn new(code: u8, message: impl Into<String>) -> Ack {
+        Ack {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+pub fn start(address: &str, sender: Sender<Request>) -> Result<(), io::Error> {
+    let listener = TcpListener::bind(address)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sender = sender.clone();
+            std::thread::spawn(move || serve(stream, sender));
+        }
+    });
+    Ok(())
+}
+
+/// Answers the commands of one client until it disconnects.
+fn serve(stream: TcpStream, sender: Sender<Request>) {
+    let _ = session(stream, &sender);
+}
+
+fn session(stream: TcpStream, sender: &Sender<Request>) -> Result<(), io::Error> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    writeln!(writer, "OK MPD {}", VERSION)?;
+    // Commands of a command list, and whether each gets a `list_OK`.
+    let mut list: Option<(Vec<String>, bool)> = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        match (line.as_str(), &mut list) {
+            ("command_list_begin", None) => list = Some((Vec::new(), false)),
+            ("command_list_ok_begin", None) => list = Some((Vec::new(), true)),
+            ("command_list_end", Some(_)) => {
+                let (commands, list_ok) = list.take().unwrap_or_default();
+                let mut answer = String::new();
+                let mut failed = false;
+                for (index, command) in commands.iter().enumerate() {
+                    match execute(command, sender) {
+                        Ok(text) => {
+                            answer.push_str(&text);
+                            if list_ok {
+                                answer.push_str("list_OK\n");
+                            }
+                        }
+                        Err(ack) => {
+                            write!(writer, "{}", answer)?;
+                            write_ack(&mut writer, &ack, index, command)?;
+                            failed = true;
+                            break;
+                        }
+                    }
+                }
+                if !failed {
+                    writeln!(writer, "{}OK", answer)?;
+                }
+            }
+            (_, Some((commands, _))) => commands.push(line),
+            ("close", None) => return Ok(()),
+            ("idle", None) => idle(&mut reader, &mut writer, sender, &[])?,
+            (_, None) if line.starts_with("idle ") => {
+                let subsystems: Vec<String> = arguments(&line[5..]).unwrap_or_default();
+                idle(&mut reader, &mut writer, sender, &subsystems)?;
+            }
+            // Outside of `idle`, there is nothing to stop.
+            ("noidle", None) => {}
+            (_, None) => match execute(&line, sender) {
+                Ok(text) => writeln!(writer, "{}OK", text)?,
+                Err(ack) => write_ack(&mut writer, &ack, 0, &line)?,
+            },
+        }
+    }
+}
+
+fn write_ack(writer: &mut impl Write, ack: &Ack, index: usize, line: &str) -> Result<(), io::Error> {
+    let name = line.split_whitespace().next().unwrap_or_default();
+    writeln!(writer, "ACK [{}@{}] {{{}}} {}", ack.code, index, name, ack.message)
+}
+
+/// Splits the arguments of a command: separated by spaces, or quoted with
+/// backslash escapes.
+fn arguments(line: &str) -> Result<Vec<String>, Ack> {
+    let mut arguments = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(first) = chars.next() else {
+            return Ok(arguments);
+        };
+        let mut argument = String::new();
+        if first == '"' {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => argument.extend(chars.next()),
+                    Some(c) => argument.push(c),
+                    None => return Err(Ack::new(ACK_ERROR_ARG, "missing closing '\"'")),
+                }
+            }
+        } else {
+            argument.push(first);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                argument.push(c);
+            }
+        }
+        arguments.push(argument);
+    }
+}
+
+/// Sends `command` to the player.
+fn send(sender: &Sender<Request>, command: Command) -> Result<Value, Ack> {
+    submit(sender, command).map_err(|e| Ack::new(ACK_ERROR_SYSTEM, e))
+}
+
+/// The `index` argument, if any, as a number.
+fn number(arguments: &[String], index: usize) -> Result<Option<usize>, Ack> {
+    match arguments.get(index) {
+        Some(argument) => argument
+            .parse()
+            .map(Some)
+            .map_err(|_| Ack::new(ACK_ERROR_ARG, format!("Integer expected: {}", argument))),
+        None => Ok(None),
+    }
+}
+
+/// Runs one command line and returns its answer, without the final `OK`.
+fn execute(line: &str, sender: &Sender<Request>) -> Result<String, Ack> {
+    let mut arguments = arguments(line)?;
+    if arguments.is_empty() {
+        return Err(Ack::new(ACK_ERROR_UNKNOWN, "No command given"));
+    }
+    let name = arguments.remove(0);
+    let command = match name.as_str() {
+        "ping" => return Ok(String::new()),
+        "status" => return status(sender),
+        "currentsong" => {
+            let status = send(sender, Command::Status)?;
+            return Ok(match status["position"].as_u64() {
+                Some(position) => song(&status, position),
+                None => String::new(),
+            });
+        }
+        "playlistinfo" | "playlistid" => return playlist_info(sender, arguments.first()),
+        "stats" => {
+            let status = send(sender, Command::Status)?;
+            return Ok(format!("songs: {}\n", status["tracks"]));
+        }
+        "getvol" => {
+            let status = send(sender, Command::Status)?;
+            return Ok(format!("volume: {}\n", volume(&status)));
+        }
+        "commands" => {
+            return Ok(COMMANDS.iter().map(|command| format!("command: {}\n", command)).collect());
+        }
+        name if EMPTY_COMMANDS.contains(&name) => return Ok(String::new()),
+        "play" | "playid" => match number(&arguments, 0)? {
+            Some(index) => Command::PlayIndex(index),
+            None => Command::Play,
+        },
+        "pause" => match arguments.first().map(String::as_str) {
+            Some("1") => Command::Pause,
+            Some("0") => Command::Play,
+            Some(argument) => {
+                return Err(Ack::new(ACK_ERROR_ARG, format!("Boolean (0/1) expected: {}", argument)))
+            }
+            None => Command::Toggle,
+        },
+        // There is no stopped state: pausing is the closest.
+        "stop" => Command::Pause,
+        "next" => Command::Next,
+        "previous" => Command::Previous,
+        "setvol" => match number(&arguments, 0)? {
+            Some(percent) if percent <= 100 => Command::Volume(percent as u16),
+            _ => return Err(Ack::new(ACK_ERROR_ARG, "Invalid volume value")),
+        },
+        _ => return Err(Ack::new(ACK_ERROR_UNKNOWN, format!("unknown command \"{}\"", name))),
+    };
+    send(sender, command)?;
+    Ok(String::new())
+}
+
+/// MPD volumes stop at 100%, where ksound goes up to 200%.
+fn volume(status: &Value) -> u64 {
+    status["volume"].as_u64().unwrap_or(0).min(100)
+}
+
+fn status(sender: &Sender<Request>) -> Result<String, Ack> {
+    let status = send(sender, Command::Status)?;
+    let length = status["tracks"].as_u64().unwrap_or(0);
+    let state = match status["state"].as_str() {
+        Some("playing") => "play",
+        Some("paused") => "pause",
+        _ => "stop",
+    };
+    let mut answer = format!(
+        "volume: {}\nrepeat: 0\nrandom: 0\nsingle: 0\nconsume: 0\nplaylist: 1\nplaylistlength: {}\nstate: {}\n",
+        volume(&status),
+        length,
+        state
+    );
+    if let Some(position) = status["position"].as_u64() {
+        let _ = write!(answer, "song: {}\nsongid: {}\n", position, position);
+        if length > 1 {
+            let next = (position + 1) % length;
+            let _ = write!(answer, "nextsong: {}\nnextsongid: {}\n", next, next);
+        }
+    }
+    let elapsed = status["elapsed"].as_u64();
+    let duration = status["duration"].as_u64();
+    if let Some(elapsed) = elapsed {
+        let _ = write!(answer, "time: {}:{}\nelapsed: {}.000\n", elapsed, duration.unwrap_or(0), elapsed);
+    }
+    if let Some(duration) = duration {
+        let _ = writeln!(answer, "duration: {}.000", duration);
+    }
+    Ok(answer)
+}
+
+/// `playlistinfo`, for one position or a `start:end` range when given.
+fn playlist_info(sender: &Sender<Request>, range: Option<&String>) -> Result<String, Ack> {
+    let playlist = send(sender, Command::Playlist)?;
+    let tracks = playlist["tracks"].as_array().cloned().unwrap_or_default();
+    let invalid = || Ack::new(ACK_ERROR_ARG, format!("Invalid range: {}", range.map_or("", |r| r)));
+    let (start, end) = match range.map(|range| range.split_once(':')) {
+        None => (0, tracks.len()),
+        Some(Some((start, end))) => {
+            let start = start.parse().map_err(|_| invalid())?;
+            let end = if end.is_empty() { tracks.len() } else { end.parse().map_err(|_| invalid())? };
+            (start, end.min(tracks.len()))
+        }
+        Some(None) => {
+            let index: usize = range.map_or("", |r| r).parse().map_err(|_| invalid())?;
+            if index >= tracks.len() {
+                return Err(Ack::new(ACK_ERROR_NO_EXIST, "Bad song index"));
+            }
+            (index, index + 1)
+        }
+    };
+    Ok((start..end.max(start)).map(|index| song(&tracks[index], index as u64)).collect())
+}
+
+/// The lines of one playlist entry.
+fn song(track: &Value, position: u64) -> String {
+    let mut answer = format!("file: {}\n", track["file"].as_str().unwrap_or_default());
+    for (key, tag) in [("Artist", "artist"), ("Album", "album"), ("Title", "title")] {
+        if let Some(value) = track[tag].as_str() {
+            let _ = writeln!(answer, "{}: {}", key, value);
+        }
+    }
+    let _ = write!(answer, "Pos: {}\nId: {}\n", position, position);
+    answer
+}
+
+/// What `idle` reports: the subsystems that changed between two statuses.
+fn changes(before: &Value, after: &Value) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if before["state"] != after["state"] || before["file"] != after["file"] {
+        changed.push("player");
+    }
+    if before["volume"] != after["volume"] {
+        changed.push("mixer");
+    }
+    if before["tracks"] != after["tracks"] {
+        changed.push("playlist");
+    }
+    changed
+}
+
+/// Waits for a change in one of `subsystems` (any, when empty), or for
+/// `noidle`, polling the player.
+fn idle(
+    reader: &mut BufReader<TcpStream>,
+    writer: &mut TcpStream,
+    sender: &Sender<Request>,
+    subsystems: &[String],
+) -> Result<(), io::Error> {
+    let snapshot = || submit(sender, Command::Status);
+    let Ok(before) = snapshot() else {
+        return writeln!(writer, "OK");
+    };
+    reader.get_ref().set_read_timeout(Some(IDLE_INTERVAL))?;
+    let mut line = String::new();
+    let result = loop {
+        match reader.read_line(&mut line) {
+            // The client is gone.
+            Ok(0) => break Err(io::ErrorKind::UnexpectedEof.into()),
+            // `noidle`, or anything else, which MPD takes as `noidle` too.
+            Ok(_) => break writeln!(writer, "OK"),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            Err(e) => break Err(e),
+        }
+        let Ok(after) = snapshot() else {
+            break writeln!(writer, "OK");
+        };
+        let changed: Vec<_> = changes(&before, &after)
+            .into_iter()
+            .filter(|name| subsystems.is_empty() || subsystems.iter().any(|s| s == name))
+            .collect();
+        if !changed.is_empty() {
+            let lines: String = changed.iter().map(|name| format!("changed: {}\n", name)).collect();
+            break writeln!(writer, "{}OK", lines);
+        }
+    };
+    reader.get_ref().set_read_timeout(None)?;
+    result
+}
@@ -0,0 +1,154 @@
+//! Last.fm, through its web API: <https://www.last.fm/api/scrobbling>
+
+use super::{Rejected, Service, Track};
+use crate::config;
+use anyhow::Result;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use thiserror::Error;
+
+const API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+
+/// Session key cached in `data_dir`, valid until the user revokes it.
+const SESSION_FILE: &str = "lastfm_session";
+
+/// The "Invalid session key" error code.
+const INVALID_SESSION: u64 = 9;
+
+/// Error codes worth trying again later: operation failed, service offline,
+/// temporarily unavailable and rate limit exceeded. The others, such as an
+/// invalid API key or parameters, come back the same each time.
+const TEMPORARY_ERRORS: [u64; 4] = [8, 11, 16, 29];
+
+#[derive(Debug, Error)]
+#[error("{message} (error {code})")]
+struct ApiError {
+    code: u64,
+    message: String,
+}
+
+pub struct LastFm {
+    account: config::LastFm,
+    session: Option<String>,
+}
+
+impl LastFm {
+    pub fn new(account: config::LastFm) -> Self {
+        LastFm { account, session: None }
+    }
+
+    /// Calls `method`, signed with the API secret as every write call must be.
+    fn call(&self, method: &str, mut params: Vec<(String, String)>) -> Result<Value> {
+        params.push(("method".to_string(), method.to_string()));
+        params.push(("api_key".to_string(), self.account.api_key.clone()));
+        params.sort();
+        let mut signed: String = params.iter().map(|(key, value)| format!("{}{}", key, value)).collect();
+        signed.push_str(&self.account.api_secret);
+        params.push(("api_sig".to_string(), format!("{:x}", md5::compute(signed))));
+        params.push(("format".to_string(), "json".to_string()));
+
+        let form: Vec<(&str, &str)> = params.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+        // Errors come with a 4xx status and the details in the body.
+        let response = match ureq::post(API_URL).send_form(&form) {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(e.into()),
+        };
+        let body: Value = response.into_json()?;
+        if let Some(code) = body["error"].as_u64() {
+            let message = body["message"].as_str().unwrap_or("unknown error").to_string();
+            return Err(ApiError { code, message }.into());
+        }
+        Ok(body)
+    }
+
+    /// The cached session key, else a new one from the user's login.
+    fn session(&mut self) -> Result<String> {
+        if let Some(session) = &self.session {
+            return Ok(session.clone());
+        }
+        let path = config::data_dir()?.join(SESSION_FILE);
+        let session = match fs::read_to_string(&path) {
+            Ok(session) if !session.trim().is_empty() => session.trim().to_string(),
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {
+                let params = vec![
+                    ("username".to_string(), self.account.username.clone()),
+                    ("password".to_string(), self.account.password.clone()),
+                ];
+                let body = self.call("auth.getMobileSession", params)?;
+                let session = body["session"]["key"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("no session key in the login answer"))?
+                    .to_string();
+                fs::write(&path, &session)?;
+                session
+            }
+        };
+        self.session = Some(session.clone());
+        Ok(session)
+    }
+
+    /// Calls `method` within the session, logging in again once if the
+    /// session was revoked.
+    fn call_in_session(&mut self, method: &str, params: &[(String, String)]) -> Result<Value> {
+        let mut with_session = params.to_vec();
+        with_session.push(("sk".to_string(), self.session()?));
+        match self.call(method, with_session) {
+            Err(e) if e.downcast_ref::<ApiError>().is_some_and(|e| e.code == INVALID_SESSION) => {
+                self.session = None;
+                fs::remove_file(config::data_dir()?.join(SESSION_FILE))?;
+                let mut with_session = params.to_vec();
+                with_session.push(("sk".to_string(), self.session()?));
+                self.call(method, with_session)
+            }
+            result => result,
+        }
+    }
+}
+
+/// The parameters of `track`, suffixed with `[index]` for batches.
+fn track_params(track: &Track, index: Option<usize>) -> Vec<(String, String)> {
+    let key = |name: &str| match index {
+        Some(index) => format!("{}[{}]", name, index),
+        None => name.to_string(),
+    };
+    let mut params = vec![(key("artist"), track.artist.clone()), (key("track"), track.title.clone())];
+    if let Some(album) = &track.album {
+        params.push((key("album"), album.clone()));
+    }
+    if let Some(duration) = track.duration {
+        params.push((key("duration"), duration.to_string()));
+    }
+    params
+}
+
+impl Service for LastFm {
+    fn name(&self) -> &'static str {
+        "Last.fm"
+    }
+
+    fn key(&self) -> &'static str {
+        "lastfm"
+    }
+
+    fn now_playing(&mut self, track: &Track) -> Result<()> {
+        self.call_in_session("track.updateNowPlaying", &track_params(track, None))?;
+        Ok(())
+    }
+
+    fn scrobble(&mut self, tracks: &[Track]) -> Result<()> {
+        let mut params = Vec::new();
+        for (index, track) in tracks.iter().enumerate() {
+            params.extend(track_params(track, Some(index)));
+            params.push((format!("timestamp[{}]", index), track.started.to_string()));
+        }
+        match self.call_in_session("track.scrobble", &params) {
+            Ok(_) => Ok(()),
+            Err(e) if e.downcast_ref::<ApiError>().is_some_and(|e| !TEMPORARY_ERRORS.contains(&e.code)) => {
+                Err(Rejected(format!("{:#}", e)).into())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
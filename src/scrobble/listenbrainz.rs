@@ -0,0 +1,84 @@
+//! ListenBrainz, through its web API: <https://listenbrainz.readthedocs.io/en/latest/users/api/>
+
+use super::{Rejected, Service, Track};
+use crate::config;
+use anyhow::Result;
+use serde_json::{json, Value};
+
+pub struct ListenBrainz {
+    account: config::ListenBrainz,
+}
+
+impl ListenBrainz {
+    pub fn new(account: config::ListenBrainz) -> Self {
+        ListenBrainz { account }
+    }
+
+    fn submit(&self, listen_type: &str, payload: Vec<Value>) -> Result<()> {
+        let url = format!("{}/1/submit-listens", self.account.url.trim_end_matches('/'));
+        let body = json!({ "listen_type": listen_type, "payload": payload });
+        match ureq::post(&url)
+            .set("Authorization", &format!("Token {}", self.account.token))
+            .send_json(body)
+        {
+            Ok(_) => Ok(()),
+            // Errors come with a 4xx status and the details in the body.
+            Err(ureq::Error::Status(status, response)) => {
+                let body: Value = response.into_json().unwrap_or_default();
+                let message = body["error"].as_str().unwrap_or("unknown error");
+                let message = format!("{} (status {})", message, status);
+                // Other than a timeout or too many requests, a 4xx refusal is
+                // for good; a 5xx one is the server's, worth trying again.
+                match status {
+                    400..=499 if status != 408 && status != 429 => Err(Rejected(message).into()),
+                    _ => anyhow::bail!(message),
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The `track_metadata` of a listen.
+fn metadata(track: &Track) -> Value {
+    let mut additional_info = json!({
+        "media_player": "ksound",
+        "submission_client": "ksound",
+        "submission_client_version": env!("CARGO_PKG_VERSION"),
+    });
+    if let Some(duration) = track.duration {
+        additional_info["duration_ms"] = json!(duration * 1000);
+    }
+    let mut metadata = json!({
+        "artist_name": track.artist,
+        "track_name": track.title,
+        "additional_info": additional_info,
+    });
+    if let Some(album) = &track.album {
+        metadata["release_name"] = json!(album);
+    }
+    metadata
+}
+
+impl Service for ListenBrainz {
+    fn name(&self) -> &'static str {
+        "ListenBrainz"
+    }
+
+    fn key(&self) -> &'static str {
+        "listenbrainz"
+    }
+
+    fn now_playing(&mut self, track: &Track) -> Result<()> {
+        self.submit("playing_now", vec![json!({ "track_metadata": metadata(track) })])
+    }
+
+    fn scrobble(&mut self, tracks: &[Track]) -> Result<()> {
+        let payload = tracks
+            .iter()
+            .map(|track| json!({ "listened_at": track.started, "track_metadata": metadata(track) }))
+            .collect();
+        let listen_type = if tracks.len() == 1 { "single" } else { "import" };
+        self.submit(listen_type, payload)
+    }
+}
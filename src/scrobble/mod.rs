@@ -0,0 +1,324 @@
+//! Scrobbling: the track playing and the tracks listened to are submitted to
+//! the services set up in the config file (Last.fm, ListenBrainz), from a thread of their own (a task
+//! with the `tokio` feature) so that a slow or missing network never holds up playback.
+//!
+//! A track counts once half of it, or 4 minutes of it, has been listened to;
+//! tracks of 30 seconds or less never do. Listens wait in a queue file in
+//! `data_dir` until they are accepted, so none is lost while offline or when
+//! quitting right after a track. Listens a service refuses for good, for a
+//! bad listen or a bad account, are set aside in another file rather than
+//! sent again and again, holding up the ones after them.
+
+mod lastfm;
+mod listenbrainz;
+
+use crate::config::{self, Config};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Most listens submitted in one request.
+const BATCH_SIZE: usize = 50;
+
+/// A listen, as submitted.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Track {
+    pub artist: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    /// Length of the track, in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    /// When it started playing, in seconds since the Unix epoch.
+    pub started: u64,
+}
+
+impl Track {
+    /// A listen starting now. Tracks without an artist and a title can't be
+    /// scrobbled.
+    pub fn new(
+        artist: Option<&str>,
+        title: Option<&str>,
+        album: Option<&str>,
+        duration: Option<Duration>,
+    ) -> Option<Track> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Some(Track {
+            artist: artist?.to_string(),
+            title: title?.to_string(),
+            album: album.map(String::from),
+            duration: duration.map(|d| d.as_secs()),
+            started,
+        })
+    }
+}
+
+/// How long the current track has been listened to.
+pub struct Listen {
+    pub track: Track,
+    /// Playback speed: listening a minute at 2x covers two minutes of the track.
+    speed: f32,
+    listened: Duration,
+    /// Start of the current stretch of playing, `None` while paused.
+    since: Option<Instant>,
+}
+
+impl Listen {
+    pub fn new(track: Track, speed: f32) -> Self {
+        Listen {
+            track,
+            speed,
+            listened: Duration::ZERO,
+            since: Some(Instant::now()),
+        }
+    }
+
+    pub fn pause(&mut self) {
+        if let Some(since) = self.since.take() {
+            self.listened += since.elapsed().mul_f32(self.speed);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.since.get_or_insert_with(Instant::now);
+    }
+
+    /// Whether enough of the track was listened to for a scrobble.
+    pub fn counts(&self) -> bool {
+        let Some(duration) = self.track.duration else {
+            return false;
+        };
+        let playing = self.since.map_or(Duration::ZERO, |since| since.elapsed().mul_f32(self.speed));
+        let listened = self.listened + playing;
+        duration > 30 && (listened.as_secs() * 2 >= duration || listened >= Duration::from_secs(240))
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+use std::sync::mpsc::{self, Receiver, Sender};
+#[cfg(feature = "tokio")]
+use tokio::sync::mpsc::{self, UnboundedReceiver as Receiver, UnboundedSender as Sender};
+
+/// A refusal that sending the same request again would get too, such as
+/// an invalid listen or API key, unlike network and server failures.
+#[derive(Debug, Error)]
+#[error("{0}")]
+struct Rejected(String);
+
+/// A scrobbling service.
+trait Service: Send {
+    fn name(&self) -> &'static str;
+    /// Name of its files in `data_dir`.
+    fn key(&self) -> &'static str;
+    fn now_playing(&mut self, track: &Track) -> Result<()>;
+    /// Submits up to `BATCH_SIZE` listens; fails with `Rejected` if they are
+    /// refused for good.
+    fn scrobble(&mut self, tracks: &[Track]) -> Result<()>;
+}
+
+/// Listens not accepted yet by a service, or refused by it, saved as JSON
+/// in `data_dir`.
+struct Queue {
+    path: PathBuf,
+    tracks: Vec<Track>,
+}
+
+impl Queue {
+    fn open(file_name: &str) -> Result<Queue, io::Error> {
+        let path = config::data_dir()?.join(file_name);
+        let tracks = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Queue { path, tracks })
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        if self.tracks.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let content = serde_json::to_string_pretty(&self.tracks).map_err(io::Error::other)?;
+        config::write_whole(&self.path, &content)
+    }
+}
+
+enum Message {
+    NowPlaying(Track),
+    /// Submit what is in the queue.
+    Flush,
+}
+
+/// The thread of one service.
+struct Worker {
+    sender: Sender<Message>,
+    queue: Arc<Mutex<Queue>>,
+}
+
+pub struct Scrobbler {
+    workers: Vec<Worker>,
+    /// Failures of the threads, for the status line.
+    errors: Arc<Mutex<Vec<String>>>,
+}
+
+impl Scrobbler {
+    /// Starts the services set up in `config`; `None` when there is none.
+    pub fn start(config: &Config) -> Result<Option<Scrobbler>, io::Error> {
+        let mut services: Vec<Box<dyn Service>> = Vec::new();
+        if let Some(lastfm) = &config.lastfm {
+            services.push(Box::new(lastfm::LastFm::new(lastfm.clone())));
+        }
+        if let Some(listenbrainz) = &config.listenbrainz {
+            services.push(Box::new(listenbrainz::ListenBrainz::new(listenbrainz.clone())));
+        }
+        if services.is_empty() {
+            return Ok(None);
+        }
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let mut workers = Vec::new();
+        for service in services {
+            let queue = Arc::new(Mutex::new(Queue::open(&format!("{}_queue.json", service.key()))?));
+            #[cfg(not(feature = "tokio"))]
+            let (sender, receiver) = mpsc::channel();
+            #[cfg(feature = "tokio")]
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let (worker_queue, worker_errors) = (Arc::clone(&queue), Arc::clone(&errors));
+            #[cfg(not(feature = "tokio"))]
+            std::thread::spawn(move || run(service, receiver, worker_queue, worker_errors));
+            #[cfg(feature = "tokio")]
+            crate::net::spawn(run(service, receiver, worker_queue, worker_errors));
+            // Listens left over from the last run.
+            let _ = sender.send(Message::Flush);
+            workers.push(Worker { sender, queue });
+        }
+        Ok(Some(Scrobbler { workers, errors }))
+    }
+
+    pub fn now_playing(&self, track: &Track) {
+        for worker in &self.workers {
+            let _ = worker.sender.send(Message::NowPlaying(track.clone()));
+        }
+    }
+
+    /// Queues the listen, saved before this returns, and has it submitted.
+    pub fn scrobble(&self, track: &Track) -> Result<(), io::Error> {
+        for worker in &self.workers {
+            let mut queue = lock(&worker.queue);
+            queue.tracks.push(track.clone());
+            queue.save()?;
+            let _ = worker.sender.send(Message::Flush);
+        }
+        Ok(())
+    }
+
+    /// What went wrong since the last call.
+    pub fn take_errors(&self) -> Vec<String> {
+        std::mem::take(&mut *lock(&self.errors))
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(not(feature = "tokio"))]
+fn run(
+    mut service: Box<dyn Service>,
+    receiver: Receiver<Message>,
+    queue: Arc<Mutex<Queue>>,
+    errors: Arc<Mutex<Vec<String>>>,
+) {
+    let name = service.name();
+    let mut failing = false;
+    for message in receiver {
+        let result = handle(&mut *service, message, &queue);
+        report(name, result, &mut failing, &errors);
+    }
+}
+
+/// The requests block: they are made on the runtime's blocking pool, one
+/// at a time.
+#[cfg(feature = "tokio")]
+async fn run(
+    service: Box<dyn Service>,
+    mut receiver: Receiver<Message>,
+    queue: Arc<Mutex<Queue>>,
+    errors: Arc<Mutex<Vec<String>>>,
+) {
+    let name = service.name();
+    let service = Arc::new(Mutex::new(service));
+    let mut failing = false;
+    while let Some(message) = receiver.recv().await {
+        let (service, queue) = (Arc::clone(&service), Arc::clone(&queue));
+        let result = crate::net::blocking(move || handle(&mut **lock(&service), message, &queue)).await;
+        report(name, result.unwrap_or_else(|e| Err(e.into())), &mut failing, &errors);
+    }
+}
+
+fn handle(service: &mut dyn Service, message: Message, queue: &Mutex<Queue>) -> Result<()> {
+    match message {
+        Message::NowPlaying(track) => service.now_playing(&track),
+        Message::Flush => flush(service, queue),
+    }
+}
+
+/// Only the first of a series of failures is reported, as when offline
+/// every track would fail the same way.
+fn report(name: &str, result: Result<()>, failing: &mut bool, errors: &Mutex<Vec<String>>) {
+    match result {
+        Ok(()) => *failing = false,
+        Err(e) => {
+            if !*failing {
+                lock(errors).push(format!("Couldn't scrobble to {}: {:#}", name, e));
+            } else {
+                tracing::warn!("Couldn't scrobble to {}: {:#}", name, e);
+            }
+            *failing = true;
+        }
+    }
+}
+
+/// Submits the queue in batches, dropping each batch once accepted, and
+/// moving it to `<key>_rejected.json` once refused for good.
+fn flush(service: &mut dyn Service, queue: &Mutex<Queue>) -> Result<()> {
+    let (mut refused, mut refusal) = (0, None);
+    loop {
+        let batch: Vec<Track> = lock(queue).tracks.iter().take(BATCH_SIZE).cloned().collect();
+        if batch.is_empty() {
+            break;
+        }
+        match service.scrobble(&batch) {
+            Ok(()) => {}
+            Err(e) if e.is::<Rejected>() => {
+                let mut rejected = Queue::open(&format!("{}_rejected.json", service.key()))?;
+                rejected.tracks.extend(batch.iter().cloned());
+                rejected.save()?;
+                refused += batch.len();
+                refusal = Some((e, rejected.path));
+            }
+            Err(e) => return Err(e),
+        }
+        let mut queue = lock(queue);
+        queue.tracks.drain(..batch.len());
+        queue.save()?;
+    }
+    match refusal {
+        Some((e, path)) => Err(e.context(format!("{} listens refused, set aside in {}", refused, path.display()))),
+        None => Ok(()),
+    }
+}
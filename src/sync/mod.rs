@@ -0,0 +1,181 @@
+//! The favorites, skip list, bans, star ratings and resume position in one
+//! TOML file, written by `ksound sync export` on a machine and merged by
+//! `ksound sync import` with what another one has: nothing already there
+//! is overwritten.
+//!
+//! Tracks are found by their place in the library (the `paths` of the
+//! config file) first, so that the music doesn't have to be in the same
+//! folder on both machines, then by their path.
+
+use crate::cache::MetadataCache;
+use crate::config::{self, Ban, Bans, Session, TrackEntry, TrackStore};
+use crate::player;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Bundle {
+    /// Seconds since the Unix epoch.
+    pub exported: u64,
+    #[serde(default, rename = "favorite")]
+    pub favorites: Vec<TrackEntry>,
+    #[serde(default, rename = "skip")]
+    pub skipped: Vec<TrackEntry>,
+    #[serde(default, rename = "ban")]
+    pub bans: Vec<Ban>,
+    #[serde(default, rename = "rating")]
+    pub ratings: Vec<Rating>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<SavedSession>,
+}
+
+/// The stars of a rated track.
+#[derive(Serialize, Deserialize)]
+pub struct Rating {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative: Option<String>,
+    pub stars: u8,
+}
+
+/// Where playback stood when ksound was last quit.
+#[derive(Serialize, Deserialize)]
+pub struct SavedSession {
+    pub track: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative: Option<String>,
+    /// In seconds.
+    pub position: f64,
+    /// When it was saved, in seconds since the Unix epoch: the newest
+    /// session wins.
+    pub saved: u64,
+}
+
+/// What `import` took.
+#[derive(Default)]
+pub struct Report {
+    pub favorites: usize,
+    pub skipped: usize,
+    pub bans: usize,
+    /// Ratings written to tracks that had none.
+    pub ratings: usize,
+    /// Ratings left out, the track being rated here already.
+    pub ratings_kept: usize,
+    /// Ratings of tracks not found here.
+    pub ratings_missing: usize,
+    /// Whether the resume position was taken, being newer than this
+    /// machine's.
+    pub session: bool,
+}
+
+impl Bundle {
+    pub fn parse(content: &str) -> Result<Bundle> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+/// What there is to carry, with the ratings of `files`, the tracks of the
+/// library.
+pub fn export(library: &[PathBuf], files: &[PathBuf]) -> Result<Bundle> {
+    let roots = canonical(library);
+    let mut cache = MetadataCache::new()?;
+    let ratings = files
+        .iter()
+        .filter_map(|file| {
+            let stars = cache.metadata(file)?.rating?;
+            Some(Rating {
+                path: file.to_string_lossy().to_string(),
+                relative: relative(&roots, file),
+                stars,
+            })
+        })
+        .collect();
+    cache.save()?;
+
+    let session = Session::load().map(|session| SavedSession {
+        track: session.track.to_string_lossy().to_string(),
+        relative: relative(&roots, &session.track),
+        position: session.position,
+        saved: Session::saved_at().map_or(0, seconds),
+    });
+    Ok(Bundle {
+        exported: seconds(SystemTime::now()),
+        favorites: TrackStore::favorites(library)?.entries().to_vec(),
+        skipped: TrackStore::skip_list(library)?.entries().to_vec(),
+        bans: Bans::open()?.entries().to_vec(),
+        ratings,
+        session,
+    })
+}
+
+/// Merges `bundle` with what this machine has: the entries, bans and
+/// ratings it lacks are added, and the resume position taken if newer.
+pub fn import(bundle: Bundle, library: &[PathBuf]) -> Result<Report> {
+    let roots = canonical(library);
+    let mut report = Report {
+        favorites: TrackStore::favorites(library)?.merge(bundle.favorites)?,
+        skipped: TrackStore::skip_list(library)?.merge(bundle.skipped)?,
+        bans: Bans::open()?.merge(bundle.bans)?,
+        ..Report::default()
+    };
+
+    for rating in bundle.ratings {
+        let Some(track) = locate(&roots, rating.relative.as_deref(), &rating.path) else {
+            report.ratings_missing += 1;
+            continue;
+        };
+        if player::TrackMetadata::from_path(&track).is_some_and(|metadata| metadata.rating.is_some()) {
+            report.ratings_kept += 1;
+            continue;
+        }
+        player::write_rating(&track, rating.stars)?;
+        report.ratings += 1;
+    }
+
+    if let Some(saved) = bundle.session {
+        let newer = Session::saved_at().is_none_or(|local| seconds(local) < saved.saved);
+        if let Some(track) = locate(&roots, saved.relative.as_deref(), &saved.track).filter(|_| newer) {
+            let session = config::Session {
+                track,
+                position: saved.position,
+                favorites_only: false,
+                filter: None,
+                playlist: Vec::new(),
+            };
+            session.save()?;
+            report.session = true;
+        }
+    }
+    Ok(report)
+}
+
+fn canonical(library: &[PathBuf]) -> Vec<PathBuf> {
+    library.iter().filter_map(|root| root.canonicalize().ok()).collect()
+}
+
+/// `path` relative to the library folder holding it.
+fn relative(roots: &[PathBuf], path: &Path) -> Option<String> {
+    let path = path.canonicalize().ok()?;
+    roots
+        .iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .map(|relative| relative.to_string_lossy().to_string())
+}
+
+/// The file of a track here: at the same place in the library, or at the
+/// same path.
+fn locate(roots: &[PathBuf], relative: Option<&str>, path: &str) -> Option<PathBuf> {
+    relative
+        .and_then(|relative| roots.iter().map(|root| root.join(relative)).find(|path| path.is_file()))
+        .or_else(|| Some(PathBuf::from(path)).filter(|path| path.is_file()))
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |age| age.as_secs())
+}
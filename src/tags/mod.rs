@@ -0,0 +1,459 @@
+use crate::player::TrackMetadata;
+use anyhow::Result;
+use id3::{Content, Frame, Tag, TagLike, Version};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Text frames where rippers and download sites like to leave their name or URL.
+const AD_PRONE_FRAMES: [&str; 8] = ["TENC", "TXXX", "TCOP", "TPUB", "TOWN", "TSSE", "TRSN", "TRSO"];
+
+/// How the comments rippers and encoders sign their files start, in lowercase.
+/// Other comments may be the listener's own notes, or data such as iTunSMPB,
+/// the encoder delay used for gapless playback, and are kept.
+const JUNK_COMMENTS: [&str; 12] = [
+    "amazon.com song id",
+    "created by grip",
+    "downloaded from",
+    "eac v",
+    "encoded by",
+    "encoded with",
+    "exact audio copy",
+    "lame ",
+    "lame3",
+    "ripped by",
+    "ripped with",
+    "uploaded by",
+];
+
+/// How the owners of the private frames that only track a purchase or a
+/// library entry start: Amazon's store, Windows Media Player and Zune.
+const TRACKING_OWNERS: [&str; 3] = ["www.amazon.com", "WM/", "Zune"];
+
+fn looks_like_url(text: &str) -> bool {
+    let text = text.to_lowercase();
+    text.contains("http://") || text.contains("https://") || text.contains("www.")
+}
+
+fn shorten(text: &str) -> String {
+    let text = text.replace('\n', " ");
+    if text.chars().count() > 50 {
+        format!("{}…", text.chars().take(49).collect::<String>())
+    } else {
+        text
+    }
+}
+
+fn is_junk_comment(text: &str) -> bool {
+    let text = text.trim().to_lowercase();
+    text.is_empty() || looks_like_url(&text) || JUNK_COMMENTS.iter().any(|start| text.starts_with(start))
+}
+
+/// Why a frame should go, or `None` if it is worth keeping. Links, official
+/// pages included, only go when `links` is set.
+fn junk_reason(frame: &Frame, links: bool) -> Option<String> {
+    let id = frame.id();
+    match frame.content() {
+        Content::Comment(comment) if is_junk_comment(&comment.text) => {
+            Some(format!("comment \"{}\"", shorten(&comment.text)))
+        }
+        Content::Link(url) if links => Some(format!("link {}", shorten(url))),
+        Content::ExtendedLink(link) if links => Some(format!("link {}", shorten(&link.link))),
+        Content::Private(private)
+            if TRACKING_OWNERS.iter().any(|owner| private.owner_identifier.starts_with(owner)) =>
+        {
+            Some(format!("private data from \"{}\"", shorten(&private.owner_identifier)))
+        }
+        Content::Text(text) if AD_PRONE_FRAMES.contains(&id) && looks_like_url(text) => {
+            Some(format!("advertisement \"{}\"", shorten(text)))
+        }
+        Content::ExtendedText(text) if looks_like_url(&text.value) => {
+            Some(format!("advertisement \"{}\"", shorten(&text.value)))
+        }
+        _ => None,
+    }
+}
+
+/// What `tags strip` would do to one file.
+pub struct StripPlan {
+    pub path: PathBuf,
+    version: Version,
+    kept: Vec<Frame>,
+    /// (frame id, reason) for every frame that is dropped.
+    pub removed: Vec<(String, String)>,
+    pub remove_id3v1: bool,
+}
+
+impl StripPlan {
+    /// Examines the tags of `path`, links included when `links` is set.
+    /// Returns `None` for files without an ID3v2 tag.
+    pub fn for_file(path: &Path, links: bool) -> Result<Option<Self>> {
+        let tag = match Tag::read_from_path(path) {
+            Ok(tag) => tag,
+            Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut kept: Vec<Frame> = Vec::new();
+        let mut removed = Vec::new();
+
+        for frame in tag.frames() {
+            if let Some(reason) = junk_reason(frame, links) {
+                removed.push((frame.id().to_string(), reason));
+                continue;
+            }
+
+            // Only one frame per text id is allowed (TXXX aside), and exact
+            // copies of any frame are pointless.
+            let single_text = frame.id().starts_with('T') && frame.id() != "TXXX";
+            let duplicate = kept
+                .iter()
+                .any(|k| k == frame || (single_text && k.id() == frame.id()));
+            if duplicate {
+                removed.push((frame.id().to_string(), "duplicate".to_string()));
+                continue;
+            }
+
+            kept.push(frame.clone());
+        }
+
+        let remove_id3v1 = id3::v1::Tag::read_from_path(path).is_ok();
+
+        Ok(Some(StripPlan {
+            path: path.to_path_buf(),
+            version: tag.version(),
+            kept,
+            removed,
+            remove_id3v1,
+        }))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && !self.remove_id3v1
+    }
+
+    pub fn apply(&self) -> Result<()> {
+        if !self.removed.is_empty() {
+            let mut tag = Tag::with_version(self.version);
+            for frame in &self.kept {
+                tag.add_frame(frame.clone());
+            }
+            // id3 cannot write v2.2 tags: upgrade those.
+            let version = match self.version {
+                Version::Id3v22 => Version::Id3v24,
+                v => v,
+            };
+            tag.write_to_path(&self.path, version)?;
+        }
+        if self.remove_id3v1 {
+            id3::v1::Tag::remove_from_path(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// `ksound tags strip`: reports the junk found in `files`, link frames
+/// included when `links` is set, then removes it unless this is a dry run.
+/// `confirm` is asked before anything is written.
+pub fn strip(
+    files: &[PathBuf],
+    links: bool,
+    dry_run: bool,
+    confirm: impl FnOnce() -> Result<bool>,
+) -> Result<()> {
+    let mut plans = Vec::new();
+    for file in files {
+        match StripPlan::for_file(file, links) {
+            Ok(Some(plan)) if !plan.is_empty() => plans.push(plan),
+            Ok(_) => {}
+            Err(e) => eprintln!("{}: {}", file.display(), e),
+        }
+    }
+
+    let mut frame_count = 0;
+    for plan in &plans {
+        println!("{}", plan.path.display());
+        for (id, reason) in &plan.removed {
+            println!("  - {} {}", id, reason);
+        }
+        if plan.remove_id3v1 {
+            println!("  - ID3v1 tag (redundant with ID3v2)");
+        }
+        frame_count += plan.removed.len();
+    }
+
+    if plans.is_empty() {
+        println!("Nothing to strip in {} files.", files.len());
+        return Ok(());
+    }
+    println!(
+        "\n{} of {} files have junk: {} frames to remove.",
+        plans.len(),
+        files.len(),
+        frame_count
+    );
+
+    if dry_run || !confirm()? {
+        return Ok(());
+    }
+
+    for plan in &plans {
+        if let Err(e) = plan.apply() {
+            eprintln!("{}: {}", plan.path.display(), e);
+        }
+    }
+    println!("Done.");
+    Ok(())
+}
+
+/// A problem found by `ksound lint`.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LintIssue {
+    MissingTitle {
+        path: PathBuf,
+    },
+    MissingYear {
+        path: PathBuf,
+    },
+    MissingTrackNumber {
+        path: PathBuf,
+    },
+    InconsistentAlbumArtist {
+        album: String,
+        directory: PathBuf,
+        /// Album artist values seen in the album, with the tracks carrying each.
+        album_artists: BTreeMap<String, Vec<PathBuf>>,
+    },
+    Unreadable {
+        path: PathBuf,
+        error: String,
+    },
+}
+
+impl LintIssue {
+    fn heading(&self) -> &'static str {
+        match self {
+            LintIssue::MissingTitle { .. } => "Missing title",
+            LintIssue::MissingYear { .. } => "Missing year",
+            LintIssue::MissingTrackNumber { .. } => "Missing track number",
+            LintIssue::InconsistentAlbumArtist { .. } => "Inconsistent album artist",
+            LintIssue::Unreadable { .. } => "Unreadable tags",
+        }
+    }
+}
+
+/// Checks the tags of every file. Albums are grouped by name within a
+/// directory, so two unrelated "Greatest Hits" are never mixed up.
+pub fn lint(files: &[PathBuf]) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut albums: BTreeMap<(PathBuf, String), BTreeMap<String, Vec<PathBuf>>> = BTreeMap::new();
+
+    for path in files {
+        // MP4 tags as well as ID3 ones.
+        let tags = match TrackMetadata::read(path) {
+            Ok(tags) => tags.unwrap_or_default(),
+            Err(e) => {
+                issues.push(LintIssue::Unreadable {
+                    path: path.clone(),
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        if tags.title.as_ref().is_none_or(|t| t.trim().is_empty()) {
+            issues.push(LintIssue::MissingTitle { path: path.clone() });
+        }
+        if tags.year.is_none() {
+            issues.push(LintIssue::MissingYear { path: path.clone() });
+        }
+        if tags.track.is_none() {
+            issues.push(LintIssue::MissingTrackNumber { path: path.clone() });
+        }
+        if let Some(album) = tags.album.filter(|a| !a.trim().is_empty()) {
+            let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            let album_artist = tags.album_artist.unwrap_or_default();
+            albums
+                .entry((directory, album))
+                .or_default()
+                .entry(album_artist)
+                .or_default()
+                .push(path.clone());
+        }
+    }
+
+    for ((directory, album), album_artists) in albums {
+        if album_artists.len() > 1 {
+            issues.push(LintIssue::InconsistentAlbumArtist {
+                album,
+                directory,
+                album_artists,
+            });
+        }
+    }
+
+    issues
+}
+
+/// Prints lint issues grouped by kind, for humans.
+pub fn print_lint_report(files: &[PathBuf], issues: &[LintIssue]) {
+    let mut by_heading: BTreeMap<&str, Vec<&LintIssue>> = BTreeMap::new();
+    for issue in issues {
+        by_heading.entry(issue.heading()).or_default().push(issue);
+    }
+
+    for (heading, issues) in &by_heading {
+        println!("{} ({})", heading, issues.len());
+        for issue in issues {
+            match issue {
+                LintIssue::MissingTitle { path }
+                | LintIssue::MissingYear { path }
+                | LintIssue::MissingTrackNumber { path } => println!("  {}", path.display()),
+                LintIssue::Unreadable { path, error } => println!("  {}: {}", path.display(), error),
+                LintIssue::InconsistentAlbumArtist {
+                    album,
+                    directory,
+                    album_artists,
+                } => {
+                    println!("  \"{}\" in {}", album, directory.display());
+                    for (artist, paths) in album_artists {
+                        let artist = if artist.is_empty() { "(none)" } else { artist.as_str() };
+                        println!("    {}: {} tracks", artist, paths.len());
+                    }
+                }
+            }
+        }
+        println!();
+    }
+
+    println!("{} issues in {} files.", issues.len(), files.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn atom(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut atom = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend_from_slice(kind);
+        atom.extend_from_slice(payload);
+        atom
+    }
+
+    /// An `ilst` item holding `value`, of the `data_type` given.
+    fn item(kind: &[u8; 4], data_type: u32, value: &[u8]) -> Vec<u8> {
+        let mut data = data_type.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0; 4]); // locale
+        data.extend_from_slice(value);
+        atom(kind, &atom(b"data", &data))
+    }
+
+    /// A path for a file of this test, removed once it is dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, content: &[u8]) -> TempFile {
+            let path = std::env::temp_dir().join(format!("ksound-{}-{}", std::process::id(), name));
+            fs::write(&path, content).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn lint_reads_mp4_tags() {
+        let mut ilst = Vec::new();
+        ilst.extend(item(b"\xa9nam", 1, b"Title"));
+        ilst.extend(item(b"\xa9ART", 1, b"Artist"));
+        ilst.extend(item(b"\xa9alb", 1, b"Album"));
+        ilst.extend(item(b"aART", 1, b"Artist"));
+        ilst.extend(item(b"\xa9day", 1, b"2001"));
+        // Two reserved bytes, track 3 of 12, two more.
+        ilst.extend(item(b"trkn", 0, &[0, 0, 0, 3, 0, 12, 0, 0]));
+        let mut meta = vec![0; 4]; // version/flags
+        meta.extend(atom(b"ilst", &ilst));
+        let mut m4a = atom(b"ftyp", b"M4A ");
+        m4a.extend(atom(b"moov", &atom(b"udta", &atom(b"meta", &meta))));
+        m4a.extend(atom(b"mdat", &[0; 16]));
+        let file = TempFile::new("lint.m4a", &m4a);
+
+        let issues = lint(std::slice::from_ref(&file.0));
+        let headings: Vec<_> = issues.iter().map(LintIssue::heading).collect();
+        assert!(headings.is_empty(), "{:?}", headings);
+    }
+
+    #[test]
+    fn lint_reports_missing_tags() {
+        let file = TempFile::new("lint.mp3", &[0xff, 0xfb, 0x90, 0x00]);
+        let issues = lint(std::slice::from_ref(&file.0));
+        let headings: Vec<_> = issues.iter().map(LintIssue::heading).collect();
+        assert_eq!(headings, ["Missing title", "Missing year", "Missing track number"]);
+    }
+
+    #[test]
+    fn strip_removes_known_junk_only() {
+        let comment = |description: &str, text: &str| {
+            Frame::with_content(
+                "COMM",
+                Content::Comment(id3::frame::Comment {
+                    lang: "eng".to_string(),
+                    description: description.to_string(),
+                    text: text.to_string(),
+                }),
+            )
+        };
+        let private = |owner: &str| {
+            Frame::with_content(
+                "PRIV",
+                Content::Private(id3::frame::Private {
+                    owner_identifier: owner.to_string(),
+                    private_data: vec![1, 2, 3],
+                }),
+            )
+        };
+        let mut tag = Tag::new();
+        tag.set_title("Title");
+        tag.add_frame(comment("", "Great solo at 2:10"));
+        tag.add_frame(comment("iTunSMPB", " 00000000 00000210 000007E4"));
+        tag.add_frame(comment("ripper", "Ripped by SomeGroup"));
+        tag.add_frame(comment("ad", "Get more at www.example.com"));
+        tag.add_frame(private("www.amazon.com"));
+        tag.add_frame(private("WM/UniqueFileIdentifier"));
+        tag.add_frame(private("com.example.player"));
+        tag.add_frame(Frame::link("WOAR", "https://artist.example.com"));
+        tag.add_frame(Frame::text("TENC", "http://rips.example.com"));
+        let file = TempFile::new("strip.mp3", &[0xff, 0xfb, 0x90, 0x00]);
+        tag.write_to_path(&file.0, Version::Id3v24).unwrap();
+
+        let plan = StripPlan::for_file(&file.0, false).unwrap().unwrap();
+        let removed: Vec<_> = plan.removed.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(removed, ["COMM", "COMM", "PRIV", "PRIV", "TENC"]);
+        assert!(!plan.remove_id3v1);
+        let with_links = StripPlan::for_file(&file.0, true).unwrap().unwrap();
+        assert_eq!(with_links.removed.len(), 6);
+
+        plan.apply().unwrap();
+        let tag = Tag::read_from_path(&file.0).unwrap();
+        let comments: Vec<_> = tag.comments().map(|comment| comment.description.as_str()).collect();
+        assert_eq!(comments, ["", "iTunSMPB"]);
+        let owners: Vec<_> = tag
+            .frames()
+            .filter_map(|frame| match frame.content() {
+                Content::Private(private) => Some(private.owner_identifier.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(owners, ["com.example.player"]);
+        let link = tag.get("WOAR").and_then(|frame| frame.content().link());
+        assert_eq!(link, Some("https://artist.example.com"));
+        assert_eq!(tag.title(), Some("Title"));
+        assert!(tag.get("TENC").is_none());
+    }
+}
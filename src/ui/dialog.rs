@@ -0,0 +1,1103 @@
+//! Dialogs drawn over the tabs: tag editor, setting editor, confirmations,
+//! folder picker, file renaming, bookmarks, search and help.
+//! While one is open it gets every key, and the screen behind it keeps
+//! being redrawn.
+
+use super::keymap::{self, BanKey, FormKey, HelpKey, QuitKey};
+use super::{centered_rect, Keymap, Setting, TagEdit, Theme, UserAction};
+use crate::config::{expand_home, Bookmark};
+use crate::player::{EncodingFix, TrackMetadata};
+use crossterm::event::KeyCode;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use ratatui::{
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub enum Dialog {
+    EditTags(EditState),
+    /// Whether to delete a track, to the trash or for good. A favorite is
+    /// asked about again, with `ConfirmDeleteFavorite`.
+    ConfirmDelete {
+        track: PathBuf,
+        trash: bool,
+        favorite: bool,
+    },
+    ConfirmDeleteFavorite {
+        track: PathBuf,
+        trash: bool,
+    },
+    /// Whether to add a track to the skip list, asked for or left early
+    /// `count` times.
+    ConfirmSkip {
+        track: PathBuf,
+        label: String,
+        count: Option<usize>,
+    },
+    /// Whether to ban the artist or the album of a track, by its tags.
+    ConfirmBan {
+        label: String,
+        artist: Option<String>,
+        album: Option<String>,
+    },
+    /// Whether to quit while a track plays, or to detach.
+    ConfirmQuit {
+        label: String,
+    },
+    ConfirmEncodingFix {
+        track: PathBuf,
+        encoding: Option<&'static str>,
+        fixes: Vec<EncodingFix>,
+    },
+    /// Where to move a track.
+    MoveTrack(MoveState),
+    /// New name of the file of a track.
+    RenameTrack {
+        track: PathBuf,
+        input: String,
+    },
+    /// Filter of the playlist, by tags.
+    Filter {
+        input: String,
+    },
+    /// Bookmarks of a track to jump to, and the name of a new one.
+    Bookmarks(BookmarkState),
+    EditSetting(SettingEdit),
+    Search(SearchState),
+    Help(HelpState),
+}
+
+pub struct EditState {
+    track: PathBuf,
+    current_field: usize,
+    fields: Vec<String>,
+    original_values: Vec<String>,
+}
+
+const FIELD_NAMES: [&str; 4] = ["Artist", "Album", "Title", "Year"];
+
+impl EditState {
+    pub fn new(track: PathBuf, metadata: Option<&TrackMetadata>) -> Self {
+        let field = |value: Option<&String>| value.cloned().unwrap_or_default();
+        let fields = match metadata {
+            Some(m) => vec![
+                field(m.artist.as_ref()),
+                field(m.album.as_ref()),
+                field(m.title.as_ref()),
+                field(m.year.as_ref()),
+            ],
+            None => vec![String::new(); 4],
+        };
+        EditState {
+            track,
+            current_field: 0,
+            original_values: fields.clone(),
+            fields,
+        }
+    }
+
+    /// New values of the fields that changed; emptied fields are left alone.
+    fn changes(&self) -> TagEdit {
+        let mut results = self
+            .fields
+            .iter()
+            .zip(self.original_values.iter())
+            .map(|(new, old)| (new != old && !new.is_empty()).then(|| new.clone()));
+        (
+            results.next().flatten(),
+            results.next().flatten(),
+            results.next().flatten(),
+            results.next().flatten(),
+        )
+    }
+}
+
+pub struct SettingEdit {
+    setting: Setting,
+    input: String,
+}
+
+impl SettingEdit {
+    pub fn new(setting: Setting, input: String) -> Self {
+        SettingEdit { setting, input }
+    }
+}
+
+pub struct MoveState {
+    track: PathBuf,
+    label: String,
+    /// The `move_to` folders of the config, as written there.
+    folders: Vec<String>,
+    /// A folder typed in, picked after the configured ones.
+    input: String,
+    /// Index in `folders`, or `folders.len()` for the typed one.
+    selected: usize,
+}
+
+impl MoveState {
+    pub fn new(track: PathBuf, label: String, folders: Vec<String>) -> Self {
+        MoveState {
+            track,
+            label,
+            folders,
+            input: String::new(),
+            selected: 0,
+        }
+    }
+
+    /// The folder picked, `~/` expanded; none while the typed one is empty.
+    fn folder(&self) -> Option<PathBuf> {
+        let folder = self.folders.get(self.selected).unwrap_or(&self.input).trim();
+        (!folder.is_empty()).then(|| PathBuf::from(expand_home(folder)))
+    }
+}
+
+pub struct BookmarkState {
+    track: PathBuf,
+    label: String,
+    /// Where a new bookmark goes: the position when the dialog opened.
+    position: Duration,
+    bookmarks: Vec<Bookmark>,
+    /// Name of a new bookmark, picked after the saved ones.
+    input: String,
+    /// Index in `bookmarks`, or `bookmarks.len()` for the new one.
+    selected: usize,
+}
+
+impl BookmarkState {
+    pub fn new(track: PathBuf, label: String, position: Duration, bookmarks: Vec<Bookmark>) -> Self {
+        BookmarkState {
+            track,
+            label,
+            position,
+            bookmarks,
+            input: String::new(),
+            selected: 0,
+        }
+    }
+
+    /// The bookmark typed in, named after its position if left unnamed.
+    fn new_bookmark(&self) -> Bookmark {
+        let name = match self.input.trim() {
+            "" => clock(self.position),
+            name => name.to_string(),
+        };
+        Bookmark {
+            name,
+            position: self.position,
+        }
+    }
+}
+
+pub struct SearchState {
+    /// Playlist index and text of each searchable entry.
+    indexes: Vec<usize>,
+    entries: Vec<String>,
+    query: String,
+    /// Entries matching the query, best first.
+    hits: Vec<usize>,
+    selected: usize,
+}
+
+impl SearchState {
+    pub fn new(indexes: Vec<usize>, entries: Vec<String>) -> Self {
+        let mut search = SearchState {
+            indexes,
+            entries,
+            query: String::new(),
+            hits: Vec::new(),
+            selected: 0,
+        };
+        search.update_hits();
+        search
+    }
+
+    /// Best matches first; an empty query lists the playlist in order.
+    fn update_hits(&mut self) {
+        let matcher = SkimMatcherV2::default();
+        let mut hits: Vec<(i64, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, entry)| {
+                if self.query.is_empty() {
+                    Some((0, idx))
+                } else {
+                    matcher.fuzzy_match(entry, &self.query).map(|score| (score, idx))
+                }
+            })
+            .collect();
+        hits.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        self.hits = hits.into_iter().map(|(_, idx)| idx).collect();
+        self.selected = 0;
+    }
+}
+
+pub struct HelpState {
+    lines: Vec<Line<'static>>,
+    scroll: u16,
+    /// Lines shown at the last draw, for the page keys.
+    page: u16,
+    pending: Vec<KeyCode>,
+}
+
+impl HelpState {
+    /// The key bindings of every screen, built from the binding tables.
+    pub fn new(theme: &Theme, keymap: &Keymap) -> Self {
+        let mut lines = Vec::new();
+        for (title, note, screen) in keymap::SCREENS {
+            lines.push(Line::from(Span::styled(
+                title,
+                Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+            )));
+            for (label, description) in screen.entries(keymap) {
+                lines.push(Line::from(vec![
+                    Span::styled(format!("  {:<12}", label), Style::default().fg(theme.highlight)),
+                    Span::raw(description),
+                ]));
+            }
+            if let Some(note) = note {
+                lines.push(Line::from(Span::styled(
+                    format!("  {}", note),
+                    Style::default().fg(theme.dim),
+                )));
+            }
+            lines.push(Line::from(""));
+        }
+        HelpState {
+            lines,
+            scroll: 0,
+            page: 1,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Dialog {
+    /// Applies a key. Returns the action to run once the dialog is done with.
+    pub fn handle(&mut self, code: KeyCode, keymap: &Keymap) -> Option<UserAction> {
+        match self {
+            Dialog::EditTags(edit) => {
+                let last = edit.fields.len() - 1;
+                match keymap::lookup(keymap::FORM, code) {
+                    Some(FormKey::Cancel) => return Some(UserAction::Refresh),
+                    Some(FormKey::Accept) => {
+                        return Some(UserAction::SaveTags(edit.track.clone(), edit.changes()));
+                    }
+                    Some(FormKey::Up) => edit.current_field = edit.current_field.saturating_sub(1),
+                    Some(FormKey::Down) => edit.current_field = (edit.current_field + 1).min(last),
+                    Some(FormKey::Top) => edit.current_field = 0,
+                    Some(FormKey::Bottom) => edit.current_field = last,
+                    Some(FormKey::Erase) => {
+                        edit.fields[edit.current_field].pop();
+                    }
+                    Some(FormKey::Remove) => {}
+                    None => {
+                        if let KeyCode::Char(c) = code {
+                            edit.fields[edit.current_field].push(c);
+                        }
+                    }
+                }
+                None
+            }
+            Dialog::EditSetting(edit) => {
+                match keymap::lookup(keymap::FORM, code) {
+                    Some(FormKey::Cancel) => return Some(UserAction::Refresh),
+                    Some(FormKey::Accept) => {
+                        return Some(UserAction::ChangeSetting(edit.setting, Some(edit.input.clone())));
+                    }
+                    Some(FormKey::Erase) => {
+                        edit.input.pop();
+                    }
+                    Some(_) => {}
+                    None => {
+                        if let KeyCode::Char(c) = code {
+                            edit.input.push(c);
+                        }
+                    }
+                }
+                None
+            }
+            Dialog::RenameTrack { track, input } => {
+                match keymap::lookup(keymap::FORM, code) {
+                    Some(FormKey::Cancel) => return Some(UserAction::Refresh),
+                    Some(FormKey::Accept) => {
+                        return Some(UserAction::RenameConfirmed(track.clone(), input.clone()));
+                    }
+                    Some(FormKey::Erase) => {
+                        input.pop();
+                    }
+                    Some(_) => {}
+                    None => {
+                        if let KeyCode::Char(c) = code {
+                            input.push(c);
+                        }
+                    }
+                }
+                None
+            }
+            Dialog::Filter { input } => {
+                match keymap::lookup(keymap::FORM, code) {
+                    Some(FormKey::Cancel) => return Some(UserAction::Refresh),
+                    Some(FormKey::Accept) => return Some(UserAction::FilterConfirmed(input.clone())),
+                    Some(FormKey::Erase) => {
+                        input.pop();
+                    }
+                    Some(_) => {}
+                    None => {
+                        if let KeyCode::Char(c) = code {
+                            input.push(c);
+                        }
+                    }
+                }
+                None
+            }
+            Dialog::ConfirmDelete {
+                track,
+                trash,
+                favorite: true,
+            } => {
+                if !keymap::lookup(keymap::CONFIRM, code)? {
+                    return Some(UserAction::DeleteCancelled);
+                }
+                let (track, trash) = (track.clone(), *trash);
+                *self = Dialog::ConfirmDeleteFavorite { track, trash };
+                None
+            }
+            Dialog::ConfirmDelete { track, trash, .. } | Dialog::ConfirmDeleteFavorite { track, trash } => {
+                keymap::lookup(keymap::CONFIRM, code).map(|answer| {
+                    if answer {
+                        UserAction::DeleteConfirmed(track.clone(), *trash)
+                    } else {
+                        UserAction::DeleteCancelled
+                    }
+                })
+            }
+            Dialog::ConfirmSkip { track, count, .. } => keymap::lookup(keymap::CONFIRM, code).map(|answer| {
+                if answer {
+                    UserAction::SkipConfirmed(track.clone(), *count)
+                } else {
+                    UserAction::Refresh
+                }
+            }),
+            // A missing tag can't be banned: its key does nothing.
+            Dialog::ConfirmBan { artist, album, .. } => match keymap::lookup(keymap::BAN, code)? {
+                BanKey::Artist => artist.clone().map(|artist| UserAction::BanConfirmed {
+                    artist: Some(artist),
+                    album: None,
+                }),
+                BanKey::Album => album.clone().map(|album| UserAction::BanConfirmed {
+                    artist: artist.clone(),
+                    album: Some(album),
+                }),
+                BanKey::Cancel => Some(UserAction::Refresh),
+            },
+            Dialog::ConfirmQuit { .. } => keymap::lookup(keymap::QUIT, code).map(|answer| match answer {
+                QuitKey::Quit => UserAction::QuitNow,
+                QuitKey::Detach => UserAction::Detach,
+                QuitKey::Cancel => UserAction::Refresh,
+            }),
+            // With nothing to repair, any key closes the notice.
+            Dialog::ConfirmEncodingFix { fixes, .. } if fixes.is_empty() => Some(UserAction::Refresh),
+            Dialog::ConfirmEncodingFix { track, fixes, .. } => {
+                keymap::lookup(keymap::CONFIRM, code).map(|answer| {
+                    if answer {
+                        UserAction::FixEncodingConfirmed(track.clone(), std::mem::take(fixes))
+                    } else {
+                        UserAction::Refresh
+                    }
+                })
+            }
+            // Typing goes to the folder typed in, which is then picked.
+            Dialog::MoveTrack(pick) => {
+                let last = pick.folders.len();
+                match keymap::lookup(keymap::FORM, code) {
+                    Some(FormKey::Cancel) => return Some(UserAction::Refresh),
+                    Some(FormKey::Accept) => {
+                        return pick
+                            .folder()
+                            .map(|folder| UserAction::MoveConfirmed(pick.track.clone(), folder));
+                    }
+                    Some(FormKey::Up) => pick.selected = pick.selected.saturating_sub(1),
+                    Some(FormKey::Down) => pick.selected = (pick.selected + 1).min(last),
+                    Some(FormKey::Top) => pick.selected = 0,
+                    Some(FormKey::Bottom) => pick.selected = last,
+                    Some(FormKey::Erase) => {
+                        pick.input.pop();
+                        pick.selected = last;
+                    }
+                    Some(FormKey::Remove) => {}
+                    None => {
+                        if let KeyCode::Char(c) = code {
+                            pick.input.push(c);
+                            pick.selected = last;
+                        }
+                    }
+                }
+                None
+            }
+            // Like the folder picker: typing goes to the name of a new
+            // bookmark, which is then picked.
+            Dialog::Bookmarks(marks) => {
+                let last = marks.bookmarks.len();
+                match keymap::lookup(keymap::FORM, code) {
+                    Some(FormKey::Cancel) => return Some(UserAction::Refresh),
+                    Some(FormKey::Accept) => {
+                        let track = marks.track.clone();
+                        return Some(match marks.bookmarks.get(marks.selected) {
+                            Some(bookmark) => UserAction::JumpToBookmark(track, bookmark.clone()),
+                            None => UserAction::AddBookmark(track, marks.new_bookmark()),
+                        });
+                    }
+                    Some(FormKey::Up) => marks.selected = marks.selected.saturating_sub(1),
+                    Some(FormKey::Down) => marks.selected = (marks.selected + 1).min(last),
+                    Some(FormKey::Top) => marks.selected = 0,
+                    Some(FormKey::Bottom) => marks.selected = last,
+                    Some(FormKey::Erase) => {
+                        marks.input.pop();
+                        marks.selected = last;
+                    }
+                    Some(FormKey::Remove) => {
+                        let bookmark = marks.bookmarks.get(marks.selected)?;
+                        return Some(UserAction::RemoveBookmark(marks.track.clone(), bookmark.name.clone()));
+                    }
+                    None => {
+                        if let KeyCode::Char(c) = code {
+                            marks.input.push(c);
+                            marks.selected = last;
+                        }
+                    }
+                }
+                None
+            }
+            Dialog::Search(search) => {
+                match keymap::lookup(keymap::FORM, code) {
+                    Some(FormKey::Cancel) => return Some(UserAction::Refresh),
+                    Some(FormKey::Accept) => {
+                        let hit = search.hits.get(search.selected);
+                        return Some(hit.map_or(UserAction::Refresh, |&idx| {
+                            UserAction::PlayTrack(search.indexes[idx])
+                        }));
+                    }
+                    Some(FormKey::Up) => search.selected = search.selected.saturating_sub(1),
+                    Some(FormKey::Down) => search.selected += 1,
+                    Some(FormKey::Top) => search.selected = 0,
+                    Some(FormKey::Bottom) => search.selected = usize::MAX,
+                    Some(FormKey::Erase) => {
+                        search.query.pop();
+                        search.update_hits();
+                    }
+                    Some(FormKey::Remove) => {}
+                    None => {
+                        if let KeyCode::Char(c) = code {
+                            search.query.push(c);
+                            search.update_hits();
+                        }
+                    }
+                }
+                search.selected = search.selected.min(search.hits.len().saturating_sub(1));
+                None
+            }
+            Dialog::Help(help) => {
+                match keymap::resolve(keymap.help, &mut help.pending, code) {
+                    Some(HelpKey::Up) => help.scroll = help.scroll.saturating_sub(1),
+                    Some(HelpKey::Down) => help.scroll = help.scroll.saturating_add(1),
+                    Some(HelpKey::PageUp) => help.scroll = help.scroll.saturating_sub(help.page),
+                    Some(HelpKey::PageDown) => help.scroll = help.scroll.saturating_add(help.page),
+                    Some(HelpKey::Top) => help.scroll = 0,
+                    // Clamped to the last line at the next draw.
+                    Some(HelpKey::Bottom) => help.scroll = u16::MAX,
+                    Some(HelpKey::Close) => return Some(UserAction::Refresh),
+                    None => {}
+                }
+                None
+            }
+        }
+    }
+
+    pub fn draw(&mut self, f: &mut Frame, theme: &Theme) {
+        match self {
+            Dialog::EditTags(edit) => draw_edit_tags(f, edit, theme),
+            Dialog::ConfirmDelete { track, trash, .. } => draw_confirm_delete(f, track, *trash, false, theme),
+            Dialog::ConfirmDeleteFavorite { track, trash } => {
+                draw_confirm_delete(f, track, *trash, true, theme)
+            }
+            Dialog::ConfirmSkip { label, count, .. } => draw_confirm_skip(f, label, *count, theme),
+            Dialog::ConfirmBan { label, artist, album } => {
+                draw_confirm_ban(f, label, artist.as_deref(), album.as_deref(), theme)
+            }
+            Dialog::ConfirmQuit { label } => draw_confirm_quit(f, label, theme),
+            Dialog::ConfirmEncodingFix {
+                track,
+                encoding,
+                fixes,
+            } => draw_confirm_encoding_fix(f, track, *encoding, fixes, theme),
+            Dialog::MoveTrack(pick) => draw_move_track(f, pick, theme),
+            Dialog::RenameTrack { track, input } => draw_rename_track(f, track, input, theme),
+            Dialog::Filter { input } => draw_filter(f, input, theme),
+            Dialog::Bookmarks(marks) => draw_bookmarks(f, marks, theme),
+            Dialog::EditSetting(edit) => draw_edit_setting(f, edit, theme),
+            Dialog::Search(search) => draw_search(f, search, theme),
+            Dialog::Help(help) => draw_help(f, help, theme),
+        }
+    }
+}
+
+fn draw_edit_tags(f: &mut Frame, edit: &EditState, theme: &Theme) {
+    let popup_area = centered_rect(80, 60, f.area());
+
+    let mut text = vec![
+        Line::from(vec![Span::styled(
+            "Edit MP3 Tags",
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        )]),
+        Line::from(vec![Span::styled(
+            format!("File: {}", edit.track.display()),
+            Style::default().fg(theme.highlight),
+        )]),
+        Line::from(""),
+        Line::from("Use ↑↓ to navigate, type to edit, Enter to confirm, Esc to cancel"),
+        Line::from(""),
+    ];
+
+    for (idx, field_name) in FIELD_NAMES.iter().enumerate() {
+        let is_current = idx == edit.current_field;
+        let style = if is_current {
+            Style::default().fg(theme.good).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(theme.text)
+        };
+
+        text.push(Line::from(vec![
+            Span::styled(format!("{}: ", field_name), style),
+            Span::styled(edit.fields[idx].clone(), style),
+            if is_current {
+                Span::styled("█", Style::default().fg(theme.good))
+            } else {
+                Span::raw("")
+            },
+        ]));
+    }
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent)),
+        )
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_edit_setting(f: &mut Frame, edit: &SettingEdit, theme: &Theme) {
+    let popup_area = centered_rect(70, 30, f.area());
+
+    let text = vec![
+        Line::from(Span::styled(
+            edit.setting.name(),
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(Span::styled(
+            edit.setting.hint().unwrap_or(""),
+            Style::default().fg(theme.dim),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::raw(edit.input.clone()),
+            Span::styled("█", Style::default().fg(theme.good)),
+        ]),
+    ];
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(" Enter to save, Esc to cancel "),
+        )
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_rename_track(f: &mut Frame, track: &Path, input: &str, theme: &Theme) {
+    let popup_area = centered_rect(70, 30, f.area());
+    let folder = track.parent().unwrap_or(Path::new(""));
+
+    let text = vec![
+        Line::from(Span::styled(
+            "Rename the file",
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(Span::styled(
+            format!("In {}; the extension is kept if left out", folder.display()),
+            Style::default().fg(theme.dim),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::raw(input.to_string()),
+            Span::styled("█", Style::default().fg(theme.good)),
+        ]),
+    ];
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(" Enter to rename, Esc to cancel "),
+        )
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_filter(f: &mut Frame, input: &str, theme: &Theme) {
+    let popup_area = centered_rect(70, 30, f.area());
+
+    let text = vec![
+        Line::from(Span::styled(
+            "Play only the tracks matching",
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(Span::styled(
+            "e.g. genre:ambient year:>2000 -rating:0, or words of the artist, album or title; empty for all",
+            Style::default().fg(theme.dim),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::raw(input.to_string()),
+            Span::styled("█", Style::default().fg(theme.good)),
+        ]),
+    ];
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(" Enter to filter, Esc to cancel "),
+        )
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+/// `favorite` for the second question about a favorite.
+fn draw_confirm_delete(f: &mut Frame, track: &Path, trash: bool, favorite: bool, theme: &Theme) {
+    let popup_area = centered_rect(70, 30, f.area());
+    let heading = match favorite {
+        true => "★ This is a favorite ★",
+        false => "⚠ Delete Confirmation ⚠",
+    };
+    let (question, answer) = match (favorite, trash) {
+        (true, true) => ("Move it to the trash all the same?", "Yes, to the trash   "),
+        (true, false) => ("Delete it for good all the same?", "Yes, delete this file   "),
+        (false, true) => ("Move this file to the trash?", "Yes, to the trash   "),
+        (false, false) => ("Are you sure you want to delete for good:", "Yes, delete this file   "),
+    };
+
+    let text = vec![
+        Line::from(vec![Span::styled(
+            heading,
+            Style::default().fg(theme.bad).add_modifier(Modifier::BOLD),
+        )]),
+        Line::from(""),
+        Line::from(vec![Span::raw(question)]),
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            format!("{}", track.display()),
+            Style::default().fg(theme.highlight).add_modifier(Modifier::ITALIC),
+        )]),
+        Line::from(""),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("  [Y] ", Style::default().fg(theme.good).add_modifier(Modifier::BOLD)),
+            Span::raw(answer),
+            Span::styled("  [N] ", Style::default().fg(theme.bad).add_modifier(Modifier::BOLD)),
+            Span::raw("No, cancel"),
+        ]),
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            "Press ESC to cancel",
+            Style::default().fg(theme.dim).add_modifier(Modifier::ITALIC),
+        )]),
+    ];
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.bad).add_modifier(Modifier::BOLD))
+                .title(" WARNING ")
+                .title_style(Style::default().fg(theme.bad).add_modifier(Modifier::BOLD)),
+        )
+        .alignment(ratatui::layout::Alignment::Center)
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_confirm_skip(f: &mut Frame, label: &str, count: Option<usize>, theme: &Theme) {
+    let popup_area = centered_rect(60, 30, f.area());
+
+    let text = vec![
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            label.to_string(),
+            Style::default().fg(theme.highlight).add_modifier(Modifier::ITALIC),
+        )]),
+        Line::from(""),
+        Line::from(match count {
+            None => "Add it to the skip list?".to_string(),
+            Some(1) => "was left early. Add it to the skip list?".to_string(),
+            Some(count) => format!("was left early {} times in a row. Add it to the skip list?", count),
+        }),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("  [Y] ", Style::default().fg(theme.good).add_modifier(Modifier::BOLD)),
+            Span::raw("Yes, never play it again   "),
+            Span::styled("  [N] ", Style::default().fg(theme.bad).add_modifier(Modifier::BOLD)),
+            Span::raw("No, keep it"),
+        ]),
+    ];
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(" Skip for good? ")
+                .title_style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+        )
+        .alignment(ratatui::layout::Alignment::Center)
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_confirm_ban(f: &mut Frame, label: &str, artist: Option<&str>, album: Option<&str>, theme: &Theme) {
+    let popup_area = centered_rect(60, 30, f.area());
+    let key = |key: &'static str| Span::styled(key, Style::default().fg(theme.bad).add_modifier(Modifier::BOLD));
+
+    let mut text = vec![
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            label.to_string(),
+            Style::default().fg(theme.highlight).add_modifier(Modifier::ITALIC),
+        )]),
+        Line::from(""),
+        Line::from("Never play again:"),
+        Line::from(""),
+    ];
+    if let Some(artist) = artist {
+        text.push(Line::from(vec![key("[A] "), Span::raw(format!("every track by {}", artist))]));
+    }
+    if let Some(album) = album {
+        let album = match artist {
+            Some(artist) => format!("the album {} by {}", album, artist),
+            None => format!("the album {}", album),
+        };
+        text.push(Line::from(vec![key("[L] "), Span::raw(album)]));
+    }
+    if artist.is_none() && album.is_none() {
+        text.push(Line::from("Nothing to ban: the track has no artist or album tag."));
+    }
+    text.push(Line::from(""));
+    text.push(Line::from(vec![
+        Span::styled("[N] ", Style::default().fg(theme.good).add_modifier(Modifier::BOLD)),
+        Span::raw("No, cancel"),
+    ]));
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(" Ban? ")
+                .title_style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+        )
+        .alignment(ratatui::layout::Alignment::Center)
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_confirm_quit(f: &mut Frame, label: &str, theme: &Theme) {
+    let popup_area = centered_rect(60, 30, f.area());
+
+    let text = vec![
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            label.to_string(),
+            Style::default().fg(theme.highlight).add_modifier(Modifier::ITALIC),
+        )]),
+        Line::from(""),
+        Line::from("is playing. Quit anyway?"),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("  [Y] ", Style::default().fg(theme.bad).add_modifier(Modifier::BOLD)),
+            Span::raw("Yes, quit   "),
+            Span::styled("  [D] ", Style::default().fg(theme.good).add_modifier(Modifier::BOLD)),
+            Span::raw("Detach, keep playing   "),
+            Span::styled("  [N] ", Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+            Span::raw("No, stay"),
+        ]),
+    ];
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(" Quit? ")
+                .title_style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+        )
+        .alignment(ratatui::layout::Alignment::Center)
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_move_track(f: &mut Frame, pick: &MoveState, theme: &Theme) {
+    let popup_area = centered_rect(70, 50, f.area());
+    let row = |index: usize, text: String| {
+        if index == pick.selected {
+            Line::from(Span::styled(
+                format!("▶ {}", text),
+                Style::default().fg(theme.good).add_modifier(Modifier::BOLD),
+            ))
+        } else {
+            Line::from(format!("  {}", text))
+        }
+    };
+
+    let mut text = vec![
+        Line::from(Span::styled(
+            pick.label.clone(),
+            Style::default().fg(theme.highlight).add_modifier(Modifier::ITALIC),
+        )),
+        Line::from(""),
+    ];
+    for (index, folder) in pick.folders.iter().enumerate() {
+        text.push(row(index, folder.clone()));
+    }
+    let other = pick.folders.len();
+    let mut typed = row(other, format!("Other folder: {}", pick.input));
+    if pick.selected == other {
+        typed.push_span(Span::styled("█", Style::default().fg(theme.good)));
+    }
+    text.push(typed);
+    if pick.folders.is_empty() {
+        text.push(Line::from(""));
+        text.push(Line::from(Span::styled(
+            "Folders offered here are set with `move_to` in the config file",
+            Style::default().fg(theme.dim),
+        )));
+    }
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(" Move to (↑↓ Enter, type a folder, Esc to cancel) "),
+        )
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_bookmarks(f: &mut Frame, marks: &BookmarkState, theme: &Theme) {
+    let popup_area = centered_rect(70, 50, f.area());
+    let row = |index: usize, text: String| {
+        if index == marks.selected {
+            Line::from(Span::styled(
+                format!("▶ {}", text),
+                Style::default().fg(theme.good).add_modifier(Modifier::BOLD),
+            ))
+        } else {
+            Line::from(format!("  {}", text))
+        }
+    };
+
+    let mut text = vec![
+        Line::from(Span::styled(
+            marks.label.clone(),
+            Style::default().fg(theme.highlight).add_modifier(Modifier::ITALIC),
+        )),
+        Line::from(""),
+    ];
+    for (index, bookmark) in marks.bookmarks.iter().enumerate() {
+        text.push(row(index, format!("{:>8}  {}", clock(bookmark.position), bookmark.name)));
+    }
+    let other = marks.bookmarks.len();
+    let mut typed = row(other, format!("New at {}: {}", clock(marks.position), marks.input));
+    if marks.selected == other {
+        typed.push_span(Span::styled("█", Style::default().fg(theme.good)));
+    }
+    text.push(typed);
+    // Keeps the row selected in sight in a long list.
+    let shown = popup_area.height.saturating_sub(2) as usize;
+    let scroll = (marks.selected + 3).saturating_sub(shown);
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .title(" Bookmarks (↑↓ Enter to jump, type a name to add, Del to remove, Esc) "),
+        )
+        .scroll((scroll as u16, 0));
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+/// "1:02:03", or "02:03" under an hour.
+fn clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds / 3600 {
+        0 => format!("{:02}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds % 3600 / 60, seconds % 60),
+    }
+}
+
+/// The before/after preview of an encoding repair. With no fixes, it only
+/// tells the user there is nothing to do.
+fn draw_confirm_encoding_fix(
+    f: &mut Frame,
+    track: &Path,
+    encoding: Option<&str>,
+    fixes: &[EncodingFix],
+    theme: &Theme,
+) {
+    let popup_area = centered_rect(80, 50, f.area());
+
+    let mut text = vec![
+        Line::from(vec![Span::styled(
+            "Fix Tag Encoding",
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        )]),
+        Line::from(vec![Span::styled(
+            format!("File: {}", track.display()),
+            Style::default().fg(theme.highlight),
+        )]),
+        Line::from(""),
+    ];
+
+    match encoding {
+        Some(encoding) if !fixes.is_empty() => {
+            text.push(Line::from(format!("Detected encoding: {}", encoding)));
+            text.push(Line::from(""));
+            for fix in fixes {
+                text.push(Line::from(vec![
+                    Span::styled(format!("{}: ", fix.field), Style::default().fg(theme.text)),
+                    Span::styled(fix.before.clone(), Style::default().fg(theme.bad)),
+                ]));
+                text.push(Line::from(vec![
+                    Span::raw(" ".repeat(fix.field.len() + 2)),
+                    Span::styled(
+                        fix.after.clone(),
+                        Style::default().fg(theme.good).add_modifier(Modifier::BOLD),
+                    ),
+                ]));
+            }
+            text.push(Line::from(""));
+            text.push(Line::from(vec![
+                Span::styled("  [Y] ", Style::default().fg(theme.good).add_modifier(Modifier::BOLD)),
+                Span::raw("Rewrite as UTF-8   "),
+                Span::styled("  [N] ", Style::default().fg(theme.bad).add_modifier(Modifier::BOLD)),
+                Span::raw("Cancel"),
+            ]));
+        }
+        _ => {
+            text.push(Line::from("No garbled tags found, nothing to repair."));
+            text.push(Line::from(""));
+            text.push(Line::from(vec![Span::styled(
+                "Press any key to continue",
+                Style::default().fg(theme.dim).add_modifier(Modifier::ITALIC),
+            )]));
+        }
+    }
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent)),
+        )
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_search(f: &mut Frame, search: &SearchState, theme: &Theme) {
+    let popup_area = centered_rect(80, 70, f.area());
+    let visible = popup_area.height.saturating_sub(5) as usize;
+    // Scroll so the selection stays on screen.
+    let first = search.selected.saturating_sub(visible.saturating_sub(1));
+
+    let mut text = vec![
+        Line::from(vec![
+            Span::styled("/", Style::default().fg(theme.highlight)),
+            Span::raw(search.query.clone()),
+            Span::styled("█", Style::default().fg(theme.good)),
+        ]),
+        Line::from(Span::styled(
+            format!("{} of {} tracks", search.hits.len(), search.entries.len()),
+            Style::default().fg(theme.dim),
+        )),
+        Line::from(""),
+    ];
+    for (row, idx) in search.hits.iter().enumerate().skip(first).take(visible) {
+        if row == search.selected {
+            text.push(Line::from(Span::styled(
+                format!("▶ {}", search.entries[*idx]),
+                Style::default().fg(theme.good).add_modifier(Modifier::BOLD),
+            )));
+        } else {
+            text.push(Line::from(format!("  {}", search.entries[*idx])));
+        }
+    }
+
+    let paragraph = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.accent))
+            .title(" Jump to track (↑↓ Enter, Esc to cancel) "),
+    );
+    f.render_widget(Clear, popup_area);
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_help(f: &mut Frame, help: &mut HelpState, theme: &Theme) {
+    let area = f.area();
+    help.page = area.height.saturating_sub(2).max(1);
+    // Never scroll past the last line.
+    help.scroll = help.scroll.min((help.lines.len() as u16).saturating_sub(help.page));
+    let paragraph = Paragraph::new(help.lines.clone()).scroll((help.scroll, 0)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.accent))
+            .title(" Help "),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
@@ -0,0 +1,1152 @@
+//! Key bindings of every screen. Input handling, the controls pane and the
+//! help screen all read these tables, so what is shown is what is bound.
+
+use super::tabs::Tab;
+use super::{Pane, UiError, UserAction};
+use crossterm::event::KeyCode;
+use std::collections::BTreeMap;
+
+/// Keys sharing one line of help, and the action each of them triggers.
+/// A key can be a sequence, like vim's "gg" or ":q".
+#[derive(Clone)]
+pub struct Binding<A: 'static> {
+    /// How the keys are shown, e.g. "1-5" for five rating keys.
+    pub label: &'static str,
+    pub description: &'static str,
+    pub keys: &'static [(&'static [KeyCode], A)],
+}
+
+/// Keys of the tag editor, the folder picker, the file renaming, the
+/// bookmarks and the search overlay, on top of typing text.
+#[derive(Clone, Copy)]
+pub enum FormKey {
+    Up,
+    Down,
+    Top,
+    Bottom,
+    Accept,
+    Cancel,
+    Erase,
+    /// Removes the bookmark selected.
+    Remove,
+}
+
+/// Keys of the help screen.
+#[derive(Clone, Copy)]
+pub enum HelpKey {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Close,
+}
+
+/// Keys of the ban dialog.
+#[derive(Clone, Copy)]
+pub enum BanKey {
+    Artist,
+    Album,
+    Cancel,
+}
+
+/// Keys of the quit confirmation.
+#[derive(Clone, Copy)]
+pub enum QuitKey {
+    Quit,
+    Detach,
+    Cancel,
+}
+
+/// Keys of the list tabs, tried before the player bindings.
+#[derive(Clone, Copy)]
+pub enum ListKey {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Select,
+    /// Select the playing track.
+    Current,
+    /// Sort the playlist by the next field.
+    Sort,
+    /// Add the selected track to the playlist.
+    Enqueue,
+    /// Play the selected track after the current one.
+    PlayNext,
+    /// Take the selected track out of the list.
+    Remove,
+}
+
+/// Keys switching tabs, the same in every preset.
+#[derive(Clone, Copy)]
+pub enum TabKey {
+    Next,
+    Previous,
+    Go(Tab),
+}
+
+/// The bindings that differ between presets.
+#[derive(Clone, Copy)]
+pub struct Keymap {
+    pub name: &'static str,
+    /// Whether some player keys come from the `[keys]` config table.
+    pub customized: bool,
+    pub normal: &'static [Binding<UserAction>],
+    pub list: &'static [Binding<ListKey>],
+    pub help: &'static [Binding<HelpKey>],
+}
+
+/// Names accepted by the `keymap` config key.
+pub const PRESETS: [&str; 2] = ["default", "vim"];
+
+impl Keymap {
+    pub fn preset(name: &str) -> Result<Keymap, UiError> {
+        match name {
+            "default" => Ok(Keymap {
+                name: "default",
+                customized: false,
+                normal: NORMAL,
+                list: LIST,
+                help: HELP,
+            }),
+            "vim" => Ok(Keymap {
+                name: "vim",
+                customized: false,
+                normal: VIM_NORMAL,
+                list: VIM_LIST,
+                help: VIM_HELP,
+            }),
+            _ => Err(UiError::Keymap(format!(
+                "unknown keymap \"{}\" (available: {})",
+                name,
+                PRESETS.join(", ")
+            ))),
+        }
+    }
+
+    /// The player bindings with the keys of some actions replaced. Keys of
+    /// `overrides` are action names ("next", "volume_up", ...), values are
+    /// space-separated key sequences like "n <right>" or "gg".
+    ///
+    /// The tables are built once at startup and live as long as the program.
+    pub fn with_overrides(self, overrides: &BTreeMap<String, String>) -> Result<Keymap, UiError> {
+        let mut report = KeyReport::default();
+        let keymap = self.apply_overrides(overrides, &mut report);
+        match report.errors.into_iter().next() {
+            Some(error) => Err(UiError::Keymap(error)),
+            None => Ok(keymap),
+        }
+    }
+
+    /// Like `with_overrides`, but skips the invalid entries and reports them
+    /// along with the keys of the table that won't work as written.
+    pub fn check_overrides(self, overrides: &BTreeMap<String, String>) -> (Keymap, KeyReport) {
+        let mut report = KeyReport::default();
+        let keymap = self.apply_overrides(overrides, &mut report);
+        keymap.find_conflicts(&mut report);
+        (keymap, report)
+    }
+
+    fn apply_overrides(self, overrides: &BTreeMap<String, String>, report: &mut KeyReport) -> Keymap {
+        if overrides.is_empty() {
+            return self;
+        }
+        let mut normal: Vec<Binding<UserAction>> = self.normal.to_vec();
+
+        for (name, spec) in overrides {
+            let Some(&(_, ref action, description)) =
+                ACTIONS.iter().find(|(action_name, _, _)| action_name == name)
+            else {
+                report.errors.push(format!("unknown action \"{}\" in [keys]", name));
+                continue;
+            };
+            let sequences: Vec<_> = spec
+                .split_whitespace()
+                .filter_map(|sequence| {
+                    let keys = parse_sequence(sequence);
+                    if keys.is_none() {
+                        report.errors.push(format!("invalid key \"{}\" for {}", sequence, name));
+                    }
+                    keys
+                })
+                .collect();
+
+            // The action loses its old keys and the new keys their old action.
+            for binding in &mut normal {
+                let keys: Vec<(&'static [KeyCode], UserAction)> = binding
+                    .keys
+                    .iter()
+                    .filter(|(keys, bound)| bound != action && !sequences.contains(keys))
+                    .cloned()
+                    .collect();
+                let taken = binding.keys.iter().filter(|(keys, bound)| bound != action && sequences.contains(keys));
+                for (keys, bound) in taken {
+                    let (label, previous) = (sequence_label(keys), action_name(bound));
+                    if report.rebound.iter().any(|(_, rebound)| rebound == previous) {
+                        report.warnings.push(format!(
+                            "\"{}\" is given to both {} and {}; {} gets it",
+                            label, previous, name, name
+                        ));
+                    } else if !overrides.contains_key(previous) {
+                        report.warnings.push(format!("\"{}\" is taken from {} by {}", label, previous, name));
+                    }
+                }
+                if keys.len() != binding.keys.len() {
+                    binding.label = leak_str(describe(&keys));
+                    binding.keys = Box::leak(keys.into_boxed_slice());
+                }
+            }
+            normal.retain(|binding| !binding.keys.is_empty());
+            // An empty value just disables the action.
+            if sequences.is_empty() {
+                continue;
+            }
+
+            report.rebound.retain(|(keys, _)| !sequences.contains(keys));
+            report.rebound.extend(sequences.iter().map(|keys| (*keys, name.clone())));
+            let keys: Vec<(&'static [KeyCode], UserAction)> =
+                sequences.into_iter().map(|keys| (keys, action.clone())).collect();
+            normal.push(Binding {
+                label: leak_str(describe(&keys)),
+                description,
+                keys: Box::leak(keys.into_boxed_slice()),
+            });
+        }
+
+        Keymap {
+            customized: true,
+            normal: Box::leak(normal.into_boxed_slice()),
+            ..self
+        }
+    }
+
+    /// Keys from `[keys]` that can't be typed: hidden by a shorter sequence,
+    /// caught by the tab keys, or by the list keys in the list tabs.
+    fn find_conflicts(&self, report: &mut KeyReport) {
+        let normal: Vec<(&[KeyCode], &UserAction)> = self
+            .normal
+            .iter()
+            .flat_map(|binding| binding.keys.iter().map(|(keys, action)| (*keys, action)))
+            .collect();
+
+        for (keys, name) in &report.rebound {
+            let label = sequence_label(keys);
+            for (other, action) in &normal {
+                if other.len() < keys.len() && keys.starts_with(other) {
+                    report.warnings.push(format!(
+                        "\"{}\" ({}) can't be typed: \"{}\" triggers {} first",
+                        label,
+                        name,
+                        sequence_label(other),
+                        action_name(action)
+                    ));
+                } else if other.len() > keys.len()
+                    && other.starts_with(keys)
+                    // Reported from the other side when both come from [keys].
+                    && !report.rebound.iter().any(|(rebound, _)| rebound == other)
+                {
+                    report.warnings.push(format!(
+                        "\"{}\" ({}) hides \"{}\" ({})",
+                        label,
+                        name,
+                        sequence_label(other),
+                        action_name(action)
+                    ));
+                }
+            }
+            if lookup(TABS, keys[0]).is_some() {
+                report.warnings.push(format!("\"{}\" ({}) can't be typed: it switches tabs", label, name));
+            }
+            let list_key = self.list.iter().find_map(|binding| {
+                binding
+                    .keys
+                    .iter()
+                    .any(|(list_keys, _)| list_keys[0] == keys[0])
+                    .then_some(binding.description)
+            });
+            if let Some(description) = list_key {
+                report.warnings.push(format!(
+                    "\"{}\" ({}) doesn't work in the Library and Playlists tabs, where it is \"{}\"",
+                    label, name, description
+                ));
+            }
+        }
+    }
+
+    /// Prints every binding, screen by screen, as the help view lists them.
+    pub fn print(&self) {
+        let customized = if self.customized { ", customized" } else { "" };
+        println!("Keymap: {}{}", self.name, customized);
+        for (title, note, screen) in SCREENS {
+            println!();
+            match note {
+                Some(note) => println!("{} ({})", title, note),
+                None => println!("{}", title),
+            }
+            for (label, description) in screen.entries(self) {
+                println!("  {:<14} {}", label, description);
+            }
+        }
+    }
+}
+
+/// What `Keymap::check_overrides` found in the `[keys]` config table.
+#[derive(Default)]
+pub struct KeyReport {
+    /// Entries that were ignored: unknown actions and invalid keys.
+    pub errors: Vec<String>,
+    /// Keys that don't do what the table says.
+    pub warnings: Vec<String>,
+    /// Every sequence set in the table, with its action name.
+    rebound: Vec<(&'static [KeyCode], String)>,
+}
+
+impl KeyReport {
+    pub fn print(&self) {
+        println!();
+        if self.errors.is_empty() && self.warnings.is_empty() {
+            println!("No problems in [keys].");
+        }
+        for error in &self.errors {
+            println!("error: {}", error);
+        }
+        for warning in &self.warnings {
+            println!("warning: {}", warning);
+        }
+    }
+}
+
+/// Config name of `action`, as written in `[keys]`.
+fn action_name(action: &UserAction) -> &'static str {
+    ACTIONS
+        .iter()
+        .find(|(_, bound, _)| bound == action)
+        .map_or("?", |(name, _, _)| name)
+}
+
+/// Player actions that can be rebound in the `[keys]` config table.
+const ACTIONS: &[(&str, UserAction, &str)] = &[
+    ("play_pause", UserAction::PlayPause, "Play/Pause"),
+    ("next", UserAction::Next, "Next track"),
+    ("previous", UserAction::Previous, "Previous track"),
+    ("seek_forward", UserAction::SeekForward, "Seek forward"),
+    ("seek_backward", UserAction::SeekBackward, "Seek backward"),
+    ("next_chapter", UserAction::NextChapter, "Next chapter"),
+    ("previous_chapter", UserAction::PreviousChapter, "Previous chapter"),
+    ("volume_up", UserAction::VolumeUp, "Volume up"),
+    ("volume_down", UserAction::VolumeDown, "Volume down"),
+    ("favorite", UserAction::MarkFavorite, "Favorite"),
+    ("favorites_only", UserAction::ToggleFavoritesOnly, "Play only the favorites"),
+    ("stop_after", UserAction::ToggleStopAfterCurrent, "Stop after this track"),
+    ("reshuffle", UserAction::ReshuffleRest, "Shuffle the rest of the queue again"),
+    ("skip", UserAction::MarkSkip, "Skip from now on"),
+    ("skip_for_now", UserAction::SkipForNow, "Skip until quitting"),
+    ("undo", UserAction::Undo, "Undo favorite/skip/delete"),
+    ("ban", UserAction::Ban, "Ban the artist or album"),
+    ("delete", UserAction::Delete, "Delete"),
+    ("move", UserAction::Move, "Move to a folder"),
+    ("rename", UserAction::Rename, "Rename the file"),
+    ("bookmarks", UserAction::Bookmarks, "Bookmarks of the track"),
+    ("edit_tags", UserAction::EditTags, "Edit tags"),
+    ("fix_encoding", UserAction::FixEncoding, "Fix encoding"),
+    ("rate_1", UserAction::Rate(1), "Rate ★"),
+    ("rate_2", UserAction::Rate(2), "Rate ★★"),
+    ("rate_3", UserAction::Rate(3), "Rate ★★★"),
+    ("rate_4", UserAction::Rate(4), "Rate ★★★★"),
+    ("rate_5", UserAction::Rate(5), "Rate ★★★★★"),
+    ("clear_rating", UserAction::Rate(0), "Clear rating"),
+    ("toggle_art", UserAction::TogglePane(Pane::Art), "Toggle album art"),
+    ("toggle_meters", UserAction::TogglePane(Pane::Meters), "Toggle level meters"),
+    ("toggle_controls", UserAction::TogglePane(Pane::Controls), "Toggle controls"),
+    ("toggle_queue", UserAction::TogglePane(Pane::Queue), "Toggle queue"),
+    ("shrink", UserAction::ResizeNowPlaying(-1), "Shrink now playing"),
+    ("grow", UserAction::ResizeNowPlaying(1), "Grow now playing"),
+    ("time_display", UserAction::ToggleTimeDisplay, "Elapsed/remaining time"),
+    ("mini", UserAction::ToggleMini, "Mini display"),
+    ("search", UserAction::Search, "Search"),
+    ("filter", UserAction::Filter, "Filter the playlist by tags"),
+    ("clear_filter", UserAction::ClearFilter, "Clear the filter"),
+    ("help", UserAction::Help, "Help"),
+    ("detach", UserAction::Detach, "Detach: keep playing without the interface"),
+    ("quit", UserAction::Quit, "Quit"),
+];
+
+/// Names of the keys written in angle brackets in `[keys]`, e.g. "<space>".
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("space", KeyCode::Char(' ')),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backspace", KeyCode::Backspace),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+];
+
+/// Parses "gg", ":q" or "<space>" into a key sequence.
+fn parse_sequence(sequence: &str) -> Option<&'static [KeyCode]> {
+    let mut keys = Vec::new();
+    let mut chars = sequence.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' || !chars.as_str().contains('>') {
+            keys.push(KeyCode::Char(c));
+            continue;
+        }
+        let rest = chars.as_str();
+        let end = rest.find('>')?;
+        let name = rest[..end].to_lowercase();
+        let code = match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+            _ => KEY_NAMES.iter().find(|(key_name, _)| *key_name == name)?.1,
+        };
+        keys.push(code);
+        chars = rest[end + 1..].chars();
+    }
+    (!keys.is_empty()).then(|| &*Box::leak(keys.into_boxed_slice()))
+}
+
+/// Label of rebound keys for the controls pane and the help screen, e.g. "n/→".
+fn describe<A>(keys: &[(&[KeyCode], A)]) -> String {
+    keys.iter()
+        .map(|(sequence, _)| sequence_label(sequence))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// How one key sequence is shown, e.g. "gg" or "Space".
+fn sequence_label(sequence: &[KeyCode]) -> String {
+    sequence
+        .iter()
+        .map(|code| match code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Left => "←".to_string(),
+            KeyCode::Right => "→".to_string(),
+            KeyCode::Up => "↑".to_string(),
+            KeyCode::Down => "↓".to_string(),
+            KeyCode::PageUp => "PgUp".to_string(),
+            KeyCode::PageDown => "PgDn".to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            other => format!("{:?}", other),
+        })
+        .collect()
+}
+
+fn leak_str(text: String) -> &'static str {
+    Box::leak(text.into_boxed_str())
+}
+
+const RATING_KEYS: &[(&[KeyCode], UserAction)] = &[
+    (&[KeyCode::Char('1')], UserAction::Rate(1)),
+    (&[KeyCode::Char('2')], UserAction::Rate(2)),
+    (&[KeyCode::Char('3')], UserAction::Rate(3)),
+    (&[KeyCode::Char('4')], UserAction::Rate(4)),
+    (&[KeyCode::Char('5')], UserAction::Rate(5)),
+];
+
+const PANE_KEYS: &[(&[KeyCode], UserAction)] = &[
+    (&[KeyCode::Char('v'), KeyCode::Char('a')], UserAction::TogglePane(Pane::Art)),
+    (&[KeyCode::Char('v'), KeyCode::Char('m')], UserAction::TogglePane(Pane::Meters)),
+    (&[KeyCode::Char('v'), KeyCode::Char('c')], UserAction::TogglePane(Pane::Controls)),
+    (&[KeyCode::Char('v'), KeyCode::Char('q')], UserAction::TogglePane(Pane::Queue)),
+];
+
+const NORMAL: &[Binding<UserAction>] = &[
+    Binding {
+        label: "Space",
+        description: "Play/Pause",
+        keys: &[(&[KeyCode::Char(' ')], UserAction::PlayPause)],
+    },
+    Binding {
+        label: "→",
+        description: "Next",
+        keys: &[(&[KeyCode::Right], UserAction::Next)],
+    },
+    Binding {
+        label: "←",
+        description: "Previous",
+        keys: &[(&[KeyCode::Left], UserAction::Previous)],
+    },
+    Binding {
+        label: "</>",
+        description: "Chapter",
+        keys: &[
+            (&[KeyCode::Char('<')], UserAction::PreviousChapter),
+            (&[KeyCode::Char('>')], UserAction::NextChapter),
+        ],
+    },
+    Binding {
+        label: "f",
+        description: "Favorite",
+        keys: &[(&[KeyCode::Char('f')], UserAction::MarkFavorite)],
+    },
+    Binding {
+        label: "F",
+        description: "Play only the favorites",
+        keys: &[(&[KeyCode::Char('F')], UserAction::ToggleFavoritesOnly)],
+    },
+    Binding {
+        label: "z",
+        description: "Stop after this track",
+        keys: &[(&[KeyCode::Char('z')], UserAction::ToggleStopAfterCurrent)],
+    },
+    Binding {
+        label: "r",
+        description: "Shuffle the rest of the queue again",
+        keys: &[(&[KeyCode::Char('r')], UserAction::ReshuffleRest)],
+    },
+    Binding {
+        label: "s",
+        description: "Skip for now",
+        keys: &[(&[KeyCode::Char('s')], UserAction::SkipForNow)],
+    },
+    Binding {
+        label: "S",
+        description: "Skip for good",
+        keys: &[(&[KeyCode::Char('S')], UserAction::MarkSkip)],
+    },
+    Binding {
+        label: "u",
+        description: "Undo",
+        keys: &[(&[KeyCode::Char('u')], UserAction::Undo)],
+    },
+    Binding {
+        label: "b",
+        description: "Ban artist/album",
+        keys: &[(&[KeyCode::Char('b')], UserAction::Ban)],
+    },
+    Binding {
+        label: "d",
+        description: "Delete",
+        keys: &[(&[KeyCode::Char('d')], UserAction::Delete)],
+    },
+    Binding {
+        label: "M",
+        description: "Move",
+        keys: &[(&[KeyCode::Char('M')], UserAction::Move)],
+    },
+    Binding {
+        label: "R",
+        description: "Rename",
+        keys: &[(&[KeyCode::Char('R')], UserAction::Rename)],
+    },
+    Binding {
+        label: "B",
+        description: "Bookmarks",
+        keys: &[(&[KeyCode::Char('B')], UserAction::Bookmarks)],
+    },
+    Binding {
+        label: "e",
+        description: "Edit tags",
+        keys: &[(&[KeyCode::Char('e')], UserAction::EditTags)],
+    },
+    Binding {
+        label: "c",
+        description: "Fix encoding",
+        keys: &[(&[KeyCode::Char('c')], UserAction::FixEncoding)],
+    },
+    Binding {
+        label: "1-5",
+        description: "Rate",
+        keys: RATING_KEYS,
+    },
+    Binding {
+        label: "0",
+        description: "Clear rating",
+        keys: &[(&[KeyCode::Char('0')], UserAction::Rate(0))],
+    },
+    Binding {
+        label: "+/-",
+        description: "Volume",
+        keys: &[
+            (&[KeyCode::Char('+')], UserAction::VolumeUp),
+            (&[KeyCode::Char('-')], UserAction::VolumeDown),
+        ],
+    },
+    Binding {
+        label: "va/vm/vc/vq",
+        description: "Toggle art/meters/controls/queue",
+        keys: PANE_KEYS,
+    },
+    Binding {
+        label: "[/]",
+        description: "Resize",
+        keys: &[
+            (&[KeyCode::Char('[')], UserAction::ResizeNowPlaying(-1)),
+            (&[KeyCode::Char(']')], UserAction::ResizeNowPlaying(1)),
+        ],
+    },
+    Binding {
+        label: "t",
+        description: "Elapsed/remaining time",
+        keys: &[(&[KeyCode::Char('t')], UserAction::ToggleTimeDisplay)],
+    },
+    Binding {
+        label: "m",
+        description: "Mini display",
+        keys: &[(&[KeyCode::Char('m')], UserAction::ToggleMini)],
+    },
+    Binding {
+        label: "/",
+        description: "Search",
+        keys: &[(&[KeyCode::Char('/')], UserAction::Search)],
+    },
+    Binding {
+        label: "|/\\",
+        description: "Filter/Clear filter",
+        keys: &[
+            (&[KeyCode::Char('|')], UserAction::Filter),
+            (&[KeyCode::Char('\\')], UserAction::ClearFilter),
+        ],
+    },
+    Binding {
+        label: "?",
+        description: "Help",
+        keys: &[(&[KeyCode::Char('?')], UserAction::Help)],
+    },
+    Binding {
+        label: "q",
+        description: "Quit",
+        keys: &[(&[KeyCode::Char('q')], UserAction::Quit)],
+    },
+    Binding {
+        label: "D",
+        description: "Detach",
+        keys: &[(&[KeyCode::Char('D')], UserAction::Detach)],
+    },
+];
+
+const VIM_NORMAL: &[Binding<UserAction>] = &[
+    Binding {
+        label: "Space",
+        description: "Play/Pause",
+        keys: &[(&[KeyCode::Char(' ')], UserAction::PlayPause)],
+    },
+    Binding {
+        label: "h/l",
+        description: "Seek",
+        keys: &[
+            (&[KeyCode::Char('h')], UserAction::SeekBackward),
+            (&[KeyCode::Char('l')], UserAction::SeekForward),
+        ],
+    },
+    Binding {
+        label: "</>",
+        description: "Chapter",
+        keys: &[
+            (&[KeyCode::Char('<')], UserAction::PreviousChapter),
+            (&[KeyCode::Char('>')], UserAction::NextChapter),
+        ],
+    },
+    Binding {
+        label: "j/k",
+        description: "Volume",
+        keys: &[
+            (&[KeyCode::Char('j')], UserAction::VolumeDown),
+            (&[KeyCode::Char('k')], UserAction::VolumeUp),
+        ],
+    },
+    Binding {
+        label: "n",
+        description: "Next",
+        keys: &[(&[KeyCode::Char('n')], UserAction::Next)],
+    },
+    Binding {
+        label: "N",
+        description: "Previous",
+        keys: &[(&[KeyCode::Char('N')], UserAction::Previous)],
+    },
+    Binding {
+        label: "f",
+        description: "Favorite",
+        keys: &[(&[KeyCode::Char('f')], UserAction::MarkFavorite)],
+    },
+    Binding {
+        label: "F",
+        description: "Play only the favorites",
+        keys: &[(&[KeyCode::Char('F')], UserAction::ToggleFavoritesOnly)],
+    },
+    Binding {
+        label: "z",
+        description: "Stop after this track",
+        keys: &[(&[KeyCode::Char('z')], UserAction::ToggleStopAfterCurrent)],
+    },
+    Binding {
+        label: "r",
+        description: "Shuffle the rest of the queue again",
+        keys: &[(&[KeyCode::Char('r')], UserAction::ReshuffleRest)],
+    },
+    Binding {
+        label: "s",
+        description: "Skip for now",
+        keys: &[(&[KeyCode::Char('s')], UserAction::SkipForNow)],
+    },
+    Binding {
+        label: "S",
+        description: "Skip for good",
+        keys: &[(&[KeyCode::Char('S')], UserAction::MarkSkip)],
+    },
+    Binding {
+        label: "u",
+        description: "Undo",
+        keys: &[(&[KeyCode::Char('u')], UserAction::Undo)],
+    },
+    Binding {
+        label: "b",
+        description: "Ban artist/album",
+        keys: &[(&[KeyCode::Char('b')], UserAction::Ban)],
+    },
+    Binding {
+        label: "dd",
+        description: "Delete",
+        keys: &[(&[KeyCode::Char('d'), KeyCode::Char('d')], UserAction::Delete)],
+    },
+    Binding {
+        label: "M",
+        description: "Move",
+        keys: &[(&[KeyCode::Char('M')], UserAction::Move)],
+    },
+    Binding {
+        label: "R",
+        description: "Rename",
+        keys: &[(&[KeyCode::Char('R')], UserAction::Rename)],
+    },
+    Binding {
+        label: "B",
+        description: "Bookmarks",
+        keys: &[(&[KeyCode::Char('B')], UserAction::Bookmarks)],
+    },
+    Binding {
+        label: "e",
+        description: "Edit tags",
+        keys: &[(&[KeyCode::Char('e')], UserAction::EditTags)],
+    },
+    Binding {
+        label: "c",
+        description: "Fix encoding",
+        keys: &[(&[KeyCode::Char('c')], UserAction::FixEncoding)],
+    },
+    Binding {
+        label: "1-5",
+        description: "Rate",
+        keys: RATING_KEYS,
+    },
+    Binding {
+        label: "0",
+        description: "Clear rating",
+        keys: &[(&[KeyCode::Char('0')], UserAction::Rate(0))],
+    },
+    Binding {
+        label: "va/vm/vc/vq",
+        description: "Toggle art/meters/controls/queue",
+        keys: PANE_KEYS,
+    },
+    Binding {
+        label: "[/]",
+        description: "Resize",
+        keys: &[
+            (&[KeyCode::Char('[')], UserAction::ResizeNowPlaying(-1)),
+            (&[KeyCode::Char(']')], UserAction::ResizeNowPlaying(1)),
+        ],
+    },
+    Binding {
+        label: "t",
+        description: "Elapsed/remaining time",
+        keys: &[(&[KeyCode::Char('t')], UserAction::ToggleTimeDisplay)],
+    },
+    Binding {
+        label: "m",
+        description: "Mini display",
+        keys: &[(&[KeyCode::Char('m')], UserAction::ToggleMini)],
+    },
+    Binding {
+        label: "/",
+        description: "Search",
+        keys: &[(&[KeyCode::Char('/')], UserAction::Search)],
+    },
+    Binding {
+        label: "|/\\",
+        description: "Filter/Clear filter",
+        keys: &[
+            (&[KeyCode::Char('|')], UserAction::Filter),
+            (&[KeyCode::Char('\\')], UserAction::ClearFilter),
+        ],
+    },
+    Binding {
+        label: "?",
+        description: "Help",
+        keys: &[(&[KeyCode::Char('?')], UserAction::Help)],
+    },
+    Binding {
+        label: ":q",
+        description: "Quit",
+        keys: &[(&[KeyCode::Char(':'), KeyCode::Char('q')], UserAction::Quit)],
+    },
+    Binding {
+        label: "D",
+        description: "Detach",
+        keys: &[(&[KeyCode::Char('D')], UserAction::Detach)],
+    },
+];
+
+pub const TABS: &[Binding<TabKey>] = &[
+    Binding {
+        label: "Tab/Shift-Tab",
+        description: "Next/previous tab",
+        keys: &[(&[KeyCode::Tab], TabKey::Next), (&[KeyCode::BackTab], TabKey::Previous)],
+    },
+    Binding {
+        label: "F1-F6",
+        description: "Now Playing, Library, Playlists, Settings, Favorites, Skipped",
+        keys: &[
+            (&[KeyCode::F(1)], TabKey::Go(Tab::NowPlaying)),
+            (&[KeyCode::F(2)], TabKey::Go(Tab::Library)),
+            (&[KeyCode::F(3)], TabKey::Go(Tab::Playlists)),
+            (&[KeyCode::F(4)], TabKey::Go(Tab::Settings)),
+            (&[KeyCode::F(5)], TabKey::Go(Tab::Favorites)),
+            (&[KeyCode::F(6)], TabKey::Go(Tab::Skipped)),
+        ],
+    },
+];
+
+const LIST: &[Binding<ListKey>] = &[
+    Binding {
+        label: "↑/↓",
+        description: "Move",
+        keys: &[(&[KeyCode::Up], ListKey::Up), (&[KeyCode::Down], ListKey::Down)],
+    },
+    Binding {
+        label: "PgUp/PgDn",
+        description: "Move by page",
+        keys: &[(&[KeyCode::PageUp], ListKey::PageUp), (&[KeyCode::PageDown], ListKey::PageDown)],
+    },
+    Binding {
+        label: "Home/End",
+        description: "First/last track",
+        keys: &[(&[KeyCode::Home], ListKey::Top), (&[KeyCode::End], ListKey::Bottom)],
+    },
+    Binding {
+        label: "Enter",
+        description: "Play the selected track",
+        keys: &[(&[KeyCode::Enter], ListKey::Select)],
+    },
+    Binding {
+        label: ".",
+        description: "Go to the playing track",
+        keys: &[(&[KeyCode::Char('.')], ListKey::Current)],
+    },
+    Binding {
+        label: "o",
+        description: "Sort the playlist by title, artist, album, year, duration or path",
+        keys: &[(&[KeyCode::Char('o')], ListKey::Sort)],
+    },
+    Binding {
+        label: "A",
+        description: "Add the selected track to the playlist",
+        keys: &[(&[KeyCode::Char('A')], ListKey::Enqueue)],
+    },
+    Binding {
+        label: "a",
+        description: "Play the selected track after the current one",
+        keys: &[(&[KeyCode::Char('a')], ListKey::PlayNext)],
+    },
+    Binding {
+        label: "x",
+        description: "Remove the selected favorite or skipped track from its list",
+        keys: &[(&[KeyCode::Char('x')], ListKey::Remove)],
+    },
+];
+
+const VIM_LIST: &[Binding<ListKey>] = &[
+    Binding {
+        label: "j/k",
+        description: "Move",
+        keys: &[(&[KeyCode::Char('j')], ListKey::Down), (&[KeyCode::Char('k')], ListKey::Up)],
+    },
+    Binding {
+        label: "PgUp/PgDn",
+        description: "Move by page",
+        keys: &[(&[KeyCode::PageUp], ListKey::PageUp), (&[KeyCode::PageDown], ListKey::PageDown)],
+    },
+    Binding {
+        label: "gg/G",
+        description: "First/last track",
+        keys: &[
+            (&[KeyCode::Char('g'), KeyCode::Char('g')], ListKey::Top),
+            (&[KeyCode::Char('G')], ListKey::Bottom),
+        ],
+    },
+    Binding {
+        label: "Enter",
+        description: "Play the selected track",
+        keys: &[(&[KeyCode::Enter], ListKey::Select)],
+    },
+    Binding {
+        label: ".",
+        description: "Go to the playing track",
+        keys: &[(&[KeyCode::Char('.')], ListKey::Current)],
+    },
+    Binding {
+        label: "o",
+        description: "Sort the playlist by title, artist, album, year, duration or path",
+        keys: &[(&[KeyCode::Char('o')], ListKey::Sort)],
+    },
+    Binding {
+        label: "A",
+        description: "Add the selected track to the playlist",
+        keys: &[(&[KeyCode::Char('A')], ListKey::Enqueue)],
+    },
+    Binding {
+        label: "a",
+        description: "Play the selected track after the current one",
+        keys: &[(&[KeyCode::Char('a')], ListKey::PlayNext)],
+    },
+    Binding {
+        label: "x",
+        description: "Remove the selected favorite or skipped track from its list",
+        keys: &[(&[KeyCode::Char('x')], ListKey::Remove)],
+    },
+];
+
+pub const FORM: &[Binding<FormKey>] = &[
+    Binding {
+        label: "↑/↓",
+        description: "Previous/next field or result",
+        keys: &[(&[KeyCode::Up], FormKey::Up), (&[KeyCode::Down], FormKey::Down)],
+    },
+    Binding {
+        label: "Home/End",
+        description: "First/last field or result",
+        keys: &[(&[KeyCode::Home], FormKey::Top), (&[KeyCode::End], FormKey::Bottom)],
+    },
+    Binding {
+        label: "Enter",
+        description: "Confirm",
+        keys: &[(&[KeyCode::Enter], FormKey::Accept)],
+    },
+    Binding {
+        label: "Esc",
+        description: "Cancel",
+        keys: &[(&[KeyCode::Esc], FormKey::Cancel)],
+    },
+    Binding {
+        label: "Backspace",
+        description: "Erase the last character",
+        keys: &[(&[KeyCode::Backspace], FormKey::Erase)],
+    },
+    Binding {
+        label: "Del",
+        description: "Remove the bookmark selected",
+        keys: &[(&[KeyCode::Delete], FormKey::Remove)],
+    },
+];
+
+pub const CONFIRM: &[Binding<bool>] = &[
+    Binding {
+        label: "y",
+        description: "Yes",
+        keys: &[(&[KeyCode::Char('y')], true), (&[KeyCode::Char('Y')], true)],
+    },
+    Binding {
+        label: "n/Esc",
+        description: "No, cancel",
+        keys: &[
+            (&[KeyCode::Char('n')], false),
+            (&[KeyCode::Char('N')], false),
+            (&[KeyCode::Esc], false),
+        ],
+    },
+];
+
+pub const BAN: &[Binding<BanKey>] = &[
+    Binding {
+        label: "a",
+        description: "Ban the artist",
+        keys: &[(&[KeyCode::Char('a')], BanKey::Artist), (&[KeyCode::Char('A')], BanKey::Artist)],
+    },
+    Binding {
+        label: "l",
+        description: "Ban the album",
+        keys: &[(&[KeyCode::Char('l')], BanKey::Album), (&[KeyCode::Char('L')], BanKey::Album)],
+    },
+    Binding {
+        label: "n/Esc",
+        description: "Cancel",
+        keys: &[
+            (&[KeyCode::Char('n')], BanKey::Cancel),
+            (&[KeyCode::Char('N')], BanKey::Cancel),
+            (&[KeyCode::Esc], BanKey::Cancel),
+        ],
+    },
+];
+
+pub const QUIT: &[Binding<QuitKey>] = &[
+    Binding {
+        label: "y/q",
+        description: "Quit",
+        keys: &[
+            (&[KeyCode::Char('y')], QuitKey::Quit),
+            (&[KeyCode::Char('Y')], QuitKey::Quit),
+            (&[KeyCode::Char('q')], QuitKey::Quit),
+        ],
+    },
+    Binding {
+        label: "d",
+        description: "Detach: keep playing without the interface",
+        keys: &[(&[KeyCode::Char('d')], QuitKey::Detach), (&[KeyCode::Char('D')], QuitKey::Detach)],
+    },
+    Binding {
+        label: "n/Esc",
+        description: "Cancel",
+        keys: &[
+            (&[KeyCode::Char('n')], QuitKey::Cancel),
+            (&[KeyCode::Char('N')], QuitKey::Cancel),
+            (&[KeyCode::Esc], QuitKey::Cancel),
+        ],
+    },
+];
+
+const HELP: &[Binding<HelpKey>] = &[
+    Binding {
+        label: "↑/↓",
+        description: "Scroll",
+        keys: &[(&[KeyCode::Up], HelpKey::Up), (&[KeyCode::Down], HelpKey::Down)],
+    },
+    Binding {
+        label: "PgUp/PgDn",
+        description: "Scroll by page",
+        keys: &[(&[KeyCode::PageUp], HelpKey::PageUp), (&[KeyCode::PageDown], HelpKey::PageDown)],
+    },
+    Binding {
+        label: "Home/End",
+        description: "Top/bottom",
+        keys: &[(&[KeyCode::Home], HelpKey::Top), (&[KeyCode::End], HelpKey::Bottom)],
+    },
+    Binding {
+        label: "?/q/Esc",
+        description: "Close help",
+        keys: &[
+            (&[KeyCode::Char('?')], HelpKey::Close),
+            (&[KeyCode::Char('q')], HelpKey::Close),
+            (&[KeyCode::Esc], HelpKey::Close),
+        ],
+    },
+];
+
+const VIM_HELP: &[Binding<HelpKey>] = &[
+    Binding {
+        label: "j/k",
+        description: "Scroll",
+        keys: &[(&[KeyCode::Char('k')], HelpKey::Up), (&[KeyCode::Char('j')], HelpKey::Down)],
+    },
+    Binding {
+        label: "PgUp/PgDn",
+        description: "Scroll by page",
+        keys: &[(&[KeyCode::PageUp], HelpKey::PageUp), (&[KeyCode::PageDown], HelpKey::PageDown)],
+    },
+    Binding {
+        label: "gg/G",
+        description: "Top/bottom",
+        keys: &[
+            (&[KeyCode::Char('g'), KeyCode::Char('g')], HelpKey::Top),
+            (&[KeyCode::Char('G')], HelpKey::Bottom),
+        ],
+    },
+    Binding {
+        label: "?/q/Esc",
+        description: "Close help",
+        keys: &[
+            (&[KeyCode::Char('?')], HelpKey::Close),
+            (&[KeyCode::Char('q')], HelpKey::Close),
+            (&[KeyCode::Esc], HelpKey::Close),
+        ],
+    },
+];
+
+/// Screens listed by the help view, with a note on what they accept besides their bindings.
+pub const SCREENS: [(&str, Option<&str>, Screen); 8] = [
+    ("Tabs", None, Screen::Tabs),
+    ("Player", None, Screen::Normal),
+    ("Track lists", Some("The player keys work there too"), Screen::List),
+    (
+        "Search, tag editor, folder picker, renaming and bookmarks",
+        Some("Type to edit the query, the field, the folder or the bookmark name"),
+        Screen::Form,
+    ),
+    ("Confirmations", None, Screen::Confirm),
+    ("Ban", None, Screen::Ban),
+    ("Quit while playing", Some("With confirm_quit = true"), Screen::Quit),
+    ("Help", None, Screen::Help),
+];
+
+#[derive(Clone, Copy)]
+pub enum Screen {
+    Tabs,
+    Normal,
+    List,
+    Form,
+    Confirm,
+    Ban,
+    Quit,
+    Help,
+}
+
+impl Screen {
+    /// (label, description) of every binding of the screen.
+    pub fn entries(self, keymap: &Keymap) -> Vec<(&'static str, &'static str)> {
+        match self {
+            Screen::Tabs => entries(TABS),
+            Screen::Normal => entries(keymap.normal),
+            Screen::List => entries(keymap.list),
+            Screen::Form => entries(FORM),
+            Screen::Confirm => entries(CONFIRM),
+            Screen::Ban => entries(BAN),
+            Screen::Quit => entries(QUIT),
+            Screen::Help => entries(keymap.help),
+        }
+    }
+}
+
+fn entries<A>(table: &[Binding<A>]) -> Vec<(&'static str, &'static str)> {
+    table.iter().map(|b| (b.label, b.description)).collect()
+}
+
+/// Adds `code` to the key sequence being typed in `pending`. Returns the
+/// action once a whole sequence is recognized; keys that cannot start or
+/// continue any sequence are dropped.
+pub fn resolve<A: Clone>(table: &[Binding<A>], pending: &mut Vec<KeyCode>, code: KeyCode) -> Option<A> {
+    pending.push(code);
+    loop {
+        let mut is_prefix = false;
+        for (keys, action) in table.iter().flat_map(|binding| binding.keys.iter()) {
+            if *keys == pending.as_slice() {
+                pending.clear();
+                return Some(action.clone());
+            }
+            is_prefix |= keys.starts_with(pending);
+        }
+        if is_prefix {
+            return None;
+        }
+        // Dead end: the last key may still start a new sequence.
+        if pending.len() <= 1 {
+            pending.clear();
+            return None;
+        }
+        pending.drain(..pending.len() - 1);
+    }
+}
+
+/// The action bound to the single key `code` in `table`, if any.
+pub fn lookup<A: Clone>(table: &[Binding<A>], code: KeyCode) -> Option<A> {
+    resolve(table, &mut Vec::new(), code)
+}
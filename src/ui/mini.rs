@@ -0,0 +1,62 @@
+//! The one-line display of `--mini`, for tiny terminal panes:
+//! "▶ Artist - Title  02:10/04:30 ♥".
+
+use super::tabs::Context;
+use ratatui::{
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::Paragraph,
+    Frame,
+};
+use std::time::Duration;
+
+pub fn draw(f: &mut Frame, area: Rect, context: &Context) {
+    let Context {
+        theme,
+        layout,
+        now_playing,
+        ..
+    } = *context;
+    let symbol = if now_playing.playing { "▶ " } else { "⏸ " };
+    let label = now_playing
+        .track
+        .map(|track| now_playing.tracks.track_label(track))
+        .unwrap_or_else(|| "Nothing playing".to_string());
+
+    let time = match (now_playing.position, now_playing.duration) {
+        (Some(position), Some(total)) if layout.remaining_time && position <= total => {
+            format!("-{}/{}", mm_ss(total - position), mm_ss(total))
+        }
+        (Some(position), Some(total)) => format!("{}/{}", mm_ss(position), mm_ss(total)),
+        (Some(position), None) => mm_ss(position),
+        _ => String::new(),
+    };
+    let heart = if now_playing.is_favorite { " ♥" } else { "" };
+    let stop = if now_playing.stop_after_current { " ⏹" } else { "" };
+
+    // Cut the label rather than the time when the pane is narrow.
+    let used = [symbol, time.as_str(), heart, stop].iter().map(|s| s.chars().count()).sum::<usize>() + 2;
+    let room = (area.width as usize).saturating_sub(used);
+    let label = if label.chars().count() > room {
+        let mut cut: String = label.chars().take(room.saturating_sub(1)).collect();
+        cut.push('…');
+        cut
+    } else {
+        label
+    };
+
+    let line = Line::from(vec![
+        Span::styled(symbol, Style::default().fg(theme.accent)),
+        Span::styled(label, Style::default().fg(theme.text).add_modifier(Modifier::BOLD)),
+        Span::raw("  "),
+        Span::styled(time, Style::default().fg(theme.dim)),
+        Span::styled(heart, Style::default().fg(theme.bad)),
+        Span::styled(stop, Style::default().fg(theme.highlight)),
+    ]);
+    f.render_widget(Paragraph::new(line), area);
+}
+
+fn mm_ss(duration: Duration) -> String {
+    format!("{:02}:{:02}", duration.as_secs() / 60, duration.as_secs() % 60)
+}
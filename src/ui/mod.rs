@@ -1,6 +1,8 @@
-use crate::player::TrackMetadata;
+use crate::cache::Thumbnail;
+use crate::config::{Bookmark, Config, PaneLayout, RepeatMode};
+use crate::player::{Chapter, EncodingFix, SortKey, TechnicalInfo, TrackMetadata};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent},
+    event::{Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{
         disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
@@ -8,70 +10,273 @@ use crossterm::{
 };
 use ratatui::{
     backend::CrosstermBackend,
+    buffer::Buffer,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    style::Style,
     text::{Line, Span},
-    widgets::{Block, Borders, Gauge, Paragraph, Wrap},
-    Terminal,
+    widgets::Paragraph,
+    Frame, Terminal,
 };
 use std::io;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
+mod dialog;
+mod keymap;
+mod mini;
+mod tabs;
+mod theme;
+
+use dialog::{BookmarkState, Dialog, EditState, HelpState, MoveState, SearchState, SettingEdit};
+use keymap::TabKey;
+use tabs::{LibraryTab, PlaylistsTab, QueuePane, SettingsTab, StoredTab, Tab};
+pub use keymap::Keymap;
+pub use tabs::Setting;
+pub use theme::Theme;
+
 #[derive(Error, Debug)]
 pub enum UiError {
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
+    #[error("Theme error: {0}")]
+    Theme(String),
+    #[error("Keymap error: {0}")]
+    Keymap(String),
 }
 
 pub struct UI {
     terminal: Terminal<CrosstermBackend<io::Stdout>>,
-    mode: UiMode,
-    // For tag editing
-    edit_state: EditState,
+    /// Dialog shown over the tabs, getting all the keys.
+    dialog: Option<Dialog>,
+    theme: Theme,
+    keymap: Keymap,
+    layout: PaneLayout,
+    config: Config,
+    status: Option<Status>,
+    /// One-line display instead of the tabs.
+    mini: bool,
+    tab: Tab,
+    queue: QueuePane,
+    library: LibraryTab,
+    playlists: PlaylistsTab,
+    settings: SettingsTab,
+    favorites: StoredTab,
+    skipped: StoredTab,
+    /// Keys typed so far of a multi-key binding, like "g" of "gg", for the
+    /// player and the list bindings.
+    pending_keys: Vec<KeyCode>,
+    pending_list_keys: Vec<KeyCode>,
+    /// The last full frame, for `draw_progress` to draw over, when nothing
+    /// but the progress bar can move in it.
+    last_frame: Option<Buffer>,
+    /// Where the progress bar is in `last_frame`, if shown.
+    progress_area: Option<Rect>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-enum UiMode {
-    Normal,
-    EditingTags,
-    ConfirmDelete,
+/// Tag lookups for the track lists, answered from what is already known
+/// without reading the files.
+pub trait TrackInfo {
+    fn cached_metadata(&self, path: &Path) -> Option<&TrackMetadata>;
+    fn track_label(&self, path: &Path) -> String;
 }
 
-#[derive(Debug, Clone)]
-struct EditState {
-    current_field: usize,
-    fields: Vec<String>,
-    field_names: Vec<&'static str>,
+/// A favorite or a skipped track, as their tabs list them.
+#[derive(Clone)]
+pub struct StoredTrack {
+    /// Identifier of its entry, to take it out.
+    pub id: String,
+    pub path: PathBuf,
+    pub label: String,
+    /// Whether the file is gone.
+    pub missing: bool,
 }
 
-impl Default for EditState {
-    fn default() -> Self {
-        Self {
-            current_field: 0,
-            fields: vec![String::new(); 4],
-            field_names: vec!["Artist", "Album", "Title", "Year"],
-        }
-    }
+/// Everything the tabs show about the playing track and the playlist.
+pub struct NowPlaying<'a> {
+    pub track: Option<&'a PathBuf>,
+    pub playlist: &'a [PathBuf],
+    /// Playlist index of `track`.
+    pub index: Option<usize>,
+    /// Changes with the playlist and with the tags known, for the tabs that
+    /// sort them to know when to do it again.
+    pub revision: u64,
+    pub tracks: &'a dyn TrackInfo,
+    /// False while paused.
+    pub playing: bool,
+    /// Labels of the playing track (first, highlighted) and of the ones queued after it.
+    pub queue: Vec<String>,
+    /// The tracks queued after the playing one, as in `queue`.
+    pub upcoming: &'a [PathBuf],
+    /// How many of `upcoming`, first, were put there to play next.
+    pub priority: usize,
+    pub metadata: Option<&'a TrackMetadata>,
+    pub technical_info: Option<&'a TechnicalInfo>,
+    pub art: Option<&'a Thumbnail>,
+    /// Chapters of `track`, in order.
+    pub chapters: &'a [Chapter],
+    pub is_favorite: bool,
+    pub favorites: Arc<Vec<StoredTrack>>,
+    pub skipped: Arc<Vec<StoredTrack>>,
+    /// Whether `playlist` only holds the favorites of the whole one.
+    pub favorites_only: bool,
+    /// Filter of the tracks in `playlist`, as typed.
+    pub filter: Option<&'a str>,
+    /// Whether playback pauses once `track` is over.
+    pub stop_after_current: bool,
+    /// Left and right peak levels, 0.0 to 1.0.
+    pub levels: [f32; 2],
+    pub position: Option<Duration>,
+    pub duration: Option<Duration>,
+}
+
+/// New (artist, album, title, year) values from the tag editor; `None` leaves a field unchanged.
+pub type TagEdit = (Option<String>, Option<String>, Option<String>, Option<String>);
+
+/// How long a status message stays, the last second dimmed.
+const STATUS_DURATION: Duration = Duration::from_secs(4);
+
+/// Kind of a status bar message, which sets its color.
+#[derive(Clone, Copy)]
+pub enum StatusKind {
+    Info,
+    Error,
 }
 
+struct Status {
+    text: String,
+    kind: StatusKind,
+    shown_at: Instant,
+}
+
+/// Parts of the main screen that can be hidden.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Pane {
+    Art,
+    Meters,
+    Controls,
+    Queue,
+}
+
+#[derive(Clone, PartialEq)]
 pub enum UserAction {
+    /// Quit, once confirmed if `confirm_quit` is set and a track is playing.
     Quit,
+    /// Quit without asking: Ctrl-C, signals, the desktop and the remotes.
+    QuitNow,
+    /// Close the interface and give the terminal back, playback going on
+    /// under the remote controls and media keys.
+    Detach,
     PlayPause,
     Next,
     Previous,
     VolumeUp,
     VolumeDown,
     MarkFavorite,
+    /// Play only the favorites of the playlist, or all of it again.
+    ToggleFavoritesOnly,
+    /// Pause once the current track is over, or don't anymore.
+    ToggleStopAfterCurrent,
+    /// Shuffle again the tracks not played yet.
+    ReshuffleRest,
+    /// Add the current track to the skip list, once confirmed.
     MarkSkip,
+    /// Leave the current track out until the player quits.
+    SkipForNow,
+    /// Take back the latest favorite, skip or file moved to the trash.
+    Undo,
+    /// Offer to ban the artist or the album of the current track.
+    Ban,
     Delete,
+    /// Offer to move the current file to another folder.
+    Move,
+    /// Offer to rename the current file.
+    Rename,
+    /// Offer the bookmarks of the current track, to jump to one or to add
+    /// one at the position playing.
+    Bookmarks,
     EditTags,
+    FixEncoding,
+    /// Set the star rating of the current track (0 clears it).
+    Rate(u8),
+    /// Open the jump-to-track search.
+    Search,
+    /// Offer to narrow the playlist to the tracks matching tags.
+    Filter,
+    /// Play the whole playlist again, but for favorites-only.
+    ClearFilter,
+    SeekForward,
+    SeekBackward,
+    /// Seek to the next chapter of the track.
+    NextChapter,
+    /// Seek to the start of the chapter, or to the previous one.
+    PreviousChapter,
+    TogglePane(Pane),
+    /// Grow (positive) or shrink the now playing section, in lines.
+    ResizeNowPlaying(i16),
+    /// Switch the progress bar between time played and time left.
+    ToggleTimeDisplay,
+    /// Switch between the one-line display and the tabs.
+    ToggleMini,
+    /// Show the list of key bindings.
+    Help,
+    /// Play the playlist entry at this index.
+    PlayTrack(usize),
+    /// Play this file, added to the playlist if it isn't in it.
+    PlayFile(PathBuf),
+    /// Play this track of the queue now, those before it still coming next.
+    PlayUpcoming(PathBuf),
+    /// Play this file after the current track, and after the others added
+    /// so before.
+    PlayNext(PathBuf),
+    /// Add this file at the end of the playlist.
+    Enqueue(PathBuf),
+    /// Take the favorite with this identifier out.
+    RemoveFavorite(String),
+    /// Take the skipped track with this identifier out of the skip list.
+    Unskip(String),
+    /// Reorder the playlist; playback follows the new order.
+    SortPlaylist(SortKey),
+    /// Ask for a new value of a setting edited as text.
+    EditSetting(Setting),
+    /// Set a setting to the given text, or to its next value without one.
+    ChangeSetting(Setting, Option<String>),
+    /// Answers of the dialogs.
+    SaveTags(PathBuf, TagEdit),
+    /// Delete this track, to the trash or for good.
+    DeleteConfirmed(PathBuf, bool),
+    /// Add this track to the skip list, with the number of times it was
+    /// left early if that is why.
+    SkipConfirmed(PathBuf, Option<usize>),
+    DeleteCancelled,
+    /// Move this track into this folder.
+    MoveConfirmed(PathBuf, PathBuf),
+    /// Rename the file of this track to this name.
+    RenameConfirmed(PathBuf, String),
+    /// Play only the tracks matching this filter; all of them if empty.
+    FilterConfirmed(String),
+    /// Bookmark this place of this track.
+    AddBookmark(PathBuf, Bookmark),
+    /// Play on from this bookmark, if this track is still playing.
+    JumpToBookmark(PathBuf, Bookmark),
+    /// Remove the bookmark of this name from this track.
+    RemoveBookmark(PathBuf, String),
+    /// Ban every track of this artist, or this album (by this artist if
+    /// given).
+    BanConfirmed {
+        artist: Option<String>,
+        album: Option<String>,
+    },
+    FixEncodingConfirmed(PathBuf, Vec<EncodingFix>),
+    /// Nothing to do but the screen changed.
+    Refresh,
     None,
 }
 
 impl UI {
-    pub fn new() -> Result<Self, UiError> {
+    /// `config` holds the settings in effect, flags applied, for the Settings tab.
+    pub fn new(theme: Theme, keymap: Keymap, config: Config) -> Result<Self, UiError> {
         enable_raw_mode()?;
         let mut stdout = io::stdout();
         execute!(stdout, EnterAlternateScreen)?;
@@ -80,446 +285,506 @@ impl UI {
 
         Ok(UI {
             terminal,
-            mode: UiMode::Normal,
-            edit_state: EditState::default(),
+            dialog: None,
+            theme,
+            keymap,
+            layout: config.layout,
+            config,
+            status: None,
+            mini: false,
+            tab: Tab::NowPlaying,
+            queue: QueuePane::default(),
+            library: LibraryTab::default(),
+            playlists: PlaylistsTab::default(),
+            settings: SettingsTab::default(),
+            favorites: StoredTab::favorites(),
+            skipped: StoredTab::skipped(),
+            pending_keys: Vec::new(),
+            pending_list_keys: Vec::new(),
+            last_frame: None,
+            progress_area: None,
         })
     }
 
-    pub fn draw(
-        &mut self,
-        current_track: Option<&PathBuf>,
-        current_metadata: Option<&TrackMetadata>,
-        is_favorite: bool,
-        current_position: Option<Duration>,
-        total_duration: Option<Duration>,
-    ) -> Result<(), UiError> {
-        self.terminal.draw(|f| {
-            let size = f.area();
-
-            // Main layout
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(3), // Header
-                    Constraint::Length(3), // Track info
-                    Constraint::Length(3), // Progress bar
-                    Constraint::Min(5),    // Controls
-                ])
-                .split(size);
-
-            // Header
-            let title = Paragraph::new("=== KSound Player ===")
-                .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
-                .alignment(ratatui::layout::Alignment::Center)
-                .block(Block::default().borders(Borders::NONE));
-            f.render_widget(title, chunks[0]);
-
-            // Track info
-            let track_text = if let Some(track) = current_track {
-                let display_str = if let Some(metadata) = current_metadata {
-                    let artist = metadata.artist.as_deref().unwrap_or("Unknown Artist");
-                    let album = metadata.album.as_deref().unwrap_or("Unknown Album");
-                    let title = metadata.title.as_deref().unwrap_or("Unknown Title");
-                    let year = metadata.year.as_deref().unwrap_or("");
-                    let all_unknown = artist == "Unknown Artist"
-                        && album == "Unknown Album"
-                        && title == "Unknown Title"
-                        && year.is_empty();
-
-                    let rel_path = match track
-                        .strip_prefix(std::env::current_dir().unwrap_or_else(|_| track.clone()))
-                        .ok()
-                    {
-                        Some(p) => p.display().to_string(),
-                        None => track.display().to_string(),
-                    };
-
-                    if all_unknown {
-                        if is_favorite {
-                            format!("★ {}", rel_path)
-                        } else {
-                            rel_path
-                        }
-                    } else {
-                        let year_str = if year.is_empty() {
-                            String::new()
-                        } else {
-                            format!(" ({})", year)
-                        };
-                        if is_favorite {
-                            format!("★ {} - {} - {}{} [{}]", artist, album, title, year_str, rel_path)
-                        } else {
-                            format!("{} - {} - {}{} [{}]", artist, album, title, year_str, rel_path)
-                        }
-                    }
-                } else {
-                    if is_favorite {
-                        format!("★ {}", track.display())
-                    } else {
-                        track.display().to_string()
-                    }
-                };
-                format!("Now playing: {}", display_str)
-            } else {
-                "No track playing".to_string()
+    pub fn draw(&mut self, now_playing: &NowPlaying) -> Result<(), UiError> {
+        let theme = self.theme;
+        let keymap = self.keymap;
+        let layout = self.layout;
+
+        if self
+            .status
+            .as_ref()
+            .is_some_and(|status| status.shown_at.elapsed() >= STATUS_DURATION)
+        {
+            self.status = None;
+        }
+        let status_line = self.status.as_ref().map(|status| {
+            let fading = status.shown_at.elapsed() + Duration::from_secs(1) >= STATUS_DURATION;
+            let color = match status.kind {
+                _ if fading => theme.dim,
+                StatusKind::Info => theme.good,
+                StatusKind::Error => theme.bad,
             };
+            Line::from(Span::styled(status.text.clone(), Style::default().fg(color)))
+        });
+        let mini = self.mini;
+        let dialog = &mut self.dialog;
+        let tab = self.tab;
+        let queue = &mut self.queue;
+        let library = &mut self.library;
+        let playlists = &mut self.playlists;
+        let settings = &mut self.settings;
+        let favorites = &mut self.favorites;
+        let skipped = &mut self.skipped;
+        let context = tabs::Context {
+            theme: &theme,
+            keymap: &keymap,
+            layout: &layout,
+            config: &self.config,
+            now_playing,
+        };
 
-            let track_paragraph = Paragraph::new(track_text)
-                .style(Style::default().fg(Color::White))
-                .wrap(Wrap { trim: true })
-                .block(Block::default().borders(Borders::NONE));
-            f.render_widget(track_paragraph, chunks[1]);
-
-            // Progress bar
-            if let (Some(current), Some(total)) = (current_position, total_duration) {
-                if total.as_secs_f32() > 0.0 && current <= total {
-                    let progress = (current.as_secs_f32() / total.as_secs_f32()).min(1.0);
-                    let time_label = format!(
-                        "{:02}:{:02} / {:02}:{:02}",
-                        current.as_secs() / 60,
-                        current.as_secs() % 60,
-                        total.as_secs() / 60,
-                        total.as_secs() % 60
-                    );
-
-                    let gauge = Gauge::default()
-                        .block(Block::default().borders(Borders::NONE))
-                        .gauge_style(
-                            Style::default()
-                                .fg(Color::Cyan)
-                                .bg(Color::Black)
-                                .add_modifier(Modifier::BOLD),
-                        )
-                        .label(time_label)
-                        .ratio(progress as f64);
-                    f.render_widget(gauge, chunks[2]);
-                } else {
-                    let gauge = Gauge::default()
-                        .block(Block::default().borders(Borders::NONE))
-                        .gauge_style(Style::default().fg(Color::DarkGray).bg(Color::Black))
-                        .label("00:00 / 00:00")
-                        .ratio(0.0);
-                    f.render_widget(gauge, chunks[2]);
+        let mut progress_area = None;
+        let frame = self.terminal.draw(|f| {
+            if mini {
+                // The status takes the track's line when there is no other.
+                let rows = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(1), Constraint::Length(1)])
+                    .split(f.area());
+                match status_line {
+                    Some(line) if rows[1].height == 0 => f.render_widget(Paragraph::new(line), rows[0]),
+                    status_line => {
+                        mini::draw(f, rows[0], &context);
+                        if let Some(line) = status_line {
+                            f.render_widget(Paragraph::new(line), rows[1]);
+                        }
+                    }
                 }
             } else {
-                let gauge = Gauge::default()
-                    .block(Block::default().borders(Borders::NONE))
-                    .gauge_style(Style::default().fg(Color::DarkGray).bg(Color::Black))
-                    .label("00:00 / 00:00")
-                    .ratio(0.0);
-                f.render_widget(gauge, chunks[2]);
+                let tabs = (queue, library, playlists, settings, favorites, skipped);
+                progress_area = draw_tabs(f, tab, tabs, &context, status_line);
+            }
+            if let Some(dialog) = dialog {
+                dialog.draw(f, &theme);
             }
-
-            // Controls
-            let controls_text = vec![
-                Line::from(vec![
-                    Span::styled("Space", Style::default().fg(Color::Yellow)),
-                    Span::raw(": Play/Pause  "),
-                    Span::styled("→", Style::default().fg(Color::Yellow)),
-                    Span::raw(": Next  "),
-                    Span::styled("←", Style::default().fg(Color::Yellow)),
-                    Span::raw(": Previous"),
-                ]),
-                Line::from(vec![
-                    Span::styled("f", Style::default().fg(Color::Yellow)),
-                    Span::raw(": Favorite  "),
-                    Span::styled("s", Style::default().fg(Color::Yellow)),
-                    Span::raw(": Skip  "),
-                    Span::styled("d", Style::default().fg(Color::Yellow)),
-                    Span::raw(": Delete  "),
-                    Span::styled("e", Style::default().fg(Color::Yellow)),
-                    Span::raw(": Edit tags"),
-                ]),
-                Line::from(vec![
-                    Span::styled("+/-", Style::default().fg(Color::Yellow)),
-                    Span::raw(": Volume  "),
-                    Span::styled("q", Style::default().fg(Color::Yellow)),
-                    Span::raw(": Quit"),
-                ]),
-            ];
-
-            let controls = Paragraph::new(controls_text)
-                .style(Style::default().fg(Color::White))
-                .block(
-                    Block::default()
-                        .borders(Borders::TOP)
-                        .border_style(Style::default().fg(Color::DarkGray))
-                        .title("Controls"),
-                );
-            f.render_widget(controls, chunks[3]);
         })?;
+        // The time of the one-line display, and a dialog over the progress
+        // bar, only a full draw gets right.
+        self.last_frame = (!mini && self.dialog.is_none()).then(|| frame.buffer.clone());
+        self.progress_area = progress_area;
 
         Ok(())
     }
 
-    pub fn handle_input(&mut self) -> Result<UserAction, UiError> {
-        if self.mode == UiMode::EditingTags {
-            // Handle tag editing input
-            return self.handle_edit_input();
+    /// Redraws the progress bar alone over the last full frame, or the whole
+    /// screen when that can't be done.
+    pub fn draw_progress(&mut self, now_playing: &NowPlaying) -> Result<(), UiError> {
+        let Some(last_frame) = &self.last_frame else {
+            return self.draw(now_playing);
+        };
+        if self.status.is_some() || last_frame.area.as_size() != self.terminal.size()? {
+            return self.draw(now_playing);
         }
+        // Not on the screen: nothing to redraw.
+        let Some(area) = self.progress_area else {
+            return Ok(());
+        };
+        let context = tabs::Context {
+            theme: &self.theme,
+            keymap: &self.keymap,
+            layout: &self.layout,
+            config: &self.config,
+            now_playing,
+        };
+        self.terminal.draw(|f| {
+            f.buffer_mut().clone_from(last_frame);
+            tabs::now_playing::draw_progress(f, area, &context);
+        })?;
+        Ok(())
+    }
 
-        if self.mode == UiMode::ConfirmDelete {
-            // Handle delete confirmation
-            return self.handle_confirm_input();
+    /// What a terminal event asks for.
+    pub fn handle_event(&mut self, event: Event) -> UserAction {
+        let Event::Key(KeyEvent { code, modifiers, .. }) = event else {
+            // Resizes and the like only need a redraw.
+            return UserAction::Refresh;
+        };
+        // Raw mode turns Ctrl-C into a key press: it still quits, dialog or not.
+        if code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL) {
+            return UserAction::QuitNow;
         }
 
-        // Normal mode input
-        if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(KeyEvent { code, .. }) = event::read()? {
-                return Ok(match code {
-                    KeyCode::Char('q') => UserAction::Quit,
-                    KeyCode::Char(' ') => UserAction::PlayPause,
-                    KeyCode::Right => UserAction::Next,
-                    KeyCode::Left => UserAction::Previous,
-                    KeyCode::Char('f') => UserAction::MarkFavorite,
-                    KeyCode::Char('s') => UserAction::MarkSkip,
-                    KeyCode::Char('d') => UserAction::Delete,
-                    KeyCode::Char('e') => UserAction::EditTags,
-                    KeyCode::Char('+') => UserAction::VolumeUp,
-                    KeyCode::Char('-') => UserAction::VolumeDown,
-                    _ => UserAction::None,
-                });
+        if let Some(dialog) = &mut self.dialog {
+            let action = dialog.handle(code, &self.keymap);
+            if action.is_some() {
+                self.dialog = None;
             }
+            return action.unwrap_or(UserAction::Refresh);
         }
-
-        Ok(UserAction::None)
+        self.handle_key(code)
     }
 
-    fn handle_edit_input(&mut self) -> Result<UserAction, UiError> {
-        // This will be implemented for tag editing
-        // For now, just exit edit mode
-        if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(KeyEvent { code, .. }) = event::read()? {
-                match code {
-                    KeyCode::Esc => {
-                        self.mode = UiMode::Normal;
-                    }
-                    _ => {}
-                }
+    /// Tab switching first, then the list keys of list tabs, and those of the
+    /// queue pane the player bindings leave free, then the player bindings.
+    fn handle_key(&mut self, code: KeyCode) -> UserAction {
+        if let Some(key) = keymap::lookup(keymap::TABS, code) {
+            self.tab = match key {
+                TabKey::Next => self.tab.next(),
+                TabKey::Previous => self.tab.previous(),
+                TabKey::Go(tab) => tab,
+            };
+            return UserAction::Refresh;
+        }
+
+        let is_list = self.tab != Tab::NowPlaying;
+        // A player sequence in progress, like ":" of ":q", goes on there.
+        if is_list && self.pending_keys.is_empty() {
+            if let Some(key) = keymap::resolve(self.keymap.list, &mut self.pending_list_keys, code) {
+                let action = match self.tab {
+                    Tab::Library => self.library.handle(key),
+                    Tab::Settings => self.settings.handle(key),
+                    Tab::Favorites => self.favorites.handle(key),
+                    Tab::Skipped => self.skipped.handle(key),
+                    _ => self.playlists.handle(key),
+                };
+                return action.unwrap_or(UserAction::Refresh);
+            }
+            if !self.pending_list_keys.is_empty() {
+                return UserAction::None;
+            }
+        }
+        let queue_shown = self.tab == Tab::NowPlaying && self.layout.queue;
+        let free = || {
+            let mut sequences = self.keymap.normal.iter().flat_map(|binding| binding.keys);
+            !sequences.any(|(keys, _)| keys.first() == Some(&code))
+        };
+        if queue_shown && self.pending_keys.is_empty() && free() {
+            if let Some(key) = keymap::resolve(self.keymap.list, &mut self.pending_list_keys, code) {
+                return self.queue.handle(key).unwrap_or(UserAction::Refresh);
+            }
+            if !self.pending_list_keys.is_empty() {
+                return UserAction::None;
             }
         }
-        Ok(UserAction::None)
+
+        keymap::resolve(self.keymap.normal, &mut self.pending_keys, code).unwrap_or(UserAction::None)
     }
 
-    fn handle_confirm_input(&mut self) -> Result<UserAction, UiError> {
-        // This will be implemented for delete confirmation
-        if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(KeyEvent { code, .. }) = event::read()? {
-                match code {
-                    KeyCode::Char('y') | KeyCode::Char('Y') => {
-                        self.mode = UiMode::Normal;
-                        return Ok(UserAction::Delete);
-                    }
-                    KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
-                        self.mode = UiMode::Normal;
-                        return Ok(UserAction::None);
-                    }
-                    _ => {}
-                }
-            }
+    /// Shows a message in the status bar for a few seconds.
+    /// Errors are logged too, as the status line forgets them.
+    pub fn set_status(&mut self, kind: StatusKind, text: impl Into<String>) {
+        let text = text.into();
+        if let StatusKind::Error = kind {
+            tracing::warn!("{}", text);
         }
-        Ok(UserAction::None)
+        self.status = Some(Status {
+            text,
+            kind,
+            shown_at: Instant::now(),
+        });
     }
 
-    pub fn set_playing(&mut self, _playing: bool) {
-        // This can be used to update UI state if needed
+    /// Whether a status message is on screen: it needs redraws to go away.
+    pub fn has_status(&self) -> bool {
+        self.status.is_some()
     }
 
-    pub fn confirm_deletion(&mut self, track: &PathBuf) -> Result<bool, UiError> {
-        self.mode = UiMode::ConfirmDelete;
-        
-        self.terminal.draw(|f| {
-            let size = f.area();
-            
-            // Create a centered popup - increased height to ensure all content is visible
-            let popup_area = centered_rect(70, 30, size);
-            
-            let text = vec![
-                Line::from(vec![
-                    Span::styled("⚠ Delete Confirmation ⚠", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
-                ]),
-                Line::from(""),
-                Line::from(vec![
-                    Span::raw("Are you sure you want to delete:"),
-                ]),
-                Line::from(""),
-                Line::from(vec![
-                    Span::styled(format!("{}", track.display()), Style::default().fg(Color::Yellow).add_modifier(Modifier::ITALIC)),
-                ]),
-                Line::from(""),
-                Line::from(""),
-                Line::from(vec![
-                    Span::styled("  [Y] ", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
-                    Span::raw("Yes, delete this file   "),
-                    Span::styled("  [N] ", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
-                    Span::raw("No, cancel"),
-                ]),
-                Line::from(""),
-                Line::from(vec![
-                    Span::styled("Press ESC to cancel", Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC)),
-                ]),
-            ];
-            
-            let paragraph = Paragraph::new(text)
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .border_style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
-                        .title(" WARNING ")
-                        .title_style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
-                )
-                .alignment(ratatui::layout::Alignment::Center)
-                .wrap(Wrap { trim: true });
-            
-            f.render_widget(paragraph, popup_area);
-        })?;
+    pub fn layout(&self) -> PaneLayout {
+        self.layout
+    }
 
-        // Wait for user input
-        loop {
-            if let Event::Key(KeyEvent { code, .. }) = event::read()? {
-                match code {
-                    KeyCode::Char('y') | KeyCode::Char('Y') => {
-                        self.mode = UiMode::Normal;
-                        return Ok(true);
-                    }
-                    KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
-                        self.mode = UiMode::Normal;
-                        return Ok(false);
-                    }
-                    _ => {}
-                }
-            }
-        }
+    pub fn toggle_pane(&mut self, pane: Pane) {
+        let shown = match pane {
+            Pane::Art => &mut self.layout.art,
+            Pane::Meters => &mut self.layout.meters,
+            Pane::Controls => &mut self.layout.controls,
+            Pane::Queue => &mut self.layout.queue,
+        };
+        *shown = !*shown;
+    }
+
+    pub fn resize_now_playing(&mut self, delta: i16) {
+        let height = self.layout.now_playing_height.saturating_add_signed(delta);
+        self.layout.now_playing_height = height.clamp(MIN_NOW_PLAYING_HEIGHT, MAX_NOW_PLAYING_HEIGHT);
     }
 
-    pub fn edit_tags_form(
+    pub fn toggle_mini(&mut self) {
+        self.mini = !self.mini;
+    }
+
+    pub fn toggle_time_display(&mut self) {
+        self.layout.remaining_time = !self.layout.remaining_time;
+    }
+
+    /// Asks before deleting `track`, twice if it is a `favorite`:
+    /// `DeleteConfirmed` or `DeleteCancelled` follows.
+    pub fn confirm_deletion(&mut self, track: &Path, favorite: bool) {
+        self.dialog = Some(Dialog::ConfirmDelete {
+            track: track.to_path_buf(),
+            trash: self.config.trash,
+            favorite,
+        });
+    }
+
+    /// Asks where to move `track`, among the `move_to` folders of the
+    /// config or typed in: `MoveConfirmed` follows if one is picked.
+    pub fn choose_folder(&mut self, track: &Path, label: String) {
+        self.dialog = Some(Dialog::MoveTrack(MoveState::new(
+            track.to_path_buf(),
+            label,
+            self.config.move_to.clone(),
+        )));
+    }
+
+    /// Text field for a new file name of `track`, holding its current one:
+    /// `RenameConfirmed` follows if accepted.
+    pub fn rename_file(&mut self, track: &Path) {
+        let input = track.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        self.dialog = Some(Dialog::RenameTrack {
+            track: track.to_path_buf(),
+            input,
+        });
+    }
+
+    /// Text field for a filter of the playlist, holding the `current` one:
+    /// `FilterConfirmed` follows if accepted.
+    pub fn ask_filter(&mut self, current: Option<String>) {
+        self.dialog = Some(Dialog::Filter {
+            input: current.unwrap_or_default(),
+        });
+    }
+
+    /// Lists the `bookmarks` of `track`, with a field to name a new one at
+    /// `position`: `JumpToBookmark`, `AddBookmark` or `RemoveBookmark`
+    /// follows.
+    pub fn show_bookmarks(
         &mut self,
-        track: &PathBuf,
-        metadata: Option<&TrackMetadata>,
-    ) -> Result<(Option<String>, Option<String>, Option<String>, Option<String>), UiError> {
-        // Initialize edit state
-        let (cur_artist, cur_album, cur_title, cur_year) = if let Some(m) = metadata {
-            (
-                m.artist.as_deref().unwrap_or(""),
-                m.album.as_deref().unwrap_or(""),
-                m.title.as_deref().unwrap_or(""),
-                m.year.as_deref().unwrap_or(""),
-            )
-        } else {
-            ("", "", "", "")
-        };
+        track: &Path,
+        label: String,
+        position: Duration,
+        bookmarks: Vec<Bookmark>,
+    ) {
+        self.dialog = Some(Dialog::Bookmarks(BookmarkState::new(
+            track.to_path_buf(),
+            label,
+            position,
+            bookmarks,
+        )));
+    }
 
-        self.edit_state.fields = vec![
-            cur_artist.to_string(),
-            cur_album.to_string(),
-            cur_title.to_string(),
-            cur_year.to_string(),
-        ];
-        self.edit_state.current_field = 0;
-        self.mode = UiMode::EditingTags;
-
-        let original_values = self.edit_state.fields.clone();
-
-        loop {
-            // Draw the edit form
-            self.terminal.draw(|f| {
-                let size = f.area();
-                let popup_area = centered_rect(80, 60, size);
-
-                let mut text = vec![
-                    Line::from(vec![
-                        Span::styled("Edit MP3 Tags", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
-                    ]),
-                    Line::from(vec![
-                        Span::styled(format!("File: {}", track.display()), Style::default().fg(Color::Yellow)),
-                    ]),
-                    Line::from(""),
-                    Line::from("Use ↑↓ to navigate, type to edit, Enter to confirm, Esc to cancel"),
-                    Line::from(""),
-                ];
-
-                for (idx, field_name) in self.edit_state.field_names.iter().enumerate() {
-                    let is_current = idx == self.edit_state.current_field;
-                    let field_value = &self.edit_state.fields[idx];
-                    
-                    let style = if is_current {
-                        Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
-                    } else {
-                        Style::default().fg(Color::White)
-                    };
-
-                    text.push(Line::from(vec![
-                        Span::styled(format!("{}: ", field_name), style),
-                        Span::styled(field_value.clone(), style),
-                        if is_current {
-                            Span::styled("█", Style::default().fg(Color::Green))
-                        } else {
-                            Span::raw("")
-                        },
-                    ]));
-                }
+    /// Asks before adding `track` to the skip list, telling it was left
+    /// early `count` times if that is why: `SkipConfirmed` follows if
+    /// accepted.
+    pub fn confirm_skip(&mut self, track: &Path, label: String, count: Option<usize>) {
+        self.dialog = Some(Dialog::ConfirmSkip {
+            track: track.to_path_buf(),
+            label,
+            count,
+        });
+    }
 
-                let paragraph = Paragraph::new(text)
-                    .block(Block::default().borders(Borders::ALL).border_style(Style::default().fg(Color::Cyan)))
-                    .wrap(Wrap { trim: true });
+    /// Asks before quitting while `label` plays: `QuitNow` or `Detach`
+    /// follows if accepted.
+    pub fn confirm_quit(&mut self, label: String) {
+        self.dialog = Some(Dialog::ConfirmQuit { label });
+    }
 
-                f.render_widget(paragraph, popup_area);
-            })?;
+    /// Offers to ban the artist or the album of a track with these tags:
+    /// `BanConfirmed` follows if one is picked.
+    pub fn confirm_ban(&mut self, label: String, artist: Option<String>, album: Option<String>) {
+        self.dialog = Some(Dialog::ConfirmBan { label, artist, album });
+    }
 
-            // Handle input
-            if let Event::Key(KeyEvent { code, .. }) = event::read()? {
-                match code {
-                    KeyCode::Esc => {
-                        self.mode = UiMode::Normal;
-                        return Ok((None, None, None, None));
-                    }
-                    KeyCode::Enter => {
-                        self.mode = UiMode::Normal;
-                        let results = self.edit_state.fields.iter()
-                            .zip(original_values.iter())
-                            .map(|(new, old)| {
-                                if new != old && !new.is_empty() {
-                                    Some(new.clone())
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect::<Vec<_>>();
-                        return Ok((
-                            results[0].clone(),
-                            results[1].clone(),
-                            results[2].clone(),
-                            results[3].clone(),
-                        ));
-                    }
-                    KeyCode::Up => {
-                        if self.edit_state.current_field > 0 {
-                            self.edit_state.current_field -= 1;
-                        }
-                    }
-                    KeyCode::Down => {
-                        if self.edit_state.current_field < self.edit_state.fields.len() - 1 {
-                            self.edit_state.current_field += 1;
-                        }
-                    }
-                    KeyCode::Char(c) => {
-                        self.edit_state.fields[self.edit_state.current_field].push(c);
-                    }
-                    KeyCode::Backspace => {
-                        self.edit_state.fields[self.edit_state.current_field].pop();
-                    }
-                    _ => {}
+    /// Shows the before/after preview of an encoding repair and asks for
+    /// confirmation: `FixEncodingConfirmed` follows if accepted.
+    pub fn confirm_encoding_fix(&mut self, track: &Path, encoding: Option<&'static str>, fixes: Vec<EncodingFix>) {
+        self.dialog = Some(Dialog::ConfirmEncodingFix {
+            track: track.to_path_buf(),
+            encoding,
+            fixes,
+        });
+    }
+
+    /// Fuzzy search overlay over `entries`, each jumping to the playlist
+    /// index at the same position in `indexes`: `PlayTrack` follows.
+    pub fn search_tracks(&mut self, indexes: Vec<usize>, entries: Vec<String>) {
+        self.dialog = Some(Dialog::Search(SearchState::new(indexes, entries)));
+    }
+
+    /// Full-screen list of the key bindings of every screen.
+    pub fn show_help(&mut self) {
+        self.dialog = Some(Dialog::Help(HelpState::new(&self.theme, &self.keymap)));
+    }
+
+    /// The settings in effect, as changed from the Settings tab.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Text field for a new value of `setting`: `ChangeSetting` follows if accepted.
+    pub fn edit_setting(&mut self, setting: Setting) {
+        let text = setting.text(&self.config, &self.layout);
+        self.dialog = Some(Dialog::EditSetting(SettingEdit::new(setting, text)));
+    }
+
+    /// Sets `setting` from `text`, or to its next value without one, and
+    /// applies it to the screen. Invalid values leave everything as it was.
+    pub fn change_setting(&mut self, setting: Setting, text: Option<&str>) -> Result<(), String> {
+        let mut config = self.config.clone();
+        let mut theme = self.theme;
+        let mut keymap = self.keymap;
+        let mut layout = self.layout;
+        let list = |text: &str| -> Vec<String> {
+            text.split(',').map(str::trim).filter(|item| !item.is_empty()).map(String::from).collect()
+        };
+        let number = |text: &str| -> Result<u16, String> {
+            text.trim().parse().map_err(|_| format!("\"{}\" is not a whole number", text.trim()))
+        };
+
+        match (setting, text) {
+            (Setting::Paths, Some(text)) => config.paths = list(text),
+            (Setting::Extensions, Some(text)) => {
+                config.extensions = list(text).iter().map(|ext| ext.trim_start_matches('.').to_string()).collect()
+            }
+            (Setting::Volume, Some(text)) => config.volume = number(text)?,
+            (Setting::VolumeStep, Some(text)) => config.volume_step = number(text)?,
+            (Setting::ArtistSpacing, Some(text)) => config.artist_spacing = number(text)?,
+            (Setting::Crossfade, Some(text)) => {
+                config.crossfade = text
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("\"{}\" is not a number of seconds", text.trim()))?
+            }
+            (Setting::NowPlayingHeight, Some(text)) => {
+                let height = number(text)?;
+                if !(MIN_NOW_PLAYING_HEIGHT..=MAX_NOW_PLAYING_HEIGHT).contains(&height) {
+                    return Err(format!(
+                        "the height must be between {} and {} lines",
+                        MIN_NOW_PLAYING_HEIGHT, MAX_NOW_PLAYING_HEIGHT
+                    ));
+                }
+                layout.now_playing_height = height;
+            }
+            (Setting::Resume, _) => config.resume = !config.resume,
+            (Setting::Shuffle, _) => config.shuffle = !config.shuffle,
+            (Setting::ShuffleAlbums, _) => config.shuffle_albums = !config.shuffle_albums,
+            (Setting::Party, _) => config.party = !config.party,
+            (Setting::CrossfadeOnNext, _) => config.crossfade_on_next = !config.crossfade_on_next,
+            (Setting::CrossfadeOnEnd, _) => config.crossfade_on_end = !config.crossfade_on_end,
+            (Setting::CrossfadeWithinAlbums, _) => {
+                config.crossfade_within_albums = !config.crossfade_within_albums
+            }
+            (Setting::Repeat, _) => {
+                config.repeat = match config.repeat {
+                    RepeatMode::Off => RepeatMode::One,
+                    RepeatMode::One => RepeatMode::All,
+                    RepeatMode::All => RepeatMode::Off,
                 }
             }
+            (Setting::Theme, _) => {
+                let index = theme::BUILTIN_THEMES.iter().position(|name| *name == theme.name).unwrap_or(0);
+                let name = theme::BUILTIN_THEMES[(index + 1) % theme::BUILTIN_THEMES.len()];
+                theme = Theme::load(name, &config.colors).map_err(|e| e.to_string())?;
+                config.theme = Some(name.to_string());
+            }
+            (Setting::Keymap, _) => {
+                let index = keymap::PRESETS.iter().position(|name| *name == keymap.name).unwrap_or(0);
+                let name = keymap::PRESETS[(index + 1) % keymap::PRESETS.len()];
+                keymap = Keymap::preset(name)
+                    .and_then(|preset| preset.with_overrides(&config.keys))
+                    .map_err(|e| e.to_string())?;
+                config.keymap = Some(name.to_string());
+            }
+            (_, None) => return Ok(()),
         }
+        config.check()?;
+
+        self.config = config;
+        self.theme = theme;
+        self.keymap = keymap;
+        self.layout = layout;
+        self.pending_keys.clear();
+        self.pending_list_keys.clear();
+        Ok(())
+    }
+
+    /// Tag editor for `track`: `SaveTags` follows if accepted.
+    pub fn edit_tags_form(&mut self, track: &Path, metadata: Option<&TrackMetadata>) {
+        self.dialog = Some(Dialog::EditTags(EditState::new(track.to_path_buf(), metadata)));
     }
 }
 
 impl Drop for UI {
     fn drop(&mut self) {
-        let _ = disable_raw_mode();
-        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+        restore_terminal();
+    }
+}
+
+/// Gives the terminal back to the shell, as it was before `UI::new`. Does
+/// no harm if it already was.
+pub fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen);
+}
+
+/// Bounds of the now playing section: header, one line of info and the
+/// progress bar at least; beyond the maximum the art would not fit the width.
+const MIN_NOW_PLAYING_HEIGHT: u16 = 7;
+const MAX_NOW_PLAYING_HEIGHT: u16 = 40;
+
+/// Tab bar, the active tab and the status bar.
+fn draw_tabs(
+    f: &mut Frame,
+    tab: Tab,
+    (queue, library, playlists, settings, favorites, skipped): (
+        &mut QueuePane,
+        &mut LibraryTab,
+        &mut PlaylistsTab,
+        &mut SettingsTab,
+        &mut StoredTab,
+        &mut StoredTab,
+    ),
+    context: &tabs::Context,
+    status_line: Option<Line>,
+) -> Option<Rect> {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1), // Tab bar
+            Constraint::Min(0),    // Tab content
+            Constraint::Length(1), // Status bar
+        ])
+        .split(f.area());
+
+    tabs::draw_tab_bar(f, rows[0], tab, context.theme);
+    let progress_area = match tab {
+        Tab::NowPlaying => Some(tabs::now_playing::draw(f, rows[1], queue, context)),
+        Tab::Library => {
+            library.draw(f, rows[1], context);
+            None
+        }
+        Tab::Playlists => {
+            playlists.draw(f, rows[1], context);
+            None
+        }
+        Tab::Settings => {
+            settings.draw(f, rows[1], context);
+            None
+        }
+        Tab::Favorites => {
+            favorites.draw(f, rows[1], context);
+            None
+        }
+        Tab::Skipped => {
+            skipped.draw(f, rows[1], context);
+            None
+        }
+    };
+    if let Some(line) = status_line {
+        f.render_widget(Paragraph::new(line), rows[2]);
     }
+    progress_area
 }
 
 // Helper function to create centered rectangle
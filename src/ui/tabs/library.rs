@@ -0,0 +1,91 @@
+//! Every track of the library, sorted by artist, album and title.
+//!
+//! The sorted rows are kept from one draw to the next, and only sorted again
+//! when the playlist or its tags change: a draw builds the visible rows only,
+//! and finds the playing track by binary search.
+
+use super::track_list::TrackList;
+use super::Context;
+use crate::ui::keymap::ListKey;
+use crate::ui::{TrackInfo, UserAction};
+use ratatui::{layout::Rect, text::Line, Frame};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// What the rows are sorted by: artist, album and label, in lowercase.
+type SortKey = (String, String, String);
+
+#[derive(Default)]
+pub struct LibraryTab {
+    list: TrackList,
+    /// Playlist index of each row, and its sort key, in order.
+    rows: Vec<(SortKey, usize)>,
+    /// `NowPlaying::revision` the rows were sorted at.
+    revision: Option<u64>,
+    /// Row of the playing track, as of the last draw.
+    current: Option<usize>,
+    /// File of the selected row, as of the last draw.
+    selected: Option<PathBuf>,
+}
+
+fn sort_key(tracks: &dyn TrackInfo, path: &Path) -> SortKey {
+    let metadata = tracks.cached_metadata(path);
+    let field = |value: Option<&String>| value.map(|v| v.to_lowercase()).unwrap_or_default();
+    (
+        field(metadata.and_then(|m| m.artist.as_ref())),
+        field(metadata.and_then(|m| m.album.as_ref())),
+        tracks.track_label(path).to_lowercase(),
+    )
+}
+
+impl LibraryTab {
+    pub fn handle(&mut self, key: ListKey) -> Option<UserAction> {
+        if let (ListKey::Current, Some(row)) = (key, self.current) {
+            self.list.select(row);
+        }
+        if let ListKey::PlayNext = key {
+            return self.selected.clone().map(UserAction::PlayNext);
+        }
+        let row = self.list.handle(key)?;
+        self.rows.get(row).map(|&(_, index)| UserAction::PlayTrack(index))
+    }
+
+    pub fn draw(&mut self, f: &mut Frame, area: Rect, context: &Context) {
+        let now_playing = context.now_playing;
+        let playlist = now_playing.playlist;
+        let tracks = now_playing.tracks;
+
+        if self.revision != Some(now_playing.revision) {
+            // One row per distinct file, listed twice or not.
+            let mut seen = HashSet::new();
+            self.rows = (0..playlist.len())
+                .filter(|&idx| seen.insert(&playlist[idx]))
+                .map(|idx| (sort_key(tracks, &playlist[idx]), idx))
+                .collect();
+            self.rows.sort_unstable();
+            self.revision = Some(now_playing.revision);
+        }
+        self.current = now_playing.track.and_then(|track| {
+            let key = sort_key(tracks, track);
+            let first = self.rows.partition_point(|(row_key, _)| *row_key < key);
+            // Among the tracks that sort the same, if any.
+            let mut same = self.rows[first..].iter().take_while(|(row_key, _)| *row_key == key);
+            let offset = same.position(|&(_, idx)| playlist.get(idx) == Some(track))?;
+            Some(first + offset)
+        });
+
+        let rows = &self.rows;
+        let title = format!("Library ({} tracks)", rows.len());
+        self.list.draw(f, area, context, title, rows.len(), |row| {
+            let path = &playlist[rows[row].1];
+            let album = tracks
+                .cached_metadata(path)
+                .and_then(|m| m.album.clone())
+                .map(|album| format!("  [{}]", album))
+                .unwrap_or_default();
+            Line::from(format!("  {}{}", tracks.track_label(path), album))
+        });
+        let selected = self.list.selected().and_then(|row| self.rows.get(row));
+        self.selected = selected.and_then(|&(_, idx)| playlist.get(idx)).cloned();
+    }
+}
@@ -0,0 +1,91 @@
+//! The tabs of the main screen. Each one draws itself below the tab bar;
+//! list tabs also get the navigation keys before the player bindings.
+
+pub mod now_playing;
+
+mod library;
+mod playlists;
+mod settings;
+mod stored;
+mod track_list;
+
+pub use library::LibraryTab;
+pub use now_playing::QueuePane;
+pub use playlists::PlaylistsTab;
+pub use settings::{Setting, SettingsTab};
+pub use stored::StoredTab;
+
+use super::{Keymap, NowPlaying, Theme};
+use crate::config::{Config, PaneLayout};
+use ratatui::{
+    layout::Rect,
+    style::{Modifier, Style},
+    text::Line,
+    widgets::Tabs,
+    Frame,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tab {
+    NowPlaying,
+    Library,
+    Playlists,
+    Settings,
+    Favorites,
+    Skipped,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 6] = [
+        Tab::NowPlaying,
+        Tab::Library,
+        Tab::Playlists,
+        Tab::Settings,
+        Tab::Favorites,
+        Tab::Skipped,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Tab::NowPlaying => "Now Playing",
+            Tab::Library => "Library",
+            Tab::Playlists => "Playlists",
+            Tab::Settings => "Settings",
+            Tab::Favorites => "Favorites",
+            Tab::Skipped => "Skipped",
+        }
+    }
+
+    fn index(self) -> usize {
+        Tab::ALL.iter().position(|tab| *tab == self).unwrap_or(0)
+    }
+
+    pub fn next(self) -> Tab {
+        Tab::ALL[(self.index() + 1) % Tab::ALL.len()]
+    }
+
+    pub fn previous(self) -> Tab {
+        Tab::ALL[(self.index() + Tab::ALL.len() - 1) % Tab::ALL.len()]
+    }
+}
+
+/// What every tab draws from.
+pub struct Context<'a> {
+    pub theme: &'a Theme,
+    pub keymap: &'a Keymap,
+    pub layout: &'a PaneLayout,
+    pub config: &'a Config,
+    pub now_playing: &'a NowPlaying<'a>,
+}
+
+pub fn draw_tab_bar(f: &mut Frame, area: Rect, active: Tab, theme: &Theme) {
+    let titles = Tab::ALL
+        .iter()
+        .enumerate()
+        .map(|(idx, tab)| Line::from(format!("F{} {}", idx + 1, tab.title())));
+    let tabs = Tabs::new(titles)
+        .select(active.index())
+        .style(Style::default().fg(theme.dim))
+        .highlight_style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD));
+    f.render_widget(tabs, area);
+}
@@ -0,0 +1,441 @@
+//! The main tab: album art, track details, progress, controls and queue.
+
+use super::Context;
+use crate::cache::Thumbnail;
+use crate::player::chapter_at;
+use crate::ui::keymap::ListKey;
+use crate::ui::{NowPlaying, Theme, UserAction};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Gauge, Paragraph, Widget, Wrap},
+    Frame,
+};
+use std::path::PathBuf;
+
+/// The track selected in the queue pane, for Enter to play it straight
+/// away. The selection follows the track as the queue moves up.
+#[derive(Default)]
+pub struct QueuePane {
+    selected: Option<PathBuf>,
+    /// Tracks and lines of the queue seen at the last draw, for the keys.
+    shown: Vec<PathBuf>,
+    height: usize,
+}
+
+impl QueuePane {
+    /// Applies a navigation key: moving up from the first track or `.`
+    /// leaves none selected. Returns the action of `Select`.
+    pub fn handle(&mut self, key: ListKey) -> Option<UserAction> {
+        let last = self.shown.len().checked_sub(1)?;
+        let page = self.height.max(1);
+        let index = self.selected.as_ref().and_then(|track| self.shown.iter().position(|t| t == track));
+        let index = match key {
+            ListKey::Up => index.and_then(|index| index.checked_sub(1)),
+            ListKey::Down => Some(index.map_or(0, |index| (index + 1).min(last))),
+            ListKey::PageUp => index.and_then(|index| index.checked_sub(page)),
+            ListKey::PageDown => Some(index.map_or(0, |index| (index + page).min(last))),
+            ListKey::Top => Some(0),
+            ListKey::Bottom => Some(last),
+            ListKey::Select => return self.selected.take().map(UserAction::PlayUpcoming),
+            ListKey::PlayNext => return self.selected.take().map(UserAction::PlayNext),
+            ListKey::Current => None,
+            ListKey::Sort | ListKey::Enqueue | ListKey::Remove => index,
+        };
+        self.selected = index.map(|index| self.shown[index].clone());
+        None
+    }
+}
+
+/// Returns where the progress bar and level meters are, for
+/// `draw_progress` to redraw them alone.
+pub fn draw(f: &mut Frame, area: Rect, queue_pane: &mut QueuePane, context: &Context) -> Rect {
+    let Context {
+        theme,
+        keymap,
+        layout,
+        now_playing,
+        ..
+    } = *context;
+    let NowPlaying {
+        track: current_track,
+        ref queue,
+        upcoming,
+        priority,
+        metadata: current_metadata,
+        technical_info,
+        art,
+        is_favorite,
+        stop_after_current,
+        ..
+    } = *now_playing;
+
+    // Main layout: art + now playing, then the optional controls and queue
+    let mut constraints = vec![Constraint::Length(layout.now_playing_height)];
+    if layout.controls {
+        constraints.push(Constraint::Length(5));
+    }
+    constraints.push(Constraint::Min(if layout.queue { 3 } else { 0 }));
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(area);
+    let controls_area = layout.controls.then(|| rows[1]);
+    let queue_area = layout.queue.then(|| rows[rows.len() - 1]);
+
+    // Album art pane, two pixels per cell with half blocks
+    let now_playing = if let Some(art) = art.filter(|_| layout.art) {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(rows[0].height * 2 + 1), Constraint::Min(20)])
+            .split(rows[0]);
+        f.render_widget(AlbumArt { thumbnail: art }, columns[0]);
+        columns[1]
+    } else {
+        rows[0]
+    };
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Header
+            Constraint::Min(1),    // Track info
+            Constraint::Length(3), // Progress bar
+        ])
+        .split(now_playing)
+        .to_vec();
+
+    // Header
+    let title = Paragraph::new("=== KSound Player ===")
+        .style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD))
+        .alignment(ratatui::layout::Alignment::Center)
+        .block(Block::default().borders(Borders::NONE));
+    f.render_widget(title, chunks[0]);
+
+    // Track info
+    let track_text = if let Some(track) = current_track {
+        let display_str = if let Some(metadata) = current_metadata {
+            let artist = metadata.artist.as_deref().unwrap_or("Unknown Artist");
+            let album = metadata.album.as_deref().unwrap_or("Unknown Album");
+            let title = metadata.title.as_deref().unwrap_or("Unknown Title");
+            let year = metadata.year.as_deref().unwrap_or("");
+            let rating = rating_stars(metadata.rating);
+            let all_unknown = artist == "Unknown Artist"
+                && album == "Unknown Album"
+                && title == "Unknown Title"
+                && year.is_empty();
+
+            let rel_path = match track
+                .strip_prefix(std::env::current_dir().unwrap_or_else(|_| track.clone()))
+                .ok()
+            {
+                Some(p) => p.display().to_string(),
+                None => track.display().to_string(),
+            };
+
+            if all_unknown {
+                if is_favorite {
+                    format!("★ {}{}", rel_path, rating)
+                } else {
+                    format!("{}{}", rel_path, rating)
+                }
+            } else {
+                let year_str = if year.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", year)
+                };
+                if is_favorite {
+                    format!("★ {} - {} - {}{}{} [{}]", artist, album, title, year_str, rating, rel_path)
+                } else {
+                    format!("{} - {} - {}{}{} [{}]", artist, album, title, year_str, rating, rel_path)
+                }
+            }
+        } else {
+            if is_favorite {
+                format!("★ {}", track.display())
+            } else {
+                track.display().to_string()
+            }
+        };
+        format!("Now playing: {}", display_str)
+    } else {
+        "No track playing".to_string()
+    };
+
+    let mut track_lines = vec![Line::from(track_text)];
+    if let Some(info) = technical_info {
+        track_lines.push(Line::from(Span::styled(
+            info.summary(),
+            Style::default().fg(theme.dim),
+        )));
+    }
+    if stop_after_current {
+        track_lines.push(Line::from(Span::styled(
+            "⏹ Stopping after this track",
+            Style::default().fg(theme.highlight),
+        )));
+    }
+
+    let track_paragraph = Paragraph::new(track_lines)
+        .style(Style::default().fg(theme.text))
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::NONE));
+    f.render_widget(track_paragraph, chunks[1]);
+
+    draw_progress(f, chunks[2], context);
+
+    // Controls
+    let mut controls_spans = Vec::new();
+    for binding in keymap.normal {
+        controls_spans.push(Span::styled(binding.label, Style::default().fg(theme.highlight)));
+        controls_spans.push(Span::raw(format!(": {}  ", binding.description)));
+    }
+    let controls_text = Line::from(controls_spans);
+
+    let controls = Paragraph::new(controls_text)
+        .style(Style::default().fg(theme.text))
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::default()
+                .borders(Borders::TOP)
+                .border_style(Style::default().fg(theme.dim))
+                .title("Controls"),
+        );
+    if let Some(area) = controls_area {
+        f.render_widget(controls, area);
+    }
+
+    // Queue: the current track then what comes next
+    queue_pane.shown = upcoming.to_vec();
+    queue_pane.height = queue_area.map_or(0, |area| area.height.saturating_sub(2) as usize);
+    // Gone once it plays.
+    let selected = queue_pane.selected.as_ref().and_then(|track| upcoming.iter().position(|t| t == track));
+    if selected.is_none() {
+        queue_pane.selected = None;
+    }
+    // The upcoming lines come after the current track's, if any.
+    let first_upcoming = queue.len() - upcoming.len();
+    let queue_lines: Vec<Line> = queue
+        .iter()
+        .enumerate()
+        .map(|(idx, label)| {
+            if idx < first_upcoming {
+                Line::from(Span::styled(
+                    format!("▶ {}", label),
+                    Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+                ))
+            } else if selected == Some(idx - first_upcoming) {
+                Line::from(Span::styled(
+                    format!("› {}", label),
+                    Style::default().fg(theme.good).add_modifier(Modifier::BOLD),
+                ))
+            } else if idx - first_upcoming < priority {
+                // Put there to play next.
+                Line::from(Span::styled(format!("+ {}", label), Style::default().fg(theme.highlight)))
+            } else {
+                Line::from(format!("  {}", label))
+            }
+        })
+        .collect();
+    // Scrolled for the selected track to show.
+    let visible = queue_area.map_or(0, |area| area.height.saturating_sub(1) as usize);
+    let scroll = selected.map_or(0, |index| (first_upcoming + index + 1).saturating_sub(visible));
+    let queue_pane = Paragraph::new(queue_lines)
+        .scroll((scroll as u16, 0))
+        .style(Style::default().fg(theme.text))
+        .block(
+            Block::default()
+                .borders(Borders::TOP)
+                .border_style(Style::default().fg(theme.dim))
+                .title("Up next"),
+        );
+    if let Some(area) = queue_area {
+        f.render_widget(queue_pane, area);
+    }
+    chunks[2]
+}
+
+/// The progress bar, and the level meters beside it: what moves while
+/// playing. The bar also tells the chapter playing.
+pub fn draw_progress(f: &mut Frame, area: Rect, context: &Context) {
+    let Context {
+        theme,
+        layout,
+        now_playing,
+        ..
+    } = *context;
+    let NowPlaying {
+        levels,
+        position: current_position,
+        duration: total_duration,
+        chapters,
+        ..
+    } = *now_playing;
+    let mut progress_area = area;
+
+    // Level meters beside the progress bar
+    if layout.meters {
+        let progress_row = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(10), Constraint::Length(LEVEL_METER_WIDTH)])
+            .split(area);
+        progress_area = progress_row[0];
+        f.render_widget(LevelMeter { levels, theme }, progress_row[1]);
+    }
+
+    // "3/12 The Storm · 84:10 / 360:00"
+    let chapter = current_position
+        .and_then(|position| chapter_at(chapters, position))
+        .map(|index| format!("{}/{} {} · ", index + 1, chapters.len(), chapters[index].title))
+        .unwrap_or_default();
+
+    // Progress bar
+    if let (Some(current), Some(total)) = (current_position, total_duration) {
+        if total.as_secs_f32() > 0.0 && current <= total {
+            let progress = (current.as_secs_f32() / total.as_secs_f32()).min(1.0);
+            // "-02:20 / 04:30" when counting down.
+            let (sign, shown) = if layout.remaining_time {
+                ("-", total - current)
+            } else {
+                ("", current)
+            };
+            let time_label = format!(
+                "{}{}{:02}:{:02} / {:02}:{:02}",
+                chapter,
+                sign,
+                shown.as_secs() / 60,
+                shown.as_secs() % 60,
+                total.as_secs() / 60,
+                total.as_secs() % 60
+            );
+
+            let gauge = Gauge::default()
+                .block(Block::default().borders(Borders::NONE))
+                .gauge_style(
+                    Style::default()
+                        .fg(theme.accent)
+                        .bg(theme.gauge_background)
+                        .add_modifier(Modifier::BOLD),
+                )
+                .label(time_label)
+                .ratio(progress as f64);
+            f.render_widget(gauge, progress_area);
+        } else {
+            let gauge = Gauge::default()
+                .block(Block::default().borders(Borders::NONE))
+                .gauge_style(Style::default().fg(theme.dim).bg(theme.gauge_background))
+                .label(format!("{}00:00 / 00:00", chapter))
+                .ratio(0.0);
+            f.render_widget(gauge, progress_area);
+        }
+    } else {
+        let gauge = Gauge::default()
+            .block(Block::default().borders(Borders::NONE))
+            .gauge_style(Style::default().fg(theme.dim).bg(theme.gauge_background))
+            .label(format!("{}00:00 / 00:00", chapter))
+            .ratio(0.0);
+        f.render_widget(gauge, progress_area);
+    }
+
+}
+
+/// Draws a cover thumbnail with "▀" cells: the foreground colours the upper
+/// pixel, the background the lower one.
+struct AlbumArt<'a> {
+    thumbnail: &'a Thumbnail,
+}
+
+impl Widget for AlbumArt<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // Keep the picture square, leaving one blank column as a gutter.
+        let width = area.width.saturating_sub(1).min(area.height * 2);
+        let height = area.height;
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        for row in 0..height {
+            for col in 0..width {
+                let x = col as f32 / width as f32;
+                let top = self.thumbnail.sample(x, (row * 2) as f32 / (height * 2) as f32);
+                let bottom = self.thumbnail.sample(x, (row * 2 + 1) as f32 / (height * 2) as f32);
+                buf[(area.x + col, area.y + row)]
+                    .set_char('▀')
+                    .set_fg(Color::Rgb(top[0], top[1], top[2]))
+                    .set_bg(Color::Rgb(bottom[0], bottom[1], bottom[2]));
+            }
+        }
+    }
+}
+
+/// Width of the level meters, labels included.
+const LEVEL_METER_WIDTH: u16 = 26;
+/// Lowest level shown by the meters, in dBFS.
+const LEVEL_FLOOR_DB: f32 = -48.0;
+
+/// Left/right peak meters in dBFS, turning to "CLIP" at full scale.
+struct LevelMeter<'a> {
+    levels: [f32; 2],
+    theme: &'a Theme,
+}
+
+impl Widget for LevelMeter<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // "L " before the bar, " -12" or " CLIP" after it.
+        let bar_width = area.width.saturating_sub(8);
+        if bar_width == 0 {
+            return;
+        }
+
+        for (row, (name, peak)) in ["L", "R"].iter().zip(self.levels).enumerate() {
+            if row as u16 >= area.height {
+                break;
+            }
+            let y = area.y + row as u16;
+            let db = if peak > 0.0 {
+                (20.0 * peak.log10()).max(LEVEL_FLOOR_DB)
+            } else {
+                LEVEL_FLOOR_DB
+            };
+            let filled = ((db - LEVEL_FLOOR_DB) / -LEVEL_FLOOR_DB * bar_width as f32).round() as u16;
+
+            buf.set_string(area.x, y, name, Style::default().fg(self.theme.dim));
+            for col in 0..bar_width {
+                let cell_db = LEVEL_FLOOR_DB * (1.0 - (col + 1) as f32 / bar_width as f32);
+                let (symbol, color) = if col >= filled {
+                    ("·", self.theme.dim)
+                } else if cell_db > -1.0 {
+                    ("█", self.theme.bad)
+                } else if cell_db > -9.0 {
+                    ("█", self.theme.highlight)
+                } else {
+                    ("█", self.theme.good)
+                };
+                buf.set_string(area.x + 2 + col, y, symbol, Style::default().fg(color));
+            }
+
+            let (label, style) = if peak >= 0.999 {
+                (" CLIP".to_string(), Style::default().fg(self.theme.bad).add_modifier(Modifier::BOLD))
+            } else if db > LEVEL_FLOOR_DB {
+                (format!(" {:>4.0}", db), Style::default().fg(self.theme.dim))
+            } else {
+                ("    -".to_string(), Style::default().fg(self.theme.dim))
+            };
+            buf.set_string(area.x + 2 + bar_width, y, label, style);
+        }
+    }
+}
+
+/// Renders a 1-5 star rating as " ★★★☆☆", or nothing when unrated.
+fn rating_stars(rating: Option<u8>) -> String {
+    match rating {
+        Some(stars) => {
+            let stars = stars.min(5) as usize;
+            format!(" {}{}", "★".repeat(stars), "☆".repeat(5 - stars))
+        }
+        None => String::new(),
+    }
+}
@@ -0,0 +1,66 @@
+//! The current playlist, in play order.
+
+use super::track_list::TrackList;
+use super::Context;
+use crate::player::SortKey;
+use crate::ui::keymap::ListKey;
+use crate::ui::UserAction;
+use ratatui::{layout::Rect, text::Line, Frame};
+use std::path::PathBuf;
+
+#[derive(Default)]
+pub struct PlaylistsTab {
+    list: TrackList,
+    /// Last order picked with the sort key, cycled through `SortKey::ALL`.
+    sort: Option<SortKey>,
+    /// Index of the playing entry, as of the last draw.
+    current: Option<usize>,
+    /// File of the selected entry, as of the last draw.
+    selected: Option<PathBuf>,
+}
+
+impl PlaylistsTab {
+    pub fn handle(&mut self, key: ListKey) -> Option<UserAction> {
+        if let ListKey::Sort = key {
+            let next = match self.sort {
+                Some(current) => {
+                    let index = SortKey::ALL.iter().position(|k| *k == current).unwrap_or(0);
+                    SortKey::ALL[(index + 1) % SortKey::ALL.len()]
+                }
+                None => SortKey::ALL[0],
+            };
+            self.sort = Some(next);
+            return Some(UserAction::SortPlaylist(next));
+        }
+        if let (ListKey::Current, Some(index)) = (key, self.current) {
+            self.list.select(index);
+        }
+        if let ListKey::PlayNext = key {
+            return self.selected.clone().map(UserAction::PlayNext);
+        }
+        self.list.handle(key).map(UserAction::PlayTrack)
+    }
+
+    pub fn draw(&mut self, f: &mut Frame, area: Rect, context: &Context) {
+        let now_playing = context.now_playing;
+        let playlist = now_playing.playlist;
+        self.current = now_playing.index;
+        let mut title = format!("Current playlist ({} tracks", playlist.len());
+        if now_playing.favorites_only {
+            title.push_str(", favorites only");
+        }
+        if let Some(filter) = now_playing.filter {
+            title.push_str(&format!(", matching {}", filter));
+        }
+        if let Some(sort) = self.sort {
+            title.push_str(&format!(", by {}", sort.name()));
+        }
+        title.push(')');
+        self.list.draw(f, area, context, title, playlist.len(), |idx| {
+            let path = &playlist[idx];
+            let marker = if now_playing.track == Some(path) { "▶" } else { " " };
+            Line::from(format!("{} {}", marker, now_playing.tracks.track_label(path)))
+        });
+        self.selected = self.list.selected().and_then(|idx| playlist.get(idx)).cloned();
+    }
+}
@@ -0,0 +1,263 @@
+//! What the player is running with, from the config file and the flags.
+//! Enter changes the selected setting and writes it back to the config file.
+
+use super::track_list::TrackList;
+use super::Context;
+use crate::config::{self, Config, PaneLayout};
+use crate::ui::keymap::ListKey;
+use crate::ui::{Pane, UserAction};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::Paragraph,
+    Frame,
+};
+
+/// Config values that can be changed from the Settings tab.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Setting {
+    Paths,
+    Resume,
+    Volume,
+    VolumeStep,
+    Shuffle,
+    ShuffleAlbums,
+    ArtistSpacing,
+    Repeat,
+    Party,
+    Crossfade,
+    CrossfadeOnNext,
+    CrossfadeOnEnd,
+    CrossfadeWithinAlbums,
+    Extensions,
+    Theme,
+    Keymap,
+    NowPlayingHeight,
+}
+
+impl Setting {
+    pub fn name(self) -> &'static str {
+        match self {
+            Setting::Paths => "Default paths",
+            Setting::Resume => "Resume last session",
+            Setting::Volume => "Volume at startup",
+            Setting::VolumeStep => "Volume step",
+            Setting::Shuffle => "Shuffle",
+            Setting::ShuffleAlbums => "Shuffle albums",
+            Setting::ArtistSpacing => "Artist spacing",
+            Setting::Repeat => "Repeat",
+            Setting::Party => "Party mode",
+            Setting::Crossfade => "Crossfade",
+            Setting::CrossfadeOnNext => "Crossfade on Next",
+            Setting::CrossfadeOnEnd => "Crossfade at end",
+            Setting::CrossfadeWithinAlbums => "Crossfade in albums",
+            Setting::Extensions => "Scanned extensions",
+            Setting::Theme => "Theme",
+            Setting::Keymap => "Keymap",
+            Setting::NowPlayingHeight => "Now playing height",
+        }
+    }
+
+    /// Key of the config file it is saved under.
+    pub fn key(self) -> &'static str {
+        match self {
+            Setting::Paths => "paths",
+            Setting::Resume => "resume",
+            Setting::Volume => "volume",
+            Setting::VolumeStep => "volume_step",
+            Setting::Shuffle => "shuffle",
+            Setting::ShuffleAlbums => "shuffle_albums",
+            Setting::ArtistSpacing => "artist_spacing",
+            Setting::Repeat => "repeat",
+            Setting::Party => "party",
+            Setting::Crossfade => "crossfade",
+            Setting::CrossfadeOnNext => "crossfade_on_next",
+            Setting::CrossfadeOnEnd => "crossfade_on_end",
+            Setting::CrossfadeWithinAlbums => "crossfade_within_albums",
+            Setting::Extensions => "extensions",
+            Setting::Theme => "theme",
+            Setting::Keymap => "keymap",
+            Setting::NowPlayingHeight => "layout",
+        }
+    }
+
+    /// What to type, for the settings edited as text; the others switch to
+    /// their next value on Enter.
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            Setting::Paths => Some("Folders played by default, separated by commas"),
+            Setting::Volume => Some("Percent, 0 to 200"),
+            Setting::VolumeStep => Some("Percent per key press, 1 to 100"),
+            Setting::ArtistSpacing => Some("Other tracks between two of an artist, 0 to 50 (0 turns it off)"),
+            Setting::Crossfade => Some("Seconds, 0 to 30 (0 turns it off)"),
+            Setting::Extensions => Some("File extensions, separated by commas"),
+            Setting::NowPlayingHeight => Some("Lines, 7 to 40"),
+            _ => None,
+        }
+    }
+
+    /// The value as it is edited.
+    pub fn text(self, config: &Config, layout: &PaneLayout) -> String {
+        match self {
+            Setting::Paths => config.paths.join(", "),
+            Setting::Volume => config.volume.to_string(),
+            Setting::VolumeStep => config.volume_step.to_string(),
+            Setting::ArtistSpacing => config.artist_spacing.to_string(),
+            Setting::Crossfade => config.crossfade.to_string(),
+            Setting::Extensions => config.extensions.join(", "),
+            Setting::NowPlayingHeight => layout.now_playing_height.to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Row {
+    Profile,
+    ConfigFile,
+    Setting(Setting),
+    Pane(Pane),
+    TimeDisplay,
+}
+
+const ROWS: [Row; 24] = [
+    Row::Profile,
+    Row::ConfigFile,
+    Row::Setting(Setting::Paths),
+    Row::Setting(Setting::Resume),
+    Row::Setting(Setting::Volume),
+    Row::Setting(Setting::VolumeStep),
+    Row::Setting(Setting::Shuffle),
+    Row::Setting(Setting::ShuffleAlbums),
+    Row::Setting(Setting::ArtistSpacing),
+    Row::Setting(Setting::Repeat),
+    Row::Setting(Setting::Party),
+    Row::Setting(Setting::Crossfade),
+    Row::Setting(Setting::CrossfadeOnNext),
+    Row::Setting(Setting::CrossfadeOnEnd),
+    Row::Setting(Setting::CrossfadeWithinAlbums),
+    Row::Setting(Setting::Extensions),
+    Row::Setting(Setting::Theme),
+    Row::Setting(Setting::Keymap),
+    Row::Pane(Pane::Art),
+    Row::Pane(Pane::Meters),
+    Row::Pane(Pane::Controls),
+    Row::Pane(Pane::Queue),
+    Row::Setting(Setting::NowPlayingHeight),
+    Row::TimeDisplay,
+];
+
+impl Row {
+    fn name(self) -> &'static str {
+        match self {
+            Row::Profile => "Profile",
+            Row::ConfigFile => "Config file",
+            Row::Setting(setting) => setting.name(),
+            Row::Pane(Pane::Art) => "Album art",
+            Row::Pane(Pane::Meters) => "Level meters",
+            Row::Pane(Pane::Controls) => "Controls",
+            Row::Pane(Pane::Queue) => "Queue",
+            Row::TimeDisplay => "Time display",
+        }
+    }
+
+    fn value(self, context: &Context) -> String {
+        let Context {
+            theme,
+            keymap,
+            layout,
+            config,
+            ..
+        } = *context;
+        let on_off = |on: bool| if on { "on" } else { "off" }.to_string();
+        let shown = |shown: bool| if shown { "shown" } else { "hidden" }.to_string();
+        match self {
+            Row::Profile => config::profile().unwrap_or("none").to_string(),
+            Row::ConfigFile => Config::path()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| "unavailable".to_string()),
+            Row::Setting(Setting::Paths) if config.paths.is_empty() => ".".to_string(),
+            Row::Setting(Setting::Resume) => on_off(config.resume),
+            Row::Setting(Setting::Volume) => format!("{}%", config.volume),
+            Row::Setting(Setting::VolumeStep) => format!("{}%", config.volume_step),
+            Row::Setting(Setting::Shuffle) => on_off(config.shuffle),
+            Row::Setting(Setting::ShuffleAlbums) => on_off(config.shuffle_albums),
+            Row::Setting(Setting::ArtistSpacing) => match config.artist_spacing {
+                0 => "off".to_string(),
+                1 => "1 track".to_string(),
+                spacing => format!("{} tracks", spacing),
+            },
+            Row::Setting(Setting::Repeat) => format!("{:?}", config.repeat).to_lowercase(),
+            Row::Setting(Setting::Party) => on_off(config.party),
+            Row::Setting(Setting::Crossfade) if config.crossfade > 0.0 => format!("{} s", config.crossfade),
+            Row::Setting(Setting::Crossfade) => "off".to_string(),
+            Row::Setting(Setting::CrossfadeOnNext) => on_off(config.crossfade_on_next),
+            Row::Setting(Setting::CrossfadeOnEnd) => on_off(config.crossfade_on_end),
+            Row::Setting(Setting::CrossfadeWithinAlbums) => on_off(config.crossfade_within_albums),
+            Row::Setting(Setting::Theme) if theme.customized => {
+                format!("{} (with custom colors)", theme.name)
+            }
+            Row::Setting(Setting::Theme) => theme.name.to_string(),
+            Row::Setting(Setting::Keymap) if keymap.customized => {
+                format!("{} (with custom keys)", keymap.name)
+            }
+            Row::Setting(Setting::Keymap) => keymap.name.to_string(),
+            Row::Setting(Setting::NowPlayingHeight) => format!("{} lines", layout.now_playing_height),
+            Row::Setting(setting) => setting.text(config, layout),
+            Row::Pane(Pane::Art) => shown(layout.art),
+            Row::Pane(Pane::Meters) => shown(layout.meters),
+            Row::Pane(Pane::Controls) => shown(layout.controls),
+            Row::Pane(Pane::Queue) => shown(layout.queue),
+            Row::TimeDisplay if layout.remaining_time => "remaining".to_string(),
+            Row::TimeDisplay => "elapsed".to_string(),
+        }
+    }
+
+    fn action(self) -> Option<UserAction> {
+        match self {
+            Row::Profile | Row::ConfigFile => None,
+            Row::Setting(setting) if setting.hint().is_some() => Some(UserAction::EditSetting(setting)),
+            Row::Setting(setting) => Some(UserAction::ChangeSetting(setting, None)),
+            Row::Pane(pane) => Some(UserAction::TogglePane(pane)),
+            Row::TimeDisplay => Some(UserAction::ToggleTimeDisplay),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SettingsTab {
+    list: TrackList,
+}
+
+impl SettingsTab {
+    pub fn handle(&mut self, key: ListKey) -> Option<UserAction> {
+        let row = self.list.handle(key)?;
+        ROWS[row].action()
+    }
+
+    pub fn draw(&mut self, f: &mut Frame, area: Rect, context: &Context) {
+        let theme = context.theme;
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(2)])
+            .split(area);
+
+        self.list.draw(f, rows[0], context, "Settings".to_string(), ROWS.len(), |idx| {
+            let row = ROWS[idx];
+            Line::from(vec![
+                Span::styled(format!("  {:<20}", row.name()), Style::default().fg(theme.highlight)),
+                Span::raw(row.value(context)),
+            ])
+        });
+
+        let hint = Paragraph::new(vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                "  Enter changes the selected setting and saves it; paths and extensions apply at the next start.",
+                Style::default().fg(theme.dim).add_modifier(Modifier::ITALIC),
+            )),
+        ]);
+        f.render_widget(hint, rows[1]);
+    }
+}
@@ -0,0 +1,93 @@
+//! The tracks of a list kept in the data directory, the favorites or the
+//! skip list, oldest first. Those whose file is gone are shown apart: they
+//! can still be taken out.
+
+use super::track_list::TrackList;
+use super::Context;
+use crate::ui::keymap::ListKey;
+use crate::ui::{NowPlaying, StoredTrack, UserAction};
+use ratatui::{layout::Rect, style::Style, text::Line, Frame};
+use std::sync::Arc;
+
+pub struct StoredTab {
+    list: TrackList,
+    title: &'static str,
+    /// The tracks of `NowPlaying` this tab shows.
+    tracks: fn(&NowPlaying) -> Arc<Vec<StoredTrack>>,
+    /// What takes the entry with this identifier out of the list.
+    remove: fn(String) -> UserAction,
+    /// The tracks and the row of the playing one, as of the last draw.
+    shown: Arc<Vec<StoredTrack>>,
+    current: Option<usize>,
+}
+
+impl StoredTab {
+    pub fn favorites() -> StoredTab {
+        StoredTab::new(
+            "Favorites",
+            |now_playing| Arc::clone(&now_playing.favorites),
+            UserAction::RemoveFavorite,
+        )
+    }
+
+    pub fn skipped() -> StoredTab {
+        StoredTab::new("Skipped tracks", |now_playing| Arc::clone(&now_playing.skipped), UserAction::Unskip)
+    }
+
+    fn new(
+        title: &'static str,
+        tracks: fn(&NowPlaying) -> Arc<Vec<StoredTrack>>,
+        remove: fn(String) -> UserAction,
+    ) -> StoredTab {
+        StoredTab {
+            list: TrackList::default(),
+            title,
+            tracks,
+            remove,
+            shown: Arc::default(),
+            current: None,
+        }
+    }
+
+    pub fn handle(&mut self, key: ListKey) -> Option<UserAction> {
+        if let (ListKey::Current, Some(row)) = (key, self.current) {
+            self.list.select(row);
+        }
+        let selected = self.list.selected().and_then(|row| self.shown.get(row));
+        match key {
+            ListKey::Enqueue => selected.map(|track| UserAction::Enqueue(track.path.clone())),
+            ListKey::PlayNext => selected.map(|track| UserAction::PlayNext(track.path.clone())),
+            ListKey::Remove => selected.map(|track| (self.remove)(track.id.clone())),
+            _ => {
+                let row = self.list.handle(key)?;
+                self.shown.get(row).map(|track| UserAction::PlayFile(track.path.clone()))
+            }
+        }
+    }
+
+    pub fn draw(&mut self, f: &mut Frame, area: Rect, context: &Context) {
+        let now_playing = context.now_playing;
+        self.shown = (self.tracks)(now_playing);
+        let tracks = &self.shown;
+        self.current = now_playing
+            .track
+            .and_then(|playing| tracks.iter().position(|track| track.path == *playing));
+
+        let missing = tracks.iter().filter(|track| track.missing).count();
+        let title = match missing {
+            0 => format!("{} ({} tracks)", self.title, tracks.len()),
+            _ => format!("{} ({} tracks, {} missing)", self.title, tracks.len(), missing),
+        };
+        let bad = context.theme.bad;
+        self.list.draw(f, area, context, title, tracks.len(), |idx| {
+            let track = &tracks[idx];
+            let marker = if now_playing.track == Some(&track.path) { "▶" } else { " " };
+            if track.missing {
+                Line::from(format!("{} {}  (missing: {})", marker, track.label, track.path.display()))
+                    .style(Style::default().fg(bad))
+            } else {
+                Line::from(format!("{} {}", marker, track.label))
+            }
+        });
+    }
+}
@@ -0,0 +1,96 @@
+//! Selection and scrolling shared by the list tabs.
+
+use super::Context;
+use crate::ui::keymap::ListKey;
+use ratatui::{
+    layout::Rect,
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+/// A scrollable list whose rows are only built for the visible part.
+#[derive(Default)]
+pub struct TrackList {
+    selected: usize,
+    offset: usize,
+    /// Row count and height seen at the last draw, for the keys.
+    len: usize,
+    height: usize,
+}
+
+impl TrackList {
+    /// Applies a navigation key. Returns the selected row on `Select`.
+    pub fn handle(&mut self, key: ListKey) -> Option<usize> {
+        let last = self.len.saturating_sub(1);
+        let page = self.height.max(1);
+        match key {
+            ListKey::Up => self.selected = self.selected.saturating_sub(1),
+            ListKey::Down => self.selected = (self.selected + 1).min(last),
+            ListKey::PageUp => self.selected = self.selected.saturating_sub(page),
+            ListKey::PageDown => self.selected = (self.selected + page).min(last),
+            ListKey::Top => self.selected = 0,
+            ListKey::Bottom => self.selected = last,
+            ListKey::Select => return (self.len > 0).then_some(self.selected),
+            ListKey::Current | ListKey::Sort | ListKey::Enqueue | ListKey::PlayNext | ListKey::Remove => {}
+        }
+        None
+    }
+
+    /// The selected row, if there is any.
+    pub fn selected(&self) -> Option<usize> {
+        (self.len > 0).then_some(self.selected)
+    }
+
+    /// Selects `row`, shown in the middle of the list at the next draw.
+    pub fn select(&mut self, row: usize) {
+        self.selected = row;
+        let last_page = self.len.saturating_sub(self.height);
+        self.offset = row.saturating_sub(self.height / 2).min(last_page);
+    }
+
+    /// Draws `len` rows in a titled block. `row` builds the line of one
+    /// row and is only called for the visible ones.
+    pub fn draw<'r>(
+        &mut self,
+        f: &mut Frame,
+        area: Rect,
+        context: &Context,
+        title: String,
+        len: usize,
+        row: impl Fn(usize) -> Line<'r>,
+    ) {
+        let theme = context.theme;
+        self.len = len;
+        self.height = area.height.saturating_sub(1) as usize;
+        self.selected = self.selected.min(len.saturating_sub(1));
+        // Scroll just enough to keep the selection visible.
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.height > 0 && self.selected >= self.offset + self.height {
+            self.offset = self.selected + 1 - self.height;
+        }
+
+        let lines: Vec<Line> = (self.offset..len.min(self.offset + self.height))
+            .map(|idx| {
+                let line = row(idx);
+                if idx == self.selected {
+                    line.style(Style::default().fg(theme.good).add_modifier(Modifier::BOLD))
+                } else {
+                    line
+                }
+            })
+            .collect();
+
+        let list = Paragraph::new(lines)
+            .style(Style::default().fg(theme.text))
+            .block(
+                Block::default()
+                    .borders(Borders::TOP)
+                    .border_style(Style::default().fg(theme.dim))
+                    .title(title),
+            );
+        f.render_widget(list, area);
+    }
+}
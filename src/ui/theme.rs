@@ -0,0 +1,104 @@
+//! Colors of the interface, by role, so they can be adapted to the terminal.
+
+use super::UiError;
+use ratatui::style::Color;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub name: &'static str,
+    /// Whether some colors come from the `[colors]` config table.
+    pub customized: bool,
+    /// Titles, progress bar and dialog borders.
+    pub accent: Color,
+    /// Key names and file names.
+    pub highlight: Color,
+    pub text: Color,
+    /// Secondary information and separators.
+    pub dim: Color,
+    /// Selection and confirmations.
+    pub good: Color,
+    /// Warnings and values about to be replaced.
+    pub bad: Color,
+    /// Unfilled part of the progress bar.
+    pub gauge_background: Color,
+}
+
+/// Names accepted by `--theme` and the `theme` config key.
+pub const BUILTIN_THEMES: [&str; 3] = ["default", "light", "mono"];
+
+impl Theme {
+    /// The theme called `name`, if it is one of `BUILTIN_THEMES`.
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme {
+                name: "default",
+                customized: false,
+                accent: Color::Cyan,
+                highlight: Color::Yellow,
+                text: Color::White,
+                dim: Color::DarkGray,
+                good: Color::Green,
+                bad: Color::Red,
+                gauge_background: Color::Black,
+            }),
+            // For terminals with a light background.
+            "light" => Some(Theme {
+                name: "light",
+                customized: false,
+                accent: Color::Blue,
+                highlight: Color::Magenta,
+                text: Color::Black,
+                dim: Color::Gray,
+                good: Color::Green,
+                bad: Color::Red,
+                gauge_background: Color::Gray,
+            }),
+            // The terminal's own colors only.
+            "mono" => Some(Theme {
+                name: "mono",
+                customized: false,
+                accent: Color::Reset,
+                highlight: Color::Reset,
+                text: Color::Reset,
+                dim: Color::Reset,
+                good: Color::Reset,
+                bad: Color::Reset,
+                gauge_background: Color::Reset,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The built-in theme `name` with some of its colors replaced. Keys of
+    /// `overrides` are role names ("accent", "dim", ...), values are color
+    /// names ("light-blue"), indexes ("208") or "#rrggbb".
+    pub fn load(name: &str, overrides: &BTreeMap<String, String>) -> Result<Theme, UiError> {
+        let mut theme = Theme::builtin(name).ok_or_else(|| {
+            UiError::Theme(format!(
+                "unknown theme \"{}\" (available: {})",
+                name,
+                BUILTIN_THEMES.join(", ")
+            ))
+        })?;
+
+        for (role, value) in overrides {
+            let slot = match role.as_str() {
+                "accent" => &mut theme.accent,
+                "highlight" => &mut theme.highlight,
+                "text" => &mut theme.text,
+                "dim" => &mut theme.dim,
+                "good" => &mut theme.good,
+                "bad" => &mut theme.bad,
+                "gauge_background" => &mut theme.gauge_background,
+                _ => return Err(UiError::Theme(format!("unknown color role \"{}\"", role))),
+            };
+            *slot = Color::from_str(value)
+                .map_err(|_| UiError::Theme(format!("invalid color \"{}\" for {}", value, role)))?;
+        }
+        theme.customized = !overrides.is_empty();
+
+        Ok(theme)
+    }
+}